@@ -1 +1,75 @@
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BenchmarkId,
+    Criterion,
+    Throughput,
+};
+use handshaker::p2p::messages::calculate_checksum;
+use sha2::{
+    Digest,
+    Sha256,
+};
 
+/// Checksum implementation prior to the optimization, kept as a baseline.
+fn calculate_checksum_baseline(data: &[u8]) -> u32 {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    let mut result = hasher.finalize_reset();
+
+    hasher.update(result);
+    result = hasher.finalize();
+
+    u32::from_le_bytes(result[..std::mem::size_of::<u32>()].try_into().unwrap())
+}
+
+fn checksum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("checksum");
+
+    // Empty verack, typical version, full receive buffer and a large message
+    for size in [0, 103, 4096, 1 << 20] {
+        let data = vec![0xab_u8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("baseline", size), &data, |b, data| {
+            b.iter(|| calculate_checksum_baseline(data))
+        });
+        group.bench_with_input(BenchmarkId::new("current", size), &data, |b, data| {
+            b.iter(|| calculate_checksum(data))
+        });
+    }
+
+    group.finish();
+}
+
+/// Simulates scanning many peers, each exchanging the handful of small
+/// messages a handshake consists of.
+fn handshake_scan(c: &mut Criterion) {
+    let messages: Vec<Vec<u8>> = [103, 0, 8, 8]
+        .iter()
+        .cycle()
+        .take(4 * 1000)
+        .map(|size| vec![0xcd_u8; *size])
+        .collect();
+
+    let mut group = c.benchmark_group("handshake_scan");
+    group.bench_function("baseline", |b| {
+        b.iter(|| {
+            messages
+                .iter()
+                .map(|m| calculate_checksum_baseline(m))
+                .fold(0, u32::wrapping_add)
+        })
+    });
+    group.bench_function("current", |b| {
+        b.iter(|| {
+            messages
+                .iter()
+                .map(|m| calculate_checksum(m))
+                .fold(0, u32::wrapping_add)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, checksum, handshake_scan);
+criterion_main!(benches);
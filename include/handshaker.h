@@ -0,0 +1,84 @@
+/*
+ * C bindings of the handshaker library.
+ *
+ * Build the shared library with:
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type cdylib
+ */
+
+#ifndef HANDSHAKER_H
+#define HANDSHAKER_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define HANDSHAKER_OK 0
+#define HANDSHAKER_INVALID_ARGUMENT 1
+#define HANDSHAKER_CONNECTION_REFUSED 2
+#define HANDSHAKER_CONNECTION_HANG_UP 3
+#define HANDSHAKER_INVALID_DATA 4
+#define HANDSHAKER_IO_ERROR 5
+#define HANDSHAKER_PONG_NONCE_MISMATCH 6
+#define HANDSHAKER_NETWORK_MISMATCH 7
+#define HANDSHAKER_MESSAGE_TIMEOUT 8
+#define HANDSHAKER_TIMEOUT 9
+#define HANDSHAKER_RUNTIME_ERROR 10
+#define HANDSHAKER_TARGET_DENIED 11
+#define HANDSHAKER_STALLED 12
+#define HANDSHAKER_PROXY_ERROR 13
+#define HANDSHAKER_STAGE_TIMEOUT 14
+#define HANDSHAKER_SELF_CONNECTION 15
+
+/* Options of the handshake, see handshaker_default_options. */
+typedef struct HandshakerOptions {
+    /* Time limit of the whole handshake in milliseconds, unlimited if zero */
+    uint32_t timeout_ms;
+    /* Protocol version advertised in the Version message */
+    int32_t version;
+    /* User agent, the library's one if NULL */
+    const char *user_agent;
+    /* Start height advertised in the Version message */
+    int32_t start_height;
+    /* Whether the node should announce relayed transactions */
+    bool relay;
+} HandshakerOptions;
+
+/* Information about the node the handshake was performed with. */
+typedef struct HandshakerResult {
+    /* Magic of the network the handshake was performed on */
+    uint32_t network;
+    int32_t version;
+    /* Bit mask of the services provided by the node */
+    uint64_t services;
+    /* User agent, truncated if too long */
+    char user_agent[256];
+    int32_t start_height;
+    bool relay;
+} HandshakerResult;
+
+/* Gets the options the handshake is performed with if none are given. */
+HandshakerOptions handshaker_default_options(void);
+
+/*
+ * Performs the handshake with the node at the given "host:port" address,
+ * blocking until it completes. The network, e.g. "main" or "testnet", is
+ * detected from the node's response if NULL. Default options are used if
+ * the options are NULL. On success, information about the node is written
+ * to the result.
+ *
+ * Returns HANDSHAKER_OK or the code of the error which occurred.
+ */
+int32_t handshaker_handshake(const char *address,
+                             const char *network,
+                             const HandshakerOptions *options,
+                             HandshakerResult *out_result);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* HANDSHAKER_H */
@@ -0,0 +1,166 @@
+use std::{
+    fs,
+    io,
+    net::Ipv4Addr,
+    path::Path,
+    time::Duration,
+};
+
+use serde_json::{
+    json,
+    Value,
+};
+
+use crate::subnet::Subnet;
+
+/// Period misbehaving nodes are banned for, matching Bitcoin Core's default
+pub const BAN_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Version of the ban entry format written by Bitcoin Core
+const ENTRY_VERSION: u64 = 1;
+
+/// Single banned subnet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BanEntry {
+    /// Banned subnet as written by Bitcoin Core, e.g. `1.2.3.4/32`
+    pub address: String,
+    /// UNIX timestamp of the ban creation
+    pub created: u64,
+    /// UNIX timestamp the ban expires at
+    pub until: u64,
+}
+
+/// Banned subnets in the format of Bitcoin Core's `banlist.json`, i.e.
+/// `{"banned_nets": [{"version", "ban_created", "banned_until", "address"}]}`.
+/// IPv6 and Tor entries are kept as they are, but never match any node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Banlist {
+    pub entries: Vec<BanEntry>,
+}
+
+impl Banlist {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid banlist"))
+    }
+
+    fn parse(content: &str) -> Option<Self> {
+        let root: Value = serde_json::from_str(content).ok()?;
+        let entries = root
+            .get("banned_nets")?
+            .as_array()?
+            .iter()
+            .map(|entry| {
+                Some(BanEntry {
+                    address: entry.get("address")?.as_str()?.to_string(),
+                    created: entry.get("ban_created")?.as_u64()?,
+                    until: entry.get("banned_until")?.as_u64()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { entries })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, format!("{:#}\n", self.to_json()))
+    }
+
+    fn to_json(&self) -> Value {
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|e| {
+                json!({
+                    "version": ENTRY_VERSION,
+                    "ban_created": e.created,
+                    "banned_until": e.until,
+                    "address": e.address,
+                })
+            })
+            .collect();
+        json!({ "banned_nets": entries })
+    }
+
+    /// Checks whether any ban which is still in effect covers the address
+    pub fn is_banned(&self, addr: Ipv4Addr, now: u64) -> bool {
+        self.entries.iter().any(|e| {
+            e.until > now
+                && e.address
+                    .parse::<Subnet>()
+                    .is_ok_and(|subnet| subnet.contains(addr))
+        })
+    }
+
+    /// Bans the address for the default ban time, unless already banned
+    pub fn ban(&mut self, addr: Ipv4Addr, now: u64) {
+        if self.is_banned(addr, now) {
+            return;
+        }
+        self.entries.push(BanEntry {
+            address: Subnet::new(addr, 32).to_string(),
+            created: now,
+            until: now + BAN_TIME.as_secs(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn parse() {
+        let banlist = Banlist::parse(
+            r#"{
+                "banned_nets": [
+                    {
+                        "version": 1,
+                        "ban_created": 1699990000,
+                        "banned_until": 1700050000,
+                        "address": "10.0.0.0/24"
+                    },
+                    {
+                        "version": 1,
+                        "ban_created": 1699990000,
+                        "banned_until": 1700050000,
+                        "address": "2001:db8::/32"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(banlist.entries.len(), 2);
+        assert!(banlist.is_banned(Ipv4Addr::new(10, 0, 0, 7), NOW));
+        assert!(!banlist.is_banned(Ipv4Addr::new(10, 0, 1, 7), NOW));
+        assert!(!banlist.is_banned(Ipv4Addr::new(10, 0, 0, 7), 1_700_050_000));
+
+        assert_eq!(Banlist::parse("{}"), None);
+        assert_eq!(Banlist::parse(r#"{"banned_nets": [{}]}"#), None);
+    }
+
+    #[test]
+    fn ban() {
+        let mut banlist = Banlist::default();
+        banlist.ban(Ipv4Addr::new(10, 0, 0, 1), NOW);
+        banlist.ban(Ipv4Addr::new(10, 0, 0, 1), NOW);
+        assert_eq!(banlist.entries.len(), 1);
+
+        let json = banlist.to_json();
+        assert_eq!(
+            json,
+            json!({
+                "banned_nets": [{
+                    "version": 1,
+                    "ban_created": NOW,
+                    "banned_until": NOW + 86400,
+                    "address": "10.0.0.1/32",
+                }]
+            })
+        );
+        assert_eq!(Banlist::parse(&json.to_string()), Some(banlist));
+    }
+}
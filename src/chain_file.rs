@@ -0,0 +1,68 @@
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use handshaker::p2p::messages::NetworkParams;
+
+use crate::cli::parse_magic;
+
+/// Network defined in a TOML file, e.g.
+///
+/// ```toml
+/// name = "private"
+/// magic = "0xaabbccdd"
+/// default_port = 9333
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    name: String,
+    /// Magic bytes as they appear on the wire, like `--magic`
+    magic: String,
+    default_port: u16,
+}
+
+/// Loads the parameters of the network defined in the file
+pub fn load(path: &Path) -> io::Result<NetworkParams> {
+    parse(&fs::read_to_string(path)?)
+}
+
+fn parse(content: &str) -> io::Result<NetworkParams> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let definition: Definition =
+        toml::from_str(content).map_err(|e| invalid(e.to_string()))?;
+    let magic = parse_magic(&definition.magic).map_err(invalid)?;
+    Ok(NetworkParams::new(
+        definition.name,
+        magic,
+        definition.default_port,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definition() {
+        let params = parse(
+            "# Private chain\n\
+            name = \"private\"\n\
+            magic = \"0xaabbccdd\"\n\
+            default_port = 9333\n",
+        )
+        .unwrap();
+        assert_eq!(params, NetworkParams::new("private", 0xdd_cc_bb_aa, 9333));
+
+        assert!(parse("name = \"private\"\nmagic = \"0xaabbccdd\"\n").is_err());
+        assert!(parse("name = \"x\"\nmagic = \"0xaabb\"\ndefault_port = 1\n").is_err());
+        assert!(parse(
+            "name = \"x\"\nmagic = \"aabbccdd\"\ndefault_port = 1\nport = 2\n"
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::net::SocketAddrV4;
+
+use futures::StreamExt;
+
+use handshaker::p2p::{
+    messages::Chain,
+    CrawlRecord,
+    Crawler,
+};
+
+/// Describes the reachable node in a single line
+pub fn describe(record: &CrawlRecord) -> String {
+    format!(
+        "Node at {} (depth {}): {}, gossiped {} addresses",
+        record.address,
+        record.depth,
+        record.report,
+        record.addresses.len()
+    )
+}
+
+/// Crawls the network from the seeds, printing each reachable node as soon
+/// as it is visited. Returns the number of reachable nodes.
+pub async fn run<C>(crawler: Crawler<'_, C>, seeds: Vec<SocketAddrV4>) -> usize
+where
+    C: Chain + Clone + Send + Sync,
+{
+    let mut records = std::pin::pin!(crawler.crawl(seeds));
+    let mut reachable = 0;
+    while let Some(record) = records.next().await {
+        println!("{}", describe(&record));
+        reachable += 1;
+    }
+    reachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use handshaker::prelude::*;
+
+    #[test]
+    fn describe_record() {
+        let record = CrawlRecord {
+            address: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8333).into(),
+            depth: 1,
+            report: HandshakeReport::default(),
+            addresses: Vec::new(),
+        };
+        assert!(describe(&record).starts_with("Node at 127.0.0.1:8333 (depth 1): "));
+        assert!(describe(&record).ends_with(", gossiped 0 addresses"));
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    net::{
+        Ipv4Addr,
+        SocketAddrV4,
+    },
+    time::Duration,
+};
+
+use futures::future::join_all;
+use log::info;
+use strum::IntoEnumIterator;
+use tokio::time::timeout;
+
+use handshaker::prelude::*;
+
+/// Performs handshakes with the host on the default ports of all supported
+/// networks concurrently, returning the networks the host serves
+pub async fn run(node: &Node, ip: Ipv4Addr, time_limit: Duration) -> Vec<Network> {
+    let attempts = Network::iter().map(|network| async move {
+        let address = SocketAddrV4::new(ip, network.default_port());
+        info!("Probing {} for the {:?} network", address, network);
+
+        match timeout(time_limit, node.handshake(network, address)).await {
+            Ok(Ok(_)) => Some(network),
+            Ok(Err(e)) => {
+                info!("No {:?} node at {}: {}", network, address, e);
+                None
+            }
+            Err(_) => {
+                info!("No {:?} node at {}: timed out", network, address);
+                None
+            }
+        }
+    });
+
+    join_all(attempts).await.into_iter().flatten().collect()
+}
+
+/// Describes the networks served by the host, e.g.
+/// `10.0.0.1: Main (8333), Signet (38333)`
+pub fn describe(ip: Ipv4Addr, networks: &[Network]) -> String {
+    if networks.is_empty() {
+        return format!("{}: no supported network", ip);
+    }
+
+    let networks: Vec<String> = networks
+        .iter()
+        .map(|n| format!("{:?} ({})", n, n.default_port()))
+        .collect();
+    format!("{}: {}", ip, networks.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description() {
+        let ip = Ipv4Addr::new(10, 0, 0, 1);
+        assert_eq!(describe(ip, &[]), "10.0.0.1: no supported network");
+        assert_eq!(
+            describe(ip, &[Network::Main, Network::Signet]),
+            "10.0.0.1: Main (8333), Signet (38333)"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fmt::Display,
+};
+
+use serde_json::{
+    json,
+    Value,
+};
+
+use handshaker::prelude::*;
+
+use crate::summary::{
+    HandshakeSummary,
+    Outcome,
+};
+
+/// Width of the longest histogram bar
+const BAR_WIDTH: usize = 40;
+
+/// Numbers of nodes per user agent family and per protocol version.
+#[derive(Debug, Default, PartialEq)]
+pub struct Distribution {
+    /// Number of nodes the handshake completed with
+    pub total: usize,
+    pub user_agents: BTreeMap<String, usize>,
+    pub versions: BTreeMap<ProtocolVersion, usize>,
+}
+
+impl Distribution {
+    /// Counts the nodes the handshake completed with
+    pub fn of(results: &[HandshakeSummary]) -> Self {
+        let mut distribution = Self::default();
+        for result in results {
+            if let Outcome::Success(report) | Outcome::Slow(report) = &result.outcome {
+                distribution.total += 1;
+                *distribution
+                    .user_agents
+                    .entry(user_agent_family(&report.config.user_agent))
+                    .or_default() += 1;
+                *distribution
+                    .versions
+                    .entry(report.config.version)
+                    .or_default() += 1;
+            }
+        }
+        distribution
+    }
+
+    /// Renders both distributions as tables, most common first, optionally
+    /// with a histogram bar per row
+    pub fn render(&self, histogram: bool) -> String {
+        let mut text = String::new();
+        self.push_section(&mut text, "USER AGENT", &self.user_agents, histogram);
+        text.push('\n');
+        self.push_section(&mut text, "VERSION", &self.versions, histogram);
+        text
+    }
+
+    fn push_section<K: Display>(
+        &self,
+        text: &mut String,
+        header: &str,
+        counts: &BTreeMap<K, usize>,
+        histogram: bool,
+    ) {
+        let mut rows: Vec<(String, usize)> =
+            counts.iter().map(|(k, &n)| (k.to_string(), n)).collect();
+        rows.sort_by_key(|&(_, n)| Reverse(n));
+
+        let width = rows
+            .iter()
+            .map(|(k, _)| k.chars().count())
+            .chain([header.len()])
+            .max()
+            .unwrap_or_default();
+        let max = rows.first().map_or(0, |&(_, n)| n);
+
+        text.push_str(&format!(
+            "{:<width$}  NODES  SHARE\n",
+            header,
+            width = width
+        ));
+        for (key, count) in rows {
+            let mut line = format!(
+                "{:<width$}  {:>5}  {:>5.1}%",
+                key,
+                count,
+                self.share(count) * 100.0,
+                width = width
+            );
+            if histogram {
+                let bar = (count * BAR_WIDTH).div_ceil(max.max(1));
+                line.push_str(&format!("  {}", "#".repeat(bar)));
+            }
+            text.push_str(&line);
+            text.push('\n');
+        }
+    }
+
+    fn share(&self, count: usize) -> f64 {
+        count as f64 / self.total.max(1) as f64
+    }
+
+    pub fn to_json(&self) -> Value {
+        let entries = |counts: Vec<(String, usize)>| -> Vec<Value> {
+            counts
+                .into_iter()
+                .map(|(key, count)| {
+                    json!({
+                        "name": key,
+                        "nodes": count,
+                        "share": self.share(count),
+                    })
+                })
+                .collect()
+        };
+
+        json!({
+            "nodes": self.total,
+            "user_agents": entries(
+                self.user_agents.iter().map(|(k, &n)| (k.clone(), n)).collect()
+            ),
+            "versions": entries(
+                self.versions.iter().map(|(k, &n)| (k.to_string(), n)).collect()
+            ),
+        })
+    }
+}
+
+/// Gets the name of the software which built the user agent, i.e. the name
+/// of its last component, e.g. `Knots` for `/Satoshi:25.1.0/Knots:20230807/`
+pub fn user_agent_family(user_agent: &str) -> String {
+    user_agent
+        .split('/')
+        .rfind(|component| !component.is_empty())
+        .map(|component| component.split([':', '(']).next().unwrap_or_default())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        net::{
+            Ipv4Addr,
+            SocketAddrV4,
+        },
+        time::Duration,
+    };
+
+    fn result(version: i32, user_agent: &str) -> HandshakeSummary {
+        HandshakeSummary {
+            address: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8333),
+            outcome: Outcome::Success(HandshakeReport {
+                config: NodeConfig {
+                    version: ProtocolVersion(version),
+                    user_agent: user_agent.to_string(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            latency: Duration::ZERO,
+            attempts: 1,
+            score: 0.0,
+        }
+    }
+
+    #[test]
+    fn family() {
+        assert_eq!(user_agent_family("/Satoshi:25.0.0/"), "Satoshi");
+        assert_eq!(
+            user_agent_family("/Satoshi:25.1.0/Knots:20230807/"),
+            "Knots"
+        );
+        assert_eq!(user_agent_family("/btcwire:0.5.0/btcd:0.23.3/"), "btcd");
+        assert_eq!(user_agent_family("/Satoshi:0.21.0(bitcore)/"), "Satoshi");
+        assert_eq!(user_agent_family(""), "unknown");
+    }
+
+    #[test]
+    fn distribution() {
+        let mut results = vec![
+            result(70016, "/Satoshi:25.0.0/"),
+            result(70016, "/Satoshi:24.0.1/"),
+            result(70015, "/btcwire:0.5.0/btcd:0.23.3/"),
+            result(70016, "/Satoshi:25.1.0/Knots:20230807/"),
+        ];
+        results[0].outcome = Outcome::Timeout;
+
+        let distribution = Distribution::of(&results);
+        assert_eq!(distribution.total, 3);
+        assert_eq!(
+            distribution.versions,
+            BTreeMap::from([(ProtocolVersion(70015), 1), (ProtocolVersion(70016), 2)])
+        );
+
+        assert_eq!(
+            distribution.render(true),
+            "USER AGENT  NODES  SHARE\n\
+             Knots           1   33.3%  ########################################\n\
+             Satoshi         1   33.3%  ########################################\n\
+             btcd            1   33.3%  ########################################\n\
+             \n\
+             VERSION  NODES  SHARE\n\
+             70016        2   66.7%  ########################################\n\
+             70015        1   33.3%  ####################\n"
+        );
+
+        let json = distribution.to_json();
+        assert_eq!(json["nodes"], 3);
+        assert_eq!(json["versions"][1]["name"], "70016");
+        assert_eq!(json["versions"][1]["nodes"], 2);
+    }
+}
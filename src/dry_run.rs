@@ -0,0 +1,49 @@
+use std::net::{
+    SocketAddr,
+    SocketAddrV4,
+};
+
+use handshaker::p2p::{
+    messages::{
+        compose,
+        Chain,
+        Codec,
+        Command,
+        FrameHeader,
+        VersionMessage,
+    },
+    Node,
+    Pretty,
+};
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Composes the Version message which would be sent to the given address
+/// and prints its framed bytes together with the decoded fields, without
+/// opening any connection.
+pub async fn run(node: &Node, chain: impl Chain, address: SocketAddrV4) {
+    let data = compose(
+        &chain,
+        Command::Version,
+        node.version_message(SocketAddr::from(address)).await,
+    );
+
+    println!("Version message for {} ({} bytes):", address, data.len());
+    print_hex(&data[..]);
+
+    let mut payload = &data[..];
+    let header = FrameHeader::decode(&chain, &mut payload).unwrap();
+    let msg = VersionMessage::decode(&mut payload).unwrap();
+
+    println!("Header:");
+    print!("{}", Pretty(&header));
+    println!("Payload:");
+    print!("{}", Pretty(&msg));
+}
+
+fn print_hex(data: &[u8]) {
+    for (i, line) in data.chunks(BYTES_PER_LINE).enumerate() {
+        let bytes: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("  {:04x}  {}", i * BYTES_PER_LINE, bytes.join(" "));
+    }
+}
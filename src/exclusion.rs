@@ -0,0 +1,71 @@
+use std::net::{
+    Ipv4Addr,
+    SocketAddrV4,
+};
+
+use handshaker::p2p::TargetPolicy;
+use log::info;
+
+use crate::{
+    banlist::Banlist,
+    subnet::Subnet,
+};
+
+/// Nodes no connection is attempted to, i.e. the ones in the excluded
+/// subnets and the ones banned at the start of the run. Acts as the target
+/// policy of the node, so that it covers the addresses discovered from
+/// other nodes too.
+#[derive(Clone, Debug, Default)]
+pub struct Exclusions {
+    pub subnets: Vec<Subnet>,
+    pub banlist: Banlist,
+    /// UNIX timestamp the bans are checked at
+    pub now: u64,
+}
+
+impl Exclusions {
+    /// Checks whether the node is excluded or banned, logging why it is
+    pub fn excludes(&self, ip: Ipv4Addr) -> bool {
+        if self.subnets.iter().any(|subnet| subnet.contains(ip)) {
+            info!("Skipping excluded node at {}", ip);
+            return true;
+        }
+        if self.banlist.is_banned(ip, self.now) {
+            info!("Skipping banned node at {}", ip);
+            return true;
+        }
+        false
+    }
+}
+
+impl TargetPolicy for Exclusions {
+    fn allows(&self, address: SocketAddrV4) -> bool {
+        !self.excludes(*address.ip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn excludes() {
+        let mut exclusions = Exclusions {
+            subnets: vec!["10.0.0.0/8".parse().unwrap()],
+            now: NOW,
+            ..Default::default()
+        };
+        exclusions.banlist.ban(Ipv4Addr::new(192, 168, 0, 1), NOW);
+
+        assert!(exclusions.excludes(Ipv4Addr::new(10, 1, 2, 3)));
+        assert!(exclusions.excludes(Ipv4Addr::new(192, 168, 0, 1)));
+        let address = SocketAddrV4::new(Ipv4Addr::new(192, 168, 0, 2), 8333);
+        assert!(exclusions.allows(address));
+
+        // Bans expire
+        exclusions.now += 2 * crate::banlist::BAN_TIME.as_secs();
+        assert!(!exclusions.excludes(Ipv4Addr::new(192, 168, 0, 1)));
+    }
+}
@@ -0,0 +1,316 @@
+//! C bindings of the handshake, declared in `include/handshaker.h`.
+//!
+//! Built as a shared library with:
+//!
+//! ```bash
+//! cargo rustc --lib --release --features ffi --crate-type cdylib
+//! ```
+
+use std::{
+    ffi::{
+        c_char,
+        CStr,
+    },
+    net::SocketAddrV4,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use crate::p2p::{
+    messages::{
+        Network,
+        Service,
+        Services,
+    },
+    ConnectionError,
+    HandshakeReport,
+    Node,
+    NodeConfig,
+    UserAgent,
+};
+
+/// Protocol version advertised unless the options set another one
+const DEFAULT_VERSION: i32 = 70015;
+
+/// Size of the user agent buffer in the result, including the terminating nul
+const USER_AGENT_CAPACITY: usize = 256;
+
+pub const HANDSHAKER_OK: i32 = 0;
+pub const HANDSHAKER_INVALID_ARGUMENT: i32 = 1;
+pub const HANDSHAKER_CONNECTION_REFUSED: i32 = 2;
+pub const HANDSHAKER_CONNECTION_HANG_UP: i32 = 3;
+pub const HANDSHAKER_INVALID_DATA: i32 = 4;
+pub const HANDSHAKER_IO_ERROR: i32 = 5;
+pub const HANDSHAKER_PONG_NONCE_MISMATCH: i32 = 6;
+pub const HANDSHAKER_NETWORK_MISMATCH: i32 = 7;
+pub const HANDSHAKER_MESSAGE_TIMEOUT: i32 = 8;
+pub const HANDSHAKER_TIMEOUT: i32 = 9;
+pub const HANDSHAKER_RUNTIME_ERROR: i32 = 10;
+pub const HANDSHAKER_TARGET_DENIED: i32 = 11;
+pub const HANDSHAKER_STALLED: i32 = 12;
+pub const HANDSHAKER_PROXY_ERROR: i32 = 13;
+pub const HANDSHAKER_STAGE_TIMEOUT: i32 = 14;
+pub const HANDSHAKER_SELF_CONNECTION: i32 = 15;
+
+/// Options of the handshake, see `handshaker_default_options`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HandshakerOptions {
+    /// Time limit of the whole handshake in milliseconds, unlimited if zero
+    pub timeout_ms: u32,
+    /// Protocol version advertised in the Version message
+    pub version: i32,
+    /// Nul-terminated user agent, the library's one if null
+    pub user_agent: *const c_char,
+    /// Start height advertised in the Version message
+    pub start_height: i32,
+    /// Whether the node should announce relayed transactions
+    pub relay: bool,
+}
+
+impl Default for HandshakerOptions {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 1000,
+            version: DEFAULT_VERSION,
+            user_agent: std::ptr::null(),
+            start_height: 0,
+            relay: false,
+        }
+    }
+}
+
+/// Information about the node the handshake was performed with.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HandshakerResult {
+    /// Magic of the network the handshake was performed on
+    pub network: u32,
+    pub version: i32,
+    /// Bit mask of the services provided by the node
+    pub services: u64,
+    /// Nul-terminated user agent, truncated if too long
+    pub user_agent: [c_char; USER_AGENT_CAPACITY],
+    pub start_height: i32,
+    pub relay: bool,
+}
+
+impl From<&HandshakeReport> for HandshakerResult {
+    fn from(report: &HandshakeReport) -> Self {
+        let mut user_agent = [0; USER_AGENT_CAPACITY];
+        let bytes = report.config.user_agent.as_bytes();
+        for (c, &b) in user_agent
+            .iter_mut()
+            .zip(bytes.iter().take(USER_AGENT_CAPACITY - 1))
+        {
+            *c = b as c_char;
+        }
+
+        Self {
+            network: report.network.map_or(0, |n| n as u32),
+            version: report.config.version.into(),
+            services: report.config.services.into(),
+            user_agent,
+            start_height: report.config.start_height,
+            relay: report.config.relay,
+        }
+    }
+}
+
+fn error_code(error: &ConnectionError) -> i32 {
+    match error {
+        ConnectionError::ConnectionHangUp => HANDSHAKER_CONNECTION_HANG_UP,
+        ConnectionError::ConnectionRefusedError => HANDSHAKER_CONNECTION_REFUSED,
+        ConnectionError::InvalidDataError => HANDSHAKER_INVALID_DATA,
+        ConnectionError::IOError(_) => HANDSHAKER_IO_ERROR,
+        ConnectionError::PongNonceMismatch => HANDSHAKER_PONG_NONCE_MISMATCH,
+        ConnectionError::NetworkMismatch(_) => HANDSHAKER_NETWORK_MISMATCH,
+        ConnectionError::MessageTimeoutError => HANDSHAKER_MESSAGE_TIMEOUT,
+        ConnectionError::TargetDenied => HANDSHAKER_TARGET_DENIED,
+        ConnectionError::Stalled => HANDSHAKER_STALLED,
+        ConnectionError::ProxyError(_) => HANDSHAKER_PROXY_ERROR,
+        ConnectionError::StageTimeout(_) => HANDSHAKER_STAGE_TIMEOUT,
+        ConnectionError::SelfConnection => HANDSHAKER_SELF_CONNECTION,
+        ConnectionError::DecodeError(_) => HANDSHAKER_INVALID_DATA,
+    }
+}
+
+/// Reads the nul-terminated string, None if null or not UTF-8
+unsafe fn read_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Gets the options the handshake is performed with if none are given.
+#[no_mangle]
+pub extern "C" fn handshaker_default_options() -> HandshakerOptions {
+    HandshakerOptions::default()
+}
+
+/// Performs the handshake with the node at the given `host:port` address,
+/// blocking until it completes. The network, e.g. `main` or `testnet`, is
+/// detected from the node's response if null. Default options are used if
+/// the options are null. On success, information about the node is written
+/// to the result.
+///
+/// Returns `HANDSHAKER_OK` or the code of the error which occurred.
+///
+/// # Safety
+///
+/// Strings have to be nul-terminated and pointers either null or valid
+/// for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn handshaker_handshake(
+    address: *const c_char,
+    network: *const c_char,
+    options: *const HandshakerOptions,
+    out_result: *mut HandshakerResult,
+) -> i32 {
+    let Some(address) = read_str(address).and_then(|a| a.parse::<SocketAddrV4>().ok())
+    else {
+        return HANDSHAKER_INVALID_ARGUMENT;
+    };
+    let network = if network.is_null() {
+        None
+    } else {
+        match read_str(network).and_then(|n| Network::from_str(n, true).ok()) {
+            Some(v) => Some(v),
+            None => return HANDSHAKER_INVALID_ARGUMENT,
+        }
+    };
+    let options = options.as_ref().copied().unwrap_or_default();
+    let user_agent = if options.user_agent.is_null() {
+        UserAgent::library().to_string()
+    } else {
+        match read_str(options.user_agent) {
+            Some(v) => v.to_string(),
+            None => return HANDSHAKER_INVALID_ARGUMENT,
+        }
+    };
+    if out_result.is_null() {
+        return HANDSHAKER_INVALID_ARGUMENT;
+    }
+
+    let node = Node::new(NodeConfig {
+        version: options.version.into(),
+        services: Services::new(&[Service::Network]),
+        user_agent,
+        start_height: options.start_height,
+        relay: options.relay,
+        ..Default::default()
+    });
+
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(v) => v,
+        Err(_) => return HANDSHAKER_RUNTIME_ERROR,
+    };
+    let outcome = runtime.block_on(async {
+        let handshake = async {
+            match network {
+                Some(network) => node.handshake(network, address).await,
+                None => node.detect_handshake(address).await,
+            }
+        };
+        match options.timeout_ms {
+            0 => Some(handshake.await),
+            ms => tokio::time::timeout(Duration::from_millis(ms.into()), handshake)
+                .await
+                .ok(),
+        }
+    });
+
+    match outcome {
+        Some(Ok(report)) => {
+            *out_result = HandshakerResult::from(&report);
+            HANDSHAKER_OK
+        }
+        Some(Err(e)) => error_code(&e),
+        None => HANDSHAKER_TIMEOUT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        ffi::CString,
+        net::TcpListener,
+        ptr,
+    };
+
+    use crate::p2p::messages::ProtocolVersion;
+
+    fn handshake(address: &str, network: Option<&str>) -> i32 {
+        let address = CString::new(address).unwrap();
+        let network = network.map(|n| CString::new(n).unwrap());
+        let mut result = HandshakerResult::from(&HandshakeReport::default());
+        unsafe {
+            handshaker_handshake(
+                address.as_ptr(),
+                network.as_ref().map_or(ptr::null(), |n| n.as_ptr()),
+                ptr::null(),
+                &mut result,
+            )
+        }
+    }
+
+    #[test]
+    fn invalid_argument() {
+        assert_eq!(handshake("localhost", None), HANDSHAKER_INVALID_ARGUMENT);
+        assert_eq!(
+            handshake("127.0.0.1:8333", Some("bitcoin")),
+            HANDSHAKER_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            unsafe {
+                handshaker_handshake(
+                    ptr::null(),
+                    ptr::null(),
+                    ptr::null(),
+                    ptr::null_mut(),
+                )
+            },
+            HANDSHAKER_INVALID_ARGUMENT
+        );
+    }
+
+    #[test]
+    fn refused() {
+        // Reserve a port nothing listens on
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        assert_eq!(
+            handshake(&address, Some("testnet")),
+            HANDSHAKER_CONNECTION_REFUSED
+        );
+        assert_eq!(handshake(&address, None), HANDSHAKER_CONNECTION_REFUSED);
+    }
+
+    #[test]
+    fn result() {
+        let report = HandshakeReport {
+            network: Some(Network::Main),
+            config: NodeConfig {
+                version: ProtocolVersion(70016),
+                services: Services::new(&[Service::Network, Service::Witness]),
+                user_agent: "x".repeat(300),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = HandshakerResult::from(&report);
+        assert_eq!(result.network, 0xd9b4bef9);
+        assert_eq!(result.services, 0x09);
+        let user_agent = unsafe { CStr::from_ptr(result.user_agent.as_ptr()) };
+        assert_eq!(user_agent.to_bytes().len(), USER_AGENT_CAPACITY - 1);
+    }
+}
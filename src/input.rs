@@ -0,0 +1,199 @@
+use std::{
+    convert::Infallible,
+    fmt::{
+        Display,
+        Formatter,
+    },
+    fs,
+    io,
+    net::SocketAddrV4,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use hickory_resolver::{
+    error::ResolveError,
+    TokioAsyncResolver,
+};
+
+use crate::cli::parse_socket_address;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum InputError {
+    ReadError(io::Error),
+    FetchError(reqwest::Error),
+    LookupError(ResolveError),
+    InvalidLineError(usize, String),
+}
+
+impl Display for InputError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputError::ReadError(e) => write!(f, "Unable to read the list: {}", e),
+            InputError::FetchError(e) => write!(f, "Unable to fetch the list: {}", e),
+            InputError::LookupError(e) => {
+                write!(f, "Unable to look up the record: {}", e)
+            }
+            InputError::InvalidLineError(line, e) => write!(f, "line {}: {}", line, e),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Where the addresses of nodes to handshake with are listed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// Local file
+    File(PathBuf),
+    /// HTTP(S) URL serving the list
+    Url(String),
+    /// Name of a DNS SRV record, e.g. `_bitcoin._tcp.example.com`
+    Srv(String),
+}
+
+impl FromStr for Input {
+    type Err = Infallible;
+
+    /// Parses `http://` and `https://` URLs, `srv:` prefixed record names
+    /// and paths of anything else
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if s.starts_with("http://") || s.starts_with("https://") {
+            Input::Url(s.to_string())
+        } else if let Some(name) = s.strip_prefix("srv:") {
+            Input::Srv(name.to_string())
+        } else {
+            Input::File(PathBuf::from(s))
+        })
+    }
+}
+
+impl Display for Input {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Input::File(path) => write!(f, "{}", path.display()),
+            Input::Url(url) => write!(f, "{}", url),
+            Input::Srv(name) => write!(f, "srv:{}", name),
+        }
+    }
+}
+
+/// Loads the addresses from the input, so that a freshly loaded list
+/// reflects any changes made to it since the last load
+pub async fn load(input: &Input) -> Result<Vec<SocketAddrV4>, InputError> {
+    match input {
+        Input::File(path) => {
+            parse_list(&fs::read_to_string(path).map_err(InputError::ReadError)?)
+        }
+        Input::Url(url) => parse_list(&fetch(url).await.map_err(InputError::FetchError)?),
+        Input::Srv(name) => lookup(name).await.map_err(InputError::LookupError),
+    }
+}
+
+async fn fetch(url: &str) -> Result<String, reqwest::Error> {
+    reqwest::get(url).await?.error_for_status()?.text().await
+}
+
+/// Resolves the targets of the SRV record to their IPv4 addresses,
+/// combined with the ports the record lists
+async fn lookup(name: &str) -> Result<Vec<SocketAddrV4>, ResolveError> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let mut addresses = Vec::new();
+    for srv in resolver.srv_lookup(name).await?.iter() {
+        let ips = resolver.ipv4_lookup(srv.target().clone()).await?;
+        addresses.extend(ips.iter().map(|ip| SocketAddrV4::new(ip.0, srv.port())));
+    }
+    Ok(addresses)
+}
+
+/// Parses addresses listed one per line, skipping blank lines and `#` comments
+fn parse_list(content: &str) -> Result<Vec<SocketAddrV4>, InputError> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                return None;
+            }
+            Some(
+                parse_socket_address(line)
+                    .map_err(|e| InputError::InvalidLineError(i + 1, e.to_string())),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::TcpListener,
+    };
+
+    #[test]
+    fn parse_input() {
+        assert_eq!(
+            "https://example.com/peers.txt".parse(),
+            Ok(Input::Url("https://example.com/peers.txt".to_string()))
+        );
+        assert_eq!(
+            "srv:_bitcoin._tcp.example.com".parse(),
+            Ok(Input::Srv("_bitcoin._tcp.example.com".to_string()))
+        );
+        assert_eq!(
+            "peers.txt".parse(),
+            Ok(Input::File(PathBuf::from("peers.txt")))
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let list =
+            parse_list("# Seeds\n10.0.0.1:8333\n\n  10.0.0.2:18333 # testnet\n").unwrap();
+        assert_eq!(
+            list,
+            vec![
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333),
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 18333),
+            ]
+        );
+
+        assert!(matches!(
+            parse_list("10.0.0.1:8333\n10.0.0.2"),
+            Err(InputError::InvalidLineError(2, _))
+        ));
+    }
+
+    #[tokio::test]
+    async fn url() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/peers.txt", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let body = "10.0.0.1:8333\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let input = url.parse().unwrap();
+        assert_eq!(
+            load(&input).await.unwrap(),
+            vec![SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333)]
+        );
+    }
+}
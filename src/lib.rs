@@ -0,0 +1,4 @@
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod p2p;
+pub mod prelude;
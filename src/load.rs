@@ -0,0 +1,229 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    net::SocketAddrV4,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use futures::future::join_all;
+use log::info;
+use tokio::time::{
+    sleep,
+    timeout,
+};
+
+use handshaker::prelude::*;
+
+/// Outcome of a single connection of the load test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AttemptOutcome {
+    /// Handshake performed, connection held open until the end of the test
+    Success,
+    /// Connection refused, or closed by the node during the handshake
+    Refused,
+    /// Handshake failed for another reason
+    Failure,
+    /// Handshake not finished in time
+    Timeout,
+}
+
+/// Single connection of the load test.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attempt {
+    /// Number of connections held open when the attempt started
+    pub open: usize,
+    /// Time spent on the handshake
+    pub latency: Duration,
+    pub outcome: AttemptOutcome,
+}
+
+/// Connections of the load test in the order they were started.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub attempts: Vec<Attempt>,
+}
+
+impl LoadReport {
+    /// Counts the attempts with the given outcome
+    pub fn count(&self, outcome: AttemptOutcome) -> usize {
+        self.attempts
+            .iter()
+            .filter(|a| a.outcome == outcome)
+            .count()
+    }
+
+    /// Gets the share of successful attempts, from 0 to 1
+    pub fn success_rate(&self) -> f64 {
+        if self.attempts.is_empty() {
+            return 0.0;
+        }
+        self.count(AttemptOutcome::Success) as f64 / self.attempts.len() as f64
+    }
+
+    /// Gets the latency of successful handshakes below which the given
+    /// share of them falls, e.g. 0.5 for the median
+    pub fn latency_percentile(&self, share: f64) -> Option<Duration> {
+        let mut latencies: Vec<Duration> = self
+            .attempts
+            .iter()
+            .filter(|a| a.outcome == AttemptOutcome::Success)
+            .map(|a| a.latency)
+            .collect();
+        latencies.sort();
+
+        let last = latencies.len().checked_sub(1)?;
+        let index = (last as f64 * share.clamp(0.0, 1.0)).round() as usize;
+        Some(latencies[index])
+    }
+
+    /// Gets the number of open connections at which the node refused
+    /// a connection for the first time
+    pub fn first_refusal(&self) -> Option<usize> {
+        self.attempts
+            .iter()
+            .find(|a| a.outcome == AttemptOutcome::Refused)
+            .map(|a| a.open)
+    }
+}
+
+impl Display for LoadReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Connections: {}", self.attempts.len())?;
+        writeln!(
+            f,
+            "Succeeded: {} ({:.1}%)",
+            self.count(AttemptOutcome::Success),
+            self.success_rate() * 100.0
+        )?;
+        write!(f, "Refused: {}", self.count(AttemptOutcome::Refused))?;
+        match self.first_refusal() {
+            Some(open) => writeln!(f, ", first at {} open connections", open)?,
+            None => writeln!(f)?,
+        }
+        writeln!(f, "Failed: {}", self.count(AttemptOutcome::Failure))?;
+        writeln!(f, "Timed out: {}", self.count(AttemptOutcome::Timeout))?;
+
+        let ms = |share| {
+            self.latency_percentile(share)
+                .map_or("-".to_string(), |l| format!("{} ms", l.as_millis()))
+        };
+        writeln!(
+            f,
+            "Latency: p50 {}, p90 {}, p99 {}, max {}",
+            ms(0.5),
+            ms(0.9),
+            ms(0.99),
+            ms(1.0)
+        )
+    }
+}
+
+/// Opens the given number of connections to the node, starting them evenly
+/// over the ramp-up period. Successful connections are held open until all
+/// of them are finished, so that the node sees them simultaneously.
+pub async fn run(
+    node: &Node,
+    network: Network,
+    target: SocketAddrV4,
+    connections: u32,
+    ramp: Duration,
+    time_limit: Duration,
+) -> LoadReport {
+    let open = AtomicUsize::new(0);
+
+    let attempts = (0..connections).map(|i| {
+        let open = &open;
+        async move {
+            sleep(ramp * i / connections).await;
+
+            let open_before = open.load(Ordering::Relaxed);
+            let start = Instant::now();
+            let result = timeout(time_limit, node.connect(network, target)).await;
+            let latency = start.elapsed();
+
+            let (outcome, connection) = match result {
+                Ok(Ok(connection)) => {
+                    open.fetch_add(1, Ordering::Relaxed);
+                    (AttemptOutcome::Success, Some(connection))
+                }
+                Ok(Err(
+                    ConnectionError::ConnectionRefusedError
+                    | ConnectionError::ConnectionHangUp,
+                )) => (AttemptOutcome::Refused, None),
+                Ok(Err(e)) => {
+                    info!("Connection {} to {} failed: {}", i + 1, target, e);
+                    (AttemptOutcome::Failure, None)
+                }
+                Err(_) => (AttemptOutcome::Timeout, None),
+            };
+
+            let attempt = Attempt {
+                open: open_before,
+                latency,
+                outcome,
+            };
+            (attempt, connection)
+        }
+    });
+
+    // Connections are closed only once all of them are finished
+    let results = join_all(attempts).await;
+    LoadReport {
+        attempts: results.into_iter().map(|(attempt, _)| attempt).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(open: usize, latency: u64, outcome: AttemptOutcome) -> Attempt {
+        Attempt {
+            open,
+            latency: Duration::from_millis(latency),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn report() {
+        let report = LoadReport {
+            attempts: vec![
+                attempt(0, 30, AttemptOutcome::Success),
+                attempt(1, 10, AttemptOutcome::Success),
+                attempt(2, 1, AttemptOutcome::Refused),
+                attempt(2, 20, AttemptOutcome::Success),
+                attempt(3, 1000, AttemptOutcome::Timeout),
+            ],
+        };
+
+        assert_eq!(report.success_rate(), 0.6);
+        assert_eq!(report.first_refusal(), Some(2));
+        assert_eq!(
+            report.latency_percentile(0.5),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            report.to_string(),
+            "Connections: 5\n\
+             Succeeded: 3 (60.0%)\n\
+             Refused: 1, first at 2 open connections\n\
+             Failed: 0\n\
+             Timed out: 1\n\
+             Latency: p50 20 ms, p90 30 ms, p99 30 ms, max 30 ms\n"
+        );
+
+        let empty = LoadReport::default();
+        assert_eq!(empty.success_rate(), 0.0);
+        assert_eq!(empty.latency_percentile(0.5), None);
+    }
+}
@@ -1,52 +1,644 @@
-use clap::Parser;
+use std::{
+    io::IsTerminal,
+    process::ExitCode,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use clap::{
+    CommandFactory,
+    Parser,
+};
 use log::{
     error,
     info,
+    warn,
+};
+use tokio::{
+    sync::Semaphore,
+    task::JoinSet,
+    time::timeout,
 };
-use tokio::time::timeout;
 
-use crate::p2p::messages::{
-    Service,
-    Services,
+use crate::{
+    banlist::Banlist,
+    cli::{
+        Commands,
+        DistributionFormat,
+        Format,
+        NetworkChoice,
+        ResolveStrategy,
+        Sort,
+        Target,
+        TransportChoice,
+        Verbosity,
+    },
+    distribution::Distribution,
+    exclusion::Exclusions,
+    output::Printer,
+    peers::PeerDb,
+    probe::Probe,
+    summary::{
+        Bandwidth,
+        Outcome,
+    },
 };
 
+#[cfg(feature = "addr")]
+use handshaker::p2p::Crawler;
+use handshaker::{
+    p2p::{
+        messages::NetworkParams,
+        resolve_seed,
+        resolve_seeds,
+        AdaptiveTimeout,
+        MedianHeight,
+        RandomNonce,
+        SeededNonce,
+        Socks5Proxy,
+        StageTimeouts,
+    },
+    prelude::*,
+};
+
+mod banlist;
+mod chain_file;
 mod cli;
-mod p2p;
+#[cfg(feature = "addr")]
+mod crawl;
+mod discover;
+mod distribution;
+mod dry_run;
+mod exclusion;
+mod input;
+mod load;
+mod output;
+mod peers;
+mod probe;
+mod resolve;
+mod score;
+mod soak;
+mod subnet;
+mod summary;
 
 #[tokio::main]
-async fn main() {
-    env_logger::init();
+async fn main() -> ExitCode {
+    const APP_NAME: &str = env!("CARGO_PKG_NAME");
 
-    const BITCOIN_PROTOCOL_VERSION: i32 = 70015;
+    let mut args = cli::Arguments::parse();
+    let verbosity = args.verbosity();
 
-    const APP_NAME: &str = env!("CARGO_PKG_NAME");
-    const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
-
-    let config = p2p::NodeConfig {
-        version: BITCOIN_PROTOCOL_VERSION,
-        services: Services::new(&[Service::Network]),
-        user_agent: format!("{}/{}/", APP_NAME, APP_VERSION),
-        start_height: 1,
-        relay: false,
+    // Verbosity sets the defaults, which RUST_LOG can still refine
+    env_logger::Builder::new()
+        .parse_filters(args.log_directives())
+        .parse_default_env()
+        .init();
+
+    let mut stdout = std::io::stdout();
+    match &args.command {
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(
+                *shell,
+                &mut cli::Arguments::command(),
+                APP_NAME,
+                &mut stdout,
+            );
+            return ExitCode::SUCCESS;
+        }
+        Some(Commands::Man) => {
+            if let Err(e) =
+                clap_mangen::Man::new(cli::Arguments::command()).render(&mut stdout)
+            {
+                error!("Unable to render man page: {}", e);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        // Needs the node, so it is run once the node is set up
+        Some(Commands::Load { .. } | Commands::Soak { .. }) | None => {}
+        #[cfg(feature = "addr")]
+        Some(Commands::Crawl { .. }) => {}
+    }
+
+    // Networks other than the built-in ones are given by their parameters
+    let mut custom = match &args.chain_file {
+        Some(path) => match chain_file::load(path) {
+            Ok(params) => Some(params),
+            Err(e) => {
+                error!("Unable to load chain from {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => args.chain.map(NetworkParams::from),
     };
+    if let Some(magic) = args.magic {
+        custom = Some(match custom {
+            Some(params) => params.with_magic(magic),
+            None => NetworkParams::new("Custom", magic, args.network.default_port()),
+        });
+    }
+    if let Some(params) = &custom {
+        info!("Performing handshakes on the {} network", params);
+    }
+
+    let default_port = match &custom {
+        Some(params) => params.default_port,
+        None => args.network.default_port(),
+    };
+    args.targets = std::mem::take(&mut args.targets)
+        .into_iter()
+        .map(|target| target.with_default_port(default_port))
+        .collect();
 
-    let node = p2p::Node::new(config);
-    let args = cli::Arguments::parse();
+    // Onion services are dialed through the proxy, which resolves them
+    let onions: Vec<_> = args
+        .targets
+        .iter()
+        .filter_map(|target| match target {
+            Target::Onion(host, port) => Some((host.clone(), *port)),
+            _ => None,
+        })
+        .collect();
+    if !onions.is_empty() && args.proxy.is_none() {
+        error!("Onion addresses can be dialed only through a proxy, see --proxy");
+        return ExitCode::FAILURE;
+    }
+
+    let targets = std::mem::take(&mut args.targets);
+    let mut addresses = match resolve::resolve(targets, args.resolve_strategy).await {
+        Ok(addresses) => addresses,
+        Err((target, e)) => {
+            error!("Unable to resolve {}: {}", target, e);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    for address in args.addresses {
-        info!("Performing a handshake with {}", address);
+    if let Some(input) = &args.input {
+        match input::load(input).await {
+            Ok(loaded) => {
+                info!("Loaded {} nodes from {}", loaded.len(), input);
+                addresses.extend(loaded);
+            }
+            Err(e) => {
+                error!("Unable to load nodes from {}: {}", input, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
 
-        match timeout(args.timeout, node.handshake(args.network, address)).await {
-            Ok(v) => match v {
-                Ok(node_config) => info!(
-                    "Handshake successfully performed, node at {}: {}",
-                    address, node_config
-                ),
-                Err(e) => error!("Error occurred during handshake: {}", e),
+    if let Some(seed) = &args.dns_seed {
+        // Seeds are looked up per network, so one has to be chosen up front
+        let network = args.network.or_main();
+        let seeded = match seed {
+            Some(seed) => match resolve_seed(seed, network.default_port()).await {
+                Ok(addresses) => addresses,
+                Err(e) => {
+                    error!("Unable to resolve DNS seed {}: {}", seed, e);
+                    return ExitCode::FAILURE;
+                }
             },
+            None => resolve_seeds(network).await,
+        };
+        info!("Discovered {} nodes through DNS seeds", seeded.len());
+        addresses.extend(seeded);
+    }
+
+    let time_limit = args.time_limit();
+    let retry = args.retry_policy();
+    let config = NodeConfig {
+        version: ProtocolVersion(args.protocol_version),
+        services: args.services,
+        user_agent: args.user_agent.clone(),
+        start_height: args.start_height,
+        relay: args.relay,
+        nonce: args.nonce,
+        timestamp: args.timestamp,
+        nonce_source: match args.seed {
+            Some(seed) => Arc::new(SeededNonce::new(seed)),
+            None => Arc::new(RandomNonce),
+        },
+        responder: args.responder,
+    };
+
+    let mut node = Node::new(config);
+    if let Some(height_rpc) = args.height_rpc.take() {
+        node = node.with_height_source(height_rpc);
+    } else if args.height_median {
+        node = node.with_height_source(MedianHeight::new(args.start_height));
+    }
+
+    if let Some(proxy) = args.proxy.take() {
+        let address =
+            match resolve::resolve(vec![proxy.clone()], ResolveStrategy::First).await {
+                Ok(addresses) => addresses.first().copied(),
+                Err((_, e)) => {
+                    error!("Unable to resolve proxy {}: {}", proxy, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+        let Some(address) = address else {
+            error!("Proxy {} has no IPv4 address", proxy);
+            return ExitCode::FAILURE;
+        };
+        let mut proxy = Socks5Proxy::new(address);
+        if let Some((username, password)) = args.proxy_auth.take() {
+            proxy = proxy.with_credentials(username, password);
+        }
+        node = node.with_proxy(proxy);
+    }
+
+    if args.transport == TransportChoice::Auto {
+        node = node.with_v2_transport(args.timeout);
+    }
+
+    if let Some(budget) = args.stall_budget {
+        node = node.with_stall_budget(budget);
+    }
+
+    // Only dissection needs the raw messages, the timeline their lengths
+    node = node
+        .with_lenient(args.lenient)
+        .with_max_payload_length(args.max_payload_length)
+        .with_frames(args.dissect);
+    node = node.with_stage_timeouts(StageTimeouts {
+        connect: args.connect_timeout,
+        version: args.version_timeout,
+        verack: args.verack_timeout,
+        pong: args.pong_timeout,
+    });
+
+    if args.adaptive_timeout {
+        node = node.with_adaptive_timeout(AdaptiveTimeout {
+            ceiling: args.timeout,
+            ..Default::default()
+        });
+    }
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut exclusions = Exclusions {
+        subnets: std::mem::take(&mut args.exclude),
+        now,
+        ..Default::default()
+    };
+    if let Some(path) = &args.exclude_file {
+        match subnet::load_list(path) {
+            Ok(subnets) => exclusions.subnets.extend(subnets),
+            Err(e) => {
+                error!("Unable to load exclusion list: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if let Some(path) = &args.banlist {
+        match Banlist::load(path) {
+            Ok(banlist) => exclusions.banlist = banlist,
             Err(e) => {
-                error!("Timeout of {} ms exceeded: {}", args.timeout.as_millis(), e)
+                error!("Unable to load banlist: {}", e);
+                return ExitCode::FAILURE;
             }
         }
     }
+    // Addresses discovered from other nodes are checked by the node itself
+    node = node.with_target_policy(exclusions.clone());
+
+    if let Some(Commands::Load {
+        target,
+        connections,
+        ramp,
+        timeout,
+        network,
+    }) = args.command
+    {
+        if exclusions.excludes(*target.ip()) {
+            error!("Target {} is excluded", target);
+            return ExitCode::FAILURE;
+        }
+        let network = network.unwrap_or_else(|| NetworkChoice::Auto.guess(target));
+        info!(
+            "Opening {} connections to {} over {:?}",
+            connections, target, ramp
+        );
+        let report = load::run(&node, network, target, connections, ramp, timeout).await;
+        print!("{}", report);
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(Commands::Soak {
+        target,
+        connections,
+        duration,
+        ping_interval,
+        timeout,
+        network,
+    }) = args.command
+    {
+        let targets: Vec<_> = target
+            .into_iter()
+            .filter(|address| !exclusions.excludes(*address.ip()))
+            .map(|address| {
+                let network =
+                    network.unwrap_or_else(|| NetworkChoice::Auto.guess(address));
+                (network, address)
+            })
+            .collect();
+        if targets.is_empty() {
+            error!("All targets are excluded");
+            return ExitCode::FAILURE;
+        }
+        info!(
+            "Holding {} connections open for {}",
+            connections,
+            humantime::format_duration(duration)
+        );
+        let report = soak::run(
+            &node,
+            &targets,
+            connections,
+            duration,
+            ping_interval,
+            timeout,
+        )
+        .await;
+        print!("{}", report);
+        return ExitCode::SUCCESS;
+    }
+
+    #[cfg(feature = "addr")]
+    if let Some(Commands::Crawl {
+        target,
+        depth,
+        concurrency,
+        host_interval,
+        max_peers,
+        timeout,
+        network,
+    }) = args.command
+    {
+        let network = network.unwrap_or_else(|| NetworkChoice::Auto.guess(target[0]));
+        let seeds: Vec<_> = target
+            .into_iter()
+            .filter(|seed| !exclusions.excludes(*seed.ip()))
+            .collect();
+        if seeds.is_empty() {
+            error!("All seeds are excluded");
+            return ExitCode::FAILURE;
+        }
+        info!(
+            "Crawling the {:?} network from {} seeds up to depth {}",
+            network,
+            seeds.len(),
+            depth
+        );
+        let crawler = Crawler::new(&node, network)
+            .with_depth(depth)
+            .with_concurrency(concurrency as usize)
+            .with_host_interval(host_interval)
+            .with_max_peers(max_peers)
+            .with_timeout(timeout);
+        let reachable = crawl::run(crawler, seeds).await;
+        println!("Reachable nodes: {}", reachable);
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(window) = args.observation_window() {
+        node = node.with_observation(window);
+    }
+
+    #[cfg(feature = "addr")]
+    if args.request_addrs {
+        node = node.with_addr_request(args.addr_window());
+    }
+
+    #[cfg(feature = "addr")]
+    if let Some((address, port)) = args.local_address.take() {
+        node = node.with_local_address(address, port);
+    }
+
+    if args.dry_run {
+        for address in addresses {
+            let chain = custom
+                .clone()
+                .unwrap_or_else(|| args.network.guess(address).into());
+            dry_run::run(&node, chain, address).await;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if !args.discover.is_empty() {
+        for ip in args.discover.into_iter().filter(|ip| !exclusions.excludes(*ip)) {
+            let networks = discover::run(&node, ip, args.timeout).await;
+            println!("{}", discover::describe(ip, &networks));
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    #[cfg(unix)]
+    if !args.unix.is_empty() {
+        // Sockets have no port to guess the network from
+        let chain = custom
+            .clone()
+            .unwrap_or_else(|| args.network.or_main().into());
+        let mut failed = false;
+        for path in &args.unix {
+            info!("Performing a handshake with {}", path.display());
+            match timeout(time_limit, node.handshake_unix(&chain, path)).await {
+                Ok(Ok(report)) => println!("Node at {}: {}", path.display(), report),
+                Ok(Err(e)) => {
+                    error!("Error occurred during handshake: {}", e);
+                    failed = true;
+                }
+                Err(e) => {
+                    error!("Timeout of {} ms exceeded: {}", time_limit.as_millis(), e);
+                    failed = true;
+                }
+            }
+        }
+        return match failed {
+            true => ExitCode::from(summary::FAILURE_EXIT_CODE),
+            false => ExitCode::SUCCESS,
+        };
+    }
+
+    let mut onions_failed = false;
+
+    for (host, port) in &onions {
+        let chain = custom
+            .clone()
+            .unwrap_or_else(|| args.network.guess_port(*port).into());
+        info!("Performing a handshake with {}:{}", host, port);
+        match timeout(time_limit, node.handshake_host(chain, host, *port)).await {
+            Ok(Ok(report)) => println!("Node at {}:{}: {}", host, port, report),
+            Ok(Err(e)) => {
+                error!("Error occurred during handshake with {}: {}", host, e);
+                onions_failed = true;
+            }
+            Err(e) => {
+                error!(
+                    "Timeout of {} ms exceeded with {}: {}",
+                    time_limit.as_millis(),
+                    host,
+                    e
+                );
+                onions_failed = true;
+            }
+        }
+    }
+    if !onions.is_empty() && addresses.is_empty() {
+        return match onions_failed {
+            true => ExitCode::from(summary::FAILURE_EXIT_CODE),
+            false => ExitCode::SUCCESS,
+        };
+    }
+
+    let mut peer_db = match args.peer_db.map(PeerDb::load).transpose() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Unable to load peer database: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    addresses.retain(|address| !exclusions.excludes(*address.ip()));
+    peers::order(&mut addresses, args.order, peer_db.as_ref(), now);
+
+    let probe = Probe {
+        network: args.network,
+        chain: custom.map(Arc::new),
+        format: args.format,
+        time_limit,
+        max_latency: args.max_latency,
+        trace_timeline: args.trace_timeline,
+        dissect: args.dissect,
+        retry,
+        verbosity,
+    };
+    let node = Arc::new(node);
+    let semaphore = Arc::new(Semaphore::new(args.concurrency as usize));
+    let mut tasks = JoinSet::new();
+    for (index, address) in addresses.into_iter().enumerate() {
+        let node = node.clone();
+        let semaphore = semaphore.clone();
+        let probe = probe.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            (index, probe.run(&node, address).await)
+        });
+    }
+
+    let mut printer = args
+        .output
+        .map(|output| Printer::new(output, std::io::stdout()));
+
+    // Results are collected as handshakes finish, but kept in input order
+    let mut finished = Vec::new();
+    let mut successes = 0;
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = match joined {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Handshake task failed: {}", e);
+                continue;
+            }
+        };
+        if matches!(result.outcome, Outcome::Success(_)) {
+            successes += 1;
+        }
+        if let Some(Err(e)) = printer.as_mut().map(|p| p.print(&result)) {
+            warn!("Unable to print the record of {}: {}", result.address, e);
+        }
+        finished.push((index, result));
+
+        if args.first_success == Some(successes) {
+            tasks.abort_all();
+            break;
+        }
+    }
+    finished.sort_by_key(|(index, _)| *index);
+    let mut results: Vec<_> = finished.into_iter().map(|(_, result)| result).collect();
+
+    score::assign_scores(&mut results, &args.score_weights.unwrap_or_default());
+    match args.sort {
+        Sort::Input => {}
+        Sort::Score => results.sort_by(|a, b| b.score.total_cmp(&a.score)),
+        Sort::Latency => results.sort_by_key(|r| r.latency),
+    }
+
+    if let Some(peer_db) = &mut peer_db {
+        if args.churn {
+            for churn in peer_db.churn(&results) {
+                for address in &churn.appeared {
+                    info!("Node at {} appeared since the previous run", address);
+                }
+                for address in &churn.disappeared {
+                    info!("Node at {} disappeared since the previous run", address);
+                }
+                println!("{}", churn);
+            }
+        }
+        for result in &results {
+            peer_db.record(result, now);
+        }
+        if let Err(e) = peer_db.save() {
+            warn!("Unable to save peer database: {}", e);
+        }
+    }
+
+    if let Some(path) = &args.export_banlist {
+        let mut banlist = exclusions.banlist;
+        for result in &results {
+            if let Outcome::Success(report) | Outcome::Slow(report) = &result.outcome {
+                if report.violations > 0 {
+                    banlist.ban(*result.address.ip(), now);
+                }
+            }
+        }
+        if let Err(e) = banlist.save(path) {
+            warn!("Unable to save banlist: {}", e);
+        }
+    }
+
+    if let Some(format) = args.distribution {
+        let distribution = Distribution::of(&results);
+        match format {
+            DistributionFormat::Table => print!("{}", distribution.render(false)),
+            DistributionFormat::Histogram => print!("{}", distribution.render(true)),
+            DistributionFormat::Json => println!("{}", distribution.to_json()),
+        }
+    }
+
+    if args.first_success.is_some() {
+        results = summary::fastest_successes(results);
+        if args.format != Format::Table {
+            for result in &results {
+                println!("{}", result.address);
+            }
+        }
+    }
+
+    if let Some(Err(e)) = printer.map(|p| p.finish(&results)) {
+        warn!("Unable to print the records: {}", e);
+    }
+
+    let bandwidth = Bandwidth::of(&results);
+    if args.format == Format::Table {
+        let color = !args.no_color
+            && std::env::var_os("NO_COLOR").is_none()
+            && std::io::stdout().is_terminal();
+        print!("{}", summary::render_table(&results, color));
+        if verbosity > Verbosity::Quiet {
+            println!("\nTotal: {}", bandwidth);
+        }
+    } else {
+        info!("Total: {}", bandwidth);
+    }
+
+    // Failures before the first successes do not count with --first-success
+    match onions_failed {
+        true => ExitCode::from(summary::FAILURE_EXIT_CODE),
+        false => ExitCode::from(summary::exit_code(&results)),
+    }
 }
@@ -0,0 +1,219 @@
+use std::{
+    io::Write,
+    net::SocketAddrV4,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    cli::Output,
+    summary::{
+        HandshakeSummary,
+        Outcome,
+    },
+};
+
+/// Outcome of the handshake with a single node, flattened, so that it fits
+/// into a CSV row as well.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Record {
+    pub address: SocketAddrV4,
+    /// Whether the handshake was performed, even if slower than the maximum
+    pub success: bool,
+    /// Whether the handshake took longer than the maximum latency
+    pub slow: bool,
+    /// Code of the error the handshake failed with
+    pub error: Option<&'static str>,
+    pub version: Option<i32>,
+    pub services: Option<u64>,
+    pub user_agent: Option<String>,
+    pub start_height: Option<i32>,
+    pub relay: Option<bool>,
+    /// Transport the messages were exchanged over, i.e. v1 or v2
+    pub transport: Option<String>,
+    /// Number of attempts made
+    pub attempts: u32,
+    /// Time it took for the last attempt of the handshake to end
+    pub latency_ms: f64,
+    /// Time it took to open the TCP connection
+    pub connect_ms: Option<f64>,
+    /// Time between sending our Version message and receiving the node's one
+    pub version_rtt_ms: Option<f64>,
+    /// Time between sending our Version message and receiving the node's
+    /// Verack message
+    pub verack_rtt_ms: Option<f64>,
+    /// Time from sending the Version message to receiving the first byte
+    pub first_byte_ms: Option<f64>,
+    /// Time between sending the Ping message and receiving the Pong message
+    pub ping_rtt_ms: Option<f64>,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+impl From<&HandshakeSummary> for Record {
+    fn from(summary: &HandshakeSummary) -> Self {
+        let report = match &summary.outcome {
+            Outcome::Success(report) | Outcome::Slow(report) => Some(report),
+            Outcome::Failure(_) | Outcome::Timeout | Outcome::Stalled => None,
+        };
+        Self {
+            address: summary.address,
+            success: report.is_some(),
+            slow: matches!(summary.outcome, Outcome::Slow(_)),
+            error: summary.outcome.error_code(),
+            version: report.map(|r| r.config.version.into()),
+            services: report.map(|r| r.config.services.into()),
+            user_agent: report.map(|r| r.config.user_agent.clone()),
+            start_height: report.map(|r| r.config.start_height),
+            relay: report.map(|r| r.config.relay),
+            transport: report.map(|r| r.transport.to_string()),
+            attempts: summary.attempts,
+            latency_ms: millis(summary.latency),
+            connect_ms: report.and_then(|r| r.connect_time).map(millis),
+            version_rtt_ms: report.and_then(|r| r.version_rtt()).map(millis),
+            verack_rtt_ms: report.and_then(|r| r.verack_rtt()).map(millis),
+            first_byte_ms: report.and_then(|r| r.first_byte).map(millis),
+            ping_rtt_ms: report.and_then(|r| r.ping_rtt()).map(millis),
+        }
+    }
+}
+
+/// Prints a record per node in the chosen format. Newline-delimited JSON
+/// and CSV rows are written as soon as each handshake ends, while JSON is
+/// written as a single array once all of them ended.
+pub enum Printer<W: Write> {
+    Json(W),
+    Ndjson(W),
+    Csv(Box<csv::Writer<W>>),
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(output: Output, writer: W) -> Self {
+        match output {
+            Output::Json => Printer::Json(writer),
+            Output::Ndjson => Printer::Ndjson(writer),
+            Output::Csv => Printer::Csv(Box::new(csv::Writer::from_writer(writer))),
+        }
+    }
+
+    /// Prints the record of the node whose handshake just ended, unless
+    /// the records are printed only at the end
+    pub fn print(&mut self, summary: &HandshakeSummary) -> std::io::Result<()> {
+        let record = Record::from(summary);
+        match self {
+            Printer::Json(_) => {}
+            Printer::Ndjson(writer) => {
+                serde_json::to_writer(&mut *writer, &record)?;
+                writeln!(writer)?;
+                writer.flush()?;
+            }
+            Printer::Csv(writer) => {
+                writer.serialize(record)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the records of all nodes in their final order, unless they
+    /// were printed as their handshakes ended
+    pub fn finish(self, results: &[HandshakeSummary]) -> std::io::Result<()> {
+        if let Printer::Json(mut writer) = self {
+            let records: Vec<_> = results.iter().map(Record::from).collect();
+            serde_json::to_writer_pretty(&mut writer, &records)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use handshaker::prelude::*;
+
+    fn results() -> Vec<HandshakeSummary> {
+        let mut report = HandshakeReport::default();
+        report.config.version = ProtocolVersion(70016);
+        report.config.services = Services::new(&[Service::Network]);
+        report.config.user_agent = "/Satoshi:25.0.0/".to_string();
+        report.config.start_height = 800_000;
+        report.config.relay = true;
+        vec![
+            HandshakeSummary {
+                address: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333),
+                outcome: Outcome::Success(report),
+                latency: Duration::from_micros(12_500),
+                attempts: 1,
+                score: 0.0,
+            },
+            HandshakeSummary {
+                address: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8333),
+                outcome: Outcome::Failure(ConnectionError::ConnectionRefusedError),
+                latency: Duration::from_millis(3),
+                attempts: 1,
+                score: 0.0,
+            },
+        ]
+    }
+
+    fn print(output: Output) -> String {
+        let mut buffer = Vec::new();
+        let mut printer = Printer::new(output, &mut buffer);
+        let results = results();
+        for result in &results {
+            printer.print(result).unwrap();
+        }
+        printer.finish(&results).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn ndjson() {
+        let output = print(Output::Ndjson);
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            concat!(
+                r#"{"address":"10.0.0.1:8333","success":true,"slow":false,"error":null,"#,
+                r#""version":70016,"services":1,"user_agent":"/Satoshi:25.0.0/","#,
+                r#""start_height":800000,"relay":true,"transport":"v1","attempts":1,"#,
+                r#""latency_ms":12.5,"#,
+                r#""connect_ms":null,"version_rtt_ms":null,"verack_rtt_ms":null,"#,
+                r#""first_byte_ms":null,"ping_rtt_ms":null}"#
+            )
+        );
+        assert!(lines[1].contains(r#""success":false,"slow":false,"error":"E_REFUSED""#));
+    }
+
+    #[test]
+    fn json() {
+        let value: serde_json::Value =
+            serde_json::from_str(&print(Output::Json)).unwrap();
+        let records = value.as_array().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["user_agent"], "/Satoshi:25.0.0/");
+        assert_eq!(records[1]["error"], "E_REFUSED");
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            print(Output::Csv),
+            concat!(
+                "address,success,slow,error,version,services,user_agent,start_height,",
+                "relay,transport,attempts,latency_ms,connect_ms,version_rtt_ms,",
+                "verack_rtt_ms,first_byte_ms,ping_rtt_ms\n",
+                "10.0.0.1:8333,true,false,,70016,1,/Satoshi:25.0.0/,800000,true,v1,1,12.5,,,,,\n",
+                "10.0.0.2:8333,false,false,E_REFUSED,,,,,,,1,3.0,,,,,\n",
+            )
+        );
+    }
+}
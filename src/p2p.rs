@@ -1,6 +1,61 @@
+#[cfg(not(target_arch = "wasm32"))]
+pub mod codec;
+pub mod config;
+pub mod connection;
+#[cfg(feature = "addr")]
+pub mod crawler;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod discovery;
 pub mod error;
+pub mod height;
 pub mod messages;
 pub mod node;
+pub mod nonce;
+pub mod observation;
+pub mod policy;
+pub mod proxy;
+pub mod report;
+pub mod retry;
+pub mod runtime;
+#[cfg(feature = "tower")]
+pub mod service;
+pub mod stats;
+#[cfg(all(any(test, feature = "testing"), not(target_arch = "wasm32")))]
+pub mod testing;
+pub mod timeline;
+pub mod timeout;
+pub mod transport;
+pub mod user_agent;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use codec::*;
+pub use config::*;
+pub use connection::*;
+#[cfg(feature = "addr")]
+pub use crawler::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use discovery::*;
 pub use error::*;
+pub use height::*;
 pub use node::*;
+pub use nonce::*;
+pub use observation::*;
+pub use policy::*;
+pub use proxy::*;
+pub use report::*;
+pub use retry::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use runtime::Tokio;
+pub use runtime::{
+    DefaultRuntime,
+    Runtime,
+};
+#[cfg(feature = "tower")]
+pub use service::*;
+pub use stats::*;
+#[cfg(all(any(test, feature = "testing"), not(target_arch = "wasm32")))]
+pub use testing::*;
+pub use timeline::*;
+pub use timeout::*;
+pub use transport::*;
+pub use user_agent::*;
@@ -0,0 +1,179 @@
+use bytes::BytesMut;
+use tokio_util::codec::{
+    Decoder,
+    Encoder,
+};
+
+use crate::p2p::{
+    connection::{
+        complete_frame_length,
+        decode_frame,
+        frame_length_within,
+        MAX_PAYLOAD_LENGTH,
+    },
+    messages::{
+        compose_message,
+        Chain,
+        Message,
+        Network,
+    },
+    ConnectionError,
+};
+
+/// Codec of Bitcoin messages for tokio_util's `Framed`, turning any tokio
+/// stream into a stream of [`Message`]s and a sink accepting them. Messages
+/// are framed the same way as on a [`Connection`](crate::p2p::Connection).
+///
+/// Messages whose command is unknown to the codec are skipped, while ones
+/// of another network, with a wrong checksum or a payload longer than the
+/// limit fail the decoding.
+#[derive(Clone, Debug)]
+pub struct BitcoinMessageCodec<C: Chain = Network> {
+    chain: C,
+    /// Longest payload accepted, so that no node makes us buffer more
+    max_payload_length: u32,
+}
+
+impl<C: Chain> BitcoinMessageCodec<C> {
+    pub fn new(chain: C) -> Self {
+        Self {
+            chain,
+            max_payload_length: MAX_PAYLOAD_LENGTH,
+        }
+    }
+
+    /// Fails the decoding of payloads longer than the given number of
+    /// bytes, instead of [`MAX_PAYLOAD_LENGTH`].
+    pub fn with_max_payload_length(mut self, max_payload_length: u32) -> Self {
+        self.max_payload_length = max_payload_length;
+        self
+    }
+}
+
+impl<C: Chain + Default> Default for BitcoinMessageCodec<C> {
+    fn default() -> Self {
+        Self::new(C::default())
+    }
+}
+
+impl<C: Chain> Decoder for BitcoinMessageCodec<C> {
+    type Item = Message;
+    type Error = ConnectionError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, ConnectionError> {
+        let layout = self.chain.header_layout();
+        let max_payload_length = self.max_payload_length;
+        while let Some(length) = complete_frame_length(layout, src, max_payload_length)? {
+            let frame = src.split_to(length);
+            if let Some(message) = decode_frame(&self.chain, &frame)? {
+                return Ok(Some(message));
+            }
+        }
+
+        // Make room for the rest of the message at once
+        if let Some(length) = frame_length_within(layout, src, max_payload_length)? {
+            src.reserve(length.saturating_sub(src.len()));
+        }
+        Ok(None)
+    }
+}
+
+impl<C: Chain> Encoder<Message> for BitcoinMessageCodec<C> {
+    type Error = ConnectionError;
+
+    fn encode(
+        &mut self,
+        item: Message,
+        dst: &mut BytesMut,
+    ) -> Result<(), ConnectionError> {
+        dst.extend_from_slice(&compose_message(&self.chain, &item));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::{
+        SinkExt,
+        StreamExt,
+    };
+    use tokio_util::codec::Framed;
+
+    use crate::p2p::messages::{
+        compose,
+        CodecError,
+        Command,
+        PingMessage,
+        PongMessage,
+        VerackMessage,
+    };
+
+    #[tokio::test]
+    async fn framed() {
+        let (ours, theirs) = tokio::io::duplex(64);
+        let mut ours = Framed::new(ours, BitcoinMessageCodec::new(Network::Testnet));
+        let mut theirs = Framed::new(theirs, BitcoinMessageCodec::new(Network::Testnet));
+
+        ours.send(Message::Ping(PingMessage::new(15)))
+            .await
+            .unwrap();
+        match theirs.next().await.unwrap().unwrap() {
+            Message::Ping(ping) => assert_eq!(ping.nonce(), 15),
+            other => panic!("Unexpected message {:?}", other),
+        }
+
+        theirs
+            .send(Message::Pong(PongMessage::new(15)))
+            .await
+            .unwrap();
+        drop(theirs);
+        match ours.next().await.unwrap().unwrap() {
+            Message::Pong(pong) => assert_eq!(pong.nonce(), 15),
+            other => panic!("Unexpected message {:?}", other),
+        }
+        assert!(ours.next().await.is_none());
+    }
+
+    #[test]
+    fn decode() {
+        let mut codec = BitcoinMessageCodec::new(Network::Main);
+
+        // Unknown command followed by a Verack message and part of a Ping one
+        let mut unknown = compose(Network::Main, Command::Verack, VerackMessage {});
+        unknown[4..16].copy_from_slice(b"alert\0\0\0\0\0\0\0");
+        let ping = compose(Network::Main, Command::Ping, PingMessage::new(15));
+        let mut src = BytesMut::new();
+        src.extend_from_slice(&unknown);
+        src.extend_from_slice(&compose(Network::Main, Command::Verack, VerackMessage {}));
+        src.extend_from_slice(&ping[..10]);
+
+        assert!(matches!(
+            codec.decode(&mut src),
+            Ok(Some(Message::Verack(_)))
+        ));
+        assert!(matches!(codec.decode(&mut src), Ok(None)));
+        src.extend_from_slice(&ping[10..]);
+        assert!(matches!(codec.decode(&mut src), Ok(Some(Message::Ping(_)))));
+        assert!(src.is_empty());
+
+        // Message of another network
+        let mut src = BytesMut::from(
+            &compose(Network::Testnet, Command::Ping, PingMessage::new(15))[..],
+        );
+        assert_eq!(
+            codec.decode(&mut src).err(),
+            Some(ConnectionError::NetworkMismatch(Network::Testnet))
+        );
+
+        // Message longer than the limit
+        let mut codec =
+            BitcoinMessageCodec::new(Network::Main).with_max_payload_length(4);
+        let mut src = BytesMut::from(&ping[..]);
+        assert_eq!(
+            codec.decode(&mut src).err(),
+            Some(ConnectionError::DecodeError(CodecError::OversizedFieldError))
+        );
+    }
+}
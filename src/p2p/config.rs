@@ -0,0 +1,214 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    sync::Arc,
+};
+
+use crate::p2p::{
+    messages::{
+        ProtocolVersion,
+        Services,
+    },
+    NodeConfig,
+    NonceSource,
+    UserAgent,
+    UserAgentError,
+};
+
+/// Lowest protocol version a built configuration may advertise, i.e. the
+/// first one whose Version message carries all the fields we send
+pub const MIN_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::VERSION_FIELDS;
+
+/// Highest protocol version a built configuration may advertise, i.e. the
+/// latest one this library speaks
+pub const MAX_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion::WTXID;
+
+#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum ConfigError {
+    /// User agent not following BIP14
+    InvalidUserAgentError(UserAgentError),
+    /// Protocol version outside of the ones this library speaks
+    VersionRangeError(ProtocolVersion),
+    /// Start height below the genesis block
+    NegativeHeightError(i32),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::InvalidUserAgentError(e) => write!(f, "{}", e),
+            ConfigError::VersionRangeError(version) => write!(
+                f,
+                "Protocol version {} should range from {} to {}",
+                version, MIN_PROTOCOL_VERSION, MAX_PROTOCOL_VERSION
+            ),
+            ConfigError::NegativeHeightError(height) => {
+                write!(f, "Start height {} should not be negative", height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<UserAgentError> for ConfigError {
+    fn from(e: UserAgentError) -> Self {
+        ConfigError::InvalidUserAgentError(e)
+    }
+}
+
+impl NodeConfig {
+    /// Starts a configuration speaking the latest protocol version with
+    /// the user agent of this library, validated once built
+    pub fn builder() -> NodeConfigBuilder {
+        NodeConfigBuilder {
+            config: NodeConfig {
+                version: MAX_PROTOCOL_VERSION,
+                user_agent: UserAgent::library().to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Composes the configuration field by field, unlike the plain struct
+/// rejecting ones no node would make sense of once built.
+pub struct NodeConfigBuilder {
+    config: NodeConfig,
+}
+
+impl NodeConfigBuilder {
+    /// Sets the protocol version advertised in the Version message
+    pub fn version(mut self, version: ProtocolVersion) -> Self {
+        self.config.version = version;
+        self
+    }
+
+    /// Sets the services advertised in the Version message
+    pub fn services(mut self, services: Services) -> Self {
+        self.config.services = services;
+        self
+    }
+
+    /// Sets the user agent, e.g. `/MyApp:1.2/`, or a [`UserAgent`]
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.config.user_agent = user_agent.into();
+        self
+    }
+
+    /// Sets the height of the last block advertised
+    pub fn start_height(mut self, start_height: i32) -> Self {
+        self.config.start_height = start_height;
+        self
+    }
+
+    /// Asks the other node to announce relayed transactions
+    pub fn relay(mut self, relay: bool) -> Self {
+        self.config.relay = relay;
+        self
+    }
+
+    /// Sends the nonce in the Version message instead of a random one
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.config.nonce = Some(nonce);
+        self
+    }
+
+    /// Sends the UNIX timestamp in the Version message instead of the
+    /// current time
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.config.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Draws the nonces of Version and Ping messages from the source
+    pub fn nonce_source(mut self, nonce_source: impl NonceSource + 'static) -> Self {
+        self.config.nonce_source = Arc::new(nonce_source);
+        self
+    }
+
+    /// Waits for the other node's Version message before sending ours
+    pub fn responder(mut self, responder: bool) -> Self {
+        self.config.responder = responder;
+        self
+    }
+
+    /// Validates the user agent against BIP14, the protocol version against
+    /// the ones this library speaks and the start height.
+    pub fn build(self) -> Result<NodeConfig, ConfigError> {
+        let config = self.config;
+        config.user_agent.parse::<UserAgent>()?;
+        if !(MIN_PROTOCOL_VERSION..=MAX_PROTOCOL_VERSION).contains(&config.version) {
+            return Err(ConfigError::VersionRangeError(config.version));
+        }
+        if config.start_height < 0 {
+            return Err(ConfigError::NegativeHeightError(config.start_height));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::p2p::{
+        messages::Service,
+        SeededNonce,
+    };
+
+    #[test]
+    fn build() {
+        let config = NodeConfig::builder()
+            .version(ProtocolVersion::INVALID_CB_NO_BAN)
+            .services(Services::new(&[Service::Network]))
+            .user_agent("/MyApp:1.2/")
+            .start_height(850_000)
+            .relay(true)
+            .nonce(7)
+            .nonce_source(SeededNonce::new(1))
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.to_string(),
+            "version: 70015, services: [Network], user agent: /MyApp:1.2/, \
+            start height: 850000, relay: true"
+        );
+        assert_eq!(config.nonce, Some(7));
+
+        let config = NodeConfig::builder().build().unwrap();
+        assert_eq!(config.version, MAX_PROTOCOL_VERSION);
+        assert_eq!(config.user_agent, UserAgent::library().to_string());
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(
+            NodeConfig::builder().user_agent("").build().err(),
+            Some(ConfigError::InvalidUserAgentError(
+                UserAgentError::MalformedError
+            ))
+        );
+        assert_eq!(
+            NodeConfig::builder()
+                .version(ProtocolVersion(0))
+                .build()
+                .err(),
+            Some(ConfigError::VersionRangeError(ProtocolVersion(0)))
+        );
+        assert_eq!(
+            NodeConfig::builder()
+                .version(ProtocolVersion(70017))
+                .build()
+                .err(),
+            Some(ConfigError::VersionRangeError(ProtocolVersion(70017)))
+        );
+        assert_eq!(
+            NodeConfig::builder().start_height(-1).build().err(),
+            Some(ConfigError::NegativeHeightError(-1))
+        );
+    }
+}
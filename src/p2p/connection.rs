@@ -0,0 +1,415 @@
+use std::{
+    marker::PhantomData,
+    time::Duration,
+};
+
+use futures::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+    ReadHalf,
+    WriteHalf,
+};
+use log::debug;
+
+#[cfg(feature = "addr")]
+use crate::p2p::messages::{
+    GetAddrMessage,
+    TimestampedAddrV2,
+};
+use crate::p2p::{
+    messages::{
+        compose_message,
+        Chain,
+        CodecError,
+        Command,
+        FrameHeader,
+        HeaderLayout,
+        Message,
+        Network,
+        PongMessage,
+        ProtocolVersion,
+    },
+    runtime::{
+        timeout,
+        DefaultRuntime,
+        Instant,
+        Runtime,
+    },
+    ConnectionError,
+    HandshakeReport,
+    Observation,
+};
+
+/// Stream a connection speaks the protocol over, e.g. a TCP connection
+/// opened by the runtime or one set up by the library user
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+/// Largest payload accepted, matching Bitcoin Core's limit
+pub const MAX_PAYLOAD_LENGTH: u32 = 4_000_000;
+
+/// Number of bytes read from the socket at once
+pub(crate) const READ_CHUNK_LENGTH: usize = 4096;
+
+/// Gets the length of the Bitcoin message the data starts with, i.e. of
+/// its header and the payload the header announces, once the whole header
+/// is there
+pub(crate) fn frame_length(data: &[u8]) -> Result<Option<usize>, ConnectionError> {
+    frame_length_within(HeaderLayout::BITCOIN, data, MAX_PAYLOAD_LENGTH)
+}
+
+/// Gets the length of the message the data starts with like
+/// [`frame_length`], under the header layout of any chain and rejecting
+/// payloads longer than the given limit
+pub(crate) fn frame_length_within(
+    layout: HeaderLayout,
+    data: &[u8],
+    max_payload_length: u32,
+) -> Result<Option<usize>, ConnectionError> {
+    if data.len() < layout.length {
+        return Ok(None);
+    }
+
+    let field = &data[layout.length_offset..][..std::mem::size_of::<u32>()];
+    let length = u32::from_le_bytes(field.try_into().unwrap());
+    if length > max_payload_length {
+        return Err(CodecError::OversizedFieldError.into());
+    }
+    Ok(Some(layout.length + length as usize))
+}
+
+/// Gets the length of the complete message the data starts with, or `None`
+/// while its header or any of its payload is yet to be received
+pub(crate) fn complete_frame_length(
+    layout: HeaderLayout,
+    data: &[u8],
+    max_payload_length: u32,
+) -> Result<Option<usize>, ConnectionError> {
+    match frame_length_within(layout, data, max_payload_length)? {
+        Some(length) if data.len() >= length => Ok(Some(length)),
+        _ => Ok(None),
+    }
+}
+
+/// Bytes received from the other node, demultiplexed into messages. Nodes
+/// coalesce several messages into a single segment, e.g. Version, Verack
+/// and SendHeaders, while large ones arrive over several reads.
+pub(crate) struct ReceiveBuffer {
+    pub(crate) data: Vec<u8>,
+    /// Layout of the headers of the chain the messages are framed by
+    layout: HeaderLayout,
+    /// Longest payload accepted, so that no node makes us buffer more
+    max_payload_length: u32,
+}
+
+impl ReceiveBuffer {
+    pub(crate) fn new(chain: &impl Chain, max_payload_length: u32) -> Self {
+        Self {
+            data: Vec::new(),
+            layout: chain.header_layout(),
+            max_payload_length,
+        }
+    }
+
+    /// Takes the next complete message, i.e. the header followed by as many
+    /// payload bytes as the header announces, out of the buffer. Partial
+    /// trailing bytes are kept until the rest of them is received.
+    pub(crate) fn next_frame(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        let length =
+            complete_frame_length(self.layout, &self.data, self.max_payload_length)?;
+        Ok(length.map(|length| self.data.drain(..length).collect()))
+    }
+}
+
+/// Decodes the whole message framed by the rules of the chain, or returns
+/// `None` if its command is unknown to the codec
+pub(crate) fn decode_frame(
+    chain: &impl Chain,
+    frame: &[u8],
+) -> Result<Option<Message>, ConnectionError> {
+    let mut data = frame;
+    let header = match FrameHeader::decode(chain, &mut data) {
+        Ok(v) => v,
+        Err(e) if e.kind() == &CodecError::InvalidBytesError => {
+            debug!("Skipping message with a malformed command");
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if header.magic != chain.magic() {
+        return Err(match Network::try_from(header.magic) {
+            Ok(network) => ConnectionError::NetworkMismatch(network),
+            Err(_) => ConnectionError::InvalidDataError,
+        });
+    }
+    let mut data = header.payload(data)?;
+    if !header.verify(chain, data) {
+        return Err(ConnectionError::InvalidDataError);
+    }
+    if let Command::Unknown(_) = header.command {
+        debug!("Skipping {} message", header.command.name());
+        return Ok(None);
+    }
+
+    Ok(Some(Message::decode(header.command, &mut data)?))
+}
+
+/// Connection with a node the handshake was performed with, over which
+/// messages can be exchanged further.
+pub struct Connection<C: Chain = Network, R: Runtime = DefaultRuntime> {
+    recv_half: RecvHalf<C, R>,
+    send_half: SendHalf<C, R>,
+    /// Protocol version advertised in our Version message
+    version: ProtocolVersion,
+    /// Information about the other node gathered during the handshake
+    report: HandshakeReport,
+}
+
+impl<C: Chain + Clone, R: Runtime> Connection<C, R> {
+    /// Creates the connection over the socket the handshake was performed
+    /// over, along with the buffer holding the bytes received after the
+    /// last handshake message
+    pub(crate) fn new(
+        socket: impl Stream + 'static,
+        chain: C,
+        version: ProtocolVersion,
+        buffer: ReceiveBuffer,
+        report: HandshakeReport,
+    ) -> Self {
+        let socket: Box<dyn Stream> = Box::new(socket);
+        let (reader, writer) = socket.split();
+        Self {
+            recv_half: RecvHalf {
+                reader,
+                buffer,
+                chain: chain.clone(),
+                runtime: PhantomData,
+            },
+            send_half: SendHalf {
+                writer,
+                chain,
+                runtime: PhantomData,
+            },
+            version,
+            report,
+        }
+    }
+}
+
+impl<C: Chain, R: Runtime> Connection<C, R> {
+    /// Gets information about the other node gathered during the handshake
+    pub fn report(&self) -> &HandshakeReport {
+        &self.report
+    }
+
+    /// Gets the protocol version spoken on the connection, i.e. the lower
+    /// of the versions advertised by both nodes
+    pub fn negotiated_version(&self) -> ProtocolVersion {
+        self.version.min(self.report.config.version)
+    }
+
+    pub async fn send(&mut self, message: &Message) -> Result<(), ConnectionError> {
+        self.send_half.send(message).await
+    }
+
+    /// Receives the next message, skipping the ones whose command is
+    /// unknown to the codec
+    pub async fn recv(&mut self) -> Result<Message, ConnectionError> {
+        self.recv_half.recv().await
+    }
+
+    /// Asks the node for addresses of other nodes, returning the first batch
+    /// of more than one address received within the given time, either in
+    /// an Addr or AddrV2 message. Nodes also
+    /// announce their own address unsolicited, so single addresses received
+    /// meanwhile are returned only if no batch arrives. Ping messages are
+    /// answered while waiting.
+    ///
+    /// In case the time is over, a message might have been read partially,
+    /// so the connection should not be used any further.
+    #[cfg(feature = "addr")]
+    pub async fn request_addrs(
+        &mut self,
+        wait: Duration,
+    ) -> Result<Vec<TimestampedAddrV2>, ConnectionError> {
+        self.send(&Message::GetAddr(GetAddrMessage {})).await?;
+
+        let mut announced = Vec::new();
+        let receive = async {
+            loop {
+                match self.recv().await? {
+                    Message::Addr(msg) if msg.addresses.len() > 1 => {
+                        return Ok(msg.addresses.into_iter().map(Into::into).collect())
+                    }
+                    Message::Addr(msg) => {
+                        announced.extend(msg.addresses.into_iter().map(Into::into))
+                    }
+                    Message::AddrV2(msg) if msg.addresses.len() > 1 => {
+                        return Ok(msg.addresses)
+                    }
+                    Message::AddrV2(msg) => announced.extend(msg.addresses),
+                    Message::Ping(ping) => {
+                        let pong = Message::Pong(PongMessage::new(ping.nonce()));
+                        self.send(&pong).await?;
+                    }
+                    _ => {}
+                }
+            }
+        };
+        match timeout::<R, _>(wait, receive).await {
+            Some(result) => result,
+            None => Ok(announced),
+        }
+    }
+
+    /// Keeps the connection open for the given time, answering Ping
+    /// messages and classifying the messages the node sends meanwhile.
+    /// Messages whose command is unknown to the codec are not counted. The
+    /// node closing the connection, or sending anything invalid, ends the
+    /// observation early.
+    ///
+    /// Once the time is over, a message might have been read partially,
+    /// so the connection should not be used any further.
+    pub async fn observe(&mut self, window: Duration) -> Observation {
+        let mut observation = Observation::default();
+        let start = Instant::now();
+        if let Some(Err(e)) = timeout::<R, _>(window, self.watch(&mut observation)).await
+        {
+            debug!("Observation ended early: {}", e);
+            observation.disconnected = true;
+        }
+        observation.duration = start.elapsed();
+        observation
+    }
+
+    /// Receives messages until the node fails, classifying them and
+    /// answering Ping messages
+    async fn watch(
+        &mut self,
+        observation: &mut Observation,
+    ) -> Result<(), ConnectionError> {
+        loop {
+            let message = self.recv().await?;
+            observation.record(&message);
+            if let Message::Ping(ping) = message {
+                self.send(&Message::Pong(PongMessage::new(ping.nonce())))
+                    .await?;
+            }
+        }
+    }
+
+    /// Gets the information gathered about the other node for amending
+    pub(crate) fn report_mut(&mut self) -> &mut HandshakeReport {
+        &mut self.report
+    }
+
+    /// Closes the connection, returning the information gathered during
+    /// the handshake
+    pub(crate) fn into_report(self) -> HandshakeReport {
+        self.report
+    }
+
+    /// Splits the connection into halves which can be used concurrently,
+    /// e.g. answering Ping messages while sending requests. Session state
+    /// is not carried over, so it has to be read beforehand.
+    pub fn into_split(self) -> (RecvHalf<C, R>, SendHalf<C, R>) {
+        (self.recv_half, self.send_half)
+    }
+}
+
+/// Receiving half of a [`Connection`].
+pub struct RecvHalf<C: Chain = Network, R: Runtime = DefaultRuntime> {
+    reader: ReadHalf<Box<dyn Stream>>,
+    /// Bytes received but not demultiplexed into messages yet, starting
+    /// with the ones read along with the last handshake message
+    buffer: ReceiveBuffer,
+    chain: C,
+    runtime: PhantomData<R>,
+}
+
+impl<C: Chain, R: Runtime> RecvHalf<C, R> {
+    /// Receives the next message, skipping the ones whose command is
+    /// unknown to the codec
+    pub async fn recv(&mut self) -> Result<Message, ConnectionError> {
+        loop {
+            let frame = self.read_frame().await?;
+            if let Some(message) = decode_frame(&self.chain, &frame)? {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Reads the next whole message, reading from the socket only once the
+    /// buffer holds no complete message anymore
+    async fn read_frame(&mut self) -> Result<Vec<u8>, ConnectionError> {
+        loop {
+            if let Some(frame) = self.buffer.next_frame()? {
+                return Ok(frame);
+            }
+
+            let mut chunk = [0; READ_CHUNK_LENGTH];
+            match self.reader.read(&mut chunk).await? {
+                0 => return Err(ConnectionError::ConnectionHangUp),
+                n => self.buffer.data.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+}
+
+/// Sending half of a [`Connection`].
+pub struct SendHalf<C: Chain = Network, R: Runtime = DefaultRuntime> {
+    writer: WriteHalf<Box<dyn Stream>>,
+    chain: C,
+    runtime: PhantomData<R>,
+}
+
+impl<C: Chain, R: Runtime> SendHalf<C, R> {
+    pub async fn send(&mut self, message: &Message) -> Result<(), ConnectionError> {
+        let data = compose_message(&self.chain, message);
+        self.writer.write_all(&data).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::p2p::messages::{
+        compose,
+        MessageHeader,
+        PingMessage,
+        VerackMessage,
+    };
+
+    #[test]
+    fn receive_buffer() {
+        let verack = compose(Network::Main, Command::Verack, VerackMessage {});
+        let ping = compose(Network::Main, Command::Ping, PingMessage::new(15));
+
+        // Two complete messages coalesced with the start of a third one
+        let mut buffer = ReceiveBuffer::new(&Network::Main, MAX_PAYLOAD_LENGTH);
+        buffer.data.extend_from_slice(&verack);
+        buffer.data.extend_from_slice(&ping);
+        buffer.data.extend_from_slice(&ping[..10]);
+        assert_eq!(buffer.next_frame(), Ok(Some(verack)));
+        assert_eq!(buffer.next_frame(), Ok(Some(ping.clone())));
+        assert_eq!(buffer.next_frame(), Ok(None));
+
+        buffer.data.extend_from_slice(&ping[10..]);
+        assert_eq!(buffer.next_frame(), Ok(Some(ping.clone())));
+        assert_eq!(buffer.next_frame(), Ok(None));
+
+        let mut header = ping[..MessageHeader::LENGTH].to_vec();
+        header[16..20].copy_from_slice(&(MAX_PAYLOAD_LENGTH + 1).to_le_bytes());
+        buffer.data.extend_from_slice(&header);
+        assert_eq!(
+            buffer.next_frame(),
+            Err(ConnectionError::DecodeError(CodecError::OversizedFieldError))
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
+    net::SocketAddrV4,
+    time::Duration,
+};
+
+use futures::{
+    future::BoxFuture,
+    stream::{
+        self,
+        FuturesUnordered,
+        Stream,
+        StreamExt,
+    },
+};
+use log::info;
+
+use crate::p2p::{
+    messages::{
+        AddrV2,
+        Chain,
+        TimestampedAddrV2,
+    },
+    runtime::{
+        timeout,
+        Instant,
+    },
+    ConnectionError,
+    DefaultRuntime,
+    Destination,
+    HandshakeReport,
+    Node,
+    Runtime,
+};
+
+/// Reachable node found while crawling the network.
+pub struct CrawlRecord {
+    pub address: Destination,
+    /// Number of hops from the seed the node was learned through, 0 for seeds
+    pub depth: u32,
+    /// Information about the node gathered during the handshake
+    pub report: HandshakeReport,
+    /// Addresses the node gossiped
+    pub addresses: Vec<TimestampedAddrV2>,
+}
+
+/// Outcome of a visit to a single node, i.e. the node's report and the
+/// addresses it gossiped on success
+type Visit = (
+    Destination,
+    u32,
+    Result<(HandshakeReport, Vec<TimestampedAddrV2>), ConnectionError>,
+);
+
+/// Walks the network starting from seed nodes, performing the handshake
+/// with each node, asking it for addresses of other nodes and visiting
+/// those recursively. Tor v3 onion services are visited as well if the
+/// node opens connections through a proxy.
+pub struct Crawler<'a, C: Chain, R: Runtime = DefaultRuntime> {
+    node: &'a Node<R>,
+    chain: C,
+    /// Number of hops from the seeds beyond which nodes are not visited
+    depth: u32,
+    /// Number of nodes visited at the same time
+    concurrency: usize,
+    /// Minimum time between connections to the same host
+    host_interval: Duration,
+    /// Time to wait for the handshake and then again for addresses
+    timeout: Duration,
+    /// Number of nodes after which newly learned ones are not visited
+    max_peers: usize,
+}
+
+impl<'a, C, R> Crawler<'a, C, R>
+where
+    C: Chain + Clone + Send + Sync + 'a,
+    R: Runtime,
+{
+    /// Creates the crawler visiting nodes of the chain with the given node,
+    /// whose target policy is consulted for each address learned.
+    pub fn new(node: &'a Node<R>, chain: C) -> Self {
+        Self {
+            node,
+            chain,
+            depth: 2,
+            concurrency: 16,
+            host_interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+            max_peers: 1000,
+        }
+    }
+
+    /// Visits nodes up to the given number of hops from the seeds
+    pub fn with_depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Visits up to the given number of nodes at the same time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Waits at least the given time between connections to the same host,
+    /// e.g. to nodes listening on several ports of it
+    pub fn with_host_interval(mut self, interval: Duration) -> Self {
+        self.host_interval = interval;
+        self
+    }
+
+    /// Waits up to the given time for the handshake and then again for
+    /// addresses of other nodes
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stops visiting newly learned nodes once the given number of them,
+    /// including the seeds, is reached
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = max_peers;
+        self
+    }
+
+    /// Crawls the network from the seeds, yielding a record per reachable
+    /// node as soon as it is visited. Nodes which cannot be reached are
+    /// only logged.
+    pub fn crawl(
+        self,
+        seeds: impl IntoIterator<Item = SocketAddrV4>,
+    ) -> impl Stream<Item = CrawlRecord> + 'a {
+        let mut state = CrawlState {
+            crawler: self,
+            queue: VecDeque::new(),
+            seen: HashSet::new(),
+            next_slot: HashMap::new(),
+            in_flight: FuturesUnordered::new(),
+        };
+        for seed in seeds.into_iter().map(Destination::from) {
+            if state.seen.insert(seed.clone()) {
+                state.queue.push_back((seed, 0));
+            }
+        }
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                state.schedule();
+                let (address, depth, result) = state.in_flight.next().await?;
+                match result {
+                    Ok((report, addresses)) => {
+                        state.learn(&addresses, depth);
+                        let record = CrawlRecord {
+                            address,
+                            depth,
+                            report,
+                            addresses,
+                        };
+                        return Some((record, state));
+                    }
+                    Err(e) => info!("Node at {} not reachable: {}", address, e),
+                }
+            }
+        })
+    }
+}
+
+/// Progress of the crawl, i.e. nodes waiting to be visited and ones being
+/// visited.
+struct CrawlState<'a, C: Chain, R: Runtime> {
+    crawler: Crawler<'a, C, R>,
+    /// Nodes waiting to be visited together with their depth
+    queue: VecDeque<(Destination, u32)>,
+    /// Nodes visited or waiting to be visited
+    seen: HashSet<Destination>,
+    /// Earliest time the next connection to each host may be opened
+    next_slot: HashMap<String, Instant>,
+    in_flight: FuturesUnordered<BoxFuture<'a, Visit>>,
+}
+
+impl<'a, C, R> CrawlState<'a, C, R>
+where
+    C: Chain + Clone + Send + Sync + 'a,
+    R: Runtime,
+{
+    /// Starts visiting queued nodes until the concurrency limit is reached
+    fn schedule(&mut self) {
+        while self.in_flight.len() < self.crawler.concurrency {
+            let Some((address, depth)) = self.queue.pop_front() else {
+                break;
+            };
+
+            let now = Instant::now();
+            let start = self
+                .next_slot
+                .get(&address.host())
+                .map_or(now, |&slot| slot.max(now));
+            self.next_slot
+                .insert(address.host(), start + self.crawler.host_interval);
+
+            let node = self.crawler.node;
+            let chain = self.crawler.chain.clone();
+            let wait = self.crawler.timeout;
+            self.in_flight.push(Box::pin(async move {
+                R::sleep(start - now).await;
+                let result = visit(node, chain, &address, wait).await;
+                (address, depth, result)
+            }));
+        }
+    }
+
+    /// Queues the IPv4 nodes, and the onion ones if proxied, learned from
+    /// a node at the given depth, unless they are too deep, already seen or
+    /// not allowed
+    fn learn(&mut self, addresses: &[TimestampedAddrV2], depth: u32) {
+        if depth >= self.crawler.depth {
+            return;
+        }
+
+        let node = self.crawler.node;
+        for learned in addresses {
+            let address = match learned.address {
+                AddrV2::Ipv4(ip) => {
+                    let address = SocketAddrV4::new(ip, learned.port);
+                    if !node.allows(address) {
+                        continue;
+                    }
+                    Destination::Address(address)
+                }
+                AddrV2::TorV3(_) if node.is_proxied() => {
+                    Destination::Host(learned.address.to_string(), learned.port)
+                }
+                _ => continue,
+            };
+            if self.seen.len() >= self.crawler.max_peers {
+                break;
+            }
+
+            if self.seen.insert(address.clone()) {
+                self.queue.push_back((address, depth + 1));
+            }
+        }
+    }
+}
+
+/// Performs the handshake with the node and asks it for addresses
+async fn visit<C: Chain + Clone, R: Runtime>(
+    node: &Node<R>,
+    chain: C,
+    address: &Destination,
+    wait: Duration,
+) -> Result<(HandshakeReport, Vec<TimestampedAddrV2>), ConnectionError> {
+    let mut connection = timeout::<R, _>(wait, node.connect_to(chain, address))
+        .await
+        .ok_or(ConnectionError::MessageTimeoutError)??;
+    let addresses = connection.request_addrs(wait).await?;
+    Ok((connection.into_report(), addresses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use futures::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_util::codec::Framed;
+
+    use crate::p2p::{
+        messages::{
+            AddrMessage,
+            Message,
+            Network,
+            NetworkAddress,
+            PongMessage,
+            ProtocolVersion,
+            Service,
+            Services,
+            TimestampedAddress,
+            VerackMessage,
+            VersionMessage,
+        },
+        BitcoinMessageCodec,
+        NodeConfig,
+        Socks5Proxy,
+    };
+
+    /// Spawns the node gossiping the given addresses, returning its address
+    async fn fake_peer(gossiped: Vec<SocketAddrV4>) -> SocketAddrV4 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = match listener.local_addr().unwrap() {
+            std::net::SocketAddr::V4(address) => address,
+            std::net::SocketAddr::V6(_) => unreachable!(),
+        };
+
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let gossiped = gossiped.clone();
+                tokio::spawn(async move {
+                    let codec = BitcoinMessageCodec::new(Network::Testnet);
+                    let mut framed = Framed::new(socket, codec);
+                    while let Some(Ok(message)) = framed.next().await {
+                        let answers = match message {
+                            Message::Version(_) => {
+                                let config = NodeConfig {
+                                    version: ProtocolVersion(70016),
+                                    ..Default::default()
+                                };
+                                let version =
+                                    VersionMessage::new(address.into(), &config);
+                                vec![
+                                    Message::Version(version),
+                                    Message::Verack(VerackMessage {}),
+                                ]
+                            }
+                            Message::Ping(ping) => {
+                                vec![Message::Pong(PongMessage::new(ping.nonce()))]
+                            }
+                            Message::GetAddr(_) => {
+                                let addresses = gossiped
+                                    .iter()
+                                    .map(|&a| TimestampedAddress {
+                                        time: 1_700_000_000,
+                                        address: NetworkAddress::new(
+                                            Services::new(&[Service::Network]),
+                                            a.into(),
+                                        ),
+                                    })
+                                    .collect();
+                                vec![Message::Addr(AddrMessage { addresses })]
+                            }
+                            _ => Vec::new(),
+                        };
+                        for answer in answers {
+                            framed.send(answer).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        address
+    }
+
+    /// Reserves a port nothing listens on
+    async fn closed_port() -> SocketAddrV4 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        match listener.local_addr().unwrap() {
+            std::net::SocketAddr::V4(address) => address,
+            std::net::SocketAddr::V6(_) => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn crawl() {
+        let unreachable = closed_port().await;
+        let leaf = fake_peer(vec![unreachable]).await;
+        let denied = fake_peer(Vec::new()).await;
+        let too_deep = fake_peer(Vec::new()).await;
+        let middle = fake_peer(vec![leaf, too_deep]).await;
+        let seed = fake_peer(vec![middle, leaf, denied]).await;
+
+        let node = Node::new(Default::default())
+            .with_target_policy(move |address: SocketAddrV4| address != denied);
+        let crawler = Crawler::new(&node, Network::Testnet)
+            .with_depth(1)
+            .with_host_interval(Duration::ZERO)
+            .with_timeout(Duration::from_millis(500));
+        let mut records: Vec<_> = crawler
+            .crawl([seed])
+            .map(|r| (r.address, r.depth, r.addresses.len()))
+            .collect()
+            .await;
+        records.sort();
+
+        let mut expected = vec![
+            (Destination::from(seed), 0, 3),
+            (Destination::from(middle), 1, 2),
+            (Destination::from(leaf), 1, 1),
+        ];
+        expected.sort();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn learn_onions() {
+        let onion = TimestampedAddrV2 {
+            time: 1_700_000_000,
+            services: Services::new(&[Service::Network]),
+            address: AddrV2::TorV3([7; 32]),
+            port: 8333,
+        };
+        let queued = |node: &Node| {
+            let mut state = CrawlState {
+                crawler: Crawler::new(node, Network::Testnet),
+                queue: VecDeque::new(),
+                seen: HashSet::new(),
+                next_slot: HashMap::new(),
+                in_flight: FuturesUnordered::new(),
+            };
+            state.learn(std::slice::from_ref(&onion), 0);
+            state.queue.len()
+        };
+
+        // Onion services are reachable only through the proxy
+        assert_eq!(queued(&Node::new(Default::default())), 0);
+        let proxy = Socks5Proxy::new(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9050));
+        assert_eq!(queued(&Node::new(Default::default()).with_proxy(proxy)), 1);
+    }
+
+    #[tokio::test]
+    async fn host_interval() {
+        let second = fake_peer(Vec::new()).await;
+        let first = fake_peer(vec![second]).await;
+
+        let node = Node::new(Default::default());
+        let crawler = Crawler::new(&node, Network::Testnet)
+            .with_host_interval(Duration::from_millis(300))
+            .with_timeout(Duration::from_millis(100));
+        let start = Instant::now();
+        let records: Vec<_> = crawler.crawl([first]).collect().await;
+        assert_eq!(records.len(), 2);
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn max_peers() {
+        let second = fake_peer(Vec::new()).await;
+        let first = fake_peer(vec![second]).await;
+
+        let node = Node::new(Default::default());
+        let crawler = Crawler::new(&node, Network::Testnet)
+            .with_host_interval(Duration::ZERO)
+            .with_timeout(Duration::from_millis(100))
+            .with_max_peers(1);
+        let records: Vec<_> = crawler.crawl([first]).collect().await;
+        assert_eq!(records.len(), 1);
+    }
+}
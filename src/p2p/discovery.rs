@@ -0,0 +1,106 @@
+use std::{
+    collections::HashSet,
+    net::SocketAddrV4,
+};
+
+use futures::future::join_all;
+use hickory_resolver::{
+    error::ResolveError,
+    TokioAsyncResolver,
+};
+use log::{
+    info,
+    warn,
+};
+
+use crate::p2p::messages::Network;
+
+/// Gets the hostnames of the DNS seeds Bitcoin Core queries for nodes of
+/// the network, none for networks which are not public
+pub fn dns_seeds(network: Network) -> &'static [&'static str] {
+    match network {
+        Network::Main => &[
+            "seed.bitcoin.sipa.be",
+            "dnsseed.bluematt.me",
+            "seed.bitcoinstats.com",
+            "seed.bitcoin.jonasschnelli.ch",
+            "seed.btc.petertodd.net",
+            "seed.bitcoin.sprovoost.nl",
+            "dnsseed.emzy.de",
+            "seed.bitcoin.wiz.biz",
+            "seed.mainnet.achownodes.xyz",
+        ],
+        Network::Testnet3 => &[
+            "testnet-seed.bitcoin.jonasschnelli.ch",
+            "seed.tbtc.petertodd.net",
+            "seed.testnet.bitcoin.sprovoost.nl",
+            "testnet-seed.bluematt.me",
+        ],
+        Network::Signet => &[
+            "seed.signet.bitcoin.sprovoost.nl",
+            "seed.signet.achownodes.xyz",
+        ],
+        Network::Namecoin => &["nmc.seed.quisquis.de", "seed.nmc.markasoftware.com"],
+        Network::Testnet => &[],
+    }
+}
+
+/// Resolves the IPv4 addresses of nodes the DNS seed returns, which listen
+/// on the given port
+pub async fn resolve_seed(
+    seed: &str,
+    port: u16,
+) -> Result<Vec<SocketAddrV4>, ResolveError> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()?;
+    let ips = resolver.ipv4_lookup(seed).await?;
+    Ok(ips.iter().map(|ip| SocketAddrV4::new(ip.0, port)).collect())
+}
+
+/// Resolves all DNS seeds of the network concurrently, returning the nodes
+/// they know of, each once. Seeds which cannot be resolved are skipped.
+pub async fn resolve_seeds(network: Network) -> Vec<SocketAddrV4> {
+    let lookups = dns_seeds(network).iter().map(|&seed| async move {
+        match resolve_seed(seed, network.default_port()).await {
+            Ok(addresses) => {
+                info!("DNS seed {} returned {} nodes", seed, addresses.len());
+                addresses
+            }
+            Err(e) => {
+                warn!("Unable to resolve DNS seed {}: {}", seed, e);
+                Vec::new()
+            }
+        }
+    });
+
+    let mut seen = HashSet::new();
+    join_all(lookups)
+        .await
+        .into_iter()
+        .flatten()
+        .filter(|address| seen.insert(*address))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn seeds() {
+        assert!(!dns_seeds(Network::Main).is_empty());
+        assert!(!dns_seeds(Network::Testnet3).is_empty());
+        assert!(!dns_seeds(Network::Signet).is_empty());
+        // Regtest nodes are local only
+        assert!(dns_seeds(Network::Testnet).is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolve() {
+        let addresses = resolve_seed("localhost", 8333).await.unwrap();
+        assert_eq!(addresses, [SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8333)]);
+
+        assert!(resolve_seeds(Network::Testnet).await.is_empty());
+    }
+}
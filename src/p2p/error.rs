@@ -1,14 +1,101 @@
-use std::fmt::{
-    Display,
-    Formatter,
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    io,
 };
 
+use crate::p2p::{
+    messages::{
+        CodecError,
+        Network,
+    },
+    Destination,
+    ProxyError,
+    Stage,
+};
+
+/// I/O error the connection failed with, compared by its kind only, so
+/// that e.g. timeouts can be told apart from resets.
+#[derive(Debug)]
+pub struct IoError(pub io::Error);
+
+impl IoError {
+    pub fn kind(&self) -> io::ErrorKind {
+        self.0.kind()
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind()
+    }
+}
+
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum ConnectionError {
     ConnectionHangUp,
     ConnectionRefusedError,
     InvalidDataError,
-    IOError,
+    IOError(IoError),
+    PongNonceMismatch,
+    NetworkMismatch(Network),
+    MessageTimeoutError,
+    TargetDenied,
+    Stalled,
+    ProxyError(ProxyError),
+    /// Stage of the handshake exceeded its own timeout
+    StageTimeout(Stage),
+    /// Node sent back the nonce of our Version message, i.e. it is ourselves
+    SelfConnection,
+    /// Message not following the encoding of its command
+    DecodeError(CodecError),
+}
+
+impl ConnectionError {
+    /// Gets the code identifying the error in machine-readable output,
+    /// which unlike the description never changes
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConnectionError::ConnectionHangUp => "E_HANG_UP",
+            ConnectionError::ConnectionRefusedError => "E_REFUSED",
+            ConnectionError::InvalidDataError => "E_INVALID_DATA",
+            ConnectionError::IOError(_) => "E_IO",
+            ConnectionError::PongNonceMismatch => "E_PONG_NONCE",
+            ConnectionError::NetworkMismatch(_) => "E_NETWORK_MISMATCH",
+            ConnectionError::MessageTimeoutError => "E_TIMEOUT_MESSAGE",
+            ConnectionError::TargetDenied => "E_TARGET_DENIED",
+            ConnectionError::Stalled => "E_STALLED",
+            ConnectionError::ProxyError(_) => "E_PROXY",
+            ConnectionError::StageTimeout(stage) => match stage {
+                Stage::Connect => "E_TIMEOUT_CONNECT",
+                Stage::Version => "E_TIMEOUT_VERSION",
+                Stage::Verack => "E_TIMEOUT_VERACK",
+                Stage::Pong => "E_TIMEOUT_PONG",
+            },
+            ConnectionError::SelfConnection => "E_SELF_CONNECTION",
+            // Malformed messages were reported as invalid data before
+            // decoding errors were told apart
+            ConnectionError::DecodeError(_) => "E_INVALID_DATA",
+        }
+    }
+
+    /// Checks whether attempting the handshake again might succeed, e.g.
+    /// once a busy node accepts connections again, unlike when the node
+    /// misbehaved or is of another network
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ConnectionError::ConnectionHangUp
+                | ConnectionError::ConnectionRefusedError
+                | ConnectionError::IOError(_)
+                | ConnectionError::MessageTimeoutError
+                | ConnectionError::StageTimeout(_)
+                | ConnectionError::ProxyError(ProxyError::Rejected(_))
+        )
+    }
 }
 
 impl Display for ConnectionError {
@@ -23,11 +110,177 @@ impl Display for ConnectionError {
             ConnectionError::InvalidDataError => {
                 write!(f, "Invalid data received")
             }
-            ConnectionError::IOError => {
-                write!(f, "IO error occurred during connection")
+            ConnectionError::IOError(e) => {
+                write!(f, "IO error occurred during connection: {}", e.0)
+            }
+            ConnectionError::PongNonceMismatch => {
+                write!(f, "Pong message does not echo the Ping message nonce")
+            }
+            ConnectionError::NetworkMismatch(network) => {
+                write!(f, "Node belongs to the {:?} network", network)
+            }
+            ConnectionError::MessageTimeoutError => {
+                write!(f, "No message received within the timeout")
+            }
+            ConnectionError::TargetDenied => {
+                write!(
+                    f,
+                    "Connection to provided address denied by the target policy"
+                )
+            }
+            ConnectionError::Stalled => {
+                write!(f, "Node kept trickling data beyond the stall budget")
+            }
+            ConnectionError::ProxyError(e) => write!(f, "{}", e),
+            ConnectionError::StageTimeout(stage) => {
+                write!(f, "Handshake stage {} exceeded its timeout", stage)
+            }
+            ConnectionError::SelfConnection => {
+                write!(f, "Connected to ourselves")
+            }
+            ConnectionError::DecodeError(e) => {
+                write!(f, "Invalid message received: {}", e)
             }
         }
     }
 }
 
-impl std::error::Error for ConnectionError {}
+impl std::error::Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConnectionError::IOError(e) => Some(&e.0),
+            ConnectionError::ProxyError(e) => Some(e),
+            ConnectionError::DecodeError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConnectionError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => ConnectionError::ConnectionHangUp,
+            io::ErrorKind::ConnectionRefused => ConnectionError::ConnectionRefusedError,
+            _ => ConnectionError::IOError(IoError(e)),
+        }
+    }
+}
+
+impl From<CodecError> for ConnectionError {
+    fn from(e: CodecError) -> Self {
+        ConnectionError::DecodeError(e)
+    }
+}
+
+impl From<ProxyError> for ConnectionError {
+    fn from(e: ProxyError) -> Self {
+        ConnectionError::ProxyError(e)
+    }
+}
+
+/// Failure of the handshake along with the node it was performed with and
+/// the stage it failed in.
+#[derive(Debug, PartialEq)]
+pub struct HandshakeError {
+    /// Node the handshake was performed with
+    pub peer: Destination,
+    /// Stage of the handshake awaited when it failed
+    pub stage: Stage,
+    /// What went wrong
+    pub error: ConnectionError,
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Handshake with {} failed in the {} stage: {}",
+            self.peer, self.stage, self.error
+        )
+    }
+}
+
+impl std::error::Error for HandshakeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<HandshakeError> for ConnectionError {
+    fn from(e: HandshakeError) -> Self {
+        e.error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error;
+
+    #[test]
+    fn codes() {
+        assert_eq!(ConnectionError::ConnectionRefusedError.code(), "E_REFUSED");
+        assert_eq!(
+            ConnectionError::NetworkMismatch(Network::Signet).code(),
+            "E_NETWORK_MISMATCH"
+        );
+        assert_eq!(
+            ConnectionError::StageTimeout(Stage::Verack).code(),
+            "E_TIMEOUT_VERACK"
+        );
+        assert_eq!(ConnectionError::SelfConnection.code(), "E_SELF_CONNECTION");
+        assert_eq!(
+            ConnectionError::DecodeError(CodecError::OversizedFieldError).code(),
+            "E_INVALID_DATA"
+        );
+    }
+
+    #[test]
+    fn sources() {
+        let timed_out = ConnectionError::from(io::Error::from(io::ErrorKind::TimedOut));
+        let reset =
+            ConnectionError::from(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert_ne!(timed_out, reset);
+        assert_eq!(timed_out.code(), "E_IO");
+        let source = timed_out.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<io::Error>().map(io::Error::kind),
+            Some(io::ErrorKind::TimedOut)
+        );
+        assert_eq!(
+            ConnectionError::from(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            ConnectionError::ConnectionHangUp
+        );
+        assert_eq!(
+            ConnectionError::from(io::Error::from(io::ErrorKind::ConnectionRefused)),
+            ConnectionError::ConnectionRefusedError
+        );
+
+        let e = ConnectionError::from(CodecError::InsufficientBytesError);
+        assert_eq!(
+            e.source().unwrap().downcast_ref::<CodecError>(),
+            Some(&CodecError::InsufficientBytesError)
+        );
+        assert!(ConnectionError::ConnectionRefusedError.source().is_none());
+    }
+
+    #[test]
+    fn handshake_error() {
+        let e = HandshakeError {
+            peer: Destination::Host("example.onion".to_string(), 8333),
+            stage: Stage::Verack,
+            error: ConnectionError::ConnectionHangUp,
+        };
+        assert_eq!(
+            e.to_string(),
+            "Handshake with example.onion:8333 failed in the verack stage: \
+            Connection hang up"
+        );
+        assert_eq!(
+            e.source().unwrap().downcast_ref::<ConnectionError>(),
+            Some(&ConnectionError::ConnectionHangUp)
+        );
+        assert_eq!(ConnectionError::from(e), ConnectionError::ConnectionHangUp);
+    }
+}
@@ -1,8 +1,23 @@
+//! Bitcoin P2P messages and their wire encoding.
+//!
+//! Messages needed for the handshake (version, verack, ping and pong) are
+//! always available. Other message families are compiled only when their
+//! cargo feature is enabled:
+//! - `addr` - address gossip messages
+//! - `blocks` - block and transaction relay messages
+//!
+//! All of them are enabled by default, so library users interested only in
+//! the handshake can opt out with `default-features = false`.
+
 use std::fmt::{
     Display,
     Formatter,
 };
 
+use crate::p2p::report::{
+    write_field,
+    Pretty,
+};
 use clap::ValueEnum;
 use sha2::{
     Digest,
@@ -13,19 +28,71 @@ use strum::{
     IntoEnumIterator,
 };
 
+#[cfg(feature = "addr")]
+pub mod addr;
 pub mod address;
+#[cfg(feature = "addr")]
+pub mod addrv2;
+pub mod chain;
+pub mod compact_size;
+pub mod dissect;
+#[cfg(feature = "blocks")]
+pub mod feefilter;
+#[cfg(feature = "addr")]
+pub mod getaddr;
+#[cfg(feature = "blocks")]
+pub mod headers;
+#[cfg(feature = "blocks")]
+pub mod inv;
+pub mod message;
+pub mod params;
 pub mod ping;
 pub mod pong;
+pub mod protocol_version;
+#[cfg(feature = "addr")]
+pub mod sendaddrv2;
+#[cfg(feature = "blocks")]
+pub mod sendcmpct;
+#[cfg(feature = "blocks")]
+pub mod sendheaders;
+pub mod sendtxrcncl;
 pub mod services;
 pub mod verack;
 pub mod version;
+pub mod wtxidrelay;
 
+#[cfg(feature = "addr")]
+pub use addr::*;
 pub use address::*;
+#[cfg(feature = "addr")]
+pub use addrv2::*;
+pub use chain::*;
+pub use compact_size::*;
+pub use dissect::*;
+#[cfg(feature = "blocks")]
+pub use feefilter::*;
+#[cfg(feature = "addr")]
+pub use getaddr::*;
+#[cfg(feature = "blocks")]
+pub use headers::*;
+#[cfg(feature = "blocks")]
+pub use inv::*;
+pub use message::*;
+pub use params::*;
 pub use ping::*;
 pub use pong::*;
+pub use protocol_version::*;
+#[cfg(feature = "addr")]
+pub use sendaddrv2::*;
+#[cfg(feature = "blocks")]
+pub use sendcmpct::*;
+#[cfg(feature = "blocks")]
+pub use sendheaders::*;
+pub use sendtxrcncl::*;
 pub use services::*;
 pub use verack::*;
 pub use version::*;
+pub use wtxidrelay::*;
 
 trait FromBytes {
     fn from_be_bytes(bytes: &[u8]) -> Self;
@@ -59,6 +126,17 @@ trait ReadBytes {
     fn read_be<T: FromBytes>(&mut self) -> Option<T>;
     fn read_fixed<const N: usize>(&mut self) -> Option<[u8; N]>;
     fn read_slice(&mut self, n: usize) -> Option<&[u8]>;
+
+    /// Reads a variable length integer, i.e. a single byte below 0xfd or
+    /// the marker 0xfd, 0xfe or 0xff followed by 2, 4 or 8 bytes
+    fn read_compact_size(&mut self) -> Option<u64> {
+        match self.read_le::<u8>()? {
+            0xfd => self.read_le::<u16>().map(u64::from),
+            0xfe => self.read_le::<u32>().map(u64::from),
+            0xff => self.read_le::<u64>(),
+            n => Some(u64::from(n)),
+        }
+    }
 }
 
 impl ReadBytes for &[u8] {
@@ -105,10 +183,73 @@ impl ReadBytes for &[u8] {
     }
 }
 
+/// Reads the fields of a structure one after another, naming the field
+/// and its offset from the start of the structure in the errors of the one
+/// failing to decode.
+struct Fields<'a, 'b> {
+    data: &'a mut &'b [u8],
+    length: usize,
+}
+
+impl<'a, 'b> Fields<'a, 'b> {
+    fn new(data: &'a mut &'b [u8]) -> Self {
+        let length = data.len();
+        Self { data, length }
+    }
+
+    /// Gets the number of bytes read so far
+    fn offset(&self) -> usize {
+        self.length - self.data.len()
+    }
+
+    /// Decodes the field with the given function, e.g. the field type's
+    /// [`Codec::decode`]
+    fn decode<T>(
+        &mut self,
+        field: &'static str,
+        decode: impl FnOnce(&mut &'b [u8]) -> Result<T, CodecError>,
+    ) -> Result<T, CodecError> {
+        let offset = self.offset();
+        decode(self.data).map_err(|e| e.at(field, offset))
+    }
+
+    fn read_le<T: FromBytes>(&mut self, field: &'static str) -> Result<T, CodecError> {
+        self.decode(field, |data| {
+            data.read_le().ok_or(CodecError::InsufficientBytesError)
+        })
+    }
+
+    fn read_be<T: FromBytes>(&mut self, field: &'static str) -> Result<T, CodecError> {
+        self.decode(field, |data| {
+            data.read_be().ok_or(CodecError::InsufficientBytesError)
+        })
+    }
+
+    fn read_fixed<const N: usize>(
+        &mut self,
+        field: &'static str,
+    ) -> Result<[u8; N], CodecError> {
+        self.decode(field, |data| {
+            data.read_fixed().ok_or(CodecError::InsufficientBytesError)
+        })
+    }
+}
+
 pub trait Codec {
+    /// Gets the number of bytes the object occupies once encoded.
+    fn encoded_len(&self) -> usize;
+
+    /// Encodes an object of a specific type into a stream of bytes in
+    /// network byte order, i.e big-endian, appending it to the buffer.
+    fn encode_into(&self, data: &mut Vec<u8>);
+
     /// Encodes an object of a specific type into a stream of bytes in
     /// network byte order, i.e big-endian.
-    fn encode(&self) -> Vec<u8>;
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut data);
+        data
+    }
 
     /// Decodes stream of bytes in network byte order, i.e. big-endian,
     /// into an object.
@@ -118,9 +259,77 @@ pub trait Codec {
 }
 
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum CodecError {
     InvalidBytesError,
     InsufficientBytesError,
+    /// Payload framed differently long than its header announces
+    LengthMismatchError,
+    /// Length or count of a field above its limit, rejected before
+    /// allocating for it
+    OversizedFieldError,
+    /// Error decoding the field at the given offset from the start of the
+    /// structure holding it, e.g. the message payload
+    FieldError {
+        field: &'static str,
+        offset: usize,
+        error: Box<CodecError>,
+    },
+}
+
+impl CodecError {
+    /// Gets the code identifying the error in machine-readable output,
+    /// which unlike the description never changes
+    pub fn code(&self) -> &'static str {
+        match self {
+            CodecError::InvalidBytesError => "E_INVALID_BYTES",
+            CodecError::InsufficientBytesError => "E_INSUFFICIENT_BYTES",
+            CodecError::LengthMismatchError => "E_LENGTH_MISMATCH",
+            CodecError::OversizedFieldError => "E_OVERSIZED_FIELD",
+            CodecError::FieldError { error, .. } => error.code(),
+        }
+    }
+
+    /// Gets what went wrong, without the fields it went wrong in
+    pub fn kind(&self) -> &CodecError {
+        match self {
+            CodecError::FieldError { error, .. } => error.kind(),
+            e => e,
+        }
+    }
+
+    /// Gets the path of the field decoding failed in, e.g.
+    /// `receiver.port`, if known
+    pub fn field(&self) -> Option<String> {
+        match self {
+            CodecError::FieldError { field, error, .. } => Some(match error.field() {
+                Some(inner) => format!("{}.{}", field, inner),
+                None => field.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Gets the offset of the field decoding failed in from the start of
+    /// the outermost structure, if known
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            CodecError::FieldError { offset, error, .. } => {
+                Some(offset + error.offset().unwrap_or(0))
+            }
+            _ => None,
+        }
+    }
+
+    /// Names the field the error occurred in, at its offset from the start
+    /// of the structure holding it
+    fn at(self, field: &'static str, offset: usize) -> Self {
+        CodecError::FieldError {
+            field,
+            offset,
+            error: Box::new(self),
+        }
+    }
 }
 
 impl Display for CodecError {
@@ -132,6 +341,19 @@ impl Display for CodecError {
             CodecError::InsufficientBytesError => {
                 write!(f, "Insufficient amount of bytes provided during decoding")
             }
+            CodecError::LengthMismatchError => {
+                write!(f, "Payload length does not match the one in the header")
+            }
+            CodecError::OversizedFieldError => {
+                write!(f, "Field longer than allowed provided during decoding")
+            }
+            CodecError::FieldError { .. } => write!(
+                f,
+                "{} of field {} at offset {}",
+                self.kind(),
+                self.field().unwrap_or_default(),
+                self.offset().unwrap_or_default()
+            ),
         }
     }
 }
@@ -148,6 +370,24 @@ pub enum Network {
     Namecoin = 0xfe_b4_be_f9,
 }
 
+impl Network {
+    /// Gets the port nodes of the network listen on by default
+    pub fn default_port(self) -> u16 {
+        match self {
+            Network::Main => 8333,
+            Network::Testnet => 18444,
+            Network::Testnet3 => 18333,
+            Network::Signet => 38333,
+            Network::Namecoin => 8334,
+        }
+    }
+
+    /// Gets the network whose nodes listen on the given port by default
+    pub fn from_default_port(port: u16) -> Option<Self> {
+        Network::iter().find(|n| n.default_port() == port)
+    }
+}
+
 impl TryFrom<u32> for Network {
     type Error = &'static str;
 
@@ -157,27 +397,107 @@ impl TryFrom<u32> for Network {
                 return Ok(n);
             }
         }
-        return Err("Unknown network identifier");
+        Err("Unknown network identifier")
     }
 }
 
 #[derive(Clone, Copy, Debug, EnumIter, PartialEq)]
+#[non_exhaustive]
 pub enum Command {
     Version,
     Verack,
     Ping,
     Pong,
+    SendTxRcncl,
+    WtxidRelay,
+    #[cfg(feature = "addr")]
+    GetAddr,
+    #[cfg(feature = "addr")]
+    Addr,
+    #[cfg(feature = "addr")]
+    AddrV2,
+    #[cfg(feature = "addr")]
+    SendAddrV2,
+    #[cfg(feature = "blocks")]
+    SendHeaders,
+    #[cfg(feature = "blocks")]
+    SendCmpct,
+    #[cfg(feature = "blocks")]
+    FeeFilter,
+    #[cfg(feature = "blocks")]
+    Inv,
+    #[cfg(feature = "blocks")]
+    Headers,
+    /// Well-formed command unknown to the codec, e.g. `sendheaders`, with
+    /// its name as it appears in the header
+    #[strum(disabled)]
+    Unknown([u8; Command::REQUIRED_LENGTH]),
 }
 
 impl Command {
-    const REQUIRED_LENGTH: usize = 12;
+    pub(crate) const REQUIRED_LENGTH: usize = 12;
 
-    fn to_bytes(self) -> &'static [u8; Self::REQUIRED_LENGTH] {
+    /// Gets the command name as it appears on the wire
+    pub fn name(&self) -> &str {
         match self {
+            Command::Version => "version",
+            Command::Verack => "verack",
+            Command::Ping => "ping",
+            Command::Pong => "pong",
+            Command::SendTxRcncl => "sendtxrcncl",
+            Command::WtxidRelay => "wtxidrelay",
+            #[cfg(feature = "addr")]
+            Command::GetAddr => "getaddr",
+            #[cfg(feature = "addr")]
+            Command::Addr => "addr",
+            #[cfg(feature = "addr")]
+            Command::AddrV2 => "addrv2",
+            #[cfg(feature = "addr")]
+            Command::SendAddrV2 => "sendaddrv2",
+            #[cfg(feature = "blocks")]
+            Command::SendHeaders => "sendheaders",
+            #[cfg(feature = "blocks")]
+            Command::SendCmpct => "sendcmpct",
+            #[cfg(feature = "blocks")]
+            Command::FeeFilter => "feefilter",
+            #[cfg(feature = "blocks")]
+            Command::Inv => "inv",
+            #[cfg(feature = "blocks")]
+            Command::Headers => "headers",
+            Command::Unknown(bytes) => {
+                let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                std::str::from_utf8(&bytes[..len]).unwrap_or_default()
+            }
+        }
+    }
+
+    fn to_bytes(self) -> [u8; Self::REQUIRED_LENGTH] {
+        *match self {
             Command::Version => b"version\0\0\0\0\0",
             Command::Verack => b"verack\0\0\0\0\0\0",
             Command::Ping => b"ping\0\0\0\0\0\0\0\0",
             Command::Pong => b"pong\0\0\0\0\0\0\0\0",
+            Command::SendTxRcncl => b"sendtxrcncl\0",
+            Command::WtxidRelay => b"wtxidrelay\0\0",
+            #[cfg(feature = "addr")]
+            Command::GetAddr => b"getaddr\0\0\0\0\0",
+            #[cfg(feature = "addr")]
+            Command::Addr => b"addr\0\0\0\0\0\0\0\0",
+            #[cfg(feature = "addr")]
+            Command::AddrV2 => b"addrv2\0\0\0\0\0\0",
+            #[cfg(feature = "addr")]
+            Command::SendAddrV2 => b"sendaddrv2\0\0",
+            #[cfg(feature = "blocks")]
+            Command::SendHeaders => b"sendheaders\0",
+            #[cfg(feature = "blocks")]
+            Command::SendCmpct => b"sendcmpct\0\0\0",
+            #[cfg(feature = "blocks")]
+            Command::FeeFilter => b"feefilter\0\0\0",
+            #[cfg(feature = "blocks")]
+            Command::Inv => b"inv\0\0\0\0\0\0\0\0\0",
+            #[cfg(feature = "blocks")]
+            Command::Headers => b"headers\0\0\0\0\0",
+            Command::Unknown(bytes) => return bytes,
         }
     }
 }
@@ -185,13 +505,25 @@ impl Command {
 impl TryFrom<&[u8; Command::REQUIRED_LENGTH]> for Command {
     type Error = &'static str;
 
+    /// Gets the command from its name, which is unknown to the codec if
+    /// not one of the known ones but still well-formed, i.e. printable
+    /// ASCII characters padded with zeros
     fn try_from(data: &[u8; Self::REQUIRED_LENGTH]) -> Result<Self, Self::Error> {
         for c in Command::iter() {
-            if c.to_bytes() == data {
+            if c.to_bytes() == *data {
                 return Ok(c);
             }
         }
-        Err("Unknown command")
+
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        let (name, padding) = data.split_at(len);
+        if name.is_empty()
+            || !name.iter().all(u8::is_ascii_graphic)
+            || padding.iter().any(|&b| b != 0)
+        {
+            return Err("Malformed command");
+        }
+        Ok(Command::Unknown(*data))
     }
 }
 
@@ -207,36 +539,48 @@ pub struct MessageHeader {
     pub checksum: u32,
 }
 
+impl MessageHeader {
+    /// Length of the encoded header in number of bytes
+    pub const LENGTH: usize = 24;
+}
+
+impl Display for Pretty<'_, MessageHeader> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_field(f, "Network", format!("{:?}", self.0.network))?;
+        write_field(f, "Command", format!("{:?}", self.0.command))?;
+        write_field(f, "Length", self.0.length)?;
+        write_field(f, "Checksum", format!("{:#010x}", self.0.checksum))
+    }
+}
+
 impl Codec for MessageHeader {
-    fn encode(&self) -> Vec<u8> {
-        let mut data = Vec::<u8>::new();
+    fn encoded_len(&self) -> usize {
+        Self::LENGTH
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
         data.extend_from_slice(&(self.network as u32).to_le_bytes());
-        data.extend_from_slice(self.command.to_bytes());
+        data.extend_from_slice(&self.command.to_bytes());
         data.extend_from_slice(&self.length.to_le_bytes());
         data.extend_from_slice(&self.checksum.to_le_bytes());
-        data
     }
 
     fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
-        let network = Network::try_from(
-            data.read_le::<u32>()
-                .ok_or(CodecError::InsufficientBytesError)?,
-        )
-        .map_err(|_| CodecError::InvalidBytesError)?;
-
-        let command = Command::try_from(
-            &data
+        let mut fields = Fields::new(data);
+        let network = fields.decode("network", |data| {
+            let magic = data
+                .read_le::<u32>()
+                .ok_or(CodecError::InsufficientBytesError)?;
+            Network::try_from(magic).map_err(|_| CodecError::InvalidBytesError)
+        })?;
+        let command = fields.decode("command", |data| {
+            let command = data
                 .read_fixed::<{ Command::REQUIRED_LENGTH }>()
-                .ok_or(CodecError::InsufficientBytesError)?,
-        )
-        .map_err(|_| CodecError::InvalidBytesError)?;
-
-        let length = data
-            .read_le::<u32>()
-            .ok_or(CodecError::InsufficientBytesError)?;
-        let checksum = data
-            .read_le::<u32>()
-            .ok_or(CodecError::InsufficientBytesError)?;
+                .ok_or(CodecError::InsufficientBytesError)?;
+            Command::try_from(&command).map_err(|_| CodecError::InvalidBytesError)
+        })?;
+        let length = fields.read_le::<u32>("length")?;
+        let checksum = fields.read_le::<u32>("checksum")?;
 
         Ok(Self {
             network,
@@ -247,34 +591,77 @@ impl Codec for MessageHeader {
     }
 }
 
+/// Calculates the message checksum, i.e. the first 4 bytes of
+/// sha256(sha256(data)).
 pub fn calculate_checksum(data: &[u8]) -> u32 {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    let mut result = hasher.finalize_reset();
-
-    hasher.update(result);
-    result = hasher.finalize();
+    let hash = Sha256::digest(Sha256::digest(data));
+    u32::from_le_bytes(hash[..std::mem::size_of::<u32>()].try_into().unwrap())
+}
 
-    u32::from_le_bytes(result[..std::mem::size_of::<u32>()].try_into().unwrap())
+pub fn compose(chain: impl Chain, command: Command, payload: impl Codec) -> Vec<u8> {
+    let length = chain.header_layout().length + payload.encoded_len();
+    let mut data = Vec::with_capacity(length);
+    compose_into(chain, command, payload, &mut data);
+    data
 }
 
-pub fn compose(network: Network, command: Command, payload: impl Codec) -> Vec<u8> {
-    let payload_data = payload.encode();
-    let header = MessageHeader {
-        network,
+/// Composes the message and appends it to the buffer, growing it
+/// at most once.
+pub fn compose_into(
+    chain: impl Chain,
+    command: Command,
+    payload: impl Codec,
+    data: &mut Vec<u8>,
+) {
+    frame_into(
+        chain,
         command,
-        length: payload_data.len() as u32,
-        checksum: calculate_checksum(&payload_data[..]),
-    }
-    .encode();
+        payload.encoded_len(),
+        |data| payload.encode_into(data),
+        data,
+    );
+}
 
-    let mut data = Vec::<u8>::new();
-    data.extend(header);
-    data.extend(payload_data);
+/// Composes the message out of any payload the codec understands
+pub fn compose_message(chain: impl Chain, message: &Message) -> Vec<u8> {
+    let length = chain.header_layout().length + message.encoded_len();
+    let mut data = Vec::with_capacity(length);
+    frame_into(
+        chain,
+        message.command(),
+        message.encoded_len(),
+        |data| message.encode_into(data),
+        &mut data,
+    );
     data
 }
 
+fn frame_into(
+    chain: impl Chain,
+    command: Command,
+    payload_len: usize,
+    encode_payload: impl FnOnce(&mut Vec<u8>),
+    data: &mut Vec<u8>,
+) {
+    let start = data.len();
+    let header_length = chain.header_layout().length;
+    data.reserve(header_length + payload_len);
+
+    // Header depends on the encoded payload, so encode the payload first
+    // and move the header in front of it afterwards
+    encode_payload(data);
+    let header = FrameHeader {
+        magic: chain.magic(),
+        command,
+        length: (data.len() - start) as u32,
+        checksum: chain.checksum(&data[start..]),
+    };
+    chain.encode_header(&header, data);
+    data[start..].rotate_right(header_length);
+}
+
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
@@ -302,6 +689,7 @@ mod tests {
             length: 100,
             checksum: 0x32498d35,
         };
+        assert_eq!(header.encoded_len(), RAW_HEADER.len());
         assert_eq!(header.encode(), RAW_HEADER);
     }
 
@@ -318,6 +706,157 @@ mod tests {
         assert_eq!(header.checksum, 0x32498d35);
     }
 
+    #[test]
+    fn command_names() {
+        for command in Command::iter() {
+            let name = command.name().as_bytes();
+            assert_eq!(&command.to_bytes()[..name.len()], name);
+            assert!(command.to_bytes()[name.len()..].iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn unknown_command() {
+        let command = Command::try_from(b"alert\0\0\0\0\0\0\0").unwrap();
+        assert_eq!(command, Command::Unknown(*b"alert\0\0\0\0\0\0\0"));
+        assert_eq!(command.name(), "alert");
+        assert_eq!(&command.to_bytes(), b"alert\0\0\0\0\0\0\0");
+
+        assert!(Command::try_from(b"fee\0filter\0\0").is_err());
+        assert!(Command::try_from(b"fee filter\0\0").is_err());
+        assert!(Command::try_from(&[0; 12]).is_err());
+        assert!(Command::try_from(&[0xff; 12]).is_err());
+
+        let mut data = RAW_HEADER.to_vec();
+        data[4..16].copy_from_slice(b"xversion\0\0\0\0");
+        let header = MessageHeader::decode(&mut &data[..]).unwrap();
+        assert_eq!(header.command.name(), "xversion");
+    }
+
+    #[test]
+    fn default_ports() {
+        let mut ports: Vec<u16> = Network::iter().map(Network::default_port).collect();
+        ports.sort();
+        ports.dedup();
+        assert_eq!(ports.len(), Network::iter().count());
+
+        assert_eq!(Network::from_default_port(38333), Some(Network::Signet));
+        assert_eq!(Network::from_default_port(8080), None);
+    }
+
+    #[test]
+    fn compose_message() {
+        let data = compose(Network::Main, Command::Ping, PingMessage::new(15));
+        assert_eq!(data.len(), MessageHeader::LENGTH + 8);
+
+        let mut payload = &data[..];
+        let header = MessageHeader::decode(&mut payload).unwrap();
+        assert_eq!(header.network, Network::Main);
+        assert_eq!(header.command, Command::Ping);
+        assert_eq!(header.length, 8);
+        assert_eq!(header.checksum, calculate_checksum(payload));
+
+        let message = Message::Ping(PingMessage::new(15));
+        assert_eq!(super::compose_message(Network::Main, &message), data);
+    }
+
+    /// Checks the payload reports its encoded length exactly, so the
+    /// buffer `compose_into` is given never has to grow
+    fn assert_presized(command: Command, payload: impl Codec) {
+        assert_eq!(payload.encoded_len(), payload.encode().len(), "{:?}", command);
+
+        let mut data = Vec::with_capacity(MessageHeader::LENGTH + payload.encoded_len());
+        let capacity = data.capacity();
+        compose_into(Network::Main, command, payload, &mut data);
+        assert_eq!(data.capacity(), capacity, "{:?}", command);
+        assert_eq!(data.len(), capacity, "{:?}", command);
+    }
+
+    #[test]
+    fn encoded_lengths() {
+        use std::net::{
+            Ipv4Addr,
+            SocketAddr,
+        };
+
+        let address = SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 8333));
+        let config = crate::p2p::NodeConfig::default();
+        assert_presized(Command::Version, VersionMessage::new(address, &config));
+        assert_presized(Command::Verack, VerackMessage {});
+        assert_presized(Command::Ping, PingMessage::new(15));
+        assert_presized(Command::Pong, PongMessage::new(15));
+        assert_presized(Command::SendTxRcncl, SendTxRcnclMessage::new(1, 15));
+        assert_presized(Command::WtxidRelay, WtxidRelayMessage {});
+
+        #[cfg(feature = "addr")]
+        {
+            let services = Services::new(&[Service::Network]);
+            let addresses = vec![
+                TimestampedAddress {
+                    time: 1_700_000_000,
+                    address: NetworkAddress::new(services, address),
+                };
+                3
+            ];
+            assert_presized(Command::GetAddr, GetAddrMessage {});
+            assert_presized(Command::Addr, AddrMessage { addresses });
+            assert_presized(Command::SendAddrV2, SendAddrV2Message {});
+
+            let addresses = vec![
+                TimestampedAddrV2 {
+                    time: 1_700_000_000,
+                    services,
+                    address: AddrV2::Ipv4(Ipv4Addr::new(10, 0, 0, 1)),
+                    port: 8333,
+                },
+                TimestampedAddrV2 {
+                    time: 1_700_000_000,
+                    services,
+                    address: AddrV2::TorV3([0xab; 32]),
+                    port: 9050,
+                },
+                TimestampedAddrV2 {
+                    time: 1_700_000_000,
+                    services,
+                    address: AddrV2::Unknown(0x42, vec![0xcd; 300]),
+                    port: 1,
+                },
+            ];
+            assert_presized(Command::AddrV2, AddrV2Message { addresses });
+        }
+
+        #[cfg(feature = "blocks")]
+        {
+            let header = BlockHeader {
+                version: 0x2000_0000,
+                prev_blockhash: [0x11; 32],
+                merkle_root: [0x22; 32],
+                time: 1_700_000_000,
+                bits: 0x1703_4219,
+                nonce: 15,
+            };
+            let inventory = Inventory {
+                kind: InvType::WitnessBlock,
+                hash: [0x33; 32],
+            };
+            assert_presized(Command::SendHeaders, SendHeadersMessage {});
+            assert_presized(Command::SendCmpct, SendCmpctMessage::new(true, 2));
+            assert_presized(Command::FeeFilter, FeeFilterMessage::new(1000));
+            assert_presized(
+                Command::Inv,
+                InvMessage {
+                    inventory: vec![inventory; 300],
+                },
+            );
+            assert_presized(
+                Command::Headers,
+                HeadersMessage {
+                    headers: vec![header; 300],
+                },
+            );
+        }
+    }
+
     #[test]
     fn checksum() {
         let checksum = calculate_checksum(&[]);
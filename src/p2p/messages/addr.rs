@@ -0,0 +1,157 @@
+use super::{
+    Codec,
+    CodecError,
+    CompactSize,
+    Fields,
+    NetworkAddress,
+};
+
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+/// Largest number of addresses a single Addr message may carry
+pub const MAX_ADDR_COUNT: usize = 1000;
+
+/// Address of a node together with the time it was last seen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimestampedAddress {
+    /// UNIX timestamp the node was last seen at
+    pub time: u32,
+    pub address: NetworkAddress,
+}
+
+impl Display for TimestampedAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address)
+    }
+}
+
+impl Codec for TimestampedAddress {
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u32>() + self.address.encoded_len()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.time.to_le_bytes());
+        self.address.encode_into(data);
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let time = fields.read_le::<u32>("time")?;
+        let address = fields.decode("address", NetworkAddress::decode)?;
+        Ok(Self { time, address })
+    }
+}
+
+/// Addr message announces addresses of other nodes, either unsolicited
+/// or in response to a GetAddr message.
+#[derive(Debug, PartialEq)]
+pub struct AddrMessage {
+    pub addresses: Vec<TimestampedAddress>,
+}
+
+impl Codec for AddrMessage {
+    fn encoded_len(&self) -> usize {
+        CompactSize(self.addresses.len() as u64).encoded_len()
+            + self
+                .addresses
+                .iter()
+                .map(|a| a.encoded_len())
+                .sum::<usize>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        CompactSize(self.addresses.len() as u64).encode_into(data);
+        for address in &self.addresses {
+            address.encode_into(data);
+        }
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let count = fields.decode("count", |data| {
+            let CompactSize(count) = CompactSize::decode(data)?;
+            if count > MAX_ADDR_COUNT as u64 {
+                return Err(CodecError::OversizedFieldError);
+            }
+            Ok(count)
+        })?;
+
+        let addresses = (0..count)
+            .map(|_| fields.decode("addresses", TimestampedAddress::decode))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { addresses })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{
+        Ipv4Addr,
+        SocketAddr,
+    };
+
+    use crate::p2p::messages::{
+        Service,
+        Services,
+    };
+
+    #[rustfmt::skip]
+    mod unformatted {
+        pub const RAW_ADDR_MSG: &[u8] = &[
+            // Count
+            0x01,
+            // Time
+            0x00, 0xf1, 0x53, 0x65,
+            // Services
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // IP address
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x0a,
+            0x00, 0x00, 0x01,
+            // Port
+            0x20, 0x8d,
+        ];
+    }
+
+    use unformatted::*;
+
+    fn msg() -> AddrMessage {
+        AddrMessage {
+            addresses: vec![TimestampedAddress {
+                time: 1_700_000_000,
+                address: NetworkAddress::new(
+                    Services::new(&[Service::Network]),
+                    SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 8333)),
+                ),
+            }],
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let msg = msg();
+        assert_eq!(msg.encoded_len(), RAW_ADDR_MSG.len());
+        assert_eq!(msg.encode(), RAW_ADDR_MSG);
+    }
+
+    #[test]
+    fn decode() {
+        let mut data: &[u8] = RAW_ADDR_MSG;
+        assert_eq!(AddrMessage::decode(&mut data), Ok(msg()));
+        assert!(data.is_empty());
+        assert_eq!(msg().addresses[0].to_string(), "10.0.0.1:8333 [Network]");
+    }
+
+    #[test]
+    fn decode_too_many() {
+        let mut data: &[u8] = &[0xfd, 0xe9, 0x03];
+        let e = AddrMessage::decode(&mut data).unwrap_err();
+        assert_eq!(e.kind(), &CodecError::OversizedFieldError);
+        assert_eq!(e.field().as_deref(), Some("count"));
+    }
+}
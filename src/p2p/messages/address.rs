@@ -1,12 +1,18 @@
 use super::{
     Codec,
     CodecError,
-    ReadBytes,
+    Fields,
 };
 
-use std::net::{
-    IpAddr,
-    SocketAddr,
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    net::{
+        IpAddr,
+        SocketAddr,
+    },
 };
 
 use crate::p2p::messages::Services;
@@ -48,30 +54,40 @@ impl NetworkAddress {
     }
 
     /// Gets socket address from the bytes received
-    #[allow(dead_code)]
     pub fn address(&self) -> SocketAddr {
         SocketAddr::new(IpAddr::from(self.address), self.port)
     }
 }
 
+impl Display for NetworkAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let address = self.address();
+        let ip = address.ip().to_canonical();
+        write!(
+            f,
+            "{} {}",
+            SocketAddr::new(ip, address.port()),
+            self.services
+        )
+    }
+}
+
 impl Codec for NetworkAddress {
-    fn encode(&self) -> Vec<u8> {
-        let mut data = Vec::<u8>::new();
-        let services_data = self.services.encode();
-        data.extend(services_data);
+    fn encoded_len(&self) -> usize {
+        self.services.encoded_len() + IP_ADDRESS_LENGTH + std::mem::size_of::<u16>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        self.services.encode_into(data);
         data.extend_from_slice(&self.address);
         data.extend_from_slice(&self.port.to_be_bytes());
-        data
     }
 
     fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
-        let services = Services::decode(data)?;
-        let address = data
-            .read_fixed::<IP_ADDRESS_LENGTH>()
-            .ok_or(CodecError::InsufficientBytesError)?;
-        let port = data
-            .read_be::<u16>()
-            .ok_or(CodecError::InsufficientBytesError)?;
+        let mut fields = Fields::new(data);
+        let services = fields.decode("services", Services::decode)?;
+        let address = fields.read_fixed::<IP_ADDRESS_LENGTH>("address")?;
+        let port = fields.read_be::<u16>("port")?;
         Ok(Self {
             services,
             address,
@@ -81,6 +97,7 @@ impl Codec for NetworkAddress {
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
     use std::net::Ipv4Addr;
@@ -108,6 +125,7 @@ mod tests {
         let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 8333);
 
         let net_address = NetworkAddress::new(services, socket);
+        assert_eq!(net_address.encoded_len(), RAW_NET_ADDRESS.len());
         assert_eq!(net_address.encode(), RAW_NET_ADDRESS);
     }
 
@@ -132,12 +150,24 @@ mod tests {
         assert!(data.is_empty());
     }
 
+    #[test]
+    fn display() {
+        let services = Services::new(&[Service::Network, Service::Bloom]);
+        let socket = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 8333);
+
+        let net_address = NetworkAddress::new(services, socket);
+        assert_eq!(net_address.to_string(), "10.0.0.1:8333 [Network, Bloom]");
+    }
+
     #[test]
     fn decode_insufficient_bytes() {
         let mut data: &[u8] = &[0x0f, 0x00, 0x00];
         let result = NetworkAddress::decode(&mut data);
 
-        assert_eq!(result, Err(CodecError::InsufficientBytesError));
+        assert_eq!(
+            result.map_err(|e| e.field()),
+            Err(Some("services".to_string()))
+        );
         assert!(!data.is_empty());
     }
 }
@@ -0,0 +1,568 @@
+use super::{
+    Codec,
+    CodecError,
+    CompactSize,
+    Fields,
+    ReadBytes,
+    Services,
+    TimestampedAddress,
+    MAX_ADDR_COUNT,
+};
+
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    net::{
+        IpAddr,
+        Ipv4Addr,
+        Ipv6Addr,
+    },
+};
+
+/// Largest address accepted, matching Bitcoin Core's limit
+const MAX_ADDRV2_SIZE: u64 = 512;
+
+/// Version byte of Tor v3 onion addresses
+const TORV3_VERSION: u8 = 0x03;
+
+/// Address of a node in any of the networks BIP155 defines.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AddrV2 {
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    /// Deprecated Tor v2 onion service, i.e. its 10 byte identifier
+    TorV2([u8; 10]),
+    /// Tor v3 onion service, i.e. its ed25519 public key
+    TorV3([u8; 32]),
+    /// I2P destination, i.e. the SHA-256 hash of it
+    I2p([u8; 32]),
+    Cjdns(Ipv6Addr),
+    /// Address of a network unknown to the codec, with its network ID
+    Unknown(u8, Vec<u8>),
+}
+
+impl AddrV2 {
+    /// Gets the ID identifying the network of the address on the wire
+    pub fn network_id(&self) -> u8 {
+        match self {
+            AddrV2::Ipv4(_) => 0x01,
+            AddrV2::Ipv6(_) => 0x02,
+            AddrV2::TorV2(_) => 0x03,
+            AddrV2::TorV3(_) => 0x04,
+            AddrV2::I2p(_) => 0x05,
+            AddrV2::Cjdns(_) => 0x06,
+            AddrV2::Unknown(id, _) => *id,
+        }
+    }
+
+    /// Parses the Tor v3 onion address, e.g. `<56 characters>.onion`,
+    /// None if it is not one or its checksum does not match
+    pub fn from_onion(host: &str) -> Option<Self> {
+        let encoded = host.strip_suffix(".onion")?;
+        let decoded = base32_decode(encoded)?;
+        let (key, rest) = decoded.split_first_chunk::<32>()?;
+        match rest {
+            [checksum @ .., TORV3_VERSION] if checksum == onion_checksum(key) => {
+                Some(AddrV2::TorV3(*key))
+            }
+            _ => None,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            AddrV2::Ipv4(ip) => ip.octets().to_vec(),
+            AddrV2::Ipv6(ip) | AddrV2::Cjdns(ip) => ip.octets().to_vec(),
+            AddrV2::TorV2(id) => id.to_vec(),
+            AddrV2::TorV3(key) | AddrV2::I2p(key) => key.to_vec(),
+            AddrV2::Unknown(_, bytes) => bytes.clone(),
+        }
+    }
+}
+
+impl From<IpAddr> for AddrV2 {
+    fn from(ip: IpAddr) -> Self {
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => AddrV2::Ipv4(ip),
+            IpAddr::V6(ip) => AddrV2::Ipv6(ip),
+        }
+    }
+}
+
+impl Display for AddrV2 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddrV2::Ipv4(ip) => write!(f, "{}", ip),
+            AddrV2::Ipv6(ip) | AddrV2::Cjdns(ip) => write!(f, "[{}]", ip),
+            AddrV2::TorV2(id) => write!(f, "{}.onion", base32(id)),
+            AddrV2::TorV3(key) => {
+                // Onion address consists of the public key, the checksum
+                // of it and the version
+                let mut address = key.to_vec();
+                address.extend_from_slice(&onion_checksum(key));
+                address.push(TORV3_VERSION);
+                write!(f, "{}.onion", base32(&address))
+            }
+            AddrV2::I2p(hash) => write!(f, "{}.b32.i2p", base32(hash)),
+            AddrV2::Unknown(id, bytes) => {
+                write!(f, "[network {:#04x}, {} bytes]", id, bytes.len())
+            }
+        }
+    }
+}
+
+impl Codec for AddrV2 {
+    fn encoded_len(&self) -> usize {
+        let len = self.bytes().len();
+        std::mem::size_of::<u8>() + CompactSize(len as u64).encoded_len() + len
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        let bytes = self.bytes();
+        data.push(self.network_id());
+        CompactSize(bytes.len() as u64).encode_into(data);
+        data.extend_from_slice(&bytes);
+    }
+
+    /// Decodes the address, failing on ones whose length does not match
+    /// the length of addresses of their network
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let id = fields.read_le::<u8>("network_id")?;
+        let len = fields.decode("length", |data| {
+            let CompactSize(len) = CompactSize::decode(data)?;
+            if len > MAX_ADDRV2_SIZE {
+                return Err(CodecError::OversizedFieldError);
+            }
+            Ok(len)
+        })?;
+        fields.decode("bytes", |data| {
+            let bytes = data
+                .read_slice(len as usize)
+                .ok_or(CodecError::InsufficientBytesError)?;
+            Ok(match id {
+                0x01 => AddrV2::Ipv4(Ipv4Addr::from(fixed::<4>(bytes)?)),
+                0x02 => AddrV2::Ipv6(Ipv6Addr::from(fixed::<16>(bytes)?)),
+                0x03 => AddrV2::TorV2(fixed(bytes)?),
+                0x04 => AddrV2::TorV3(fixed(bytes)?),
+                0x05 => AddrV2::I2p(fixed(bytes)?),
+                0x06 => AddrV2::Cjdns(Ipv6Addr::from(fixed::<16>(bytes)?)),
+                _ => AddrV2::Unknown(id, bytes.to_vec()),
+            })
+        })
+    }
+}
+
+/// Address of a node in the BIP155 format together with the time it was
+/// last seen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimestampedAddrV2 {
+    /// UNIX timestamp the node was last seen at
+    pub time: u32,
+    /// Features the node supports
+    pub services: Services,
+    pub address: AddrV2,
+    pub port: u16,
+}
+
+impl From<TimestampedAddress> for TimestampedAddrV2 {
+    fn from(address: TimestampedAddress) -> Self {
+        let socket = address.address.address();
+        Self {
+            time: address.time,
+            services: address.address.services,
+            address: AddrV2::from(socket.ip()),
+            port: socket.port(),
+        }
+    }
+}
+
+impl Display for TimestampedAddrV2 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} {}", self.address, self.port, self.services)
+    }
+}
+
+impl Codec for TimestampedAddrV2 {
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u32>()
+            + CompactSize(self.services.into()).encoded_len()
+            + self.address.encoded_len()
+            + std::mem::size_of::<u16>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.time.to_le_bytes());
+        CompactSize(self.services.into()).encode_into(data);
+        self.address.encode_into(data);
+        data.extend_from_slice(&self.port.to_be_bytes());
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let time = fields.read_le::<u32>("time")?;
+        let CompactSize(services) = fields.decode("services", CompactSize::decode)?;
+        let address = fields.decode("address", AddrV2::decode)?;
+        let port = fields.read_be::<u16>("port")?;
+        Ok(Self {
+            time,
+            services: services.into(),
+            address,
+            port,
+        })
+    }
+}
+
+/// AddrV2 message (BIP155) announces addresses of other nodes like the
+/// Addr message does, including ones of networks other than IPv4 and IPv6.
+#[derive(Debug, PartialEq)]
+pub struct AddrV2Message {
+    pub addresses: Vec<TimestampedAddrV2>,
+}
+
+impl Codec for AddrV2Message {
+    fn encoded_len(&self) -> usize {
+        CompactSize(self.addresses.len() as u64).encoded_len()
+            + self
+                .addresses
+                .iter()
+                .map(|a| a.encoded_len())
+                .sum::<usize>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        CompactSize(self.addresses.len() as u64).encode_into(data);
+        for address in &self.addresses {
+            address.encode_into(data);
+        }
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let count = fields.decode("count", |data| {
+            let CompactSize(count) = CompactSize::decode(data)?;
+            if count > MAX_ADDR_COUNT as u64 {
+                return Err(CodecError::OversizedFieldError);
+            }
+            Ok(count)
+        })?;
+
+        let addresses = (0..count)
+            .map(|_| fields.decode("addresses", TimestampedAddrV2::decode))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { addresses })
+    }
+}
+
+/// Converts the address bytes into an array, failing if their length does
+/// not match the length of addresses of the network
+fn fixed<const N: usize>(bytes: &[u8]) -> Result<[u8; N], CodecError> {
+    bytes.try_into().map_err(|_| CodecError::InvalidBytesError)
+}
+
+/// Encodes the data in lowercase base32 without padding, as used by Tor
+/// and I2P addresses
+fn base32(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+    let mut encoded = String::with_capacity((data.len() * 8).div_ceil(5));
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | u32::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[(buffer >> bits) as usize & 0x1f] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[(buffer << (5 - bits)) as usize & 0x1f] as char);
+    }
+    encoded
+}
+
+/// Decodes the lowercase base32 data without padding, None if it holds
+/// other characters or leftover bits
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+    for c in encoded.bytes() {
+        let value = match c {
+            b'a'..=b'z' => c - b'a',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | u32::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    (buffer & ((1 << bits) - 1) == 0).then_some(decoded)
+}
+
+/// Calculates the two byte checksum of the Tor v3 onion service key
+fn onion_checksum(key: &[u8; 32]) -> [u8; 2] {
+    let mut data = b".onion checksum".to_vec();
+    data.extend_from_slice(key);
+    data.push(TORV3_VERSION);
+    let hash = sha3_256(&data);
+    [hash[0], hash[1]]
+}
+
+/// Calculates SHA3-256 of the data, needed only for checksums of onion
+/// addresses
+fn sha3_256(data: &[u8]) -> [u8; 32] {
+    const RATE: usize = 136;
+    const ROUND_CONSTANTS: [u64; 24] = [
+        0x0000000000000001,
+        0x0000000000008082,
+        0x800000000000808a,
+        0x8000000080008000,
+        0x000000000000808b,
+        0x0000000080000001,
+        0x8000000080008081,
+        0x8000000000008009,
+        0x000000000000008a,
+        0x0000000000000088,
+        0x0000000080008009,
+        0x000000008000000a,
+        0x000000008000808b,
+        0x800000000000008b,
+        0x8000000000008089,
+        0x8000000000008003,
+        0x8000000000008002,
+        0x8000000000000080,
+        0x000000000000800a,
+        0x800000008000000a,
+        0x8000000080008081,
+        0x8000000000008080,
+        0x0000000080000001,
+        0x8000000080008008,
+    ];
+    const ROTATIONS: [u32; 24] = [
+        1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39,
+        61, 20, 44,
+    ];
+    const LANES: [usize; 24] = [
+        10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9,
+        6, 1,
+    ];
+
+    let keccak = |state: &mut [u64; 25]| {
+        for round_constant in ROUND_CONSTANTS {
+            // Theta
+            let mut columns = [0_u64; 5];
+            for (i, column) in columns.iter_mut().enumerate() {
+                *column = (0..25).step_by(5).fold(0, |c, j| c ^ state[i + j]);
+            }
+            for i in 0..5 {
+                let t = columns[(i + 4) % 5] ^ columns[(i + 1) % 5].rotate_left(1);
+                for j in (0..25).step_by(5) {
+                    state[i + j] ^= t;
+                }
+            }
+            // Rho and pi
+            let mut t = state[1];
+            for (&lane, &rotation) in LANES.iter().zip(ROTATIONS.iter()) {
+                let next = state[lane];
+                state[lane] = t.rotate_left(rotation);
+                t = next;
+            }
+            // Chi
+            for j in (0..25).step_by(5) {
+                let row: [u64; 5] = state[j..j + 5].try_into().unwrap();
+                for i in 0..5 {
+                    state[j + i] = row[i] ^ (!row[(i + 1) % 5] & row[(i + 2) % 5]);
+                }
+            }
+            // Iota
+            state[0] ^= round_constant;
+        }
+    };
+
+    let mut padded = data.to_vec();
+    padded.push(0x06);
+    padded.resize(padded.len().div_ceil(RATE) * RATE, 0);
+    *padded.last_mut().unwrap() |= 0x80;
+
+    let mut state = [0_u64; 25];
+    for block in padded.chunks(RATE) {
+        for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+            *lane ^= u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        keccak(&mut state);
+    }
+
+    let mut hash = [0; 32];
+    for (bytes, lane) in hash.chunks_mut(8).zip(state.iter()) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::SocketAddr;
+
+    use crate::p2p::messages::{
+        NetworkAddress,
+        Service,
+    };
+
+    #[rustfmt::skip]
+    mod unformatted {
+        pub const RAW_ADDRV2_MSG: &[u8] = &[
+            // Count
+            0x02,
+            // Time
+            0x00, 0xf1, 0x53, 0x65,
+            // Services
+            0x09,
+            // Network ID
+            0x01,
+            // Address length
+            0x04,
+            // Address
+            0x0a, 0x00, 0x00, 0x01,
+            // Port
+            0x20, 0x8d,
+            // Time
+            0x00, 0xf1, 0x53, 0x65,
+            // Services
+            0x01,
+            // Network ID
+            0x04,
+            // Address length
+            0x20,
+            // Address
+            0x1d, 0x04, 0xa1, 0xd0, 0x4a, 0x33, 0x8c, 0x6e,
+            0x6a, 0xe9, 0x70, 0xbf, 0xab, 0xee, 0x49, 0x04,
+            0x9d, 0x67, 0x02, 0x25, 0x09, 0x84, 0xca, 0x95,
+            0x0c, 0x01, 0x67, 0x3f, 0x4e, 0xc0, 0x34, 0xad,
+            // Port
+            0x20, 0x8d,
+        ];
+    }
+
+    use unformatted::*;
+
+    fn addrv2_message() -> AddrV2Message {
+        AddrV2Message {
+            addresses: vec![
+                TimestampedAddrV2 {
+                    time: 1_700_000_000,
+                    services: Services::new(&[Service::Network, Service::Witness]),
+                    address: AddrV2::Ipv4(Ipv4Addr::new(10, 0, 0, 1)),
+                    port: 8333,
+                },
+                TimestampedAddrV2 {
+                    time: 1_700_000_000,
+                    services: Services::new(&[Service::Network]),
+                    address: AddrV2::TorV3(RAW_ADDRV2_MSG[21..53].try_into().unwrap()),
+                    port: 8333,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let msg = addrv2_message();
+        assert_eq!(msg.encoded_len(), RAW_ADDRV2_MSG.len());
+        assert_eq!(msg.encode(), RAW_ADDRV2_MSG);
+    }
+
+    #[test]
+    fn decode() {
+        let mut data: &[u8] = RAW_ADDRV2_MSG;
+        assert_eq!(AddrV2Message::decode(&mut data), Ok(addrv2_message()));
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn decode_malformed() {
+        // Address length not matching the network
+        let mut raw = RAW_ADDRV2_MSG.to_vec();
+        raw[20] = 0x1f;
+        let e = AddrV2Message::decode(&mut &raw[..]).unwrap_err();
+        assert_eq!(e.kind(), &CodecError::InvalidBytesError);
+        assert_eq!(e.field().as_deref(), Some("addresses.address.bytes"));
+        assert_eq!(e.offset(), Some(21));
+
+        // Address longer than allowed
+        let raw = [0x00, 0x00, 0x00, 0x00, 0x01, 0x07, 0xfd, 0x01, 0x02];
+        let e = TimestampedAddrV2::decode(&mut &raw[..]).unwrap_err();
+        assert_eq!(e.kind(), &CodecError::OversizedFieldError);
+        assert_eq!(e.field().as_deref(), Some("address.length"));
+        assert_eq!(e.offset(), Some(6));
+
+        // Unknown network
+        let raw = [0x07, 0x02, 0xab, 0xcd];
+        assert_eq!(
+            AddrV2::decode(&mut &raw[..]),
+            Ok(AddrV2::Unknown(0x07, vec![0xab, 0xcd]))
+        );
+    }
+
+    #[test]
+    fn display() {
+        let msg = addrv2_message();
+        assert_eq!(
+            msg.addresses[0].to_string(),
+            "10.0.0.1:8333 [Network, Witness]"
+        );
+        assert_eq!(
+            msg.addresses[1].address.to_string(),
+            "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion"
+        );
+        assert_eq!(
+            AddrV2::I2p(std::array::from_fn(|i| i as u8)).to_string(),
+            "aaaqeayeaudaocajbifqydiob4ibceqtcqkrmfyydenbwha5dypq.b32.i2p"
+        );
+        assert_eq!(
+            AddrV2::Cjdns("fc00::1".parse().unwrap()).to_string(),
+            "[fc00::1]"
+        );
+    }
+
+    #[test]
+    fn from_onion() {
+        let onion = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+        let address = AddrV2::from_onion(onion).unwrap();
+        assert_eq!(address, addrv2_message().addresses[1].address);
+        assert_eq!(address.to_string(), onion);
+
+        // Checksum mismatch
+        assert_eq!(
+            AddrV2::from_onion(
+                "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczaa.onion"
+            ),
+            None
+        );
+        assert_eq!(AddrV2::from_onion("duckduckgo.onion"), None);
+        assert_eq!(AddrV2::from_onion("example.com"), None);
+        assert_eq!(AddrV2::from_onion("DUCKDUCKGO.onion"), None);
+    }
+
+    #[test]
+    fn from_addr() {
+        let address = TimestampedAddress {
+            time: 1_700_000_000,
+            address: NetworkAddress::new(
+                Services::new(&[Service::Network]),
+                SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 8333)),
+            ),
+        };
+        let address = TimestampedAddrV2::from(address);
+        assert_eq!(address.address, AddrV2::Ipv4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(address.to_string(), "10.0.0.1:8333 [Network]");
+    }
+}
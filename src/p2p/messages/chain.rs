@@ -0,0 +1,375 @@
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+use super::{
+    calculate_checksum,
+    CodecError,
+    Command,
+    Feature,
+    Fields,
+    MessageHeader,
+    Network,
+    ProtocolVersion,
+    ReadBytes,
+};
+use crate::p2p::report::{
+    write_field,
+    Pretty,
+};
+
+/// Layout of the message header, i.e. all there is to know to split the
+/// received bytes into messages before any header is decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HeaderLayout {
+    /// Length of the whole header in number of bytes
+    pub length: usize,
+    /// Offset of the payload length, a little-endian u32, in the header
+    pub length_offset: usize,
+}
+
+impl HeaderLayout {
+    /// Bitcoin's header, i.e. magic bytes, command, payload length and
+    /// checksum
+    pub const BITCOIN: Self = Self {
+        length: MessageHeader::LENGTH,
+        length_offset: 4 + Command::REQUIRED_LENGTH,
+    };
+}
+
+/// Wire rules of a P2P network derived from Bitcoin's, i.e. how messages
+/// are framed and which of them the other node understands. Networks
+/// tweaking the rules, e.g. using a different checksum or header layout,
+/// implement this to reuse message composition and the handshake.
+///
+/// The v2 transport (BIP324) replaces the header with its own, so it only
+/// carries messages of chains keeping Bitcoin's header and checksum.
+pub trait Chain: Send + Sync {
+    /// Gets the magic bytes starting each message
+    fn magic(&self) -> u32;
+
+    /// Gets the layout of the header, which [`encode_header`] and
+    /// [`decode_header`] have to follow
+    ///
+    /// [`encode_header`]: Chain::encode_header
+    /// [`decode_header`]: Chain::decode_header
+    fn header_layout(&self) -> HeaderLayout {
+        HeaderLayout::BITCOIN
+    }
+
+    /// Encodes the header, appending it to the buffer
+    fn encode_header(&self, header: &FrameHeader, data: &mut Vec<u8>) {
+        data.extend_from_slice(&header.magic.to_le_bytes());
+        data.extend_from_slice(&self.command_bytes(header.command));
+        data.extend_from_slice(&header.length.to_le_bytes());
+        data.extend_from_slice(&header.checksum.to_le_bytes());
+    }
+
+    /// Decodes the header, failing on commands unknown to the chain
+    fn decode_header(&self, data: &mut &[u8]) -> Result<FrameHeader, CodecError> {
+        let mut fields = Fields::new(data);
+        let magic = fields.read_le::<u32>("magic")?;
+        let command = fields.decode("command", |data| {
+            let command = data
+                .read_fixed::<{ Command::REQUIRED_LENGTH }>()
+                .ok_or(CodecError::InsufficientBytesError)?;
+            self.command(&command).ok_or(CodecError::InvalidBytesError)
+        })?;
+        let length = fields.read_le::<u32>("length")?;
+        let checksum = fields.read_le::<u32>("checksum")?;
+
+        Ok(FrameHeader {
+            magic,
+            command,
+            length,
+            checksum,
+        })
+    }
+
+    /// Calculates the checksum of the payload, i.e. the first 4 bytes of
+    /// sha256(sha256(payload)) by default
+    fn checksum(&self, payload: &[u8]) -> u32 {
+        calculate_checksum(payload)
+    }
+
+    /// Gets the command name as it appears in the header
+    fn command_bytes(&self, command: Command) -> [u8; Command::REQUIRED_LENGTH] {
+        command.to_bytes()
+    }
+
+    /// Gets the command from its name in the header, if well-formed
+    fn command(&self, bytes: &[u8; Command::REQUIRED_LENGTH]) -> Option<Command> {
+        Command::try_from(bytes).ok()
+    }
+
+    /// Gets the lowest protocol version of the other node which supports
+    /// the command
+    fn min_version(&self, command: Command) -> ProtocolVersion {
+        match command {
+            Command::Version | Command::Verack => ProtocolVersion::default(),
+            Command::Ping | Command::Pong => Feature::Pong.min_version(),
+            // BIP330 requires wtxid relay
+            Command::SendTxRcncl | Command::WtxidRelay => {
+                Feature::WtxidRelay.min_version()
+            }
+            #[cfg(feature = "addr")]
+            Command::GetAddr | Command::Addr => Feature::AddrTime.min_version(),
+            #[cfg(feature = "addr")]
+            Command::AddrV2 | Command::SendAddrV2 => Feature::AddrV2.min_version(),
+            #[cfg(feature = "blocks")]
+            Command::SendHeaders => Feature::SendHeaders.min_version(),
+            #[cfg(feature = "blocks")]
+            Command::SendCmpct => Feature::CompactBlocks.min_version(),
+            #[cfg(feature = "blocks")]
+            Command::FeeFilter => Feature::FeeFilter.min_version(),
+            #[cfg(feature = "blocks")]
+            Command::Inv | Command::Headers => ProtocolVersion::default(),
+            Command::Unknown(_) => ProtocolVersion::default(),
+        }
+    }
+}
+
+impl Chain for Network {
+    fn magic(&self) -> u32 {
+        *self as u32
+    }
+}
+
+impl<C: Chain + ?Sized> Chain for &C {
+    fn magic(&self) -> u32 {
+        (**self).magic()
+    }
+
+    fn header_layout(&self) -> HeaderLayout {
+        (**self).header_layout()
+    }
+
+    fn encode_header(&self, header: &FrameHeader, data: &mut Vec<u8>) {
+        (**self).encode_header(header, data)
+    }
+
+    fn decode_header(&self, data: &mut &[u8]) -> Result<FrameHeader, CodecError> {
+        (**self).decode_header(data)
+    }
+
+    fn checksum(&self, payload: &[u8]) -> u32 {
+        (**self).checksum(payload)
+    }
+
+    fn command_bytes(&self, command: Command) -> [u8; Command::REQUIRED_LENGTH] {
+        (**self).command_bytes(command)
+    }
+
+    fn command(&self, bytes: &[u8; Command::REQUIRED_LENGTH]) -> Option<Command> {
+        (**self).command(bytes)
+    }
+
+    fn min_version(&self, command: Command) -> ProtocolVersion {
+        (**self).min_version(command)
+    }
+}
+
+/// Message header decoded by the rules of any chain. Unlike
+/// [`MessageHeader`](super::MessageHeader), the magic bytes are kept as
+/// they are, so that they can be compared with the expected ones.
+#[derive(Debug, PartialEq)]
+pub struct FrameHeader {
+    /// Magic bytes of the origin network
+    pub magic: u32,
+    /// Identifier of the packet content
+    pub command: Command,
+    /// Payload length in number of bytes
+    pub length: u32,
+    /// Checksum of the payload
+    pub checksum: u32,
+}
+
+impl FrameHeader {
+    /// Decodes the header as the chain lays it out, failing on commands
+    /// unknown to the chain
+    pub fn decode(chain: &impl Chain, data: &mut &[u8]) -> Result<Self, CodecError> {
+        chain.decode_header(data)
+    }
+
+    /// Gets the payload the header frames out of the bytes following it,
+    /// failing unless there are exactly as many of them as announced
+    pub fn payload<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], CodecError> {
+        if data.len() != self.length as usize {
+            return Err(CodecError::LengthMismatchError);
+        }
+        Ok(data)
+    }
+
+    /// Checks whether the checksum matches the payload under the rules of
+    /// the chain
+    pub fn verify(&self, chain: &impl Chain, payload: &[u8]) -> bool {
+        chain.checksum(payload) == self.checksum
+    }
+}
+
+impl Display for Pretty<'_, FrameHeader> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match Network::try_from(self.0.magic) {
+            Ok(network) => write_field(f, "Network", format!("{:?}", network))?,
+            Err(_) => write_field(f, "Magic", format!("{:#010x}", self.0.magic))?,
+        }
+        write_field(f, "Command", format!("{:?}", self.0.command))?;
+        write_field(f, "Length", self.0.length)?;
+        write_field(f, "Checksum", format!("{:#010x}", self.0.checksum))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::p2p::{
+        connection::{
+            decode_frame,
+            ReceiveBuffer,
+            MAX_PAYLOAD_LENGTH,
+        },
+        messages::{
+            compose,
+            Codec,
+            Message,
+            PingMessage,
+            VerackMessage,
+        },
+    };
+
+    /// Chain with its own magic bytes and a plain sum as the checksum
+    struct Toy;
+
+    impl Chain for Toy {
+        fn magic(&self) -> u32 {
+            0x01_02_03_04
+        }
+
+        fn checksum(&self, payload: &[u8]) -> u32 {
+            payload.iter().map(|&b| u32::from(b)).sum()
+        }
+    }
+
+    /// Chain whose header leaves the checksum out
+    struct Unchecked;
+
+    impl Chain for Unchecked {
+        fn magic(&self) -> u32 {
+            0x01_02_03_04
+        }
+
+        fn header_layout(&self) -> HeaderLayout {
+            HeaderLayout {
+                length: 20,
+                length_offset: 16,
+            }
+        }
+
+        fn encode_header(&self, header: &FrameHeader, data: &mut Vec<u8>) {
+            data.extend_from_slice(&header.magic.to_le_bytes());
+            data.extend_from_slice(&self.command_bytes(header.command));
+            data.extend_from_slice(&header.length.to_le_bytes());
+        }
+
+        fn decode_header(&self, data: &mut &[u8]) -> Result<FrameHeader, CodecError> {
+            let mut fields = Fields::new(data);
+            let magic = fields.read_le::<u32>("magic")?;
+            let command = fields.read_fixed::<{ Command::REQUIRED_LENGTH }>("command")?;
+            let length = fields.read_le::<u32>("length")?;
+
+            Ok(FrameHeader {
+                magic,
+                command: self.command(&command).ok_or(CodecError::InvalidBytesError)?,
+                length,
+                checksum: 0,
+            })
+        }
+
+        fn checksum(&self, _payload: &[u8]) -> u32 {
+            0
+        }
+    }
+
+    #[test]
+    fn custom_chain() {
+        let data = compose(&Toy, Command::Ping, PingMessage::new(0x0101));
+        assert_eq!(&data[..4], &[0x04, 0x03, 0x02, 0x01]);
+
+        let mut frame = &data[..];
+        let header = FrameHeader::decode(&Toy, &mut frame).unwrap();
+        assert_eq!(
+            header,
+            FrameHeader {
+                magic: Toy.magic(),
+                command: Command::Ping,
+                length: 8,
+                checksum: 2,
+            }
+        );
+        assert_eq!(frame.len(), 8);
+
+        // Bitcoin header decoding does not know the magic bytes
+        assert_eq!(
+            MessageHeader::decode(&mut &data[..]).err(),
+            Some(CodecError::InvalidBytesError.at("network", 0))
+        );
+    }
+
+    #[test]
+    fn header_layout() {
+        let ping = compose(&Unchecked, Command::Ping, PingMessage::new(15));
+        let verack = compose(&Unchecked, Command::Verack, VerackMessage {});
+        assert_eq!(ping.len(), 20 + 8);
+        assert_eq!(&ping[16..20], &8u32.to_le_bytes());
+
+        // Messages are split where the chain's headers say so
+        let mut buffer = ReceiveBuffer::new(&Unchecked, MAX_PAYLOAD_LENGTH);
+        buffer.data.extend_from_slice(&ping);
+        buffer.data.extend_from_slice(&verack);
+        let frame = buffer.next_frame().unwrap().unwrap();
+        assert_eq!(frame, ping);
+        match decode_frame(&Unchecked, &frame) {
+            Ok(Some(Message::Ping(ping))) => assert_eq!(ping.nonce(), 15),
+            other => panic!("Unexpected message {:?}", other),
+        }
+        assert_eq!(buffer.next_frame(), Ok(Some(verack)));
+        assert_eq!(buffer.next_frame(), Ok(None));
+    }
+
+    #[test]
+    fn network() {
+        let data = compose(Network::Signet, Command::Ping, PingMessage::new(15));
+        let header = FrameHeader::decode(&Network::Signet, &mut &data[..]).unwrap();
+        assert_eq!(header.magic, Network::Signet as u32);
+        assert_eq!(header.checksum, calculate_checksum(&data[24..]));
+        assert!(Pretty(&header).to_string().contains("Signet"));
+
+        let data = compose(&Toy, Command::Ping, PingMessage::new(15));
+        let header = FrameHeader::decode(&Toy, &mut &data[..]).unwrap();
+        assert!(Pretty(&header).to_string().contains("0x01020304"));
+    }
+
+    #[test]
+    fn payload() {
+        let mut data = compose(Network::Main, Command::Ping, PingMessage::new(15));
+        let mut frame = &data[..];
+        let header = FrameHeader::decode(&Network::Main, &mut frame).unwrap();
+        let payload = header.payload(frame).unwrap();
+        assert_eq!(payload, &data[24..]);
+        assert!(header.verify(&Network::Main, payload));
+        assert_eq!(
+            header.payload(&frame[..7]).err(),
+            Some(CodecError::LengthMismatchError)
+        );
+
+        // Trailing bytes, e.g. of the next message, are no part of the payload
+        data.push(0);
+        assert_eq!(
+            header.payload(&data[24..]).err(),
+            Some(CodecError::LengthMismatchError)
+        );
+        assert!(!header.verify(&Network::Main, &data[24..]));
+    }
+}
@@ -0,0 +1,104 @@
+use super::{
+    Codec,
+    CodecError,
+    ReadBytes,
+};
+
+/// Variable length integer prefixing the length of fields and lists, i.e.
+/// a single byte below 0xfd or the marker 0xfd, 0xfe or 0xff followed by
+/// 2, 4 or 8 bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactSize(pub u64);
+
+impl Codec for CompactSize {
+    fn encoded_len(&self) -> usize {
+        match self.0 {
+            0..=0xfc => 1,
+            0xfd..=0xffff => 3,
+            0x1_0000..=0xffff_ffff => 5,
+            _ => 9,
+        }
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        let n = self.0;
+        match n {
+            0..=0xfc => data.push(n as u8),
+            0xfd..=0xffff => {
+                data.push(0xfd);
+                data.extend_from_slice(&(n as u16).to_le_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                data.push(0xfe);
+                data.extend_from_slice(&(n as u32).to_le_bytes());
+            }
+            _ => {
+                data.push(0xff);
+                data.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+    }
+
+    /// Decodes the integer, rejecting ones not encoded in the shortest
+    /// form like Bitcoin Core does
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let available = data.len();
+        let size = CompactSize(
+            data.read_compact_size()
+                .ok_or(CodecError::InsufficientBytesError)?,
+        );
+        if size.encoded_len() != available - data.len() {
+            return Err(CodecError::InvalidBytesError);
+        }
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        for (n, raw) in [
+            (0, &[0x00][..]),
+            (0xfc, &[0xfc]),
+            (0xfd, &[0xfd, 0xfd, 0x00]),
+            (0xffff, &[0xfd, 0xff, 0xff]),
+            (0x1_0000, &[0xfe, 0x00, 0x00, 0x01, 0x00]),
+            (0xffff_ffff, &[0xfe, 0xff, 0xff, 0xff, 0xff]),
+            (
+                0x1_0000_0000,
+                &[0xff, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00],
+            ),
+        ] {
+            let size = CompactSize(n);
+            assert_eq!(size.encode(), raw);
+            assert_eq!(size.encoded_len(), raw.len());
+            assert_eq!(CompactSize::decode(&mut &raw[..]), Ok(size));
+        }
+    }
+
+    #[test]
+    fn decode_malformed() {
+        assert_eq!(
+            CompactSize::decode(&mut &[0xfd, 0x01][..]),
+            Err(CodecError::InsufficientBytesError)
+        );
+        assert_eq!(
+            CompactSize::decode(&mut &[][..]),
+            Err(CodecError::InsufficientBytesError)
+        );
+        // Values fitting into a shorter form
+        assert_eq!(
+            CompactSize::decode(&mut &[0xfd, 0x10, 0x00][..]),
+            Err(CodecError::InvalidBytesError)
+        );
+        assert_eq!(
+            CompactSize::decode(
+                &mut &[0xff, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00][..]
+            ),
+            Err(CodecError::InvalidBytesError)
+        );
+    }
+}
@@ -0,0 +1,495 @@
+#[cfg(feature = "addr")]
+use super::AddrV2;
+use super::{
+    Codec,
+    Command,
+    Network,
+    ReadBytes,
+    Services,
+};
+
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    net::IpAddr,
+};
+
+/// Width of the offset and field name columns of the deepest field
+const NAME_WIDTH: usize = 24;
+
+/// Decoded field of a frame together with its location in the frame.
+#[derive(Debug, PartialEq)]
+pub struct Field {
+    /// Field name
+    pub name: &'static str,
+    /// Offset from the frame start in number of bytes
+    pub offset: usize,
+    /// Length in number of bytes
+    pub len: usize,
+    /// Decoded value
+    pub value: String,
+    /// Fields the field consists of
+    pub children: Vec<Field>,
+}
+
+impl Field {
+    fn write(&self, f: &mut Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(
+            f,
+            "  {:04x}  {}{:<width$}{}",
+            self.offset,
+            "  ".repeat(depth),
+            self.name,
+            self.value,
+            width = NAME_WIDTH - 2 * depth
+        )?;
+        for child in &self.children {
+            child.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hierarchical breakdown of a frame, i.e. the message header
+/// followed by the payload.
+#[derive(Debug, PartialEq)]
+pub struct Dissection(pub Vec<Field>);
+
+impl Display for Dissection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for field in &self.0 {
+            field.write(f, 0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks through a frame, decoding its fields one by one and recording
+/// where each of them is located.
+struct Dissector<'a> {
+    /// Length of the whole frame
+    frame_len: usize,
+    /// Bytes not dissected yet
+    data: &'a [u8],
+    /// Fields dissected so far at the current depth
+    fields: Vec<Field>,
+}
+
+impl<'a> Dissector<'a> {
+    fn offset(&self) -> usize {
+        self.frame_len - self.data.len()
+    }
+
+    /// Decodes a single field, returning its value if there are enough bytes
+    fn field<T>(
+        &mut self,
+        name: &'static str,
+        decode: impl FnOnce(&mut &'a [u8]) -> Option<T>,
+        value: impl FnOnce(&T) -> String,
+    ) -> Option<T> {
+        let offset = self.offset();
+        let decoded = decode(&mut self.data)?;
+        self.fields.push(Field {
+            name,
+            offset,
+            len: self.offset() - offset,
+            value: value(&decoded),
+            children: Vec::new(),
+        });
+        Some(decoded)
+    }
+
+    /// Groups the fields decoded by the closure under a single field
+    fn group(
+        &mut self,
+        name: &'static str,
+        dissect: impl FnOnce(&mut Self) -> Option<()>,
+    ) -> Option<()> {
+        let offset = self.offset();
+        let parent = std::mem::take(&mut self.fields);
+        let result = dissect(self);
+        let children = std::mem::replace(&mut self.fields, parent);
+
+        let len = self.offset() - offset;
+        self.fields.push(Field {
+            name,
+            offset,
+            len,
+            value: format!("{} bytes", len),
+            children,
+        });
+        result
+    }
+
+    /// Records the bytes left undecoded, if any
+    fn rest(&mut self, name: &'static str) {
+        if !self.data.is_empty() {
+            let len = self.data.len();
+            self.field(
+                name,
+                |data| data.read_slice(len).map(<[u8]>::len),
+                |len| format!("{} bytes", len),
+            );
+        }
+    }
+}
+
+/// Breaks the frame down into the header and payload fields. Decoding stops
+/// at the first field which does not fit in the frame, while bytes which
+/// could not be decoded are listed as such.
+pub fn dissect(frame: &[u8]) -> Dissection {
+    let mut dissector = Dissector {
+        frame_len: frame.len(),
+        data: frame,
+        fields: Vec::new(),
+    };
+
+    let mut header = None;
+    dissector.group("Header", |d| {
+        header = Some(dissect_header(d)?);
+        Some(())
+    });
+
+    if let Some((command, length)) = header {
+        let payload_len = (length as usize).min(dissector.data.len());
+        let (payload, trailing) = dissector.data.split_at(payload_len);
+        dissector.data = payload;
+
+        let name = match command {
+            Some(Command::Version) => "Payload (version)",
+            Some(Command::Verack) => "Payload (verack)",
+            Some(Command::Ping) => "Payload (ping)",
+            Some(Command::Pong) => "Payload (pong)",
+            Some(Command::SendTxRcncl) => "Payload (sendtxrcncl)",
+            Some(Command::WtxidRelay) => "Payload (wtxidrelay)",
+            #[cfg(feature = "addr")]
+            Some(Command::GetAddr) => "Payload (getaddr)",
+            #[cfg(feature = "addr")]
+            Some(Command::Addr) => "Payload (addr)",
+            #[cfg(feature = "addr")]
+            Some(Command::AddrV2) => "Payload (addrv2)",
+            #[cfg(feature = "addr")]
+            Some(Command::SendAddrV2) => "Payload (sendaddrv2)",
+            #[cfg(feature = "blocks")]
+            Some(Command::SendHeaders) => "Payload (sendheaders)",
+            #[cfg(feature = "blocks")]
+            Some(Command::SendCmpct) => "Payload (sendcmpct)",
+            #[cfg(feature = "blocks")]
+            Some(Command::FeeFilter) => "Payload (feefilter)",
+            #[cfg(feature = "blocks")]
+            Some(Command::Inv) => "Payload (inv)",
+            #[cfg(feature = "blocks")]
+            Some(Command::Headers) => "Payload (headers)",
+            Some(Command::Unknown(_)) | None => "Payload",
+        };
+        dissector.group(name, |d| {
+            let result = match command {
+                Some(Command::Version) => dissect_version(d),
+                Some(Command::Verack) => Some(()),
+                Some(Command::Ping) | Some(Command::Pong) => d
+                    .field("Nonce", |data| data.read_le::<u64>(), |v| v.to_string())
+                    .map(|_| ()),
+                Some(Command::SendTxRcncl) => dissect_sendtxrcncl(d),
+                Some(Command::WtxidRelay) => Some(()),
+                #[cfg(feature = "addr")]
+                Some(Command::GetAddr) => Some(()),
+                #[cfg(feature = "addr")]
+                Some(Command::Addr) => dissect_addr(d),
+                #[cfg(feature = "addr")]
+                Some(Command::AddrV2) => dissect_addrv2(d),
+                #[cfg(feature = "addr")]
+                Some(Command::SendAddrV2) => Some(()),
+                #[cfg(feature = "blocks")]
+                Some(Command::SendHeaders) => Some(()),
+                #[cfg(feature = "blocks")]
+                Some(Command::SendCmpct) => dissect_sendcmpct(d),
+                #[cfg(feature = "blocks")]
+                Some(Command::FeeFilter) => d
+                    .field(
+                        "Fee rate",
+                        |data| data.read_le::<i64>(),
+                        |v| format!("{} sat/kvB", v),
+                    )
+                    .map(|_| ()),
+                #[cfg(feature = "blocks")]
+                Some(Command::Inv) | Some(Command::Headers) => None,
+                Some(Command::Unknown(_)) | None => None,
+            };
+            d.rest(if result.is_some() {
+                "Trailing bytes"
+            } else {
+                "Undecoded bytes"
+            });
+            Some(())
+        });
+
+        dissector.data = trailing;
+    }
+
+    dissector.rest("Undecoded bytes");
+    Dissection(dissector.fields)
+}
+
+fn dissect_header(d: &mut Dissector) -> Option<(Option<Command>, u32)> {
+    d.field(
+        "Network",
+        |data| data.read_le::<u32>(),
+        |v| match Network::try_from(*v) {
+            Ok(network) => format!("{:?} ({:#010x})", network, v),
+            Err(_) => format!("Unknown ({:#010x})", v),
+        },
+    )?;
+    let command = d.field(
+        "Command",
+        |data| data.read_fixed::<{ Command::REQUIRED_LENGTH }>(),
+        |v| {
+            String::from_utf8_lossy(v)
+                .trim_end_matches('\0')
+                .to_string()
+        },
+    )?;
+    let length = d.field("Length", |data| data.read_le::<u32>(), |v| v.to_string())?;
+    d.field(
+        "Checksum",
+        |data| data.read_le::<u32>(),
+        |v| format!("{:#010x}", v),
+    )?;
+
+    Some((Command::try_from(&command).ok(), length))
+}
+
+fn dissect_network_address(d: &mut Dissector) -> Option<()> {
+    d.field(
+        "Services",
+        |data| Services::decode(data).ok(),
+        |v| v.to_string(),
+    )?;
+    d.field(
+        "IP address",
+        |data| data.read_fixed::<16>(),
+        |v| IpAddr::from(*v).to_canonical().to_string(),
+    )?;
+    d.field("Port", |data| data.read_be::<u16>(), |v| v.to_string())?;
+    Some(())
+}
+
+fn dissect_version(d: &mut Dissector) -> Option<()> {
+    let version = d.field("Version", |data| data.read_le::<i32>(), |v| v.to_string())?;
+    d.field(
+        "Services",
+        |data| Services::decode(data).ok(),
+        |v| v.to_string(),
+    )?;
+    d.field("Timestamp", |data| data.read_le::<i64>(), |v| v.to_string())?;
+    d.group("Receiver", dissect_network_address)?;
+
+    if version < 106 {
+        return Some(());
+    }
+
+    d.group("Sender", dissect_network_address)?;
+    d.field(
+        "Nonce",
+        |data| data.read_le::<u64>(),
+        |v| format!("{:#018x}", v),
+    )?;
+    let user_agent_len = d.field(
+        "User agent length",
+        |data| data.read_compact_size(),
+        |v| v.to_string(),
+    )?;
+    d.field(
+        "User agent",
+        |data| {
+            data.read_slice(user_agent_len as usize)
+                .map(|v| String::from_utf8_lossy(v).to_string())
+        },
+        |v| v.clone(),
+    )?;
+    d.field(
+        "Start height",
+        |data| data.read_le::<i32>(),
+        |v| v.to_string(),
+    )?;
+
+    if version < 70001 {
+        return Some(());
+    }
+
+    d.field(
+        "Relay",
+        |data| data.read_le::<u8>(),
+        |v| (*v == 0x01).to_string(),
+    )?;
+    Some(())
+}
+
+fn dissect_sendtxrcncl(d: &mut Dissector) -> Option<()> {
+    d.field("Version", |data| data.read_le::<u32>(), |v| v.to_string())?;
+    d.field(
+        "Salt",
+        |data| data.read_le::<u64>(),
+        |v| format!("{:#018x}", v),
+    )?;
+    Some(())
+}
+
+#[cfg(feature = "blocks")]
+fn dissect_sendcmpct(d: &mut Dissector) -> Option<()> {
+    d.field(
+        "Announce",
+        |data| data.read_le::<u8>(),
+        |v| (*v != 0).to_string(),
+    )?;
+    d.field("Version", |data| data.read_le::<u64>(), |v| v.to_string())?;
+    Some(())
+}
+
+#[cfg(feature = "addr")]
+fn dissect_addr(d: &mut Dissector) -> Option<()> {
+    let count = d.field("Count", |data| data.read_compact_size(), |v| v.to_string())?;
+    for _ in 0..count {
+        d.group("Address", |d| {
+            d.field("Time", |data| data.read_le::<u32>(), |v| v.to_string())?;
+            dissect_network_address(d)
+        })?;
+    }
+    Some(())
+}
+
+#[cfg(feature = "addr")]
+fn dissect_addrv2(d: &mut Dissector) -> Option<()> {
+    let count = d.field("Count", |data| data.read_compact_size(), |v| v.to_string())?;
+    for _ in 0..count {
+        d.group("Address", |d| {
+            d.field("Time", |data| data.read_le::<u32>(), |v| v.to_string())?;
+            d.field(
+                "Services",
+                |data| data.read_compact_size().map(Services::from),
+                |v| v.to_string(),
+            )?;
+            d.field(
+                "Address",
+                |data| AddrV2::decode(data).ok(),
+                |v| v.to_string(),
+            )?;
+            d.field("Port", |data| data.read_be::<u16>(), |v| v.to_string())?;
+            Some(())
+        })?;
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{
+        Ipv4Addr,
+        SocketAddr,
+    };
+
+    use crate::p2p::{
+        messages::{
+            calculate_checksum,
+            compose,
+            MessageHeader,
+            PingMessage,
+            ProtocolVersion,
+            Service,
+            VersionMessage,
+        },
+        NodeConfig,
+    };
+
+    #[test]
+    fn ping() {
+        let frame = compose(Network::Main, Command::Ping, PingMessage::new(15));
+        let checksum = calculate_checksum(&frame[MessageHeader::LENGTH..]);
+
+        assert_eq!(
+            dissect(&frame).to_string(),
+            format!(
+                concat!(
+                    "  0000  Header                  24 bytes\n",
+                    "  0000    Network               Main (0xd9b4bef9)\n",
+                    "  0004    Command               ping\n",
+                    "  0010    Length                8\n",
+                    "  0014    Checksum              {:#010x}\n",
+                    "  0018  Payload (ping)          8 bytes\n",
+                    "  0018    Nonce                 15\n",
+                ),
+                checksum
+            )
+        );
+    }
+
+    #[test]
+    fn version() {
+        let config = NodeConfig {
+            version: ProtocolVersion(70015),
+            services: Services::new(&[Service::Network]),
+            user_agent: "/handshaker:0.1.0/".to_string(),
+            start_height: 812345,
+            relay: true,
+            nonce: Some(1),
+            timestamp: Some(1700000000),
+            ..Default::default()
+        };
+        let receiver = SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 8333));
+        let frame = compose(
+            Network::Testnet,
+            Command::Version,
+            VersionMessage::new(receiver, &config),
+        );
+
+        let Dissection(fields) = dissect(&frame);
+        assert_eq!(fields.len(), 2);
+
+        let payload = &fields[1];
+        assert_eq!(payload.name, "Payload (version)");
+        assert_eq!(payload.offset, MessageHeader::LENGTH);
+        assert_eq!(payload.len, frame.len() - MessageHeader::LENGTH);
+
+        let receiver = &payload.children[3];
+        assert_eq!(
+            (receiver.name, receiver.offset, receiver.len),
+            ("Receiver", 44, 26)
+        );
+        assert_eq!(receiver.children[1].value, "10.0.0.1");
+        assert_eq!(receiver.children[2].value, "8333");
+
+        let user_agent = &payload.children[7];
+        assert_eq!(user_agent.name, "User agent");
+        assert_eq!(user_agent.value, "/handshaker:0.1.0/");
+        assert_eq!(payload.children.last().unwrap().value, "true");
+    }
+
+    #[test]
+    fn malformed() {
+        let frame = compose(Network::Main, Command::Ping, PingMessage::new(15));
+
+        // Payload cut short
+        let Dissection(fields) = dissect(&frame[..28]);
+        let payload = &fields[1];
+        assert_eq!(payload.children.len(), 1);
+        assert_eq!(payload.children[0].name, "Undecoded bytes");
+        assert_eq!(payload.children[0].len, 4);
+
+        // Header cut short
+        let Dissection(fields) = dissect(&frame[..10]);
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0].children.len(), 1);
+        assert_eq!(fields[1].name, "Undecoded bytes");
+        assert_eq!(fields[1].offset, 4);
+
+        // Bytes after the payload
+        let mut frame = frame;
+        frame.extend_from_slice(&[0xff; 3]);
+        let Dissection(fields) = dissect(&frame);
+        assert_eq!(fields[2].name, "Undecoded bytes");
+        assert_eq!((fields[2].offset, fields[2].len), (32, 3));
+    }
+}
@@ -0,0 +1,71 @@
+use super::{
+    Codec,
+    CodecError,
+    Fields,
+};
+
+/// FeeFilter message (BIP133) asks the other node not to announce
+/// transactions paying less than the given fee rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeFilterMessage {
+    /// Minimum fee rate in satoshis per 1000 virtual bytes
+    pub fee_rate: i64,
+}
+
+impl FeeFilterMessage {
+    pub fn new(fee_rate: i64) -> Self {
+        Self { fee_rate }
+    }
+}
+
+impl Codec for FeeFilterMessage {
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<i64>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.fee_rate.to_le_bytes());
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let fee_rate = Fields::new(data).read_le::<i64>("fee_rate")?;
+        Ok(Self { fee_rate })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    mod unformatted {
+        pub const RAW_FEEFILTER_MSG: &[u8] = &[
+            // Fee rate
+            0xe8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+    }
+
+    use unformatted::*;
+
+    #[test]
+    fn encode() {
+        let msg = FeeFilterMessage::new(1000);
+        assert_eq!(msg.encoded_len(), RAW_FEEFILTER_MSG.len());
+        assert_eq!(msg.encode(), RAW_FEEFILTER_MSG);
+    }
+
+    #[test]
+    fn decode() {
+        let mut data: &[u8] = RAW_FEEFILTER_MSG;
+        assert_eq!(
+            FeeFilterMessage::decode(&mut data),
+            Ok(FeeFilterMessage::new(1000))
+        );
+        assert!(data.is_empty());
+
+        assert_eq!(
+            FeeFilterMessage::decode(&mut &RAW_FEEFILTER_MSG[..7]),
+            Err(CodecError::InsufficientBytesError.at("fee_rate", 0))
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use super::{
+    Codec,
+    CodecError,
+};
+
+/// GetAddr message asks the node for addresses of other nodes it knows
+/// about, which it answers with Addr messages. It consists of only
+/// a message header with the command string "getaddr".
+#[derive(Debug)]
+pub struct GetAddrMessage {}
+
+impl Codec for GetAddrMessage {
+    fn encoded_len(&self) -> usize {
+        0
+    }
+
+    fn encode_into(&self, _data: &mut Vec<u8>) {}
+
+    fn decode(_data: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let msg = GetAddrMessage {};
+        assert_eq!(msg.encoded_len(), 0);
+        assert!(msg.encode().is_empty());
+    }
+}
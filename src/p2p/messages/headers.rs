@@ -0,0 +1,183 @@
+use super::{
+    Codec,
+    CodecError,
+    CompactSize,
+    Fields,
+};
+
+/// Largest number of block headers a single Headers message may carry
+pub const MAX_HEADERS_COUNT: usize = 2000;
+
+/// Length of the hashes a block header refers to
+const HASH_LENGTH: usize = 32;
+
+/// Header of a block, i.e. everything the block hash is calculated from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockHeader {
+    pub version: i32,
+    /// Hash of the previous block, in the byte order of the wire
+    pub prev_blockhash: [u8; HASH_LENGTH],
+    /// Root of the merkle tree of the block's transactions
+    pub merkle_root: [u8; HASH_LENGTH],
+    /// UNIX timestamp the block was mined at
+    pub time: u32,
+    /// Difficulty target in the compact format
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl Codec for BlockHeader {
+    fn encoded_len(&self) -> usize {
+        80
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.version.to_le_bytes());
+        data.extend_from_slice(&self.prev_blockhash);
+        data.extend_from_slice(&self.merkle_root);
+        data.extend_from_slice(&self.time.to_le_bytes());
+        data.extend_from_slice(&self.bits.to_le_bytes());
+        data.extend_from_slice(&self.nonce.to_le_bytes());
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let version = fields.read_le::<i32>("version")?;
+        let prev_blockhash = fields.read_fixed::<HASH_LENGTH>("prev_blockhash")?;
+        let merkle_root = fields.read_fixed::<HASH_LENGTH>("merkle_root")?;
+        let time = fields.read_le::<u32>("time")?;
+        let bits = fields.read_le::<u32>("bits")?;
+        let nonce = fields.read_le::<u32>("nonce")?;
+        Ok(Self {
+            version,
+            prev_blockhash,
+            merkle_root,
+            time,
+            bits,
+            nonce,
+        })
+    }
+}
+
+/// Headers message announces block headers, either unsolicited to nodes
+/// which sent the SendHeaders message (BIP130) or in response to a
+/// GetHeaders message. Each header is followed by the number of the
+/// block's transactions, which is always zero.
+#[derive(Debug, PartialEq)]
+pub struct HeadersMessage {
+    pub headers: Vec<BlockHeader>,
+}
+
+impl Codec for HeadersMessage {
+    fn encoded_len(&self) -> usize {
+        CompactSize(self.headers.len() as u64).encoded_len()
+            + self
+                .headers
+                .iter()
+                .map(|h| h.encoded_len() + CompactSize(0).encoded_len())
+                .sum::<usize>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        CompactSize(self.headers.len() as u64).encode_into(data);
+        for header in &self.headers {
+            header.encode_into(data);
+            CompactSize(0).encode_into(data);
+        }
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let count = fields.decode("count", |data| {
+            let CompactSize(count) = CompactSize::decode(data)?;
+            if count > MAX_HEADERS_COUNT as u64 {
+                return Err(CodecError::OversizedFieldError);
+            }
+            Ok(count)
+        })?;
+
+        let mut headers = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            headers.push(fields.decode("headers", BlockHeader::decode)?);
+            fields.decode("tx_count", |data| match CompactSize::decode(data)? {
+                CompactSize(0) => Ok(()),
+                _ => Err(CodecError::InvalidBytesError),
+            })?;
+        }
+        Ok(Self { headers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    mod unformatted {
+        pub const RAW_HEADERS_MSG: &[u8] = &[
+            // Count
+            0x01,
+            // Version
+            0x00, 0x00, 0x00, 0x20,
+            // Previous block hash
+            0x6f, 0xe2, 0x8c, 0x0a, 0xb6, 0xf1, 0xb3, 0x72, 0xc1, 0xa6, 0xa2, 0x46, 0xae,
+            0x63, 0xf7, 0x4f, 0x93, 0x1e, 0x83, 0x65, 0xe1, 0x5a, 0x08, 0x9c, 0x68, 0xd6,
+            0x19, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // Merkle root
+            0x98, 0x20, 0x51, 0xfd, 0x1e, 0x4b, 0xa7, 0x44, 0xbb, 0xbe, 0x68, 0x0e, 0x1f,
+            0xee, 0x14, 0x67, 0x7b, 0xa1, 0xa3, 0xc3, 0x54, 0x0b, 0xf7, 0xb1, 0xcd, 0xb6,
+            0x06, 0xe8, 0x57, 0x23, 0x3e, 0x0e,
+            // Time
+            0x61, 0xbc, 0x66, 0x49,
+            // Bits
+            0xff, 0xff, 0x00, 0x1d,
+            // Nonce
+            0x01, 0xe3, 0x62, 0x99,
+            // Number of transactions
+            0x00,
+        ];
+    }
+
+    use unformatted::*;
+
+    fn msg() -> HeadersMessage {
+        let mut data = &RAW_HEADERS_MSG[1..81];
+        HeadersMessage {
+            headers: vec![BlockHeader::decode(&mut data).unwrap()],
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let msg = msg();
+        assert_eq!(msg.encoded_len(), RAW_HEADERS_MSG.len());
+        assert_eq!(msg.encode(), RAW_HEADERS_MSG);
+    }
+
+    #[test]
+    fn decode() {
+        let mut data: &[u8] = RAW_HEADERS_MSG;
+        let msg = HeadersMessage::decode(&mut data).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(msg.headers.len(), 1);
+        assert_eq!(msg.headers[0].version, 0x20000000);
+        assert_eq!(msg.headers[0].time, 1231469665);
+        assert_eq!(msg.headers[0].bits, 0x1d00ffff);
+
+        // Headers are never followed by transactions
+        let mut raw = RAW_HEADERS_MSG.to_vec();
+        *raw.last_mut().unwrap() = 0x01;
+        assert_eq!(
+            HeadersMessage::decode(&mut &raw[..]).err(),
+            Some(CodecError::InvalidBytesError.at("tx_count", 81))
+        );
+    }
+
+    #[test]
+    fn decode_too_many() {
+        let mut data: &[u8] = &[0xfd, 0xd1, 0x07];
+        let e = HeadersMessage::decode(&mut data).unwrap_err();
+        assert_eq!(e.kind(), &CodecError::OversizedFieldError);
+        assert_eq!(e.field().as_deref(), Some("count"));
+    }
+}
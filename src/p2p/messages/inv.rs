@@ -0,0 +1,245 @@
+use super::{
+    Codec,
+    CodecError,
+    CompactSize,
+    Fields,
+};
+
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+/// Largest number of items a single Inv message may carry
+pub const MAX_INV_COUNT: usize = 50_000;
+
+/// Length of the hash identifying an inventory item
+const HASH_LENGTH: usize = 32;
+
+/// Flag marking inventory types which ask for witness data (BIP144)
+const WITNESS_FLAG: u32 = 1 << 30;
+
+/// Type of the object an inventory item refers to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvType {
+    Tx,
+    Block,
+    FilteredBlock,
+    CompactBlock,
+    /// Transaction together with its witness data (BIP144)
+    WitnessTx,
+    WitnessBlock,
+    WitnessFilteredBlock,
+    /// Transaction identified by its wtxid (BIP339)
+    Wtx,
+    Unknown(u32),
+}
+
+impl InvType {
+    /// Whether the item refers to a transaction
+    pub fn is_tx(&self) -> bool {
+        matches!(self, InvType::Tx | InvType::WitnessTx | InvType::Wtx)
+    }
+
+    /// Whether the item refers to a block
+    pub fn is_block(&self) -> bool {
+        matches!(
+            self,
+            InvType::Block
+                | InvType::FilteredBlock
+                | InvType::CompactBlock
+                | InvType::WitnessBlock
+                | InvType::WitnessFilteredBlock
+        )
+    }
+}
+
+impl From<u32> for InvType {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => InvType::Tx,
+            2 => InvType::Block,
+            3 => InvType::FilteredBlock,
+            4 => InvType::CompactBlock,
+            5 => InvType::Wtx,
+            v if v == WITNESS_FLAG | 1 => InvType::WitnessTx,
+            v if v == WITNESS_FLAG | 2 => InvType::WitnessBlock,
+            v if v == WITNESS_FLAG | 3 => InvType::WitnessFilteredBlock,
+            v => InvType::Unknown(v),
+        }
+    }
+}
+
+impl From<InvType> for u32 {
+    fn from(kind: InvType) -> Self {
+        match kind {
+            InvType::Tx => 1,
+            InvType::Block => 2,
+            InvType::FilteredBlock => 3,
+            InvType::CompactBlock => 4,
+            InvType::Wtx => 5,
+            InvType::WitnessTx => WITNESS_FLAG | 1,
+            InvType::WitnessBlock => WITNESS_FLAG | 2,
+            InvType::WitnessFilteredBlock => WITNESS_FLAG | 3,
+            InvType::Unknown(v) => v,
+        }
+    }
+}
+
+/// Object announced by its type and hash.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Inventory {
+    pub kind: InvType,
+    /// Hash identifying the object, in the byte order of the wire
+    pub hash: [u8; HASH_LENGTH],
+}
+
+impl Display for Inventory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} ", self.kind)?;
+        // Hashes are displayed in the reverse byte order, as by block
+        // explorers
+        for byte in self.hash.iter().rev() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Codec for Inventory {
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u32>() + HASH_LENGTH
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&u32::from(self.kind).to_le_bytes());
+        data.extend_from_slice(&self.hash);
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let kind = InvType::from(fields.read_le::<u32>("kind")?);
+        let hash = fields.read_fixed::<HASH_LENGTH>("hash")?;
+        Ok(Self { kind, hash })
+    }
+}
+
+/// Inv message announces transactions and blocks the node has, either
+/// unsolicited or in response to a GetBlocks message.
+#[derive(Debug, PartialEq)]
+pub struct InvMessage {
+    pub inventory: Vec<Inventory>,
+}
+
+impl Codec for InvMessage {
+    fn encoded_len(&self) -> usize {
+        CompactSize(self.inventory.len() as u64).encoded_len()
+            + self
+                .inventory
+                .iter()
+                .map(|i| i.encoded_len())
+                .sum::<usize>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        CompactSize(self.inventory.len() as u64).encode_into(data);
+        for item in &self.inventory {
+            item.encode_into(data);
+        }
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let count = fields.decode("count", |data| {
+            let CompactSize(count) = CompactSize::decode(data)?;
+            if count > MAX_INV_COUNT as u64 {
+                return Err(CodecError::OversizedFieldError);
+            }
+            Ok(count)
+        })?;
+
+        let inventory = (0..count)
+            .map(|_| fields.decode("inventory", Inventory::decode))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { inventory })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    mod unformatted {
+        pub const RAW_INV_MSG: &[u8] = &[
+            // Count
+            0x02,
+            // Type
+            0x05, 0x00, 0x00, 0x00,
+            // Hash
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xab,
+            // Type
+            0x02, 0x00, 0x00, 0x40,
+            // Hash
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+    }
+
+    use unformatted::*;
+
+    fn msg() -> InvMessage {
+        let mut hash = [0; HASH_LENGTH];
+        hash[0] = 0x01;
+        hash[31] = 0xab;
+        InvMessage {
+            inventory: vec![
+                Inventory {
+                    kind: InvType::Wtx,
+                    hash,
+                },
+                Inventory {
+                    kind: InvType::WitnessBlock,
+                    hash: [0; HASH_LENGTH],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let msg = msg();
+        assert_eq!(msg.encoded_len(), RAW_INV_MSG.len());
+        assert_eq!(msg.encode(), RAW_INV_MSG);
+    }
+
+    #[test]
+    fn decode() {
+        let mut data: &[u8] = RAW_INV_MSG;
+        assert_eq!(InvMessage::decode(&mut data), Ok(msg()));
+        assert!(data.is_empty());
+
+        let msg = msg();
+        assert!(msg.inventory[0].kind.is_tx());
+        assert!(msg.inventory[1].kind.is_block());
+        assert!(msg.inventory[0].to_string().starts_with("Wtx ab0000"));
+        assert!(msg.inventory[0].to_string().ends_with("0001"));
+
+        let e = InvMessage::decode(&mut &RAW_INV_MSG[..50]).unwrap_err();
+        assert_eq!(e.field().as_deref(), Some("inventory.hash"));
+        assert_eq!(e.offset(), Some(41));
+    }
+
+    #[test]
+    fn kind() {
+        for kind in [1, 2, 3, 4, 5, WITNESS_FLAG | 1, WITNESS_FLAG | 3, 7] {
+            assert_eq!(u32::from(InvType::from(kind)), kind);
+        }
+        assert_eq!(InvType::from(7), InvType::Unknown(7));
+        assert!(!InvType::Unknown(7).is_tx());
+        assert!(!InvType::Unknown(7).is_block());
+    }
+}
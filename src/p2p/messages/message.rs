@@ -0,0 +1,219 @@
+#[cfg(feature = "addr")]
+use super::{
+    AddrMessage,
+    AddrV2Message,
+    GetAddrMessage,
+    SendAddrV2Message,
+};
+use super::{
+    Codec,
+    CodecError,
+    Command,
+    PingMessage,
+    PongMessage,
+    SendTxRcnclMessage,
+    VerackMessage,
+    VersionMessage,
+    WtxidRelayMessage,
+};
+#[cfg(feature = "blocks")]
+use super::{
+    FeeFilterMessage,
+    HeadersMessage,
+    InvMessage,
+    SendCmpctMessage,
+    SendHeadersMessage,
+};
+
+/// Any message the codec understands, together with its payload.
+#[derive(Debug)]
+pub enum Message {
+    Version(VersionMessage),
+    Verack(VerackMessage),
+    Ping(PingMessage),
+    Pong(PongMessage),
+    SendTxRcncl(SendTxRcnclMessage),
+    WtxidRelay(WtxidRelayMessage),
+    #[cfg(feature = "addr")]
+    GetAddr(GetAddrMessage),
+    #[cfg(feature = "addr")]
+    Addr(AddrMessage),
+    #[cfg(feature = "addr")]
+    AddrV2(AddrV2Message),
+    #[cfg(feature = "addr")]
+    SendAddrV2(SendAddrV2Message),
+    #[cfg(feature = "blocks")]
+    SendHeaders(SendHeadersMessage),
+    #[cfg(feature = "blocks")]
+    SendCmpct(SendCmpctMessage),
+    #[cfg(feature = "blocks")]
+    FeeFilter(FeeFilterMessage),
+    #[cfg(feature = "blocks")]
+    Inv(InvMessage),
+    #[cfg(feature = "blocks")]
+    Headers(HeadersMessage),
+    /// Message whose command is unknown to the codec, with its raw payload
+    Unknown {
+        command: Command,
+        payload: Vec<u8>,
+    },
+}
+
+impl Message {
+    /// Gets the command the message is sent with
+    pub fn command(&self) -> Command {
+        match self {
+            Message::Version(_) => Command::Version,
+            Message::Verack(_) => Command::Verack,
+            Message::Ping(_) => Command::Ping,
+            Message::Pong(_) => Command::Pong,
+            Message::SendTxRcncl(_) => Command::SendTxRcncl,
+            Message::WtxidRelay(_) => Command::WtxidRelay,
+            #[cfg(feature = "addr")]
+            Message::GetAddr(_) => Command::GetAddr,
+            #[cfg(feature = "addr")]
+            Message::Addr(_) => Command::Addr,
+            #[cfg(feature = "addr")]
+            Message::AddrV2(_) => Command::AddrV2,
+            #[cfg(feature = "addr")]
+            Message::SendAddrV2(_) => Command::SendAddrV2,
+            #[cfg(feature = "blocks")]
+            Message::SendHeaders(_) => Command::SendHeaders,
+            #[cfg(feature = "blocks")]
+            Message::SendCmpct(_) => Command::SendCmpct,
+            #[cfg(feature = "blocks")]
+            Message::FeeFilter(_) => Command::FeeFilter,
+            #[cfg(feature = "blocks")]
+            Message::Inv(_) => Command::Inv,
+            #[cfg(feature = "blocks")]
+            Message::Headers(_) => Command::Headers,
+            Message::Unknown { command, .. } => *command,
+        }
+    }
+
+    /// Decodes the payload of a message sent with the given command, taking
+    /// the whole payload of commands unknown to the codec as it is
+    pub fn decode(command: Command, data: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(match command {
+            Command::Version => Message::Version(VersionMessage::decode(data)?),
+            Command::Verack => Message::Verack(VerackMessage::decode(data)?),
+            Command::Ping => Message::Ping(PingMessage::decode(data)?),
+            Command::Pong => Message::Pong(PongMessage::decode(data)?),
+            Command::SendTxRcncl => {
+                Message::SendTxRcncl(SendTxRcnclMessage::decode(data)?)
+            }
+            Command::WtxidRelay => Message::WtxidRelay(WtxidRelayMessage::decode(data)?),
+            #[cfg(feature = "addr")]
+            Command::GetAddr => Message::GetAddr(GetAddrMessage::decode(data)?),
+            #[cfg(feature = "addr")]
+            Command::Addr => Message::Addr(AddrMessage::decode(data)?),
+            #[cfg(feature = "addr")]
+            Command::AddrV2 => Message::AddrV2(AddrV2Message::decode(data)?),
+            #[cfg(feature = "addr")]
+            Command::SendAddrV2 => Message::SendAddrV2(SendAddrV2Message::decode(data)?),
+            #[cfg(feature = "blocks")]
+            Command::SendHeaders => {
+                Message::SendHeaders(SendHeadersMessage::decode(data)?)
+            }
+            #[cfg(feature = "blocks")]
+            Command::SendCmpct => Message::SendCmpct(SendCmpctMessage::decode(data)?),
+            #[cfg(feature = "blocks")]
+            Command::FeeFilter => Message::FeeFilter(FeeFilterMessage::decode(data)?),
+            #[cfg(feature = "blocks")]
+            Command::Inv => Message::Inv(InvMessage::decode(data)?),
+            #[cfg(feature = "blocks")]
+            Command::Headers => Message::Headers(HeadersMessage::decode(data)?),
+            Command::Unknown(_) => {
+                let payload = data.to_vec();
+                *data = &[];
+                Message::Unknown { command, payload }
+            }
+        })
+    }
+
+    /// Gets the number of payload bytes once encoded
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Message::Version(msg) => msg.encoded_len(),
+            Message::Verack(msg) => msg.encoded_len(),
+            Message::Ping(msg) => msg.encoded_len(),
+            Message::Pong(msg) => msg.encoded_len(),
+            Message::SendTxRcncl(msg) => msg.encoded_len(),
+            Message::WtxidRelay(msg) => msg.encoded_len(),
+            #[cfg(feature = "addr")]
+            Message::GetAddr(msg) => msg.encoded_len(),
+            #[cfg(feature = "addr")]
+            Message::Addr(msg) => msg.encoded_len(),
+            #[cfg(feature = "addr")]
+            Message::AddrV2(msg) => msg.encoded_len(),
+            #[cfg(feature = "addr")]
+            Message::SendAddrV2(msg) => msg.encoded_len(),
+            #[cfg(feature = "blocks")]
+            Message::SendHeaders(msg) => msg.encoded_len(),
+            #[cfg(feature = "blocks")]
+            Message::SendCmpct(msg) => msg.encoded_len(),
+            #[cfg(feature = "blocks")]
+            Message::FeeFilter(msg) => msg.encoded_len(),
+            #[cfg(feature = "blocks")]
+            Message::Inv(msg) => msg.encoded_len(),
+            #[cfg(feature = "blocks")]
+            Message::Headers(msg) => msg.encoded_len(),
+            Message::Unknown { payload, .. } => payload.len(),
+        }
+    }
+
+    /// Encodes the payload, appending it to the buffer
+    pub fn encode_into(&self, data: &mut Vec<u8>) {
+        match self {
+            Message::Version(msg) => msg.encode_into(data),
+            Message::Verack(msg) => msg.encode_into(data),
+            Message::Ping(msg) => msg.encode_into(data),
+            Message::Pong(msg) => msg.encode_into(data),
+            Message::SendTxRcncl(msg) => msg.encode_into(data),
+            Message::WtxidRelay(msg) => msg.encode_into(data),
+            #[cfg(feature = "addr")]
+            Message::GetAddr(msg) => msg.encode_into(data),
+            #[cfg(feature = "addr")]
+            Message::Addr(msg) => msg.encode_into(data),
+            #[cfg(feature = "addr")]
+            Message::AddrV2(msg) => msg.encode_into(data),
+            #[cfg(feature = "addr")]
+            Message::SendAddrV2(msg) => msg.encode_into(data),
+            #[cfg(feature = "blocks")]
+            Message::SendHeaders(msg) => msg.encode_into(data),
+            #[cfg(feature = "blocks")]
+            Message::SendCmpct(msg) => msg.encode_into(data),
+            #[cfg(feature = "blocks")]
+            Message::FeeFilter(msg) => msg.encode_into(data),
+            #[cfg(feature = "blocks")]
+            Message::Inv(msg) => msg.encode_into(data),
+            #[cfg(feature = "blocks")]
+            Message::Headers(msg) => msg.encode_into(data),
+            Message::Unknown { payload, .. } => data.extend_from_slice(payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let msg = Message::Ping(PingMessage::new(15));
+        assert_eq!(msg.command(), Command::Ping);
+
+        let mut data = Vec::new();
+        msg.encode_into(&mut data);
+        assert_eq!(data.len(), msg.encoded_len());
+
+        match Message::decode(Command::Ping, &mut &data[..]).unwrap() {
+            Message::Ping(ping) => assert_eq!(ping.nonce(), 15),
+            other => panic!("Unexpected message {:?}", other),
+        }
+        assert_eq!(
+            Message::decode(Command::Pong, &mut &data[..4]).err(),
+            Some(CodecError::InsufficientBytesError.at("nonce", 0))
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+use clap::ValueEnum;
+use strum::EnumIter;
+
+use super::{
+    Chain,
+    Network,
+};
+
+/// Parameters of a network speaking the Bitcoin protocol as it is, e.g. a
+/// private chain or an altcoin, which differs from the built-in networks
+/// only by its magic bytes and default port.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkParams {
+    /// Name the network is reported by
+    pub name: String,
+    /// Magic bytes starting each message, as a little-endian number
+    pub magic: u32,
+    /// Port nodes of the network listen on by default
+    pub default_port: u16,
+}
+
+impl NetworkParams {
+    pub fn new(name: impl Into<String>, magic: u32, default_port: u16) -> Self {
+        Self {
+            name: name.into(),
+            magic,
+            default_port,
+        }
+    }
+
+    /// Replaces the magic bytes, keeping the rest of the parameters
+    pub fn with_magic(mut self, magic: u32) -> Self {
+        self.magic = magic;
+        self
+    }
+
+    /// Gets the built-in network with the same magic bytes, if any
+    pub fn network(&self) -> Option<Network> {
+        Network::try_from(self.magic).ok()
+    }
+}
+
+impl From<Network> for NetworkParams {
+    fn from(network: Network) -> Self {
+        Self::new(
+            format!("{:?}", network),
+            network as u32,
+            network.default_port(),
+        )
+    }
+}
+
+/// Chain derived from Bitcoin's whose nodes perform the same handshake,
+/// only on their own magic bytes and default port.
+#[derive(Clone, Copy, Debug, EnumIter, PartialEq, ValueEnum)]
+pub enum Preset {
+    Litecoin,
+    Dogecoin,
+    BitcoinCash,
+}
+
+impl From<Preset> for NetworkParams {
+    fn from(preset: Preset) -> Self {
+        match preset {
+            Preset::Litecoin => Self::new("Litecoin", 0xdb_b6_c0_fb, 9333),
+            Preset::Dogecoin => Self::new("Dogecoin", 0xc0_c0_c0_c0, 22556),
+            Preset::BitcoinCash => Self::new("BitcoinCash", 0xe8_f3_e1_e3, 8333),
+        }
+    }
+}
+
+impl Chain for NetworkParams {
+    fn magic(&self) -> u32 {
+        self.magic
+    }
+}
+
+impl Display for NetworkParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (magic {:08x}, port {})",
+            self.name,
+            self.magic.swap_bytes(),
+            self.default_port
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use strum::IntoEnumIterator;
+
+    use crate::p2p::messages::{
+        compose,
+        Command,
+        FrameHeader,
+        PingMessage,
+    };
+
+    #[test]
+    fn network() {
+        let params = NetworkParams::from(Network::Testnet3);
+        assert_eq!(params.name, "Testnet3");
+        assert_eq!(params.default_port, 18333);
+        assert_eq!(params.network(), Some(Network::Testnet3));
+        assert_eq!(params.to_string(), "Testnet3 (magic 0b110907, port 18333)");
+
+        let params = params.with_magic(0x01_02_03_04);
+        assert_eq!(params.network(), None);
+        assert_eq!(params.default_port, 18333);
+    }
+
+    #[test]
+    fn presets() {
+        let magics: Vec<_> = Preset::iter()
+            .map(|preset| NetworkParams::from(preset).to_string())
+            .collect();
+        assert_eq!(
+            magics,
+            [
+                "Litecoin (magic fbc0b6db, port 9333)",
+                "Dogecoin (magic c0c0c0c0, port 22556)",
+                "BitcoinCash (magic e3e1f3e8, port 8333)",
+            ]
+        );
+        for preset in Preset::iter() {
+            assert_eq!(NetworkParams::from(preset).network(), None);
+        }
+    }
+
+    #[test]
+    fn compose_custom() {
+        let params = NetworkParams::new("private", 0xaa_bb_cc_dd, 9333);
+        let data = compose(&params, Command::Ping, PingMessage::new(1));
+        assert_eq!(&data[..4], &[0xdd, 0xcc, 0xbb, 0xaa]);
+
+        let header = FrameHeader::decode(&params, &mut &data[..]).unwrap();
+        assert_eq!(header.magic, params.magic);
+        assert_eq!(header.command, Command::Ping);
+    }
+}
@@ -1,12 +1,7 @@
 use super::{
     Codec,
     CodecError,
-    ReadBytes,
-};
-
-use rand::{
-    thread_rng,
-    Rng,
+    Fields,
 };
 
 /// Ping message is sent to confirm that the TCP/IP
@@ -20,11 +15,8 @@ pub struct PingMessage {
 }
 
 impl PingMessage {
-    pub fn new() -> Self {
-        let mut rng = thread_rng();
-        Self {
-            nonce: rng.gen::<u64>(),
-        }
+    pub fn new(nonce: u64) -> Self {
+        Self { nonce }
     }
 
     /// Gets the random nonce
@@ -34,19 +26,22 @@ impl PingMessage {
 }
 
 impl Codec for PingMessage {
-    fn encode(&self) -> Vec<u8> {
-        self.nonce.to_le_bytes().to_vec()
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u64>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.nonce.to_le_bytes());
     }
 
     fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
-        let nonce = data
-            .read_le::<u64>()
-            .ok_or(CodecError::InsufficientBytesError)?;
+        let nonce = Fields::new(data).read_le::<u64>("nonce")?;
         Ok(Self { nonce })
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
@@ -55,6 +50,7 @@ mod tests {
     #[test]
     fn encode() {
         let msg = PingMessage { nonce: 15 };
+        assert_eq!(msg.encoded_len(), RAW_PING_MSG.len());
         assert_eq!(msg.encode(), RAW_PING_MSG);
     }
 
@@ -73,7 +69,10 @@ mod tests {
         let mut data: &[u8] = &[0x0f, 0x00, 0x00];
         let result = PingMessage::decode(&mut data);
 
-        assert_eq!(result, Err(CodecError::InsufficientBytesError));
+        assert_eq!(
+            result,
+            Err(CodecError::InsufficientBytesError.at("nonce", 0))
+        );
         assert!(!data.is_empty());
     }
 }
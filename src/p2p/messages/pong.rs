@@ -1,7 +1,7 @@
 use super::{
     Codec,
     CodecError,
-    ReadBytes,
+    Fields,
 };
 
 /// Pong message is sent in response to a Ping message.
@@ -23,19 +23,22 @@ impl PongMessage {
 }
 
 impl Codec for PongMessage {
-    fn encode(&self) -> Vec<u8> {
-        self.nonce.to_le_bytes().to_vec()
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u64>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.nonce.to_le_bytes());
     }
 
     fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
-        let nonce = data
-            .read_le::<u64>()
-            .ok_or(CodecError::InsufficientBytesError)?;
+        let nonce = Fields::new(data).read_le::<u64>("nonce")?;
         Ok(Self { nonce })
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
@@ -44,6 +47,7 @@ mod tests {
     #[test]
     fn encode() {
         let msg = PongMessage { nonce: 15 };
+        assert_eq!(msg.encoded_len(), RAW_PONG_MSG.len());
         assert_eq!(msg.encode(), RAW_PONG_MSG);
     }
 
@@ -62,7 +66,10 @@ mod tests {
         let mut data: &[u8] = &[0x0f, 0x00, 0x00];
         let result = PongMessage::decode(&mut data);
 
-        assert_eq!(result, Err(CodecError::InsufficientBytesError));
+        assert_eq!(
+            result,
+            Err(CodecError::InsufficientBytesError.at("nonce", 0))
+        );
         assert!(!data.is_empty());
     }
 }
@@ -0,0 +1,100 @@
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+/// Version of the P2P protocol spoken by a node, as advertised in its
+/// Version message.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ProtocolVersion(pub i32);
+
+impl ProtocolVersion {
+    /// Version message carries the sender address, nonce, user agent and
+    /// start height
+    pub const VERSION_FIELDS: Self = Self(106);
+    /// Gossiped addresses carry the time they were last seen
+    pub const ADDR_TIME: Self = Self(31402);
+    /// BIP31, Ping messages carry a nonce echoed in Pong messages
+    pub const BIP31: Self = Self(60001);
+    /// BIP37, Version message carries the relay flag
+    pub const BIP37: Self = Self(70001);
+    /// BIP130, headers can be announced instead of blocks
+    pub const SENDHEADERS: Self = Self(70012);
+    /// BIP133, transaction relay can be limited by fee rate
+    pub const FEEFILTER: Self = Self(70013);
+    /// BIP152, compact block relay
+    pub const COMPACT_BLOCKS: Self = Self(70014);
+    /// Invalid compact blocks no longer get the node banned
+    pub const INVALID_CB_NO_BAN: Self = Self(70015);
+    /// BIP339, transactions relayed by wtxid and features negotiated
+    /// between Version and Verack messages
+    pub const WTXID: Self = Self(70016);
+
+    /// Checks whether nodes speaking the version support the feature
+    pub fn supports(self, feature: Feature) -> bool {
+        self >= feature.min_version()
+    }
+}
+
+impl Display for ProtocolVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<i32> for ProtocolVersion {
+    fn from(version: i32) -> Self {
+        Self(version)
+    }
+}
+
+impl From<ProtocolVersion> for i32 {
+    fn from(version: ProtocolVersion) -> Self {
+        version.0
+    }
+}
+
+/// Protocol feature introduced by a specific version.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    VersionFields,
+    AddrTime,
+    Pong,
+    Relay,
+    SendHeaders,
+    FeeFilter,
+    CompactBlocks,
+    WtxidRelay,
+    AddrV2,
+}
+
+impl Feature {
+    /// Gets the lowest version supporting the feature
+    pub fn min_version(self) -> ProtocolVersion {
+        match self {
+            Feature::VersionFields => ProtocolVersion::VERSION_FIELDS,
+            Feature::AddrTime => ProtocolVersion::ADDR_TIME,
+            Feature::Pong => ProtocolVersion::BIP31,
+            Feature::Relay => ProtocolVersion::BIP37,
+            Feature::SendHeaders => ProtocolVersion::SENDHEADERS,
+            Feature::FeeFilter => ProtocolVersion::FEEFILTER,
+            Feature::CompactBlocks => ProtocolVersion::COMPACT_BLOCKS,
+            Feature::WtxidRelay | Feature::AddrV2 => ProtocolVersion::WTXID,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supports() {
+        assert!(ProtocolVersion(70016).supports(Feature::WtxidRelay));
+        assert!(ProtocolVersion(70015).supports(Feature::Relay));
+        assert!(!ProtocolVersion(70015).supports(Feature::WtxidRelay));
+        assert!(!ProtocolVersion(106).supports(Feature::Pong));
+        assert!(ProtocolVersion::BIP37 < ProtocolVersion::WTXID);
+        assert_eq!(ProtocolVersion::FEEFILTER.to_string(), "70013");
+    }
+}
@@ -0,0 +1,35 @@
+use super::{
+    Codec,
+    CodecError,
+};
+
+/// SendAddrV2 message (BIP155) tells the other node that addresses should
+/// be announced with AddrV2 rather than Addr messages. It is sent between
+/// the Version and Verack messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SendAddrV2Message {}
+
+impl Codec for SendAddrV2Message {
+    fn encoded_len(&self) -> usize {
+        0
+    }
+
+    fn encode_into(&self, _data: &mut Vec<u8>) {}
+
+    fn decode(_data: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let msg = SendAddrV2Message {};
+        assert_eq!(msg.encoded_len(), 0);
+        assert!(msg.encode().is_empty());
+        assert_eq!(SendAddrV2Message::decode(&mut &[][..]), Ok(msg));
+    }
+}
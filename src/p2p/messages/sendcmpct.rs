@@ -0,0 +1,79 @@
+use super::{
+    Codec,
+    CodecError,
+    Fields,
+};
+
+/// SendCmpct message (BIP152) announces support for compact block relay
+/// of the given version, in either the low or the high bandwidth mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SendCmpctMessage {
+    /// Whether new blocks should be announced with CmpctBlock messages,
+    /// i.e. the high bandwidth mode
+    pub announce: bool,
+    /// Version of compact blocks, 2 for the ones carrying witnesses
+    pub version: u64,
+}
+
+impl SendCmpctMessage {
+    pub fn new(announce: bool, version: u64) -> Self {
+        Self { announce, version }
+    }
+}
+
+impl Codec for SendCmpctMessage {
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u8>() + std::mem::size_of::<u64>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.push(self.announce as u8);
+        data.extend_from_slice(&self.version.to_le_bytes());
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let announce = fields.read_le::<u8>("announce")? != 0;
+        let version = fields.read_le::<u64>("version")?;
+        Ok(Self { announce, version })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    mod unformatted {
+        pub const RAW_SENDCMPCT_MSG: &[u8] = &[
+            // Announce
+            0x01,
+            // Version
+            0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+    }
+
+    use unformatted::*;
+
+    #[test]
+    fn encode() {
+        let msg = SendCmpctMessage::new(true, 2);
+        assert_eq!(msg.encoded_len(), RAW_SENDCMPCT_MSG.len());
+        assert_eq!(msg.encode(), RAW_SENDCMPCT_MSG);
+    }
+
+    #[test]
+    fn decode() {
+        let mut data: &[u8] = RAW_SENDCMPCT_MSG;
+        assert_eq!(
+            SendCmpctMessage::decode(&mut data),
+            Ok(SendCmpctMessage::new(true, 2))
+        );
+        assert!(data.is_empty());
+
+        assert_eq!(
+            SendCmpctMessage::decode(&mut &RAW_SENDCMPCT_MSG[..5]),
+            Err(CodecError::InsufficientBytesError.at("version", 1))
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use super::{
+    Codec,
+    CodecError,
+};
+
+/// SendHeaders message (BIP130) asks the other node to announce new
+/// blocks with Headers messages rather than Inv messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SendHeadersMessage {}
+
+impl Codec for SendHeadersMessage {
+    fn encoded_len(&self) -> usize {
+        0
+    }
+
+    fn encode_into(&self, _data: &mut Vec<u8>) {}
+
+    fn decode(_data: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let msg = SendHeadersMessage {};
+        assert_eq!(msg.encoded_len(), 0);
+        assert!(msg.encode().is_empty());
+        assert_eq!(SendHeadersMessage::decode(&mut &[][..]), Ok(msg));
+    }
+}
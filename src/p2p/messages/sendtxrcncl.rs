@@ -0,0 +1,85 @@
+use super::{
+    Codec,
+    CodecError,
+    Fields,
+};
+
+/// SendTxRcncl message (BIP330) is sent between Version and
+/// Verack messages to announce support for Erlay transaction
+/// reconciliation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SendTxRcnclMessage {
+    /// Highest reconciliation protocol version supported
+    pub version: u32,
+    /// Salt used to compute short transaction IDs
+    pub salt: u64,
+}
+
+impl SendTxRcnclMessage {
+    pub fn new(version: u32, salt: u64) -> Self {
+        Self { version, salt }
+    }
+}
+
+impl Codec for SendTxRcnclMessage {
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u32>() + std::mem::size_of::<u64>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.version.to_le_bytes());
+        data.extend_from_slice(&self.salt.to_le_bytes());
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
+        let mut fields = Fields::new(data);
+        let version = fields.read_le::<u32>("version")?;
+        let salt = fields.read_le::<u64>("salt")?;
+        Ok(Self { version, salt })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    mod unformatted {
+        pub const RAW_SENDTXRCNCL_MSG: &[u8] = &[
+            // Version
+            0x01, 0x00, 0x00, 0x00,
+            // Salt
+            0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01,
+        ];
+    }
+
+    use unformatted::*;
+
+    #[test]
+    fn encode() {
+        let msg = SendTxRcnclMessage::new(1, 0x01234567_89abcdef);
+        assert_eq!(msg.encoded_len(), RAW_SENDTXRCNCL_MSG.len());
+        assert_eq!(msg.encode(), RAW_SENDTXRCNCL_MSG);
+    }
+
+    #[test]
+    fn decode() {
+        let mut data: &[u8] = RAW_SENDTXRCNCL_MSG;
+        let result = SendTxRcnclMessage::decode(&mut data);
+
+        assert_eq!(result, Ok(SendTxRcnclMessage::new(1, 0x01234567_89abcdef)));
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn decode_insufficient_bytes() {
+        let mut data: &[u8] = &[0x01, 0x00, 0x00, 0x00, 0xef];
+        let result = SendTxRcnclMessage::decode(&mut data);
+
+        assert_eq!(
+            result,
+            Err(CodecError::InsufficientBytesError.at("salt", 4))
+        );
+        assert!(!data.is_empty());
+    }
+}
@@ -87,6 +87,12 @@ impl From<u64> for Services {
     }
 }
 
+impl From<Services> for u64 {
+    fn from(services: Services) -> Self {
+        services.services
+    }
+}
+
 impl Display for Services {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "[")?;
@@ -108,8 +114,12 @@ impl Display for Services {
 }
 
 impl Codec for Services {
-    fn encode(&self) -> Vec<u8> {
-        self.services.to_le_bytes().to_vec()
+    fn encoded_len(&self) -> usize {
+        std::mem::size_of::<u64>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.services.to_le_bytes());
     }
 
     fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
@@ -121,6 +131,7 @@ impl Codec for Services {
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod tests {
     use super::*;
 
@@ -147,6 +158,7 @@ mod tests {
     fn encode() {
         let services =
             Services::new(&[Service::Network, Service::Bloom, Service::Witness]);
+        assert_eq!(services.encoded_len(), RAW_SERVICES.len());
         assert_eq!(services.encode(), RAW_SERVICES);
     }
 
@@ -10,10 +10,12 @@ use super::{
 pub struct VerackMessage {}
 
 impl Codec for VerackMessage {
-    fn encode(&self) -> Vec<u8> {
-        Vec::<u8>::new()
+    fn encoded_len(&self) -> usize {
+        0
     }
 
+    fn encode_into(&self, _data: &mut Vec<u8>) {}
+
     fn decode(_data: &mut &[u8]) -> Result<Self, CodecError> {
         Ok(Self {})
     }
@@ -26,6 +28,7 @@ mod tests {
     #[test]
     fn encode() {
         let msg = VerackMessage {};
+        assert_eq!(msg.encoded_len(), 0);
         assert!(msg.encode().is_empty());
     }
 
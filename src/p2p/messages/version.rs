@@ -1,6 +1,10 @@
 use super::{
     Codec,
     CodecError,
+    CompactSize,
+    Feature,
+    Fields,
+    ProtocolVersion,
     ReadBytes,
 };
 
@@ -9,27 +13,33 @@ use crate::p2p::{
         NetworkAddress,
         Services,
     },
+    report::{
+        write_field,
+        Pretty,
+    },
+    runtime::SystemTime,
     NodeConfig,
 };
 
 use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
     net::SocketAddr,
     time::{
         Duration,
-        SystemTime,
         UNIX_EPOCH,
     },
 };
 
-use rand::{
-    thread_rng,
-    Rng,
-};
+/// Longest user agent accepted, matching Bitcoin Core's limit
+pub const MAX_USER_AGENT_LENGTH: usize = 256;
 
 #[derive(Debug)]
 pub struct VersionMessage {
     /// Protocol version used by the node
-    pub version: i32,
+    pub version: ProtocolVersion,
     /// Features to be enabled for the current connection
     pub services: Services,
     /// Standard UNIX timestamp in seconds
@@ -49,13 +59,18 @@ pub struct VersionMessage {
 }
 
 impl VersionMessage {
+    /// Creates the message from the configuration, using the current time
+    /// and a nonce from the nonce source unless they are set in the configuration.
     pub fn new(receiver: SocketAddr, config: &NodeConfig) -> Self {
-        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(v) => v.as_secs() as i64,
-            Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-        };
-
-        let mut rng = thread_rng();
+        let timestamp = config.timestamp.unwrap_or_else(|| {
+            match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+                Ok(v) => v.as_secs() as i64,
+                Err(_) => panic!("SystemTime before UNIX EPOCH!"),
+            }
+        });
+        let nonce = config
+            .nonce
+            .unwrap_or_else(|| config.nonce_source.next_nonce());
 
         Self {
             version: config.version,
@@ -63,79 +78,126 @@ impl VersionMessage {
             timestamp,
             receiver: NetworkAddress::new(config.services, receiver),
             sender: NetworkAddress::empty(),
-            nonce: rng.gen::<u64>(),
+            nonce,
             user_agent: config.user_agent.clone(),
             start_height: config.start_height,
             relay: config.relay,
         }
     }
 
-    /// Gets the UNIX timestamp
-    #[allow(dead_code)]
+    /// Gets the UNIX timestamp, the epoch itself if the node sent a time
+    /// before it
     pub fn timestamp(&self) -> Duration {
-        Duration::from_secs(self.timestamp.try_into().unwrap())
+        Duration::from_secs(self.timestamp.try_into().unwrap_or_default())
+    }
+
+    /// Gets the UNIX timestamp in seconds as sent, negative if before the
+    /// epoch
+    pub fn timestamp_secs(&self) -> i64 {
+        self.timestamp
     }
 
     /// Gets the receiver's node address
-    #[allow(dead_code)]
     pub fn receiver(&self) -> &NetworkAddress {
         &self.receiver
     }
 
+    /// Gets the sender's node address
+    pub fn sender(&self) -> &NetworkAddress {
+        &self.sender
+    }
+
     /// Gets the random nonce
-    #[allow(dead_code)]
     pub fn nonce(&self) -> u64 {
         self.nonce
     }
 }
 
+impl Display for Pretty<'_, VersionMessage> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let msg = self.0;
+        let timestamp = UNIX_EPOCH + msg.timestamp();
+
+        write_field(f, "Version", msg.version)?;
+        write_field(f, "Services", msg.services)?;
+        write_field(
+            f,
+            "Timestamp",
+            format!(
+                "{} ({})",
+                humantime::format_rfc3339_seconds(timestamp),
+                msg.timestamp
+            ),
+        )?;
+        write_field(f, "Receiver", msg.receiver)?;
+        write_field(f, "Sender", msg.sender)?;
+        write_field(f, "Nonce", format!("{:#018x}", msg.nonce))?;
+        write_field(f, "User agent", &msg.user_agent)?;
+        write_field(f, "Start height", msg.start_height)?;
+        write_field(f, "Relay", msg.relay)
+    }
+}
+
 impl Codec for VersionMessage {
-    fn encode(&self) -> Vec<u8> {
-        let mut data = Vec::<u8>::new();
-        data.extend_from_slice(&self.version.to_le_bytes());
+    fn encoded_len(&self) -> usize {
+        let mut len = std::mem::size_of::<i32>()
+            + self.services.encoded_len()
+            + std::mem::size_of::<i64>()
+            + self.receiver.encoded_len();
+
+        if !self.version.supports(Feature::VersionFields) {
+            return len;
+        }
 
-        let services_data = self.services.encode();
-        data.extend(services_data);
-        data.extend_from_slice(&self.timestamp.to_le_bytes());
+        len += self.sender.encoded_len()
+            + std::mem::size_of::<u64>()
+            + CompactSize(self.user_agent.len() as u64).encoded_len()
+            + self.user_agent.len()
+            + std::mem::size_of::<i32>();
 
-        let to_net_address_data = self.receiver.encode();
-        data.extend(to_net_address_data);
+        if !self.version.supports(Feature::Relay) {
+            return len;
+        }
 
-        if self.version < 106 {
-            return data;
+        len + std::mem::size_of::<u8>()
+    }
+
+    fn encode_into(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(&self.version.0.to_le_bytes());
+        self.services.encode_into(data);
+        data.extend_from_slice(&self.timestamp.to_le_bytes());
+        self.receiver.encode_into(data);
+
+        if !self.version.supports(Feature::VersionFields) {
+            return;
         }
 
-        let from_net_address_data = self.sender.encode();
-        data.extend(from_net_address_data);
-        data.extend_from_slice(&self.nonce.to_be_bytes());
+        self.sender.encode_into(data);
+        data.extend_from_slice(&self.nonce.to_le_bytes());
 
-        // Encode user agent (byte indicating field length + string)
-        data.push(self.user_agent.len() as u8);
+        // Encode user agent (length + string)
+        CompactSize(self.user_agent.len() as u64).encode_into(data);
         if !self.user_agent.is_empty() {
-            data.extend_from_slice(&self.user_agent.as_bytes());
+            data.extend_from_slice(self.user_agent.as_bytes());
         }
 
         data.extend_from_slice(&self.start_height.to_le_bytes());
 
-        if self.version < 70001 {
-            return data;
+        if !self.version.supports(Feature::Relay) {
+            return;
         }
 
         data.push(self.relay as u8);
-        data
     }
 
     fn decode(data: &mut &[u8]) -> Result<Self, CodecError> {
-        let version = data
-            .read_le::<i32>()
-            .ok_or(CodecError::InsufficientBytesError)?;
-        let services = Services::decode(data)?;
-        let timestamp = data
-            .read_le::<i64>()
-            .ok_or(CodecError::InsufficientBytesError)?;
-        let receiver = NetworkAddress::decode(data)?;
-
-        if version < 106 {
+        let mut fields = Fields::new(data);
+        let version = ProtocolVersion(fields.read_le::<i32>("version")?);
+        let services = fields.decode("services", Services::decode)?;
+        let timestamp = fields.read_le::<i64>("timestamp")?;
+        let receiver = fields.decode("receiver", NetworkAddress::decode)?;
+
+        if !version.supports(Feature::VersionFields) {
             return Ok(Self {
                 version,
                 services,
@@ -149,27 +211,29 @@ impl Codec for VersionMessage {
             });
         }
 
-        let sender = NetworkAddress::decode(data)?;
-        let nonce = data
-            .read_be::<u64>()
-            .ok_or(CodecError::InsufficientBytesError)?;
-        let user_agent_length = data
-            .read_be::<u8>()
-            .ok_or(CodecError::InsufficientBytesError)?;
-
-        let mut user_agent = String::new();
-        if user_agent_length != 0 {
-            let user_agent_data = data
-                .read_slice(user_agent_length as usize)
-                .ok_or(CodecError::InsufficientBytesError)?;
-            user_agent = std::str::from_utf8(user_agent_data).unwrap().to_string();
-        }
-
-        let start_height = data
-            .read_le::<i32>()
-            .ok_or(CodecError::InsufficientBytesError)?;
-
-        if version < 70001 {
+        let sender = fields.decode("sender", NetworkAddress::decode)?;
+        let nonce = fields.read_le::<u64>("nonce")?;
+        let user_agent = fields.decode("user_agent", |data| {
+            let CompactSize(user_agent_length) = CompactSize::decode(data)?;
+            if user_agent_length > MAX_USER_AGENT_LENGTH as u64 {
+                return Err(CodecError::OversizedFieldError);
+            }
+
+            let mut user_agent = String::new();
+            if user_agent_length != 0 {
+                let user_agent_data = data
+                    .read_slice(user_agent_length as usize)
+                    .ok_or(CodecError::InsufficientBytesError)?;
+                user_agent = std::str::from_utf8(user_agent_data)
+                    .map_err(|_| CodecError::InvalidBytesError)?
+                    .to_string();
+            }
+            Ok(user_agent)
+        })?;
+
+        let start_height = fields.read_le::<i32>("start_height")?;
+
+        if !version.supports(Feature::Relay) {
             return Ok(Self {
                 version,
                 services,
@@ -183,10 +247,7 @@ impl Codec for VersionMessage {
             });
         }
 
-        let relay = data
-            .read_le::<u8>()
-            .ok_or(CodecError::InsufficientBytesError)?
-            == 0x01;
+        let relay = fields.read_le::<u8>("relay")? != 0x00;
 
         Ok(Self {
             version,
@@ -203,6 +264,7 @@ impl Codec for VersionMessage {
 }
 
 #[cfg(test)]
+#[allow(clippy::needless_borrow, clippy::bool_assert_comparison)]
 mod tests {
     #[rustfmt::skip] 
     mod unformatted {
@@ -247,7 +309,7 @@ mod tests {
                 // Port
                 0x00, 0x00,
             // Nonce
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x39,
+            0x39, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             // User agent
             0x0f, 0x2f, 0x53, 0x61, 0x74, 0x6f, 0x73, 0x68,
             0x69, 0x3a, 0x30, 0x2e, 0x37, 0x2e, 0x32, 0x2f,
@@ -279,7 +341,7 @@ mod tests {
                 // Port
                 0x00, 0x00,
             // Nonce
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x39,
+            0x39, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             // User agent
             0x00,
             // Start height
@@ -292,16 +354,23 @@ mod tests {
     use super::*;
     use unformatted::*;
 
-    use std::net::{
-        IpAddr,
-        Ipv4Addr,
-        SocketAddr,
+    use std::{
+        net::{
+            IpAddr,
+            Ipv4Addr,
+            SocketAddr,
+        },
+        sync::Arc,
     };
 
     use chrono::prelude::*;
     use lazy_static::lazy_static;
 
-    use crate::p2p::messages::Service;
+    use crate::p2p::{
+        messages::Service,
+        NonceSource,
+        SeededNonce,
+    };
 
     lazy_static! {
         static ref SERVICES: Services = Services::new(&[Service::Network]);
@@ -319,7 +388,7 @@ mod tests {
     #[test]
     fn encode_version_lt_106() {
         let msg = VersionMessage {
-            version: 100,
+            version: ProtocolVersion(100),
             services: *SERVICES,
             timestamp: *TIMESTAMP,
             receiver: *RECEIVER,
@@ -330,13 +399,14 @@ mod tests {
             start_height: 0,
             relay: false,
         };
+        assert_eq!(msg.encoded_len(), RAW_VERSION_MSG_LT_106.len());
         assert_eq!(msg.encode(), RAW_VERSION_MSG_LT_106);
     }
 
     #[test]
     fn encode_version_lt_70001() {
         let msg = VersionMessage {
-            version: 70000,
+            version: ProtocolVersion(70000),
             services: *SERVICES,
             timestamp: *TIMESTAMP,
             receiver: *RECEIVER,
@@ -347,13 +417,14 @@ mod tests {
             // below fields are not used during encoding when version is < 70001
             relay: false,
         };
+        assert_eq!(msg.encoded_len(), RAW_VERSION_MSG_LT_70001.len());
         assert_eq!(msg.encode(), RAW_VERSION_MSG_LT_70001);
     }
 
     #[test]
     fn encode_version_ge_70001() {
         let msg = VersionMessage {
-            version: 70001,
+            version: ProtocolVersion(70001),
             services: *SERVICES,
             timestamp: *TIMESTAMP,
             receiver: *RECEIVER,
@@ -363,9 +434,42 @@ mod tests {
             start_height: 212672,
             relay: true,
         };
+        assert_eq!(msg.encoded_len(), RAW_VERSION_MSG_GE_70001.len());
         assert_eq!(msg.encode(), RAW_VERSION_MSG_GE_70001);
     }
 
+    #[test]
+    fn long_user_agent() {
+        let msg = VersionMessage {
+            version: ProtocolVersion(70001),
+            services: *SERVICES,
+            timestamp: *TIMESTAMP,
+            receiver: *RECEIVER,
+            sender: NetworkAddress::empty(),
+            nonce: 12345,
+            user_agent: "a".repeat(300),
+            start_height: 212672,
+            relay: true,
+        };
+        let raw = msg.encode();
+        assert_eq!(raw.len(), msg.encoded_len());
+        // Length prefixed by the 0xfd marker and 2 bytes
+        assert_eq!(raw[80..83], [0xfd, 0x2c, 0x01]);
+
+        // Longer than nodes accept
+        assert_eq!(
+            VersionMessage::decode(&mut &raw[..]).err(),
+            Some(CodecError::OversizedFieldError.at("user_agent", 80))
+        );
+
+        let msg = VersionMessage {
+            user_agent: "a".repeat(MAX_USER_AGENT_LENGTH),
+            ..msg
+        };
+        let decoded = VersionMessage::decode(&mut &msg.encode()[..]).unwrap();
+        assert_eq!(decoded.user_agent, msg.user_agent);
+    }
+
     #[test]
     fn decode_version_lt_106() {
         let mut data: &[u8] = &RAW_VERSION_MSG_LT_106;
@@ -373,7 +477,7 @@ mod tests {
         assert!(result.is_ok());
 
         let msg = result.unwrap();
-        assert_eq!(msg.version, 100);
+        assert_eq!(msg.version, ProtocolVersion(100));
         assert_eq!(msg.services, *SERVICES);
         assert_eq!(msg.timestamp().as_secs() as i64, *TIMESTAMP);
         assert_eq!(*msg.receiver(), *RECEIVER);
@@ -386,7 +490,7 @@ mod tests {
         assert!(result.is_ok());
 
         let msg = result.unwrap();
-        assert_eq!(msg.version, 70000);
+        assert_eq!(msg.version, ProtocolVersion(70000));
         assert_eq!(msg.services, *SERVICES);
         assert_eq!(msg.timestamp().as_secs() as i64, *TIMESTAMP);
         assert_eq!(*msg.receiver(), *RECEIVER);
@@ -402,7 +506,7 @@ mod tests {
         assert!(result.is_ok());
 
         let msg = result.unwrap();
-        assert_eq!(msg.version, 70001);
+        assert_eq!(msg.version, ProtocolVersion(70001));
         assert_eq!(msg.services, *SERVICES);
         assert_eq!(msg.timestamp().as_secs() as i64, *TIMESTAMP);
         assert_eq!(*msg.receiver(), *RECEIVER);
@@ -411,4 +515,91 @@ mod tests {
         assert_eq!(msg.start_height, 212672);
         assert_eq!(msg.relay, true);
     }
+
+    #[test]
+    fn decode_malformed() {
+        // Relay set to anything but zero
+        let mut raw = RAW_VERSION_MSG_GE_70001.to_vec();
+        *raw.last_mut().unwrap() = 0x02;
+        assert!(VersionMessage::decode(&mut &raw[..]).unwrap().relay);
+
+        // Timestamp before the epoch
+        raw[12..20].copy_from_slice(&(-1_i64).to_le_bytes());
+        let msg = VersionMessage::decode(&mut &raw[..]).unwrap();
+        assert_eq!(msg.timestamp(), Duration::ZERO);
+
+        // User agent not in UTF-8
+        let mut raw = RAW_VERSION_MSG_LT_70001.to_vec();
+        raw[81] = 0xff;
+        assert_eq!(
+            VersionMessage::decode(&mut &raw[..]).err(),
+            Some(CodecError::InvalidBytesError.at("user_agent", 80))
+        );
+    }
+
+    #[test]
+    fn field_error() {
+        // Truncated in the middle of the receiver's port
+        let e = VersionMessage::decode(&mut &RAW_VERSION_MSG_GE_70001[..45]).unwrap_err();
+        assert_eq!(e.kind(), &CodecError::InsufficientBytesError);
+        assert_eq!(e.field().as_deref(), Some("receiver.port"));
+        assert_eq!(e.offset(), Some(44));
+        assert_eq!(e.code(), "E_INSUFFICIENT_BYTES");
+        assert_eq!(
+            e.to_string(),
+            "Insufficient amount of bytes provided during decoding of field \
+            receiver.port at offset 44"
+        );
+    }
+
+    #[test]
+    fn new_with_overrides() {
+        let config = NodeConfig {
+            version: ProtocolVersion(70015),
+            services: *SERVICES,
+            user_agent: String::from("/handshaker:0.1.0/"),
+            start_height: 212672,
+            relay: true,
+            nonce: Some(12345),
+            timestamp: Some(*TIMESTAMP),
+            ..Default::default()
+        };
+
+        let msg = VersionMessage::new(*SOCKET, &config);
+        assert_eq!(msg.nonce(), 12345);
+        assert_eq!(msg.timestamp().as_secs() as i64, *TIMESTAMP);
+        assert_eq!(msg.encode(), VersionMessage::new(*SOCKET, &config).encode());
+    }
+
+    #[test]
+    fn new_with_nonce_source() {
+        let config = NodeConfig {
+            nonce_source: Arc::new(SeededNonce::new(42)),
+            ..Default::default()
+        };
+
+        let nonce = SeededNonce::new(42).next_nonce();
+        assert_eq!(VersionMessage::new(*SOCKET, &config).nonce(), nonce);
+    }
+
+    #[test]
+    fn pretty() {
+        let mut data: &[u8] = RAW_VERSION_MSG_LT_70001;
+        let msg = VersionMessage::decode(&mut data).unwrap();
+
+        assert_eq!(
+            Pretty(&msg).to_string(),
+            concat!(
+                "  Version         70000\n",
+                "  Services        [Network]\n",
+                "  Timestamp       2010-12-21T02:50:14Z (1292899814)\n",
+                "  Receiver        10.0.0.1:8333 [Network]\n",
+                "  Sender          [::]:0 []\n",
+                "  Nonce           0x0000000000003039\n",
+                "  User agent      /Satoshi:0.7.2/\n",
+                "  Start height    212672\n",
+                "  Relay           false\n",
+            )
+        );
+    }
 }
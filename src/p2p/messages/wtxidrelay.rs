@@ -0,0 +1,34 @@
+use super::{
+    Codec,
+    CodecError,
+};
+
+/// WtxidRelay message (BIP339) is sent between Version and Verack
+/// messages to announce that transactions are relayed by their wtxid.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WtxidRelayMessage {}
+
+impl Codec for WtxidRelayMessage {
+    fn encoded_len(&self) -> usize {
+        0
+    }
+
+    fn encode_into(&self, _data: &mut Vec<u8>) {}
+
+    fn decode(_data: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Self {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let msg = WtxidRelayMessage {};
+        assert_eq!(msg.encoded_len(), 0);
+        assert!(msg.encode().is_empty());
+        assert_eq!(WtxidRelayMessage::decode(&mut &[][..]), Ok(msg));
+    }
+}
@@ -1,48 +1,169 @@
 use std::{
+    collections::VecDeque,
     fmt::{
         Display,
         Formatter,
     },
+    marker::PhantomData,
     net::{
         SocketAddr,
         SocketAddrV4,
     },
+    sync::Arc,
+    time::Duration,
 };
 
+use futures::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+};
 use log::{
+    debug,
     error,
     info,
     warn,
 };
-use tokio::{
-    io::{
-        AsyncReadExt,
-        AsyncWriteExt,
-    },
-    net::TcpStream,
-};
+use strum::IntoEnumIterator;
 
 use crate::p2p::{
+    connection::{
+        ReceiveBuffer,
+        MAX_PAYLOAD_LENGTH,
+        READ_CHUNK_LENGTH,
+    },
+    height::{
+        HeightSource,
+        StaticHeight,
+    },
     messages::{
-        calculate_checksum,
         compose,
-        Codec,
+        Chain,
         CodecError,
         Command,
-        MessageHeader,
+        Feature,
+        FrameHeader,
+        Message,
         Network,
         PingMessage,
         PongMessage,
+        ProtocolVersion,
+        SendTxRcnclMessage,
         Services,
         VerackMessage,
         VersionMessage,
     },
+    nonce::{
+        NonceSource,
+        RandomNonce,
+    },
+    policy::{
+        AllowAll,
+        TargetPolicy,
+    },
+    report::{
+        write_field,
+        Pretty,
+    },
+    runtime::{
+        timeout,
+        DefaultRuntime,
+        Instant,
+        Runtime,
+        SystemTime,
+    },
+    stats::CommandStats,
+    timeline::{
+        Direction,
+        TimelineEvent,
+    },
+    timeout::{
+        AdaptiveTimeout,
+        Stage,
+        StageTimeouts,
+    },
+    transport::{
+        Transport,
+        TransportVersion,
+    },
+    Connection,
     ConnectionError,
+    Destination,
+    HandshakeError,
+    Observation,
+    ProxyError,
+    Socks5Proxy,
 };
 
+#[cfg(feature = "addr")]
+use crate::p2p::messages::{
+    AddrV2,
+    AddrV2Message,
+    SendAddrV2Message,
+    TimestampedAddrV2,
+};
+#[cfg(feature = "blocks")]
+use crate::p2p::messages::{
+    FeeFilterMessage,
+    SendCmpctMessage,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::p2p::transport::v2::V2Stream;
+
+/// Outcome of a handshake, i.e. report about the other node on success.
+pub type HandshakeResult = Result<HandshakeReport, ConnectionError>;
+
+/// Number of gossiped addresses kept in the report
+#[cfg(feature = "addr")]
+const ADDR_SAMPLE_SIZE: usize = 10;
+
+/// Commands negotiating features of the connection, i.e. wtxid relay
+/// (BIP339), addrv2 (BIP155) and Erlay (BIP330)
+const FEATURE_NEGOTIATION_COMMANDS: [&str; 3] =
+    ["wtxidrelay", "sendaddrv2", "sendtxrcncl"];
+
+/// Checks whether the feature negotiation message comes too late, i.e.
+/// after the Verack message of a node speaking at least version 70016
+fn late_negotiation(
+    command: &str,
+    version: ProtocolVersion,
+    verack_received: bool,
+) -> bool {
+    // Feature negotiation between the Version and Verack messages was
+    // introduced together with wtxid relay (BIP339)
+    verack_received
+        && version.supports(Feature::WtxidRelay)
+        && FEATURE_NEGOTIATION_COMMANDS.contains(&command)
+}
+
+/// Timing of the reads performed during a handshake.
+struct ReadClock {
+    /// Address of the other node
+    address: SocketAddr,
+    /// When our first message was sent
+    sent: Instant,
+    /// Time spent waiting for data after the first byte
+    stalled: Duration,
+    /// Longest time to wait for each read
+    message_timeout: Option<Duration>,
+    /// Stage of the handshake awaited and when it times out, if limited
+    stage: Option<(Stage, Option<Instant>)>,
+}
+
+impl ReadClock {
+    /// Starts timing the stage, unless it is the one already awaited
+    fn enter(&mut self, stage: Stage, timeouts: &StageTimeouts) {
+        if self.stage.map(|(s, _)| s) != Some(stage) {
+            let deadline = timeouts.get(stage).map(|t| Instant::now() + t);
+            self.stage = Some((stage, deadline));
+        }
+    }
+}
+
 pub struct NodeConfig {
     /// Protocol version used by the node
-    pub version: i32,
+    pub version: ProtocolVersion,
     /// Features to be enabled for the connection
     pub services: Services,
     /// User agent
@@ -51,16 +172,29 @@ pub struct NodeConfig {
     pub start_height: i32,
     /// Whether the remote peer should announce relayed transactions or not
     pub relay: bool,
+    /// Nonce sent in the Version message, random if not set
+    pub nonce: Option<u64>,
+    /// UNIX timestamp sent in the Version message, current time if not set
+    pub timestamp: Option<i64>,
+    /// Source of nonces for Version and Ping messages
+    pub nonce_source: Arc<dyn NonceSource>,
+    /// Whether to wait for the other node's Version message before sending
+    /// ours, i.e. act as the responder even on connections we initiate
+    pub responder: bool,
 }
 
 impl Default for NodeConfig {
     fn default() -> Self {
         Self {
-            version: 0,
+            version: ProtocolVersion::default(),
             services: Services::empty(),
             user_agent: String::new(),
             start_height: 0,
             relay: false,
+            nonce: None,
+            timestamp: None,
+            nonce_source: Arc::new(RandomNonce),
+            responder: false,
         }
     }
 }
@@ -75,14 +209,573 @@ impl Display for NodeConfig {
     }
 }
 
-pub struct Node {
+impl Display for Pretty<'_, NodeConfig> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let config = self.0;
+        write_field(f, "Version", config.version)?;
+        write_field(f, "Services", config.services)?;
+        write_field(f, "User agent", &config.user_agent)?;
+        write_field(f, "Start height", config.start_height)?;
+        write_field(f, "Relay", config.relay)
+    }
+}
+
+/// Addresses gossiped by the node in response to the GetAddr message.
+#[cfg(feature = "addr")]
+#[derive(Debug, Default)]
+pub struct AddrSample {
+    /// Number of addresses received
+    pub total: usize,
+    /// First of the addresses received
+    pub addresses: Vec<TimestampedAddrV2>,
+}
+
+/// Information about the other node gathered during the handshake.
+#[derive(Default)]
+pub struct HandshakeReport {
+    /// Configuration advertised in the other node's Version message
+    pub config: NodeConfig,
+    /// Network the handshake was performed on
+    pub network: Option<Network>,
+    /// Protocol version spoken on the connection, i.e. the lower one of
+    /// the versions of both nodes
+    pub negotiated_version: Option<ProtocolVersion>,
+    /// Our address as seen by the node, i.e. the receiver address of its
+    /// Version message
+    pub receiver: Option<SocketAddr>,
+    /// Difference between the node's clock and ours in seconds
+    pub time_offset: Option<i64>,
+    /// Time it took to open the TCP connection
+    pub connect_time: Option<Duration>,
+    /// Transport the messages were exchanged over
+    pub transport: TransportVersion,
+    /// Erlay transaction reconciliation parameters, if announced
+    pub txrcncl: Option<SendTxRcnclMessage>,
+    /// Whether the node relays transactions by wtxid (BIP339)
+    pub wtxidrelay: bool,
+    /// Whether the node asked for addresses to be announced with AddrV2
+    /// messages (BIP155)
+    #[cfg(feature = "addr")]
+    pub sendaddrv2: bool,
+    /// Whether the node asked for blocks to be announced with Headers
+    /// messages (BIP130)
+    #[cfg(feature = "blocks")]
+    pub sendheaders: bool,
+    /// Highest compact block version announced (BIP152)
+    #[cfg(feature = "blocks")]
+    pub sendcmpct: Option<SendCmpctMessage>,
+    /// Minimum fee rate of transactions announced to the node (BIP133)
+    #[cfg(feature = "blocks")]
+    pub feefilter: Option<FeeFilterMessage>,
+    /// Messages exchanged during the session
+    pub stats: CommandStats,
+    /// Messages exchanged during the session in the order of their exchange
+    pub timeline: Vec<TimelineEvent>,
+    /// Number of messages received out of the protocol order, e.g. before
+    /// the Version message or feature negotiation after the Verack message
+    pub violations: u32,
+    /// Time from sending the Version message, or connecting in the
+    /// responder role, to receiving the first byte
+    pub first_byte: Option<Duration>,
+    /// Longest wait for further data once the node started sending
+    pub longest_gap: Duration,
+    /// Addresses gossiped by the node, if requested
+    #[cfg(feature = "addr")]
+    pub addr_sample: Option<AddrSample>,
+    /// Messages the node sent unsolicited after the handshake, if the
+    /// connection was kept open to observe them
+    pub observation: Option<Observation>,
+}
+
+impl HandshakeReport {
+    /// Gets the time between sending the Ping message and receiving
+    /// the Pong message, if both took place
+    pub fn ping_rtt(&self) -> Option<Duration> {
+        let ping = self
+            .timeline
+            .iter()
+            .rfind(|e| e.direction == Direction::Sent && e.command == "ping")?;
+        let pong = self.timeline.iter().find(|e| {
+            e.direction == Direction::Received
+                && e.command == "pong"
+                && e.elapsed >= ping.elapsed
+        })?;
+        Some(pong.elapsed - ping.elapsed)
+    }
+
+    /// Gets the time between sending our Version message and receiving
+    /// the node's one, if the node answered ours rather than spoke first
+    pub fn version_rtt(&self) -> Option<Duration> {
+        let sent = self
+            .timeline
+            .iter()
+            .find(|e| e.direction == Direction::Sent && e.command == "version")?;
+        let received = self.timeline.iter().find(|e| {
+            e.direction == Direction::Received
+                && e.command == "version"
+                && e.elapsed >= sent.elapsed
+        })?;
+        Some(received.elapsed - sent.elapsed)
+    }
+
+    /// Gets the time between sending our Version message and receiving
+    /// the node's Verack message, i.e. the handshake proper
+    pub fn verack_rtt(&self) -> Option<Duration> {
+        let sent = self
+            .timeline
+            .iter()
+            .find(|e| e.direction == Direction::Sent && e.command == "version")?;
+        let received = self.timeline.iter().find(|e| {
+            e.direction == Direction::Received
+                && e.command == "verack"
+                && e.elapsed >= sent.elapsed
+        })?;
+        Some(received.elapsed - sent.elapsed)
+    }
+
+    /// Gets the time from connecting to receiving the message completing
+    /// the handshake, i.e. the Pong message, or the Verack message from
+    /// nodes not answering Ping messages. Unlike the duration of the whole
+    /// session, it leaves out whatever is done once the handshake is over.
+    pub fn handshake_time(&self) -> Option<Duration> {
+        let received = |command: &str| {
+            self.timeline
+                .iter()
+                .find(|e| e.direction == Direction::Received && e.command == command)
+        };
+        received("pong")
+            .or_else(|| received("verack"))
+            .map(|e| e.elapsed)
+    }
+
+    /// Records the message sent, keeping its raw bytes in the timeline
+    /// only if asked to
+    fn record_sent(
+        &mut self,
+        command: Command,
+        frame: &[u8],
+        keep_frame: bool,
+        elapsed: Duration,
+    ) {
+        self.stats.record_sent(command, frame.len());
+        self.timeline.push(TimelineEvent {
+            elapsed,
+            direction: Direction::Sent,
+            command: command.name().to_string(),
+            length: frame.len(),
+            frame: keep_frame.then(|| frame.to_vec()),
+        });
+    }
+
+    /// Records the message received like [`record_sent`](Self::record_sent)
+    fn record_received(
+        &mut self,
+        command: &str,
+        frame: &[u8],
+        keep_frame: bool,
+        elapsed: Duration,
+    ) {
+        self.stats.record_received(command, frame.len());
+        self.timeline.push(TimelineEvent {
+            elapsed,
+            direction: Direction::Received,
+            command: command.to_string(),
+            length: frame.len(),
+            frame: keep_frame.then(|| frame.to_vec()),
+        });
+    }
+}
+
+impl Display for HandshakeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.config)?;
+        if let Some(network) = self.network {
+            write!(f, ", network: {:?}", network)?;
+        }
+        if self.transport == TransportVersion::V2 {
+            write!(f, ", v2 transport")?;
+        }
+        if let Some(txrcncl) = self.txrcncl {
+            write!(f, ", erlay version: {}", txrcncl.version)?;
+        }
+        if self.wtxidrelay {
+            write!(f, ", wtxid relay")?;
+        }
+        #[cfg(feature = "addr")]
+        if self.sendaddrv2 {
+            write!(f, ", addrv2")?;
+        }
+        #[cfg(feature = "blocks")]
+        {
+            if self.sendheaders {
+                write!(f, ", headers announcements")?;
+            }
+            if let Some(sendcmpct) = self.sendcmpct {
+                write!(f, ", compact blocks version: {}", sendcmpct.version)?;
+            }
+            if let Some(feefilter) = self.feefilter {
+                write!(f, ", fee filter: {} sat/kvB", feefilter.fee_rate)?;
+            }
+        }
+        #[cfg(feature = "addr")]
+        if let Some(sample) = &self.addr_sample {
+            write!(f, ", addresses: {}", sample.total)?;
+        }
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        if let Some(connect_time) = self.connect_time {
+            write!(f, ", connect: {:.1} ms", ms(connect_time))?;
+        }
+        if let Some(rtt) = self.verack_rtt() {
+            write!(f, ", verack rtt: {:.1} ms", ms(rtt))?;
+        }
+        if let Some(rtt) = self.ping_rtt() {
+            write!(f, ", ping rtt: {:.1} ms", ms(rtt))?;
+        }
+        if let Some(observation) = &self.observation {
+            write!(f, ", observed: {}", observation)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Pretty<'_, HandshakeReport> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let report = self.0;
+        write!(f, "{}", Pretty(&report.config))?;
+        if let Some(network) = report.network {
+            write_field(f, "Network", format!("{:?}", network))?;
+        }
+        write_field(f, "Transport", report.transport)?;
+        if let Some(version) = report.negotiated_version {
+            write_field(f, "Negotiated", version)?;
+        }
+        if let Some(nonce) = report.config.nonce {
+            write_field(f, "Nonce", format!("{:#018x}", nonce))?;
+        }
+        if let Some(offset) = report.time_offset {
+            write_field(f, "Time offset", format!("{} s", offset))?;
+        }
+        if let Some(receiver) = report.receiver {
+            write_field(f, "Seen as", receiver)?;
+        }
+        match report.txrcncl {
+            Some(txrcncl) => {
+                write_field(f, "Erlay", format!("version {}", txrcncl.version))?
+            }
+            None => write_field(f, "Erlay", "not supported")?,
+        }
+        write_field(
+            f,
+            "Wtxid relay",
+            if report.wtxidrelay {
+                "supported"
+            } else {
+                "not supported"
+            },
+        )?;
+        #[cfg(feature = "addr")]
+        write_field(
+            f,
+            "AddrV2",
+            if report.sendaddrv2 {
+                "supported"
+            } else {
+                "not supported"
+            },
+        )?;
+        #[cfg(feature = "blocks")]
+        {
+            write_field(
+                f,
+                "Send headers",
+                if report.sendheaders {
+                    "requested"
+                } else {
+                    "not requested"
+                },
+            )?;
+            match report.sendcmpct {
+                Some(sendcmpct) => write_field(
+                    f,
+                    "Compact blocks",
+                    format!(
+                        "version {}, {} bandwidth",
+                        sendcmpct.version,
+                        if sendcmpct.announce { "high" } else { "low" }
+                    ),
+                )?,
+                None => write_field(f, "Compact blocks", "not supported")?,
+            }
+            match report.feefilter {
+                Some(feefilter) => write_field(
+                    f,
+                    "Fee filter",
+                    format!("{} sat/kvB", feefilter.fee_rate),
+                )?,
+                None => write_field(f, "Fee filter", "not set")?,
+            }
+        }
+        write!(f, "{}", Pretty(&report.stats))?;
+        write_field(f, "Violations", report.violations)?;
+        let ms =
+            |duration: Duration| format!("{:.1} ms", duration.as_secs_f64() * 1000.0);
+        if let Some(connect_time) = report.connect_time {
+            write_field(f, "Connect", ms(connect_time))?;
+        }
+        if let Some(rtt) = report.version_rtt() {
+            write_field(f, "Version RTT", ms(rtt))?;
+        }
+        if let Some(rtt) = report.verack_rtt() {
+            write_field(f, "Verack RTT", ms(rtt))?;
+        }
+        if let Some(rtt) = report.ping_rtt() {
+            write_field(f, "Ping RTT", ms(rtt))?;
+        }
+        if let Some(first_byte) = report.first_byte {
+            write_field(
+                f,
+                "First byte",
+                format!("{:.1} ms", first_byte.as_secs_f64() * 1000.0),
+            )?;
+            write_field(
+                f,
+                "Longest gap",
+                format!("{:.1} ms", report.longest_gap.as_secs_f64() * 1000.0),
+            )?;
+        }
+        #[cfg(feature = "addr")]
+        if let Some(sample) = &report.addr_sample {
+            write_field(f, "Addresses", format!("{} gossiped", sample.total))?;
+            for address in &sample.addresses {
+                write_field(f, "", address)?;
+            }
+        }
+        if let Some(observation) = &report.observation {
+            write!(f, "{}", Pretty(observation))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Node<R: Runtime = DefaultRuntime> {
     /// Configuration set at the application start
     config: NodeConfig,
+    /// Source of the start height advertised in Version messages
+    height_source: Box<dyn HeightSource>,
+    /// Decides which addresses connections may be attempted to
+    target_policy: Box<dyn TargetPolicy>,
+    /// Time to wait for each message, as long as it takes if not set
+    adaptive_timeout: Option<AdaptiveTimeout>,
+    /// Total time the node may keep us waiting for further data once it
+    /// started sending, as long as it takes if not set
+    stall_budget: Option<Duration>,
+    /// Time each stage of the handshake may take
+    stage_timeouts: StageTimeouts,
+    /// Whether to only warn about payloads not matching their header's
+    /// length or checksum
+    lenient: bool,
+    /// Longest payload accepted during the handshake
+    max_payload_length: u32,
+    /// Whether to keep the raw messages exchanged in the report's timeline
+    keep_frames: bool,
+    /// Time to wait for addresses after the handshake, not requested if not set
+    #[cfg(feature = "addr")]
+    addr_request: Option<Duration>,
+    /// Time to keep the connection open for after the handshake, closed
+    /// right away if not set
+    observation: Option<Duration>,
+    /// SOCKS5 proxy connections are opened through, direct if not set
+    proxy: Option<Socks5Proxy>,
+    /// Our own address and port announced to nodes accepting AddrV2
+    /// messages, not announced if not set
+    #[cfg(feature = "addr")]
+    local_address: Option<(AddrV2, u16)>,
+    /// Time to wait for the v2 key exchange before falling back to the v1
+    /// transport, v1 spoken right away if not set
+    #[cfg(not(target_arch = "wasm32"))]
+    v2_transport: Option<Duration>,
+    runtime: PhantomData<R>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Node {
     pub fn new(config: NodeConfig) -> Self {
-        Self { config }
+        Self::on_runtime(config)
+    }
+}
+
+#[cfg(all(unix, not(target_arch = "wasm32")))]
+impl Node {
+    /// Performs the handshake with the node listening on the Unix domain
+    /// socket at the given path, see [`Node::handshake_over`].
+    pub async fn handshake_unix(
+        &self,
+        chain: impl Chain,
+        path: impl AsRef<std::path::Path>,
+    ) -> HandshakeResult {
+        use tokio_util::compat::TokioAsyncReadCompatExt;
+
+        let stream = tokio::net::UnixStream::connect(path).await?;
+        self.handshake_over(chain, stream.compat()).await
+    }
+}
+
+impl<R: Runtime> Node<R> {
+    /// Creates the node speaking the protocol over the given runtime instead
+    /// of tokio, e.g. `Node::<AsyncStd>::on_runtime(config)`.
+    pub fn on_runtime(config: NodeConfig) -> Self {
+        let height_source = Box::new(StaticHeight(config.start_height));
+        Self {
+            config,
+            height_source,
+            target_policy: Box::new(AllowAll),
+            adaptive_timeout: None,
+            stall_budget: None,
+            stage_timeouts: StageTimeouts::default(),
+            lenient: false,
+            max_payload_length: MAX_PAYLOAD_LENGTH,
+            keep_frames: false,
+            #[cfg(feature = "addr")]
+            addr_request: None,
+            observation: None,
+            proxy: None,
+            #[cfg(feature = "addr")]
+            local_address: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            v2_transport: None,
+            runtime: PhantomData,
+        }
+    }
+
+    /// Replaces the start height from the configuration with the one
+    /// provided by the given source.
+    pub fn with_height_source(
+        mut self,
+        height_source: impl HeightSource + 'static,
+    ) -> Self {
+        self.height_source = Box::new(height_source);
+        self
+    }
+
+    /// Consults the given policy before each connection attempt, failing
+    /// the ones to addresses it does not allow.
+    pub fn with_target_policy(mut self, policy: impl TargetPolicy + 'static) -> Self {
+        self.target_policy = Box::new(policy);
+        self
+    }
+
+    /// Checks whether the target policy allows connections to the address,
+    /// e.g. before adding an address discovered from another node
+    pub fn allows(&self, address: SocketAddrV4) -> bool {
+        self.target_policy.allows(address)
+    }
+
+    /// Limits the time to wait for each message, scaling it from the
+    /// round-trip time measured while connecting.
+    pub fn with_adaptive_timeout(mut self, adaptive_timeout: AdaptiveTimeout) -> Self {
+        self.adaptive_timeout = Some(adaptive_timeout);
+        self
+    }
+
+    /// Cuts off nodes trickling data, i.e. fails the handshake with
+    /// [`ConnectionError::Stalled`] once the node kept us waiting for longer
+    /// than the budget in total after its first byte.
+    pub fn with_stall_budget(mut self, budget: Duration) -> Self {
+        self.stall_budget = Some(budget);
+        self
+    }
+
+    /// Limits the time each stage of the handshake may take, failing it
+    /// with [`ConnectionError::StageTimeout`] identifying the stage.
+    pub fn with_stage_timeouts(mut self, timeouts: StageTimeouts) -> Self {
+        self.stage_timeouts = timeouts;
+        self
+    }
+
+    /// Decodes messages whose payload does not match the length or checksum
+    /// in their header instead of failing the handshake, e.g. to debug
+    /// broken nodes.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Fails the handshake once the node announces a payload longer than
+    /// the given number of bytes, instead of [`MAX_PAYLOAD_LENGTH`].
+    pub fn with_max_payload_length(mut self, max_payload_length: u32) -> Self {
+        self.max_payload_length = max_payload_length;
+        self
+    }
+
+    /// Keeps the raw messages exchanged in the timeline of the report, e.g.
+    /// to dissect them, rather than only their lengths.
+    pub fn with_frames(mut self, keep_frames: bool) -> Self {
+        self.keep_frames = keep_frames;
+        self
+    }
+
+    /// Sends the GetAddr message once the handshake is performed, waiting
+    /// up to the given time for addresses to report a sample of.
+    #[cfg(feature = "addr")]
+    pub fn with_addr_request(mut self, wait: Duration) -> Self {
+        self.addr_request = Some(wait);
+        self
+    }
+
+    /// Keeps the connection open for the given time once the handshake is
+    /// performed, answering Ping messages and classifying the messages the
+    /// node sends meanwhile, see [`Connection::observe`]. The observation
+    /// precedes the address request, if any.
+    pub fn with_observation(mut self, window: Duration) -> Self {
+        self.observation = Some(window);
+        self
+    }
+
+    /// Opens connections to nodes through the given SOCKS5 proxy instead
+    /// of directly.
+    pub fn with_proxy(mut self, proxy: Socks5Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Announces our own address, e.g. the onion address of our Tor onion
+    /// service, in an AddrV2 message once the Verack message is received
+    /// from nodes which asked for AddrV2 messages. The Version message
+    /// cannot carry addresses other than IPv4 and IPv6 ones.
+    #[cfg(feature = "addr")]
+    pub fn with_local_address(mut self, address: AddrV2, port: u16) -> Self {
+        self.local_address = Some((address, port));
+        self
+    }
+
+    /// Speaks the v2 encrypted transport (BIP324) with nodes which support
+    /// it, reconnecting to speak the v1 one with nodes which hang up or do
+    /// not complete the key exchange within the given time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_v2_transport(mut self, wait: Duration) -> Self {
+        self.v2_transport = Some(wait);
+        self
+    }
+
+    /// Whether connections are opened through a proxy, which is needed to
+    /// reach nodes known by name only
+    pub fn is_proxied(&self) -> bool {
+        self.proxy.is_some()
+    }
+
+    /// Builds the Version message sent to the node at the given address.
+    /// In case the height source fails, start height from the configuration
+    /// is used instead.
+    pub async fn version_message(&self, receiver: SocketAddr) -> VersionMessage {
+        let mut msg = VersionMessage::new(receiver, &self.config);
+        match self.height_source.height().await {
+            Ok(height) => msg.start_height = height,
+            Err(e) => warn!(
+                "Unable to get start height, using {} instead: {}",
+                self.config.start_height, e
+            ),
+        }
+        msg
     }
 
     /// Performs a handshake between NodeA and NodeB in the following way:
@@ -91,151 +784,1814 @@ impl Node {
     /// - NodeB sends back Version message and expects Verack version
     /// - handshake is successfully performed
     ///
-    /// - Ping and Pong messages are used to confirm TCP connection is valid
+    /// - in the responder role, NodeA waits for the Version message of NodeB
+    ///   and only then sends its own
+    ///
+    /// - Ping and Pong messages are used to confirm TCP connection is valid,
+    ///   so the Pong message has to echo the nonce of the Ping message
+    ///
+    /// - SendTxRcncl message received between Version and Verack messages
+    ///   is recorded to report Erlay support
     ///
-    /// Returns configuration of the node with which the handshake was performed.
+    /// - WtxidRelay, SendAddrV2, SendHeaders, SendCmpct and FeeFilter
+    ///   messages are recorded to report the optional features the node
+    ///   negotiates
+    ///
+    /// - SendAddrV2 message is sent before the Verack message if both nodes
+    ///   speak at least version 70016
+    ///
+    /// - if requested, the connection is kept open afterwards and messages
+    ///   the node sends meanwhile are classified
+    ///
+    /// - if requested, GetAddr message is sent afterwards and a sample of
+    ///   the addresses received is recorded
+    ///
+    /// Returns report about the node with which the handshake was performed.
     pub async fn handshake(
         &self,
-        network: Network,
+        chain: impl Chain,
         address: SocketAddrV4,
-    ) -> Result<NodeConfig, ConnectionError> {
-        let mut other_node_config: NodeConfig = Default::default();
+    ) -> HandshakeResult {
+        Ok(self.try_handshake(chain, address).await?)
+    }
 
-        let mut socket = TcpStream::connect(address)
+    /// Performs the handshake the same way as [`Node::handshake`], failing
+    /// with the node and the stage of the handshake the error occurred in.
+    pub async fn try_handshake(
+        &self,
+        chain: impl Chain,
+        address: SocketAddrV4,
+    ) -> Result<HandshakeReport, HandshakeError> {
+        self.handshake_with(chain, &Destination::Address(address))
             .await
-            .map_err(|_| ConnectionError::ConnectionRefusedError)?;
+    }
 
-        let version_data = compose(
-            network,
-            Command::Version,
-            VersionMessage::new(SocketAddr::from(address), &self.config),
+    /// Performs the handshake the same way as [`Node::handshake`] with the
+    /// node at the host name, e.g. a Tor v3 onion address, which the proxy
+    /// resolves, see [`Node::with_proxy`].
+    pub async fn handshake_host(
+        &self,
+        chain: impl Chain,
+        host: &str,
+        port: u16,
+    ) -> HandshakeResult {
+        Ok(self
+            .handshake_with(chain, &Destination::Host(host.to_string(), port))
+            .await?)
+    }
+
+    async fn handshake_with(
+        &self,
+        chain: impl Chain,
+        destination: &Destination,
+    ) -> Result<HandshakeReport, HandshakeError> {
+        let (socket, buffer, report) = self.establish(&chain, destination).await?;
+        let mut connection = Connection::<_, R>::new(
+            socket,
+            &chain,
+            self.config.version,
+            buffer,
+            report,
         );
-        socket
-            .write_all(&version_data[..])
+        if let Some(window) = self.observation {
+            info!(
+                "Connection {}: Observing the node for {:?}",
+                destination, window
+            );
+            let observation = connection.observe(window).await;
+            info!("Connection {}: Observed {}", destination, observation);
+            connection.report_mut().observation = Some(observation);
+        }
+        #[cfg(feature = "addr")]
+        if let Some(wait) = self.addr_request {
+            self.sample_addrs(&mut connection, &chain, wait).await;
+        }
+        Ok(connection.into_report())
+    }
+
+    /// Asks the node for addresses of other nodes, recording a sample of
+    /// the first batch received in the report
+    #[cfg(feature = "addr")]
+    async fn sample_addrs<C: Chain + Clone>(
+        &self,
+        connection: &mut Connection<C, R>,
+        chain: &impl Chain,
+        wait: Duration,
+    ) {
+        let version = connection.report().config.version;
+        if version < chain.min_version(Command::GetAddr) {
+            info!("Version {} does not support GetAddr messages", version);
+            return;
+        }
+
+        let addresses = match connection.request_addrs(wait).await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Unable to get addresses: {}", e);
+                Vec::new()
+            }
+        };
+        connection.report_mut().addr_sample = Some(AddrSample {
+            total: addresses.len(),
+            addresses: addresses.into_iter().take(ADDR_SAMPLE_SIZE).collect(),
+        });
+    }
+
+    /// Performs the handshake the same way as [`Node::handshake`], but keeps
+    /// the connection open, so that the protocol can be spoken further.
+    pub async fn connect<C: Chain + Clone>(
+        &self,
+        chain: C,
+        address: SocketAddrV4,
+    ) -> Result<Connection<C, R>, ConnectionError> {
+        self.connect_to(chain, &Destination::Address(address)).await
+    }
+
+    /// Performs the handshake the same way as [`Node::connect`] with the
+    /// node at the address or host name, the latter only through the proxy
+    pub async fn connect_to<C: Chain + Clone>(
+        &self,
+        chain: C,
+        destination: &Destination,
+    ) -> Result<Connection<C, R>, ConnectionError> {
+        let (socket, buffer, report) = self.establish(&chain, destination).await?;
+        Ok(Connection::new(
+            socket,
+            chain,
+            self.config.version,
+            buffer,
+            report,
+        ))
+    }
+
+    /// Performs the handshake over an already connected stream the same way
+    /// as [`Node::handshake`], e.g. over a Unix domain socket, a TLS tunnel
+    /// or an in-memory stream in tests. The receiver address advertised in
+    /// the Version message is the unspecified one, addresses are never
+    /// requested and the v1 transport is spoken.
+    pub async fn handshake_over<S>(&self, chain: impl Chain, stream: S) -> HandshakeResult
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let receiver = SocketAddr::from(([0, 0, 0, 0], 0));
+        let (_, _, report) = self
+            .exchange(
+                stream,
+                &chain,
+                receiver,
+                Instant::now(),
+                &mut Stage::Version,
+            )
+            .await?;
+        Ok(report)
+    }
+
+    /// Performs the handshake over an already connected stream the same way
+    /// as [`Node::handshake_over`], but keeps the connection open, so that
+    /// the protocol can be spoken further.
+    pub async fn connect_over<C, S>(
+        &self,
+        chain: C,
+        stream: S,
+    ) -> Result<Connection<C, R>, ConnectionError>
+    where
+        C: Chain + Clone,
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let receiver = SocketAddr::from(([0, 0, 0, 0], 0));
+        let (socket, buffer, report) = self
+            .exchange(
+                stream,
+                &chain,
+                receiver,
+                Instant::now(),
+                &mut Stage::Version,
+            )
+            .await?;
+        Ok(Connection::new(
+            socket,
+            chain,
+            self.config.version,
+            buffer,
+            report,
+        ))
+    }
+
+    async fn establish(
+        &self,
+        chain: &impl Chain,
+        destination: &Destination,
+    ) -> Result<(Transport<R::Stream>, ReceiveBuffer, HandshakeReport), HandshakeError>
+    {
+        let fail = |stage, error| HandshakeError {
+            peer: destination.clone(),
+            stage,
+            error,
+        };
+
+        // Nodes known by name only, e.g. onion services, are advertised the
+        // unspecified receiver address in the Version message
+        let receiver = match destination {
+            Destination::Address(address) => {
+                if !self.allows(*address) {
+                    return Err(fail(Stage::Connect, ConnectionError::TargetDenied));
+                }
+                SocketAddr::from(*address)
+            }
+            Destination::Host(..) => SocketAddr::from(([0, 0, 0, 0], 0)),
+        };
+        let start = Instant::now();
+        let socket = self
+            .open(destination)
             .await
-            .map_err(|_| ConnectionError::IOError)?;
+            .map_err(|e| fail(Stage::Connect, e))?;
+        let connect_time = start.elapsed();
 
-        loop {
-            let mut buffer = [0; 4096];
-            match socket
-                .read(&mut buffer)
+        // Nodes not speaking the v2 transport are connected to again, so
+        // the handshake is timed from the new connection
+        #[cfg(not(target_arch = "wasm32"))]
+        let (socket, start, connect_time) = match self.v2_transport {
+            Some(wait) => {
+                match timeout::<R, _>(wait, V2Stream::initiate(socket, chain.magic()))
+                    .await
+                {
+                    Some(Ok(stream)) => {
+                        info!("Connection {}: Speaking the v2 transport", destination);
+                        (Transport::V2(Box::new(stream)), start, connect_time)
+                    }
+                    result => {
+                        let reason = match result {
+                            Some(Err(e)) => e.to_string(),
+                            _ => format!("No answer within {:?}", wait),
+                        };
+                        info!(
+                            "Connection {}: Falling back to the v1 transport: {}",
+                            destination, reason
+                        );
+                        let start = Instant::now();
+                        let socket = self
+                            .open(destination)
+                            .await
+                            .map_err(|e| fail(Stage::Connect, e))?;
+                        (Transport::V1(socket), start, start.elapsed())
+                    }
+                }
+            }
+            None => (Transport::V1(socket), start, connect_time),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let socket = Transport::V1(socket);
+
+        let transport = socket.version();
+        let mut stage = Stage::Version;
+        let (socket, buffer, mut report) = self
+            .exchange(socket, chain, receiver, start, &mut stage)
+            .await
+            .map_err(|e| fail(stage, e))?;
+        report.connect_time = Some(connect_time);
+        report.transport = transport;
+        Ok((socket, buffer, report))
+    }
+
+    /// Opens the connection to the node within the connect timeout
+    async fn open(
+        &self,
+        destination: &Destination,
+    ) -> Result<R::Stream, ConnectionError> {
+        match self.stage_timeouts.connect {
+            Some(wait) => timeout::<R, _>(wait, self.dial(destination))
                 .await
-                .map_err(|_| ConnectionError::IOError)?
-            {
-                0 => return Err(ConnectionError::ConnectionHangUp),
-                n => {
-                    let mut data = &buffer[..n];
-
-                    let header = match MessageHeader::decode(&mut data) {
-                        Ok(v) => v,
-                        Err(e) => match e {
-                            CodecError::InvalidBytesError => {
-                                warn!("Connection {} error: Invalid network or command found, ignore it", address);
-                                continue;
-                            }
-                            _ => {
-                                error!("Connection {} error: {}", address, e);
-                                return Err(ConnectionError::InvalidDataError);
-                            }
-                        },
-                    };
+                .ok_or(ConnectionError::StageTimeout(Stage::Connect))?,
+            None => self.dial(destination).await,
+        }
+    }
 
-                    let checksum = calculate_checksum(data);
-                    if checksum != header.checksum {
-                        error!(
-                            "Connection {} error: Checksum mismatch {} vs. {}",
-                            address, checksum, header.checksum
+    /// Opens the connection to the node, through the proxy if set
+    async fn dial(
+        &self,
+        destination: &Destination,
+    ) -> Result<R::Stream, ConnectionError> {
+        match (&self.proxy, destination) {
+            (Some(proxy), _) => {
+                let mut socket = R::connect(proxy.address).await?;
+                proxy.connect(&mut socket, destination).await?;
+                Ok(socket)
+            }
+            (None, Destination::Address(address)) => Ok(R::connect(*address).await?),
+            (None, Destination::Host(..)) => Err(ProxyError::NotConfigured)?,
+        }
+    }
+
+    /// Exchanges the handshake messages over the stream connected at start,
+    /// keeping track of the stage awaited. Returns the bytes received after
+    /// the last handshake message too, as nodes send further messages along
+    /// with it.
+    async fn exchange<S>(
+        &self,
+        mut socket: S,
+        chain: &impl Chain,
+        address: SocketAddr,
+        start: Instant,
+        stage: &mut Stage,
+    ) -> Result<(S, ReceiveBuffer, HandshakeReport), ConnectionError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut report = HandshakeReport {
+            network: Network::try_from(chain.magic()).ok(),
+            ..Default::default()
+        };
+        // Nonce of the Ping message sent, expected to be echoed by the Pong message
+        let mut ping_nonce = None;
+        let mut version_received = false;
+        let mut verack_received = false;
+
+        let message_timeout = self.adaptive_timeout.map(|t| {
+            let rtt = start.elapsed();
+            let timeout = t.timeout(rtt);
+            debug!(
+                "Connection {}: Round-trip time {:?}, waiting up to {:?} per message",
+                address, rtt, timeout
+            );
+            timeout
+        });
+
+        let version = self.version_message(address).await;
+        // Nonce of the Version message sent, which the node sends back only
+        // if it is ourselves
+        let version_nonce = version.nonce();
+        let version_data = compose(chain, Command::Version, version);
+        if !self.config.responder {
+            socket.write_all(&version_data[..]).await?;
+            report.record_sent(
+                Command::Version,
+                &version_data,
+                self.keep_frames,
+                start.elapsed(),
+            );
+        }
+        let mut buffer = ReceiveBuffer::new(chain, self.max_payload_length);
+        let mut clock = ReadClock {
+            address,
+            sent: Instant::now(),
+            stalled: Duration::ZERO,
+            message_timeout,
+            stage: None,
+        };
+
+        loop {
+            *stage = match (version_received, verack_received) {
+                (false, _) => Stage::Version,
+                (true, false) => Stage::Verack,
+                (true, true) => Stage::Pong,
+            };
+            clock.enter(*stage, &self.stage_timeouts);
+
+            let frame = self
+                .read_frame(&mut socket, &mut buffer, &mut clock, &mut report)
+                .await?;
+            let mut data = &frame[..];
+
+            let header = match FrameHeader::decode(chain, &mut data) {
+                Ok(v) => v,
+                Err(e) => match e.kind() {
+                    CodecError::InvalidBytesError => {
+                        warn!(
+                            "Connection {} error: Malformed command found, ignore it",
+                            address
                         );
+                        continue;
+                    }
+                    _ => {
+                        error!("Connection {} error: {}", address, e);
                         return Err(ConnectionError::InvalidDataError);
                     }
-                    match header.command {
-                        Command::Version => {
-                            info!("Connection {}: Received Version message", address);
-                            let msg = VersionMessage::decode(&mut data)
-                                .map_err(|_| ConnectionError::InvalidDataError)?;
-
-                            other_node_config.version = msg.version;
-                            other_node_config.services = msg.services;
-                            other_node_config.user_agent = msg.user_agent;
-                            other_node_config.start_height = msg.start_height;
-                            other_node_config.relay = msg.relay;
-
-                            info!(
-                                "Connection {}: Sending Verack message to {}",
-                                address, other_node_config.user_agent
-                            );
-                            let verack_data =
-                                compose(network, Command::Verack, VerackMessage {});
-                            socket
-                                .write_all(&verack_data[..])
-                                .await
-                                .map_err(|_| ConnectionError::IOError)?;
-                        }
-                        Command::Verack => {
-                            info!("Connection {}: Received Verack message", address);
-                            info!("Connection {}: Sending Ping message", address);
-                            let ping_data =
-                                compose(network, Command::Ping, PingMessage::new());
-                            socket
-                                .write_all(&ping_data[..])
-                                .await
-                                .map_err(|_| ConnectionError::IOError)?;
-                        }
-                        Command::Ping => {
-                            let msg = PingMessage::decode(&mut data)
-                                .map_err(|_| ConnectionError::InvalidDataError)?;
-                            info!(
-                                "Connection {}: Received Ping message with nonce {}",
-                                address,
-                                msg.nonce()
-                            );
-
-                            info!("Connection {}: Sending Pong message", address);
-                            let pong_data = compose(
-                                network,
-                                Command::Pong,
-                                PongMessage::new(msg.nonce()),
-                            );
-                            socket
-                                .write_all(&pong_data[..])
-                                .await
-                                .map_err(|_| ConnectionError::IOError)?;
-                        }
-                        Command::Pong => {
-                            let msg = PongMessage::decode(&mut data)
-                                .map_err(|_| ConnectionError::InvalidDataError)?;
-                            info!(
-                                "Connection {}: Received Pong message with nonce {}",
-                                address,
-                                msg.nonce()
-                            );
-                            break;
-                        }
+                },
+            };
+
+            if header.magic != chain.magic() {
+                match Network::try_from(header.magic) {
+                    Ok(network) => {
+                        error!(
+                            "Connection {} error: Node belongs to the {:?} network",
+                            address, network
+                        );
+                        return Err(ConnectionError::NetworkMismatch(network));
+                    }
+                    Err(_) => {
+                        warn!(
+                            "Connection {} error: Invalid network found, ignore it",
+                            address
+                        );
+                        continue;
                     }
                 }
             }
-        }
 
-        Ok(other_node_config)
-    }
-}
+            let mut data = match header.payload(data) {
+                Ok(payload) => payload,
+                Err(e) if self.lenient => {
+                    warn!("Connection {}: {}, decode it anyway", address, e);
+                    data
+                }
+                Err(e) => {
+                    error!("Connection {} error: {}", address, e);
+                    return Err(ConnectionError::InvalidDataError);
+                }
+            };
+            if !header.verify(chain, data) {
+                let checksum = chain.checksum(data);
+                if !self.lenient {
+                    error!(
+                        "Connection {} error: Checksum mismatch {} vs. {}",
+                        address, checksum, header.checksum
+                    );
+                    return Err(ConnectionError::InvalidDataError);
+                }
+                warn!(
+                    "Connection {}: Checksum mismatch {} vs. {}, decode it anyway",
+                    address, checksum, header.checksum
+                );
+            }
+            report.record_received(
+                header.command.name(),
+                &frame[..],
+                self.keep_frames,
+                start.elapsed(),
+            );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+            // Version message has to be the first one and sent only once,
+            // while commands unknown to the codec are not judged
+            let early = !matches!(header.command, Command::Version | Command::Unknown(_))
+                && !version_received;
+            let repeated = header.command == Command::Version && version_received;
+            let late = late_negotiation(
+                header.command.name(),
+                report.config.version,
+                verack_received,
+            );
+            if early || repeated || late {
+                warn!(
+                    "Connection {}: Unexpected {} message",
+                    address,
+                    header.command.name()
+                );
+                report.violations += 1;
+            }
+            version_received |= header.command == Command::Version;
+            verack_received |= header.command == Command::Verack;
 
-    use std::net::Ipv4Addr;
+            let message = Message::decode(header.command, &mut data)?;
+            match message {
+                Message::Version(msg) => {
+                    info!("Connection {}: Received Version message", address);
+                    // Nodes not detecting self-connections send nonce 0,
+                    // so like in Bitcoin Core it proves nothing
+                    if version_nonce != 0 && msg.nonce() == version_nonce {
+                        error!(
+                            "Connection {} error: Version nonce {} is ours, connected to ourselves",
+                            address,
+                            msg.nonce()
+                        );
+                        return Err(ConnectionError::SelfConnection);
+                    }
 
-    use testcontainers::{
-        clients::Cli,
-        core::WaitFor,
-        GenericImage,
-    };
+                    report.config.version = msg.version;
+                    report.config.services = msg.services;
+                    report.config.start_height = msg.start_height;
+                    report.config.relay = msg.relay;
+                    report.config.nonce = Some(msg.nonce());
+                    report.config.timestamp = Some(msg.timestamp_secs());
+                    report.negotiated_version =
+                        Some(self.config.version.min(msg.version));
+                    let receiver = msg.receiver().address();
+                    report.receiver = Some(SocketAddr::new(
+                        receiver.ip().to_canonical(),
+                        receiver.port(),
+                    ));
+                    if let Ok(now) =
+                        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+                    {
+                        report.time_offset =
+                            Some(msg.timestamp_secs() - now.as_secs() as i64);
+                    }
+                    report.config.user_agent = msg.user_agent;
 
-    use crate::p2p::messages::{
-        Service,
-        Services,
-    };
+                    if self.config.responder {
+                        info!("Connection {}: Sending Version message", address);
+                        socket.write_all(&version_data[..]).await?;
+                        report.record_sent(
+                            Command::Version,
+                            &version_data,
+                            self.keep_frames,
+                            start.elapsed(),
+                        );
+                    }
+
+                    // Addresses of networks other than IPv4 and IPv6 can
+                    // be gossiped only with AddrV2 messages (BIP155)
+                    #[cfg(feature = "addr")]
+                    if self
+                        .config
+                        .version
+                        .min(report.config.version)
+                        .supports(Feature::AddrV2)
+                    {
+                        info!("Connection {}: Sending SendAddrV2 message", address);
+                        let sendaddrv2_data =
+                            compose(chain, Command::SendAddrV2, SendAddrV2Message {});
+                        socket.write_all(&sendaddrv2_data[..]).await?;
+                        report.record_sent(
+                            Command::SendAddrV2,
+                            &sendaddrv2_data,
+                            self.keep_frames,
+                            start.elapsed(),
+                        );
+                    }
+
+                    info!(
+                        "Connection {}: Sending Verack message to {}",
+                        address, report.config.user_agent
+                    );
+                    let verack_data = compose(chain, Command::Verack, VerackMessage {});
+                    socket.write_all(&verack_data[..]).await?;
+                    report.record_sent(
+                        Command::Verack,
+                        &verack_data,
+                        self.keep_frames,
+                        start.elapsed(),
+                    );
+                }
+                Message::Verack(_) => {
+                    info!("Connection {}: Received Verack message", address);
+                    #[cfg(feature = "addr")]
+                    if let Some((local, port)) =
+                        self.local_address.as_ref().filter(|_| report.sendaddrv2)
+                    {
+                        info!(
+                            "Connection {}: Announcing our address {}:{}",
+                            address, local, port
+                        );
+                        let time = SystemTime::now()
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .map_or(0, |t| t.as_secs() as u32);
+                        let announcement = AddrV2Message {
+                            addresses: vec![TimestampedAddrV2 {
+                                time,
+                                services: self.config.services,
+                                address: local.clone(),
+                                port: *port,
+                            }],
+                        };
+                        let addrv2_data = compose(chain, Command::AddrV2, announcement);
+                        socket.write_all(&addrv2_data[..]).await?;
+                        report.record_sent(
+                            Command::AddrV2,
+                            &addrv2_data,
+                            self.keep_frames,
+                            start.elapsed(),
+                        );
+                    }
+
+                    // Nodes predating BIP31 do not answer Ping messages
+                    if version_received
+                        && report.config.version < chain.min_version(Command::Ping)
+                    {
+                        info!(
+                            "Connection {}: Version {} does not support Ping messages",
+                            address, report.config.version
+                        );
+                        break;
+                    }
+
+                    info!("Connection {}: Sending Ping message", address);
+                    let nonce = self.config.nonce_source.next_nonce();
+                    let ping_data =
+                        compose(chain, Command::Ping, PingMessage::new(nonce));
+                    socket.write_all(&ping_data[..]).await?;
+                    report.record_sent(
+                        Command::Ping,
+                        &ping_data,
+                        self.keep_frames,
+                        start.elapsed(),
+                    );
+                    ping_nonce = Some(nonce);
+                }
+                Message::Ping(msg) => {
+                    info!(
+                        "Connection {}: Received Ping message with nonce {}",
+                        address,
+                        msg.nonce()
+                    );
+
+                    info!("Connection {}: Sending Pong message", address);
+                    let pong_data =
+                        compose(chain, Command::Pong, PongMessage::new(msg.nonce()));
+                    socket.write_all(&pong_data[..]).await?;
+                    report.record_sent(
+                        Command::Pong,
+                        &pong_data,
+                        self.keep_frames,
+                        start.elapsed(),
+                    );
+                }
+                Message::SendTxRcncl(msg) => {
+                    info!(
+                        "Connection {}: Received SendTxRcncl message with version {}",
+                        address, msg.version
+                    );
+                    report.txrcncl = Some(msg);
+                }
+                Message::Pong(msg) => {
+                    info!(
+                        "Connection {}: Received Pong message with nonce {}",
+                        address,
+                        msg.nonce()
+                    );
+
+                    if ping_nonce != Some(msg.nonce()) {
+                        error!(
+                            "Connection {} error: Pong nonce {} does not match Ping nonce {:?}",
+                            address,
+                            msg.nonce(),
+                            ping_nonce
+                        );
+                        return Err(ConnectionError::PongNonceMismatch);
+                    }
+                    break;
+                }
+                Message::WtxidRelay(_) => {
+                    info!("Connection {}: Received WtxidRelay message", address);
+                    report.wtxidrelay = true;
+                }
+                #[cfg(feature = "blocks")]
+                Message::SendHeaders(_) => {
+                    info!("Connection {}: Received SendHeaders message", address);
+                    report.sendheaders = true;
+                }
+                #[cfg(feature = "blocks")]
+                Message::SendCmpct(msg) => {
+                    info!(
+                        "Connection {}: Received SendCmpct message with version {}",
+                        address, msg.version
+                    );
+                    // Nodes might announce several versions, e.g. 1 and 2
+                    if report.sendcmpct.is_none_or(|c| msg.version > c.version) {
+                        report.sendcmpct = Some(msg);
+                    }
+                }
+                #[cfg(feature = "blocks")]
+                Message::FeeFilter(msg) => {
+                    info!(
+                        "Connection {}: Received FeeFilter message with fee rate {}",
+                        address, msg.fee_rate
+                    );
+                    report.feefilter = Some(msg);
+                }
+                #[cfg(feature = "blocks")]
+                Message::Inv(_) | Message::Headers(_) => {
+                    debug!(
+                        "Connection {}: Ignoring {} message",
+                        address,
+                        message.command().name()
+                    );
+                }
+                Message::Unknown { command, payload } => {
+                    debug!(
+                        "Connection {}: Skipping {} message of {} bytes",
+                        address,
+                        command.name(),
+                        payload.len()
+                    );
+                }
+                #[cfg(feature = "addr")]
+                Message::SendAddrV2(_) => {
+                    info!("Connection {}: Received SendAddrV2 message", address);
+                    report.sendaddrv2 = true;
+                }
+                #[cfg(feature = "addr")]
+                Message::GetAddr(_) | Message::Addr(_) | Message::AddrV2(_) => {
+                    debug!(
+                        "Connection {}: Ignoring {} message",
+                        address,
+                        message.command().name()
+                    );
+                }
+            }
+        }
+
+        info!("Connection {}: Messages {}", address, report.stats);
+        for event in &report.timeline {
+            debug!("Connection {}: {}", address, event);
+        }
+        self.height_source.observe(report.config.start_height);
+        Ok((socket, buffer, report))
+    }
+
+    /// Reads the next message, reading from the socket only once the
+    /// buffer holds no complete message anymore
+    async fn read_frame<S>(
+        &self,
+        socket: &mut S,
+        buffer: &mut ReceiveBuffer,
+        clock: &mut ReadClock,
+        report: &mut HandshakeReport,
+    ) -> Result<Vec<u8>, ConnectionError>
+    where
+        S: AsyncRead + Unpin,
+    {
+        loop {
+            match buffer.next_frame() {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Connection {} error: Payload is too large", clock.address);
+                    return Err(e);
+                }
+            }
+
+            let mut chunk = [0; READ_CHUNK_LENGTH];
+            let n = self.read_some(socket, &mut chunk, clock, report).await?;
+            buffer.data.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// Reads whatever the node sent so far, limited by the message timeout
+    /// and the stall budget
+    async fn read_some<S>(
+        &self,
+        socket: &mut S,
+        buffer: &mut [u8],
+        clock: &mut ReadClock,
+        report: &mut HandshakeReport,
+    ) -> Result<usize, ConnectionError>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let waiting = Instant::now();
+        let read = socket.read(buffer);
+        let budget = match report.first_byte {
+            Some(_) => self.stall_budget.map(|b| b.saturating_sub(clock.stalled)),
+            None => None,
+        };
+        let deadline = clock.stage.and_then(|(stage, deadline)| {
+            deadline.map(|d| (stage, d.saturating_duration_since(Instant::now())))
+        });
+        let read = match (clock.message_timeout, budget, deadline) {
+            (None, None, None) => read.await,
+            (wait, budget, deadline) => {
+                let wait = [wait, budget, deadline.map(|(_, left)| left)]
+                    .into_iter()
+                    .flatten()
+                    .min()
+                    .unwrap_or(Duration::MAX);
+                match (timeout::<R, _>(wait, read).await, deadline) {
+                    (Some(v), _) => v,
+                    (None, _) if budget == Some(wait) => {
+                        warn!(
+                            "Connection {}: Node stalled for {:?}",
+                            clock.address,
+                            clock.stalled + wait
+                        );
+                        return Err(ConnectionError::Stalled);
+                    }
+                    (None, Some((stage, left))) if left == wait => {
+                        warn!("Connection {}: Stage {} timed out", clock.address, stage);
+                        return Err(ConnectionError::StageTimeout(stage));
+                    }
+                    (None, _) => return Err(ConnectionError::MessageTimeoutError),
+                }
+            }
+        };
+        match read? {
+            0 => Err(ConnectionError::ConnectionHangUp),
+            n => {
+                match report.first_byte {
+                    Some(_) => {
+                        let gap = waiting.elapsed();
+                        clock.stalled += gap;
+                        report.longest_gap = report.longest_gap.max(gap);
+                    }
+                    None => report.first_byte = Some(clock.sent.elapsed()),
+                }
+                Ok(n)
+            }
+        }
+    }
+
+    /// Performs the handshake with a node of unknown network. Networks are
+    /// tried one by one, starting with the one whose default port matches
+    /// the address. Node answering with the magic of another network is
+    /// handshaked with again using that network.
+    ///
+    /// Returns report about the node, including the detected network.
+    pub async fn detect_handshake(&self, address: SocketAddrV4) -> HandshakeResult {
+        let mut pending: VecDeque<Network> = Network::iter().collect();
+        if let Some(network) = Network::from_default_port(address.port()) {
+            pending.retain(|&n| n != network);
+            pending.push_front(network);
+        }
+
+        let mut error = ConnectionError::ConnectionRefusedError;
+        while let Some(network) = pending.pop_front() {
+            info!("Connection {}: Trying the {:?} network", address, network);
+            match self.try_handshake(network, address).await {
+                Ok(report) => return Ok(report),
+                // Connecting, e.g. to an address nothing listens on or
+                // through the proxy, does not depend on the network
+                Err(e) if e.stage == Stage::Connect => return Err(e.error),
+                Err(e) => match e.error {
+                    ConnectionError::NetworkMismatch(detected) => {
+                        // Continue right away with the network the node answered in
+                        if pending.contains(&detected) {
+                            pending.retain(|&n| n != detected);
+                            pending.push_front(detected);
+                        }
+                        error = ConnectionError::NetworkMismatch(detected);
+                    }
+                    // Node already answered in this network before it stalled
+                    ConnectionError::Stalled => return Err(ConnectionError::Stalled),
+                    e => error = e,
+                },
+            }
+        }
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        io,
+        net::Ipv4Addr,
+    };
+
+    use testcontainers::{
+        clients::Cli,
+        core::WaitFor,
+        GenericImage,
+    };
+    use tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::TcpListener,
+        time::sleep,
+    };
+
+    #[cfg(feature = "addr")]
+    use crate::p2p::messages::{
+        AddrMessage,
+        NetworkAddress,
+        TimestampedAddress,
+    };
+    #[cfg(feature = "blocks")]
+    use crate::p2p::messages::{
+        FeeFilterMessage,
+        InvMessage,
+        InvType,
+        Inventory,
+        SendHeadersMessage,
+    };
+    use crate::p2p::{
+        messages::{
+            Message,
+            Service,
+            Services,
+            WtxidRelayMessage,
+        },
+        IoError,
+        MockPeer,
+    };
+
+    /// Message with the given command unknown to the codec and no payload
+    fn unknown(command: &str) -> Message {
+        let mut name = [0; Command::REQUIRED_LENGTH];
+        name[..command.len()].copy_from_slice(command.as_bytes());
+        Message::Unknown {
+            command: Command::try_from(&name).unwrap(),
+            payload: Vec::new(),
+        }
+    }
+
+    /// Addresses the mock node gossips
+    #[cfg(feature = "addr")]
+    fn addr_message() -> AddrMessage {
+        let addresses = (1..=12)
+            .map(|i| TimestampedAddress {
+                time: 1_700_000_000,
+                address: NetworkAddress::new(
+                    Services::new(&[Service::Network]),
+                    SocketAddr::from((Ipv4Addr::new(10, 0, 0, i), 8333)),
+                ),
+            })
+            .collect();
+        AddrMessage { addresses }
+    }
+
+    /// Node negotiating optional features, i.e. wtxid relay and addrv2
+    /// before the Verack message and block relay ones after it
+    fn negotiating_peer() -> MockPeer {
+        let peer = MockPeer::new(Network::Testnet)
+            .with_negotiation(&Message::WtxidRelay(WtxidRelayMessage {}));
+        #[cfg(feature = "addr")]
+        let peer = peer.with_negotiation(&Message::SendAddrV2(SendAddrV2Message {}));
+        #[cfg(feature = "blocks")]
+        let peer = [
+            Message::SendHeaders(SendHeadersMessage {}),
+            Message::SendCmpct(SendCmpctMessage::new(false, 2)),
+            Message::SendCmpct(SendCmpctMessage::new(false, 1)),
+            Message::FeeFilter(FeeFilterMessage::new(1000)),
+        ]
+        .iter()
+        .fold(peer, |peer, message| peer.with_unsolicited(message));
+        peer
+    }
+
+    #[tokio::test]
+    async fn pong_nonce() {
+        let node = Node::new(Default::default());
+
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.config.version, ProtocolVersion(70016));
+        assert!(report.ping_rtt().is_some());
+
+        let address = MockPeer::new(Network::Testnet)
+            .with_pong_nonce(|nonce| nonce.wrapping_add(1))
+            .listen()
+            .await
+            .unwrap();
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::PongNonceMismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn v2_transport() {
+        let node =
+            Node::new(Default::default()).with_v2_transport(Duration::from_secs(1));
+
+        let address = MockPeer::new(Network::Testnet)
+            .speaking_v2()
+            .listen()
+            .await
+            .unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.transport, TransportVersion::V2);
+        assert!(report.ping_rtt().is_some());
+        assert!(Pretty(&report).to_string().contains("Transport       v2\n"));
+
+        // Nodes speaking the v1 transport only hang up on the key exchange
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.transport, TransportVersion::V1);
+        assert!(report.ping_rtt().is_some());
+    }
+
+    #[tokio::test]
+    async fn target_policy() {
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let node = Node::new(Default::default())
+            .with_target_policy(move |target: SocketAddrV4| target != address);
+        assert!(!node.allows(address));
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::TargetDenied)
+        );
+        assert_eq!(
+            node.detect_handshake(address).await.err(),
+            Some(ConnectionError::TargetDenied)
+        );
+    }
+
+    #[cfg(feature = "async-std")]
+    #[tokio::test]
+    async fn async_std_runtime() {
+        use crate::p2p::runtime::AsyncStd;
+
+        let node = Node::<AsyncStd>::on_runtime(Default::default());
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.config.version, ProtocolVersion(70016));
+    }
+
+    #[cfg(feature = "smol")]
+    #[tokio::test]
+    async fn smol_runtime() {
+        use crate::p2p::runtime::Smol;
+
+        let node = Node::<Smol>::on_runtime(Default::default());
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let mut connection = node.connect(Network::Testnet, address).await.unwrap();
+        connection
+            .send(&Message::Ping(PingMessage::new(7)))
+            .await
+            .unwrap();
+        match connection.recv().await.unwrap() {
+            Message::Pong(pong) => assert_eq!(pong.nonce(), 7),
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "addr")]
+    #[tokio::test]
+    async fn addr_sample() {
+        let node =
+            Node::new(Default::default()).with_addr_request(Duration::from_secs(1));
+
+        let address = MockPeer::new(Network::Testnet)
+            .with_addresses(addr_message().addresses)
+            .listen()
+            .await
+            .unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        let sample = report.addr_sample.unwrap();
+        assert_eq!(sample.total, 12);
+        assert_eq!(sample.addresses.len(), ADDR_SAMPLE_SIZE);
+        assert_eq!(sample.addresses[0].to_string(), "10.0.0.1:8333 [Network]");
+
+        let node = Node::new(Default::default());
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert!(report.addr_sample.is_none());
+
+        // Waiting for addresses is not part of the handshake
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let node =
+            Node::new(Default::default()).with_addr_request(Duration::from_millis(300));
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert!(report.handshake_time().unwrap() < Duration::from_millis(300));
+        assert_eq!(report.addr_sample.unwrap().total, 0);
+    }
+
+    #[cfg(feature = "blocks")]
+    #[tokio::test]
+    async fn observation() {
+        let inv = Message::Inv(InvMessage {
+            inventory: vec![Inventory {
+                kind: InvType::Wtx,
+                hash: [1; 32],
+            }],
+        });
+        let address = MockPeer::new(Network::Testnet)
+            .with_chatter(&inv)
+            .with_chatter(&Message::FeeFilter(FeeFilterMessage::new(1000)))
+            .with_chatter(&Message::Ping(PingMessage::new(3)))
+            .listen()
+            .await
+            .unwrap();
+
+        let node =
+            Node::new(Default::default()).with_observation(Duration::from_millis(200));
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert!(report.handshake_time().unwrap() < Duration::from_millis(200));
+        let observation = report.observation.unwrap();
+        assert_eq!(observation.inv, 1);
+        assert_eq!(observation.txs, 1);
+        assert_eq!(observation.fee_rate, Some(1000));
+        assert_eq!(observation.pings, 1);
+        assert!(!observation.disconnected);
+        assert!(observation.duration >= Duration::from_millis(200));
+
+        let report = Node::new(Default::default())
+            .handshake(Network::Testnet, address)
+            .await
+            .unwrap();
+        assert!(report.observation.is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket() {
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("handshaker-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("node.sock");
+        let _ = std::fs::remove_file(&path);
+
+        // Forward the socket to the mock peer
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut peer = tokio::net::TcpStream::connect(address).await.unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut socket, &mut peer).await;
+        });
+
+        let node = Node::new(Default::default());
+        let report = node.handshake_unix(Network::Testnet, &path).await.unwrap();
+        assert_eq!(report.config.version, ProtocolVersion(70016));
+        assert!(report.ping_rtt().is_some());
+
+        // Socket which does not exist is not reported as refusing connections
+        assert_eq!(
+            node.handshake_unix(Network::Testnet, dir.join("missing.sock"))
+                .await
+                .err(),
+            Some(ConnectionError::IOError(IoError(io::Error::from(
+                io::ErrorKind::NotFound
+            ))))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn connect_over() {
+        let stream = MockPeer::new(Network::Testnet).spawn();
+
+        let node = Node::new(Default::default());
+        let mut connection = node.connect_over(Network::Testnet, stream).await.unwrap();
+        assert_eq!(connection.report().config.version, ProtocolVersion(70016));
+        connection
+            .send(&Message::Ping(PingMessage::new(7)))
+            .await
+            .unwrap();
+        match connection.recv().await.unwrap() {
+            Message::Pong(pong) => assert_eq!(pong.nonce(), 7),
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn fragmented_message() {
+        let node = Node::new(Default::default());
+
+        let address = MockPeer::new(Network::Testnet)
+            .fragmented()
+            .listen()
+            .await
+            .unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.config.version, ProtocolVersion(70016));
+        assert_eq!(report.stats.received["version"].messages, 1);
+    }
+
+    #[tokio::test]
+    async fn stall_budget() {
+        // Peer pauses 10 ms between the Version and Verack messages
+        let address = MockPeer::new(Network::Testnet)
+            .with_verack_delay(Duration::from_millis(10))
+            .listen()
+            .await
+            .unwrap();
+
+        let node = Node::new(Default::default());
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert!(report.first_byte.is_some());
+        assert!(report.longest_gap >= Duration::from_millis(10));
+
+        let node = node.with_stall_budget(Duration::from_millis(5));
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::Stalled)
+        );
+        assert_eq!(
+            node.detect_handshake(address).await.err(),
+            Some(ConnectionError::Stalled)
+        );
+    }
+
+    #[tokio::test]
+    async fn stage_timeouts() {
+        let timeouts = StageTimeouts {
+            version: Some(Duration::from_millis(100)),
+            verack: Some(Duration::from_millis(100)),
+            pong: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let node = Node::new(Default::default()).with_stage_timeouts(timeouts);
+
+        let peer = MockPeer::new(Network::Testnet);
+        let address = peer.listen().await.unwrap();
+        assert!(node.handshake(Network::Testnet, address).await.is_ok());
+
+        let peer =
+            MockPeer::new(Network::Testnet).with_verack_delay(Duration::from_secs(5));
+        let address = peer.listen().await.unwrap();
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::StageTimeout(Stage::Verack))
+        );
+
+        let address = MockPeer::new(Network::Testnet)
+            .without_pong()
+            .listen()
+            .await
+            .unwrap();
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::StageTimeout(Stage::Pong))
+        );
+
+        // Listener accepting connections, but never answering
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = match listener.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::StageTimeout(Stage::Version))
+        );
+
+        // Proxy never answering holds up the connection
+        let node = Node::new(Default::default())
+            .with_proxy(Socks5Proxy::new(address))
+            .with_stage_timeouts(StageTimeouts {
+                connect: Some(Duration::from_millis(100)),
+                ..Default::default()
+            });
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::StageTimeout(Stage::Connect))
+        );
+    }
+
+    #[tokio::test]
+    async fn lenient() {
+        let peer = MockPeer::new(Network::Testnet).with_wrong_checksum();
+        let node = Node::new(Default::default());
+        assert_eq!(
+            node.handshake_over(Network::Testnet, peer.spawn())
+                .await
+                .err(),
+            Some(ConnectionError::InvalidDataError)
+        );
+
+        let node = node.with_lenient(true);
+        let report = node
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .unwrap();
+        assert_eq!(report.config.user_agent, "/MockPeer/");
+    }
+
+    #[tokio::test]
+    async fn max_payload_length() {
+        let oversized = ConnectionError::DecodeError(CodecError::OversizedFieldError);
+
+        // Version message payload is longer than 50 bytes
+        let peer = MockPeer::new(Network::Testnet);
+        let node = Node::new(Default::default()).with_max_payload_length(50);
+        assert_eq!(
+            node.handshake_over(Network::Testnet, peer.spawn())
+                .await
+                .err(),
+            Some(oversized)
+        );
+
+        // Limit holds for messages received once the handshake is performed
+        #[cfg(feature = "blocks")]
+        {
+            let inv = Message::Inv(InvMessage {
+                inventory: vec![
+                    Inventory {
+                        kind: InvType::Tx,
+                        hash: [1; 32],
+                    };
+                    10
+                ],
+            });
+            let peer = MockPeer::new(Network::Testnet).with_chatter(&inv);
+            let node = Node::new(Default::default()).with_max_payload_length(200);
+            let mut connection = node
+                .connect_over(Network::Testnet, peer.spawn())
+                .await
+                .unwrap();
+            assert_eq!(
+                connection.recv().await.err(),
+                Some(ConnectionError::DecodeError(
+                    CodecError::OversizedFieldError
+                ))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn self_connection() {
+        let config = |nonce| NodeConfig {
+            version: ProtocolVersion::WTXID,
+            nonce: Some(nonce),
+            ..Default::default()
+        };
+        let peer = MockPeer::new(Network::Testnet).with_config(config(7));
+        assert_eq!(
+            Node::new(config(7))
+                .handshake_over(Network::Testnet, peer.spawn())
+                .await
+                .err(),
+            Some(ConnectionError::SelfConnection)
+        );
+
+        assert!(Node::new(config(8))
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .is_ok());
+
+        let peer = MockPeer::new(Network::Testnet).with_config(config(0));
+        assert!(Node::new(config(0))
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn try_handshake() {
+        let node = Node::new(Default::default()).with_stage_timeouts(StageTimeouts {
+            verack: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        let address = MockPeer::new(Network::Testnet)
+            .with_verack_delay(Duration::from_secs(5))
+            .listen()
+            .await
+            .unwrap();
+        assert_eq!(
+            node.try_handshake(Network::Testnet, address).await.err(),
+            Some(HandshakeError {
+                peer: Destination::Address(address),
+                stage: Stage::Verack,
+                error: ConnectionError::StageTimeout(Stage::Verack),
+            })
+        );
+
+        // Nothing listens on the port anymore
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = match listener.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+        drop(listener);
+        let e = node.try_handshake(Network::Testnet, address).await.err();
+        assert_eq!(e.as_ref().map(|e| e.stage), Some(Stage::Connect));
+        assert_eq!(
+            e.map(|e| e.error),
+            Some(ConnectionError::ConnectionRefusedError)
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_commands() {
+        let node = Node::new(Default::default());
+
+        let address = MockPeer::new(Network::Testnet)
+            .with_unsolicited(&unknown("alert"))
+            .with_unsolicited(&unknown("xversion"))
+            .listen()
+            .await
+            .unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.stats.received["alert"].messages, 1);
+        assert_eq!(report.stats.received["xversion"].messages, 1);
+        assert_eq!(report.violations, 0);
+    }
+
+    #[tokio::test]
+    async fn feature_negotiation() {
+        let node = Node::new(NodeConfig {
+            version: ProtocolVersion::WTXID,
+            ..Default::default()
+        });
+
+        let address = negotiating_peer().listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert!(report.wtxidrelay);
+        assert_eq!(report.violations, 0);
+        #[cfg(feature = "addr")]
+        {
+            assert!(report.sendaddrv2);
+            assert_eq!(report.stats.sent["sendaddrv2"].messages, 1);
+        }
+        #[cfg(feature = "blocks")]
+        {
+            assert!(report.sendheaders);
+            assert_eq!(report.sendcmpct, Some(SendCmpctMessage::new(false, 2)));
+            assert_eq!(report.feefilter, Some(FeeFilterMessage::new(1000)));
+        }
+
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert!(!report.wtxidrelay);
+        #[cfg(feature = "blocks")]
+        assert_eq!(report.sendcmpct, None);
+
+        // Older nodes are not asked for AddrV2 messages
+        #[cfg(feature = "addr")]
+        {
+            let node = Node::new(NodeConfig {
+                version: ProtocolVersion::INVALID_CB_NO_BAN,
+                ..Default::default()
+            });
+            let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+            let report = node.handshake(Network::Testnet, address).await.unwrap();
+            assert!(!report.stats.sent.contains_key("sendaddrv2"));
+        }
+    }
+
+    #[tokio::test]
+    async fn frames() {
+        let peer = MockPeer::new(Network::Testnet);
+        let node = Node::new(Default::default());
+        let report = node
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .unwrap();
+        assert_eq!(report.timeline[0].length as u64, report.stats.sent["version"].bytes);
+        assert!(report.timeline.iter().all(|event| event.frame.is_none()));
+
+        let report = node
+            .with_frames(true)
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .unwrap();
+        for event in &report.timeline {
+            assert_eq!(event.frame.as_ref().map(Vec::len), Some(event.length));
+        }
+        let version = report.timeline[0].frame.as_ref().unwrap();
+        assert_eq!(&version[4..11], b"version");
+    }
+
+    #[tokio::test]
+    async fn negative_timestamp() {
+        let peer = MockPeer::new(Network::Testnet).with_config(NodeConfig {
+            timestamp: Some(-3600),
+            ..Default::default()
+        });
+        let report = Node::new(Default::default())
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .unwrap();
+        assert_eq!(report.config.timestamp, Some(-3600));
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        assert!(report
+            .time_offset
+            .is_some_and(|offset| (offset + 3600 + now).abs() <= 1));
+    }
+
+    #[tokio::test]
+    async fn report_details() {
+        let node = Node::new(NodeConfig {
+            version: ProtocolVersion::INVALID_CB_NO_BAN,
+            ..Default::default()
+        });
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.config.version, ProtocolVersion(70016));
+        assert_eq!(
+            report.negotiated_version,
+            Some(ProtocolVersion::INVALID_CB_NO_BAN)
+        );
+        assert!(report.config.nonce.is_some());
+        assert!(report.config.timestamp.is_some());
+        assert!(report.time_offset.is_some_and(|offset| offset.abs() <= 1));
+        // The mock node reports the address we connected from as ours
+        let receiver = report.receiver.unwrap();
+        assert_eq!(receiver.ip(), Ipv4Addr::LOCALHOST);
+        assert_ne!(receiver.port(), address.port());
+        assert!(report.connect_time.is_some());
+        assert!(report.version_rtt().is_some());
+        assert!(report.verack_rtt() >= report.version_rtt());
+        assert!(report.ping_rtt().is_some());
+        assert!(report.to_string().contains(", ping rtt: "));
+
+        let pretty = Pretty(&report).to_string();
+        assert!(pretty.contains("Negotiated"));
+        assert!(pretty.contains(&receiver.to_string()));
+        assert!(pretty.contains("Version RTT"));
+        assert!(pretty.contains("Verack RTT"));
+    }
+
+    #[tokio::test]
+    async fn proxy() {
+        // SOCKS5 proxy without authentication relaying a single connection
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_address = match listener.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut greeting = [0; 3];
+            socket.read_exact(&mut greeting).await.unwrap();
+            socket.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0; 10];
+            socket.read_exact(&mut request).await.unwrap();
+            let ip = Ipv4Addr::new(request[4], request[5], request[6], request[7]);
+            let port = u16::from_be_bytes([request[8], request[9]]);
+            let mut node = tokio::net::TcpStream::connect((ip, port)).await.unwrap();
+            socket
+                .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut socket, &mut node).await;
+        });
+
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let node =
+            Node::new(Default::default()).with_proxy(Socks5Proxy::new(proxy_address));
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.config.version, ProtocolVersion(70016));
+    }
+
+    #[tokio::test]
+    async fn host_without_proxy() {
+        let node = Node::new(Default::default());
+        let result = node
+            .handshake_host(Network::Main, "example.onion", 8333)
+            .await;
+        assert!(matches!(
+            result,
+            Err(ConnectionError::ProxyError(ProxyError::NotConfigured))
+        ));
+    }
+
+    #[cfg(feature = "addr")]
+    #[tokio::test]
+    async fn local_address() {
+        let node = Node::new(NodeConfig {
+            version: ProtocolVersion::WTXID,
+            ..Default::default()
+        })
+        .with_local_address(AddrV2::TorV3([7; 32]), 8333);
+
+        let address = negotiating_peer().listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.stats.sent["addrv2"].messages, 1);
+
+        // Nodes which did not ask for AddrV2 messages are not sent any
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert!(!report.stats.sent.contains_key("addrv2"));
+    }
+
+    #[tokio::test]
+    async fn late_feature_negotiation() {
+        let node = Node::new(Default::default());
+
+        let address = MockPeer::new(Network::Testnet)
+            .with_unsolicited(&Message::WtxidRelay(WtxidRelayMessage {}))
+            .listen()
+            .await
+            .unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.stats.received["wtxidrelay"].messages, 1);
+        assert_eq!(report.violations, 1);
+
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.violations, 0);
+
+        assert!(late_negotiation("sendaddrv2", ProtocolVersion(70016), true));
+        assert!(!late_negotiation(
+            "sendaddrv2",
+            ProtocolVersion(70016),
+            false
+        ));
+        assert!(!late_negotiation(
+            "sendaddrv2",
+            ProtocolVersion(70015),
+            true
+        ));
+        assert!(!late_negotiation(
+            "sendheaders",
+            ProtocolVersion(70016),
+            true
+        ));
+    }
+
+    #[tokio::test]
+    async fn connection() {
+        let node = Node::new(NodeConfig {
+            version: ProtocolVersion(70015),
+            ..Default::default()
+        });
+
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let mut connection = node.connect(Network::Testnet, address).await.unwrap();
+        assert_eq!(connection.report().config.version, ProtocolVersion(70016));
+        assert_eq!(connection.negotiated_version(), ProtocolVersion(70015));
+
+        // Keep speaking the protocol after the handshake
+        connection
+            .send(&Message::Ping(PingMessage::new(7)))
+            .await
+            .unwrap();
+        match connection.recv().await.unwrap() {
+            Message::Pong(pong) => assert_eq!(pong.nonce(), 7),
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+
+    #[cfg(all(feature = "addr", feature = "blocks"))]
+    #[tokio::test]
+    async fn coalesced_with_pong() {
+        let node = Node::new(Default::default());
+        let inv = InvMessage {
+            inventory: vec![Inventory {
+                kind: InvType::Tx,
+                hash: [1; 32],
+            }],
+        };
+        let address = MockPeer::new(Network::Testnet)
+            .with_chatter(&Message::Inv(inv))
+            .with_chatter(&Message::Addr(addr_message()))
+            .listen()
+            .await
+            .unwrap();
+
+        // Messages read along with the Pong message are received first
+        let mut connection = node.connect(Network::Testnet, address).await.unwrap();
+        match connection.recv().await.unwrap() {
+            Message::Inv(inv) => assert_eq!(inv.inventory[0].hash, [1; 32]),
+            other => panic!("Unexpected message {:?}", other),
+        }
+        match connection.recv().await.unwrap() {
+            Message::Addr(addr) => assert_eq!(addr.addresses.len(), 12),
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn split_connection() {
+        let node = Node::new(Default::default());
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let connection = node.connect(Network::Testnet, address).await.unwrap();
+
+        let (mut recv_half, mut send_half) = connection.into_split();
+        let sender = tokio::spawn(async move {
+            for nonce in 1..=3 {
+                send_half
+                    .send(&Message::Ping(PingMessage::new(nonce)))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        for nonce in 1..=3 {
+            match recv_half.recv().await.unwrap() {
+                Message::Pong(pong) => assert_eq!(pong.nonce(), nonce),
+                other => panic!("Unexpected message {:?}", other),
+            }
+        }
+        sender.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn responder() {
+        let node = Node::new(NodeConfig {
+            responder: true,
+            ..Default::default()
+        });
+
+        let address = MockPeer::new(Network::Testnet)
+            .speaking_first()
+            .listen()
+            .await
+            .unwrap();
+        let report = node.handshake(Network::Testnet, address).await.unwrap();
+
+        let first = &report.timeline[0];
+        assert_eq!(first.direction, Direction::Received);
+        assert_eq!(first.command, "version");
+        assert_eq!(report.timeline[1].direction, Direction::Sent);
+        assert_eq!(report.timeline[1].command, "version");
+    }
+
+    #[tokio::test]
+    async fn message_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = match listener.local_addr().unwrap() {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!(),
+        };
+        // Accept the connection, but never answer
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            sleep(Duration::from_secs(5)).await;
+        });
+
+        let node = Node::new(Default::default()).with_adaptive_timeout(AdaptiveTimeout {
+            floor: Duration::from_millis(50),
+            ceiling: Duration::from_millis(50),
+            ..Default::default()
+        });
+        assert_eq!(
+            node.handshake(Network::Testnet, address).await.err(),
+            Some(ConnectionError::MessageTimeoutError)
+        );
+    }
+
+    #[tokio::test]
+    async fn detect_network() {
+        let node = Node::new(Default::default());
+
+        // Peer hangs up on our Version message, so it has to send its own first
+        let address = MockPeer::new(Network::Testnet)
+            .speaking_first()
+            .listen()
+            .await
+            .unwrap();
+        assert_eq!(
+            node.handshake(Network::Main, address).await.err(),
+            Some(ConnectionError::NetworkMismatch(Network::Testnet))
+        );
+
+        let report = node.detect_handshake(address).await.unwrap();
+        assert_eq!(report.network, Some(Network::Testnet));
+        assert_eq!(report.config.version, ProtocolVersion(70016));
+    }
+
+    #[test]
+    fn pretty_config() {
+        let config = NodeConfig {
+            version: ProtocolVersion(70015),
+            services: Services::new(&[Service::Network, Service::Witness]),
+            user_agent: "/Satoshi:25.0.0/".to_string(),
+            start_height: 812345,
+            relay: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Pretty(&config).to_string(),
+            concat!(
+                "  Version         70015\n",
+                "  Services        [Network, Witness]\n",
+                "  User agent      /Satoshi:25.0.0/\n",
+                "  Start height    812345\n",
+                "  Relay           true\n",
+            )
+        );
+    }
+
+    #[test]
+    fn pretty_report() {
+        let mut report = HandshakeReport {
+            config: NodeConfig {
+                version: ProtocolVersion(70016),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        report.stats.record_sent(Command::Version, 126);
+        report.stats.record_received("version", 126);
+        assert!(Pretty(&report).to_string().contains(concat!(
+            "  Erlay           not supported\n",
+            "  Wtxid relay     not supported\n",
+        )));
+        assert!(Pretty(&report).to_string().ends_with(concat!(
+            "  Sent            [version: 1 (126 B)]\n",
+            "  Received        [version: 1 (126 B)]\n",
+            "  Bytes           126 sent, 126 received\n",
+            "  Violations      0\n",
+        )));
+
+        report.txrcncl = Some(SendTxRcnclMessage::new(1, 0));
+        assert!(Pretty(&report)
+            .to_string()
+            .contains("  Erlay           version 1\n"));
+        assert!(report.to_string().ends_with(", erlay version: 1"));
+
+        report.wtxidrelay = true;
+        assert!(Pretty(&report)
+            .to_string()
+            .contains("  Wtxid relay     supported\n"));
+        assert!(report
+            .to_string()
+            .ends_with(", erlay version: 1, wtxid relay"));
+
+        #[cfg(feature = "blocks")]
+        {
+            report.sendcmpct = Some(SendCmpctMessage::new(true, 2));
+            report.feefilter = Some(FeeFilterMessage::new(1000));
+            let pretty = Pretty(&report).to_string();
+            assert!(pretty.contains("  Send headers    not requested\n"));
+            assert!(pretty.contains("  Compact blocks  version 2, high bandwidth\n"));
+            assert!(pretty.contains("  Fee filter      1000 sat/kvB\n"));
+            assert!(report
+                .to_string()
+                .ends_with(", compact blocks version: 2, fee filter: 1000 sat/kvB"));
+        }
+
+        report.network = Some(Network::Signet);
+        assert!(Pretty(&report)
+            .to_string()
+            .contains("  Relay           false\n  Network         Signet\n"));
+        assert!(report.to_string().contains(", network: Signet, "));
+    }
 
     #[tokio::test]
     #[ignore]
@@ -251,11 +2607,12 @@ mod tests {
         let port = bitcoin_node.get_host_port_ipv4(18444);
 
         let config = NodeConfig {
-            version: 70015,
+            version: ProtocolVersion(70015),
             services: Services::new(&[Service::Network]),
             user_agent: "test_node".to_string(),
             start_height: 10,
             relay: false,
+            ..Default::default()
         };
         let node = Node::new(config);
 
@@ -0,0 +1,62 @@
+use std::sync::Mutex;
+
+use rand::{
+    rngs::StdRng,
+    thread_rng,
+    Rng,
+    SeedableRng,
+};
+
+/// Generates nonces sent in Version and Ping messages.
+pub trait NonceSource: Send + Sync {
+    /// Gets the next nonce
+    fn next_nonce(&self) -> u64;
+}
+
+/// Generates nonces using the thread-local random number generator.
+pub struct RandomNonce;
+
+impl NonceSource for RandomNonce {
+    fn next_nonce(&self) -> u64 {
+        thread_rng().gen::<u64>()
+    }
+}
+
+/// Generates the same sequence of nonces for the same seed.
+pub struct SeededNonce {
+    rng: Mutex<StdRng>,
+}
+
+impl SeededNonce {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+}
+
+impl NonceSource for SeededNonce {
+    fn next_nonce(&self) -> u64 {
+        self.rng.lock().unwrap().gen::<u64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_nonce() {
+        let first = SeededNonce::new(42);
+        let second = SeededNonce::new(42);
+
+        let nonces: Vec<u64> = (0..3).map(|_| first.next_nonce()).collect();
+        assert_eq!(
+            nonces,
+            (0..3).map(|_| second.next_nonce()).collect::<Vec<_>>()
+        );
+        assert_ne!(nonces[0], nonces[1]);
+
+        assert_ne!(SeededNonce::new(43).next_nonce(), nonces[0]);
+    }
+}
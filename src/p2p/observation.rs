@@ -0,0 +1,231 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    time::Duration,
+};
+
+use crate::p2p::{
+    messages::Message,
+    report::{
+        write_field,
+        Pretty,
+    },
+};
+
+/// Messages the node sent unsolicited while the connection was kept open
+/// after the handshake, telling how chatty the node is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Observation {
+    /// Time the connection was kept open for
+    pub duration: Duration,
+    /// Whether the node closed the connection, or sent anything invalid,
+    /// before the time was over
+    pub disconnected: bool,
+    /// Number of Inv messages received
+    pub inv: u32,
+    /// Transactions announced in Inv messages
+    pub txs: u64,
+    /// Blocks announced in Inv messages
+    pub blocks: u64,
+    /// Number of Addr and AddrV2 messages received
+    pub addr: u32,
+    /// Addresses announced in Addr and AddrV2 messages
+    pub addresses: u64,
+    /// Number of FeeFilter messages received
+    pub feefilter: u32,
+    /// Fee rate of the last FeeFilter message received
+    pub fee_rate: Option<i64>,
+    /// Number of Headers messages received
+    pub headers: u32,
+    /// Block headers announced in Headers messages
+    pub block_headers: u64,
+    /// Number of Ping messages received, each of them answered
+    pub pings: u32,
+    /// Number of other messages received, whose command is known to the
+    /// codec
+    pub other: u32,
+}
+
+impl Observation {
+    /// Classifies the message received
+    pub fn record(&mut self, message: &Message) {
+        match message {
+            #[cfg(feature = "blocks")]
+            Message::Inv(msg) => {
+                self.inv += 1;
+                for item in &msg.inventory {
+                    if item.kind.is_tx() {
+                        self.txs += 1;
+                    } else if item.kind.is_block() {
+                        self.blocks += 1;
+                    }
+                }
+            }
+            #[cfg(feature = "addr")]
+            Message::Addr(msg) => {
+                self.addr += 1;
+                self.addresses += msg.addresses.len() as u64;
+            }
+            #[cfg(feature = "addr")]
+            Message::AddrV2(msg) => {
+                self.addr += 1;
+                self.addresses += msg.addresses.len() as u64;
+            }
+            #[cfg(feature = "blocks")]
+            Message::FeeFilter(msg) => {
+                self.feefilter += 1;
+                self.fee_rate = Some(msg.fee_rate);
+            }
+            #[cfg(feature = "blocks")]
+            Message::Headers(msg) => {
+                self.headers += 1;
+                self.block_headers += msg.headers.len() as u64;
+            }
+            Message::Ping(_) => self.pings += 1,
+            _ => self.other += 1,
+        }
+    }
+
+    /// Gets the number of messages received
+    pub fn messages(&self) -> u32 {
+        self.inv + self.addr + self.feefilter + self.headers + self.pings + self.other
+    }
+
+    /// Gets the number of messages received per minute
+    pub fn per_minute(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 0.0;
+        }
+        f64::from(self.messages()) * 60.0 / self.duration.as_secs_f64()
+    }
+}
+
+impl Display for Observation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} messages in {:.1} s (inv: {}, addr: {}, feefilter: {}, headers: {}, \
+            ping: {}, other: {})",
+            self.messages(),
+            self.duration.as_secs_f64(),
+            self.inv,
+            self.addr,
+            self.feefilter,
+            self.headers,
+            self.pings,
+            self.other
+        )
+    }
+}
+
+impl Display for Pretty<'_, Observation> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let observation = self.0;
+        write_field(
+            f,
+            "Observed",
+            format!(
+                "{:.1} s{}, {:.1} messages/min",
+                observation.duration.as_secs_f64(),
+                if observation.disconnected {
+                    " until disconnected"
+                } else {
+                    ""
+                },
+                observation.per_minute()
+            ),
+        )?;
+        write_field(
+            f,
+            "Inv",
+            format!(
+                "{} ({} txs, {} blocks)",
+                observation.inv, observation.txs, observation.blocks
+            ),
+        )?;
+        write_field(
+            f,
+            "Addr",
+            format!("{} ({} addresses)", observation.addr, observation.addresses),
+        )?;
+        match observation.fee_rate {
+            Some(fee_rate) => write_field(
+                f,
+                "FeeFilter",
+                format!("{} (last {} sat/kvB)", observation.feefilter, fee_rate),
+            )?,
+            None => write_field(f, "FeeFilter", observation.feefilter)?,
+        }
+        write_field(
+            f,
+            "Headers",
+            format!(
+                "{} ({} headers)",
+                observation.headers, observation.block_headers
+            ),
+        )?;
+        write_field(f, "Pings", observation.pings)?;
+        write_field(f, "Other", observation.other)
+    }
+}
+
+#[cfg(all(test, feature = "addr", feature = "blocks"))]
+mod tests {
+    use super::*;
+
+    use crate::p2p::messages::{
+        AddrV2Message,
+        FeeFilterMessage,
+        InvMessage,
+        InvType,
+        Inventory,
+        PingMessage,
+        VerackMessage,
+    };
+
+    #[test]
+    fn record() {
+        let inventory = [InvType::Wtx, InvType::Wtx, InvType::Block]
+            .into_iter()
+            .map(|kind| Inventory {
+                kind,
+                hash: [0; 32],
+            })
+            .collect();
+        let mut observation = Observation {
+            duration: Duration::from_secs(30),
+            ..Default::default()
+        };
+        for message in [
+            Message::Inv(InvMessage { inventory }),
+            Message::AddrV2(AddrV2Message {
+                addresses: Vec::new(),
+            }),
+            Message::FeeFilter(FeeFilterMessage::new(1000)),
+            Message::FeeFilter(FeeFilterMessage::new(2000)),
+            Message::Ping(PingMessage::new(1)),
+            Message::Verack(VerackMessage {}),
+        ] {
+            observation.record(&message);
+        }
+
+        assert_eq!(observation.inv, 1);
+        assert_eq!(observation.txs, 2);
+        assert_eq!(observation.blocks, 1);
+        assert_eq!(observation.addr, 1);
+        assert_eq!(observation.feefilter, 2);
+        assert_eq!(observation.fee_rate, Some(2000));
+        assert_eq!(observation.pings, 1);
+        assert_eq!(observation.other, 1);
+        assert_eq!(observation.messages(), 6);
+        assert_eq!(observation.per_minute(), 12.0);
+        assert_eq!(
+            observation.to_string(),
+            "6 messages in 30.0 s (inv: 1, addr: 1, feefilter: 2, headers: 0, ping: 1, \
+            other: 1)"
+        );
+        assert_eq!(Observation::default().per_minute(), 0.0);
+    }
+}
@@ -0,0 +1,43 @@
+use std::net::SocketAddrV4;
+
+/// Decides which addresses connections may be attempted to, e.g. to enforce
+/// allowlists, legal or geographic restrictions, or custom deduplication.
+/// Consulted before each connection attempt and before addresses discovered
+/// from other nodes are added to the targets.
+pub trait TargetPolicy: Send + Sync {
+    /// Checks whether a connection to the given address may be attempted
+    fn allows(&self, address: SocketAddrV4) -> bool;
+}
+
+/// Allows connections to any address.
+pub struct AllowAll;
+
+impl TargetPolicy for AllowAll {
+    fn allows(&self, _address: SocketAddrV4) -> bool {
+        true
+    }
+}
+
+impl<F> TargetPolicy for F
+where
+    F: Fn(SocketAddrV4) -> bool + Send + Sync,
+{
+    fn allows(&self, address: SocketAddrV4) -> bool {
+        self(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn closure() {
+        let policy = |address: SocketAddrV4| address.port() == 8333;
+        assert!(policy.allows(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8333)));
+        assert!(!policy.allows(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 18333)));
+        assert!(AllowAll.allows(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 18333)));
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    net::SocketAddrV4,
+};
+
+use futures::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+};
+
+use crate::p2p::ConnectionError;
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const AUTH_VERSION: u8 = 0x01;
+const CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+#[derive(Debug, PartialEq)]
+pub enum ProxyError {
+    /// Proxy accepts none of the authentication methods offered
+    UnsupportedAuth,
+    /// Proxy rejected the username and password
+    AuthFailed,
+    /// Proxy was unable to connect to the node, with the SOCKS5 reply code
+    Rejected(u8),
+    /// Proxy answered with something else than a SOCKS5 message
+    InvalidReply,
+    /// Host names, e.g. onion addresses, can be dialed only through a proxy
+    NotConfigured,
+}
+
+impl Display for ProxyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProxyError::UnsupportedAuth => {
+                write!(f, "Proxy requires an unsupported authentication method")
+            }
+            ProxyError::AuthFailed => write!(f, "Proxy rejected the credentials"),
+            ProxyError::Rejected(code) => {
+                write!(f, "Proxy unable to connect to the node, reply {}", code)
+            }
+            ProxyError::InvalidReply => write!(f, "Invalid reply received from proxy"),
+            ProxyError::NotConfigured => {
+                write!(f, "Host names can be dialed only through a proxy")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+/// Node to connect to through the proxy, either by its address or by a
+/// host name the proxy resolves, e.g. a Tor onion address.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Destination {
+    Address(SocketAddrV4),
+    Host(String, u16),
+}
+
+impl Destination {
+    /// Gets the host part of the destination, i.e. without the port
+    pub fn host(&self) -> String {
+        match self {
+            Destination::Address(address) => address.ip().to_string(),
+            Destination::Host(host, _) => host.clone(),
+        }
+    }
+}
+
+impl From<SocketAddrV4> for Destination {
+    fn from(address: SocketAddrV4) -> Self {
+        Destination::Address(address)
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Destination::Address(address) => write!(f, "{}", address),
+            Destination::Host(host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+/// SOCKS5 proxy, e.g. of Tor or a bastion host, connections to nodes are
+/// opened through (RFC 1928).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Socks5Proxy {
+    pub address: SocketAddrV4,
+    /// Username and password to authenticate with (RFC 1929), if required
+    pub credentials: Option<(String, String)>,
+}
+
+impl Socks5Proxy {
+    pub fn new(address: SocketAddrV4) -> Self {
+        Self {
+            address,
+            credentials: None,
+        }
+    }
+
+    /// Authenticates with the given username and password
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Asks the proxy, over the stream connected to it, to connect to the
+    /// node, after which the stream carries the node's traffic
+    pub async fn connect<S>(
+        &self,
+        stream: &mut S,
+        target: &Destination,
+    ) -> Result<(), ConnectionError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let method = match self.credentials {
+            Some(_) => USERNAME_PASSWORD,
+            None => NO_AUTH,
+        };
+        stream.write_all(&[VERSION, 1, method]).await?;
+        let mut choice = [0; 2];
+        stream.read_exact(&mut choice).await?;
+        match choice {
+            [VERSION, chosen] if chosen == method => {}
+            [VERSION, NO_ACCEPTABLE_METHOD] => Err(ProxyError::UnsupportedAuth)?,
+            _ => Err(ProxyError::InvalidReply)?,
+        }
+
+        if let Some((username, password)) = &self.credentials {
+            let mut request = vec![AUTH_VERSION];
+            for field in [username, password] {
+                let length =
+                    u8::try_from(field.len()).map_err(|_| ProxyError::AuthFailed)?;
+                request.push(length);
+                request.extend_from_slice(field.as_bytes());
+            }
+            stream.write_all(&request).await?;
+            let mut status = [0; 2];
+            stream.read_exact(&mut status).await?;
+            if status != [AUTH_VERSION, 0] {
+                Err(ProxyError::AuthFailed)?;
+            }
+        }
+
+        let mut request = vec![VERSION, CONNECT, 0];
+        let port = match target {
+            Destination::Address(address) => {
+                request.push(ATYP_IPV4);
+                request.extend_from_slice(&address.ip().octets());
+                address.port()
+            }
+            Destination::Host(host, port) => {
+                // Names too long for SOCKS5 cannot be reached, just like ones
+                // the proxy fails to resolve (reply 4, host unreachable)
+                let length =
+                    u8::try_from(host.len()).map_err(|_| ProxyError::Rejected(4))?;
+                request.push(ATYP_DOMAIN);
+                request.push(length);
+                request.extend_from_slice(host.as_bytes());
+                *port
+            }
+        };
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply).await?;
+        match reply {
+            [VERSION, 0, _, _] => {}
+            [VERSION, code, _, _] => Err(ProxyError::Rejected(code))?,
+            _ => Err(ProxyError::InvalidReply)?,
+        }
+        // Skip the address the proxy bound to and its port
+        let length = match reply[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => {
+                let mut length = [0];
+                stream.read_exact(&mut length).await?;
+                length[0] as usize
+            }
+            _ => Err(ProxyError::InvalidReply)?,
+        };
+        let mut bound = vec![0; length + 2];
+        stream.read_exact(&mut bound).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use futures::io::Cursor;
+
+    /// Stream replaying the proxy's answers and recording our requests
+    struct Script {
+        answers: Cursor<Vec<u8>>,
+        requests: Vec<u8>,
+    }
+
+    impl Script {
+        fn new(answers: &[u8]) -> Self {
+            Self {
+                answers: Cursor::new(answers.to_vec()),
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl AsyncRead for Script {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &mut [u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            std::pin::Pin::new(&mut self.answers).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Script {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.requests.extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    fn target() -> Destination {
+        Destination::Address(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333))
+    }
+
+    fn proxy() -> Socks5Proxy {
+        Socks5Proxy::new(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9050))
+    }
+
+    #[tokio::test]
+    async fn connect() {
+        let mut stream = Script::new(&[5, 0, 5, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        proxy().connect(&mut stream, &target()).await.unwrap();
+        assert_eq!(
+            stream.requests,
+            [5, 1, 0, 5, 1, 0, 1, 10, 0, 0, 1, 0x20, 0x8d]
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_host() {
+        let mut stream = Script::new(&[5, 0, 5, 0, 0, 3, 1, b'x', 0, 0]);
+        let target = Destination::Host("example.onion".to_string(), 8333);
+        proxy().connect(&mut stream, &target).await.unwrap();
+        assert_eq!(stream.requests[3..7], [5, 1, 0, 3]);
+        assert_eq!(stream.requests[7], 13);
+        assert_eq!(stream.requests[8..21], *b"example.onion");
+        assert_eq!(stream.requests[21..], [0x20, 0x8d]);
+        assert_eq!(target.to_string(), "example.onion:8333");
+    }
+
+    #[tokio::test]
+    async fn credentials() {
+        let mut stream = Script::new(&[5, 2, 1, 0, 5, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        let proxy = proxy().with_credentials("user", "pw");
+        proxy.connect(&mut stream, &target()).await.unwrap();
+        assert_eq!(stream.requests[..3], [5, 1, 2]);
+        assert_eq!(stream.requests[3..12], *b"\x01\x04user\x02pw");
+
+        let mut stream = Script::new(&[5, 2, 1, 1]);
+        assert_eq!(
+            proxy.connect(&mut stream, &target()).await,
+            Err(ConnectionError::ProxyError(ProxyError::AuthFailed))
+        );
+    }
+
+    #[tokio::test]
+    async fn failures() {
+        let mut stream = Script::new(&[5, 0xff]);
+        assert_eq!(
+            proxy().connect(&mut stream, &target()).await,
+            Err(ConnectionError::ProxyError(ProxyError::UnsupportedAuth))
+        );
+
+        // Connection refused by the node
+        let mut stream = Script::new(&[5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            proxy().connect(&mut stream, &target()).await,
+            Err(ConnectionError::ProxyError(ProxyError::Rejected(5)))
+        );
+
+        // HTTP proxy
+        let mut stream = Script::new(b"HTTP/1.1 400 Bad Request");
+        assert_eq!(
+            proxy().connect(&mut stream, &target()).await,
+            Err(ConnectionError::ProxyError(ProxyError::InvalidReply))
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+/// Width of the field name column in pretty rendering
+const NAME_WIDTH: usize = 16;
+
+/// Wraps a value to render it as a human-oriented multi-line report,
+/// one aligned field per line, instead of the terse `Display` form.
+pub struct Pretty<'a, T>(pub &'a T);
+
+/// Writes a single aligned line of the pretty report.
+pub(crate) fn write_field(
+    f: &mut Formatter<'_>,
+    name: &str,
+    value: impl Display,
+) -> std::fmt::Result {
+    writeln!(f, "  {:<width$}{}", name, value, width = NAME_WIDTH)
+}
@@ -0,0 +1,152 @@
+use std::{
+    future::Future,
+    time::Duration,
+};
+
+use rand::Rng;
+
+use crate::p2p::runtime::{
+    DefaultRuntime,
+    Runtime,
+};
+
+/// How often and how long after a transient failure, e.g. a refused
+/// connection or a timeout, the handshake is attempted again. The delay
+/// doubles with every retry, so that busy nodes are not hammered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of attempts after the first one
+    pub retries: u32,
+    /// Delay before the first retry
+    pub backoff: Duration,
+    /// Whether to randomize delays, so that nodes failed at once are not
+    /// retried at once either
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::from_millis(500),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Gets the delay before the given retry, counted from zero. With
+    /// jitter, it is anywhere between half of the delay and the delay.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(retry.min(31)));
+        if self.jitter {
+            let factor = rand::thread_rng().gen_range(0.5..=1.0);
+            Duration::try_from_secs_f64(delay.as_secs_f64() * factor).unwrap_or(delay)
+        } else {
+            delay
+        }
+    }
+
+    /// Performs the attempt until it succeeds, fails for good or the
+    /// retries run out. Returns the result of the last attempt together
+    /// with the errors of the earlier ones.
+    pub async fn run<T, E, F>(
+        &self,
+        mut attempt: impl FnMut() -> F,
+        is_transient: impl Fn(&E) -> bool,
+    ) -> (Result<T, E>, Vec<E>)
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let mut failed = Vec::new();
+        let mut retry = 0;
+        loop {
+            match attempt().await {
+                Err(e) if retry < self.retries && is_transient(&e) => {
+                    failed.push(e);
+                    DefaultRuntime::sleep(self.delay(retry)).await;
+                    retry += 1;
+                }
+                result => return (result, failed),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    use crate::p2p::ConnectionError;
+
+    #[test]
+    fn delay() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(100),
+            jitter: false,
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(100), policy.delay(31));
+
+        let policy = RetryPolicy {
+            jitter: true,
+            ..policy
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[tokio::test]
+    async fn run() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(1),
+            jitter: false,
+        };
+
+        // Refused twice, then accepted
+        let mut attempts = 0;
+        let (result, failed) = policy
+            .run(
+                || {
+                    attempts += 1;
+                    let result = match attempts {
+                        1 | 2 => Err(ConnectionError::ConnectionRefusedError),
+                        _ => Ok(attempts),
+                    };
+                    async move { result }
+                },
+                ConnectionError::is_transient,
+            )
+            .await;
+        assert_eq!(result, Ok(3));
+        assert_eq!(failed.len(), 2);
+
+        // Failures which would fail again are not retried
+        let (result, failed) = policy
+            .run(
+                || async { Err::<(), _>(ConnectionError::PongNonceMismatch) },
+                ConnectionError::is_transient,
+            )
+            .await;
+        assert_eq!(result, Err(ConnectionError::PongNonceMismatch));
+        assert!(failed.is_empty());
+
+        // Retries run out
+        let (result, failed) = policy
+            .run(
+                || async { Err::<(), _>(ConnectionError::ConnectionHangUp) },
+                ConnectionError::is_transient,
+            )
+            .await;
+        assert_eq!(result, Err(ConnectionError::ConnectionHangUp));
+        assert_eq!(failed.len(), 3);
+    }
+}
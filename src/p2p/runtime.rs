@@ -0,0 +1,163 @@
+use std::{
+    future::Future,
+    io,
+    net::SocketAddrV4,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{
+    future::{
+        select,
+        Either,
+    },
+    io::{
+        AsyncRead,
+        AsyncWrite,
+    },
+    pin_mut,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio_util::compat::{
+    Compat,
+    TokioAsyncReadCompatExt,
+};
+
+// Clocks of std panic on wasm32, so the ones of the browser are used there
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::{
+    Instant,
+    SystemTime,
+};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::{
+    Instant,
+    SystemTime,
+};
+
+/// Async runtime providing the TCP connections and timers the protocol
+/// is spoken over, so that it does not depend on any particular runtime.
+#[async_trait]
+pub trait Runtime: Send + Sync + 'static {
+    /// TCP connection with a node
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Opens the TCP connection with the node at the given address
+    async fn connect(address: SocketAddrV4) -> io::Result<Self::Stream>;
+
+    /// Waits for the given time to pass
+    async fn sleep(duration: Duration);
+}
+
+/// Runs the future on the runtime's timer, giving up once the given time
+/// is over.
+pub async fn timeout<R: Runtime, F: Future>(
+    duration: Duration,
+    future: F,
+) -> Option<F::Output> {
+    pin_mut!(future);
+    match select(future, R::sleep(duration)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+/// Runtime used unless another one is given, i.e. tokio, which is not
+/// available on wasm32, where the host has to provide its own instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub type DefaultRuntime = Tokio;
+#[cfg(target_arch = "wasm32")]
+pub type DefaultRuntime = NoRuntime;
+
+/// Tokio runtime.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Tokio;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl Runtime for Tokio {
+    type Stream = Compat<tokio::net::TcpStream>;
+
+    async fn connect(address: SocketAddrV4) -> io::Result<Self::Stream> {
+        Ok(tokio::net::TcpStream::connect(address).await?.compat())
+    }
+
+    async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Placeholder runtime on wasm32, which is unable to open any connection.
+#[cfg(target_arch = "wasm32")]
+pub enum NoRuntime {}
+
+#[cfg(target_arch = "wasm32")]
+#[async_trait]
+impl Runtime for NoRuntime {
+    type Stream = futures::io::Cursor<Vec<u8>>;
+
+    async fn connect(_address: SocketAddrV4) -> io::Result<Self::Stream> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    async fn sleep(_duration: Duration) {
+        futures::future::pending().await
+    }
+}
+
+/// async-std runtime.
+#[cfg(feature = "async-std")]
+pub struct AsyncStd;
+
+#[cfg(feature = "async-std")]
+#[async_trait]
+impl Runtime for AsyncStd {
+    type Stream = async_std::net::TcpStream;
+
+    async fn connect(address: SocketAddrV4) -> io::Result<Self::Stream> {
+        async_std::net::TcpStream::connect(address).await
+    }
+
+    async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+}
+
+/// smol runtime.
+#[cfg(feature = "smol")]
+pub struct Smol;
+
+#[cfg(feature = "smol")]
+#[async_trait]
+impl Runtime for Smol {
+    type Stream = smol::net::TcpStream;
+
+    async fn connect(address: SocketAddrV4) -> io::Result<Self::Stream> {
+        smol::net::TcpStream::connect(address).await
+    }
+
+    async fn sleep(duration: Duration) {
+        smol::Timer::after(duration).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn timeout_elapses() {
+        assert_eq!(
+            timeout::<Tokio, _>(Duration::from_millis(50), async { 1 }).await,
+            Some(1)
+        );
+        assert_eq!(
+            timeout::<Tokio, _>(
+                Duration::from_millis(10),
+                Tokio::sleep(Duration::from_secs(10))
+            )
+            .await,
+            None
+        );
+    }
+}
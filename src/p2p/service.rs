@@ -0,0 +1,104 @@
+use std::{
+    future::Future,
+    net::SocketAddrV4,
+    pin::Pin,
+    sync::Arc,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use tower_service::Service;
+
+use crate::p2p::{
+    messages::Network,
+    ConnectionError,
+    HandshakeReport,
+    HandshakeResult,
+    Node,
+};
+
+/// Request to perform the handshake with a node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HandshakeRequest {
+    /// Address of the node
+    pub address: SocketAddrV4,
+    /// Network of the node, detected from its response if not set
+    pub network: Option<Network>,
+}
+
+/// Performs handshakes as a [`Service`], so that tower middleware, e.g.
+/// for retries, rate limiting or load shedding, can be wrapped around it.
+/// Clones share the same node.
+#[derive(Clone)]
+pub struct HandshakerClient {
+    node: Arc<Node>,
+}
+
+impl HandshakerClient {
+    pub fn new(node: Node) -> Self {
+        Self {
+            node: Arc::new(node),
+        }
+    }
+}
+
+impl Service<HandshakeRequest> for HandshakerClient {
+    type Response = HandshakeReport;
+    type Error = ConnectionError;
+    type Future = Pin<Box<dyn Future<Output = HandshakeResult> + Send>>;
+
+    /// Handshakes are independent of each other, so the client is always ready
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: HandshakeRequest) -> Self::Future {
+        let node = self.node.clone();
+        Box::pin(async move {
+            match request.network {
+                Some(network) => node.handshake(network, request.address).await,
+                None => node.detect_handshake(request.address).await,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use tokio::net::TcpListener;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn refused() {
+        // Reserve a port nothing listens on
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let client = HandshakerClient::new(Node::new(Default::default()));
+        let request = HandshakeRequest {
+            address: SocketAddrV4::new(Ipv4Addr::LOCALHOST, port),
+            network: Some(Network::Testnet),
+        };
+        assert_eq!(
+            client.clone().oneshot(request).await.err(),
+            Some(ConnectionError::ConnectionRefusedError)
+        );
+        assert_eq!(
+            client
+                .oneshot(HandshakeRequest {
+                    network: None,
+                    ..request
+                })
+                .await
+                .err(),
+            Some(ConnectionError::ConnectionRefusedError)
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{
+        Display,
+        Formatter,
+    },
+};
+
+use crate::p2p::{
+    messages::{
+        Command,
+        MessageHeader,
+    },
+    report::{
+        write_field,
+        Pretty,
+    },
+};
+
+/// Number of messages of a single command and bytes they occupied.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Tally {
+    /// Number of messages
+    pub messages: u32,
+    /// Number of bytes, including message headers
+    pub bytes: u64,
+}
+
+/// Messages exchanged during a session, tallied per command name.
+/// Commands unknown to the codec are tallied by the name found in
+/// their header.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommandStats {
+    /// Messages sent to the other node
+    pub sent: BTreeMap<String, Tally>,
+    /// Messages received from the other node
+    pub received: BTreeMap<String, Tally>,
+}
+
+impl CommandStats {
+    pub fn record_sent(&mut self, command: Command, bytes: usize) {
+        record(&mut self.sent, command.name(), bytes);
+    }
+
+    pub fn record_received(&mut self, command: &str, bytes: usize) {
+        record(&mut self.received, command, bytes);
+    }
+
+    /// Gets the total number of bytes sent
+    pub fn bytes_sent(&self) -> u64 {
+        self.sent.values().map(|t| t.bytes).sum()
+    }
+
+    /// Gets the total number of bytes received
+    pub fn bytes_received(&self) -> u64 {
+        self.received.values().map(|t| t.bytes).sum()
+    }
+
+    /// Gets the number of payload bytes exchanged in both directions,
+    /// i.e. without message headers
+    pub fn payload_bytes(&self) -> u64 {
+        self.sent
+            .values()
+            .chain(self.received.values())
+            .map(|t| {
+                t.bytes
+                    .saturating_sub(u64::from(t.messages) * MessageHeader::LENGTH as u64)
+            })
+            .sum()
+    }
+}
+
+fn record(tallies: &mut BTreeMap<String, Tally>, command: &str, bytes: usize) {
+    let tally = tallies.entry(command.to_string()).or_default();
+    tally.messages += 1;
+    tally.bytes += bytes as u64;
+}
+
+struct Tallies<'a>(&'a BTreeMap<String, Tally>);
+
+impl Display for Tallies<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for (i, (command, tally)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {} ({} B)", command, tally.messages, tally.bytes)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl Display for CommandStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sent {}, received {}",
+            Tallies(&self.sent),
+            Tallies(&self.received)
+        )
+    }
+}
+
+impl Display for Pretty<'_, CommandStats> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_field(f, "Sent", Tallies(&self.0.sent))?;
+        write_field(f, "Received", Tallies(&self.0.received))?;
+        write_field(
+            f,
+            "Bytes",
+            format!(
+                "{} sent, {} received",
+                self.0.bytes_sent(),
+                self.0.bytes_received()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tallies() {
+        let mut stats = CommandStats::default();
+        stats.record_sent(Command::Version, 126);
+        stats.record_sent(Command::Verack, 24);
+        stats.record_received("version", 126);
+        stats.record_received("inv", 61);
+        stats.record_received("inv", 97);
+
+        assert_eq!(
+            stats.received["inv"],
+            Tally {
+                messages: 2,
+                bytes: 158
+            }
+        );
+        assert_eq!(stats.bytes_sent(), 150);
+        assert_eq!(stats.bytes_received(), 284);
+        assert_eq!(stats.payload_bytes(), 434 - 5 * 24);
+        assert_eq!(
+            stats.to_string(),
+            "sent [verack: 1 (24 B), version: 1 (126 B)], \
+            received [inv: 2 (158 B), version: 1 (126 B)]"
+        );
+    }
+}
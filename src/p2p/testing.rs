@@ -0,0 +1,436 @@
+use std::{
+    net::{
+        SocketAddr,
+        SocketAddrV4,
+    },
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::StreamExt;
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncWrite,
+        AsyncWriteExt,
+        DuplexStream,
+    },
+    net::TcpListener,
+    time::sleep,
+};
+use tokio_util::{
+    codec::Framed,
+    compat::{
+        Compat,
+        FuturesAsyncReadCompatExt,
+        TokioAsyncReadCompatExt,
+    },
+};
+
+#[cfg(feature = "addr")]
+use crate::p2p::messages::{
+    AddrMessage,
+    TimestampedAddress,
+};
+use crate::p2p::{
+    messages::{
+        compose_message,
+        Message,
+        Network,
+        PongMessage,
+        ProtocolVersion,
+        VerackMessage,
+        VersionMessage,
+    },
+    transport::v2::V2Stream,
+    BitcoinMessageCodec,
+    ConnectionError,
+    NodeConfig,
+};
+
+/// Capacity of the in-memory stream in each direction
+const DUPLEX_CAPACITY: usize = 64 * 1024;
+
+/// Number of bytes of the Version message written at once when fragmented
+const FRAGMENT_LENGTH: usize = 7;
+
+/// Node answering handshakes over an in-memory stream or on a local port,
+/// whose behavior is scripted to test how the other side copes with it,
+/// e.g. a slow or misbehaving node.
+#[derive(Clone)]
+pub struct MockPeer {
+    network: Network,
+    /// Configuration advertised in the Version message
+    config: Arc<NodeConfig>,
+    /// Whether to send the Version message without waiting for the other one
+    speaks_first: bool,
+    /// Time to wait before sending the Verack message
+    verack_delay: Option<Duration>,
+    /// Whether to send the Version message with a corrupted checksum
+    wrong_checksum: bool,
+    /// Whether to send the Version message a few bytes at a time
+    fragmented: bool,
+    /// Whether to speak the v2 transport instead of the v1 one
+    v2: bool,
+    /// Messages sent between the Version and Verack messages
+    negotiation: Vec<Vec<u8>>,
+    /// Messages sent right after the Verack message
+    unsolicited: Vec<Vec<u8>>,
+    /// Messages sent along with the Pong message
+    chatter: Vec<Vec<u8>>,
+    /// Whether to answer Ping messages
+    answers_ping: bool,
+    /// Gets the nonce of the Pong message from the Ping's one
+    pong_nonce: fn(u64) -> u64,
+    /// Addr message GetAddr messages are answered with
+    #[cfg(feature = "addr")]
+    addresses: Option<Vec<u8>>,
+}
+
+impl MockPeer {
+    /// Creates the node of the network, speaking version 70016
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            config: Arc::new(NodeConfig {
+                version: ProtocolVersion::WTXID,
+                user_agent: "/MockPeer/".to_string(),
+                ..Default::default()
+            }),
+            speaks_first: false,
+            verack_delay: None,
+            wrong_checksum: false,
+            fragmented: false,
+            v2: false,
+            negotiation: Vec::new(),
+            unsolicited: Vec::new(),
+            chatter: Vec::new(),
+            answers_ping: true,
+            pong_nonce: |nonce| nonce,
+            #[cfg(feature = "addr")]
+            addresses: None,
+        }
+    }
+
+    /// Advertises the given configuration in the Version message
+    pub fn with_config(mut self, config: NodeConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Sends the Version message right away instead of answering the other one
+    pub fn speaking_first(mut self) -> Self {
+        self.speaks_first = true;
+        self
+    }
+
+    /// Waits before sending the Verack message
+    pub fn with_verack_delay(mut self, delay: Duration) -> Self {
+        self.verack_delay = Some(delay);
+        self
+    }
+
+    /// Sends the Version message with a checksum not matching its payload
+    pub fn with_wrong_checksum(mut self) -> Self {
+        self.wrong_checksum = true;
+        self
+    }
+
+    /// Sends the Version message a few bytes at a time, so that it arrives
+    /// over several reads
+    pub fn fragmented(mut self) -> Self {
+        self.fragmented = true;
+        self
+    }
+
+    /// Speaks the v2 transport (BIP324), hanging up on nodes speaking the
+    /// v1 one
+    pub fn speaking_v2(mut self) -> Self {
+        self.v2 = true;
+        self
+    }
+
+    /// Sends the message between the Version and Verack messages, i.e.
+    /// negotiates the feature it stands for
+    pub fn with_negotiation(mut self, message: &Message) -> Self {
+        self.negotiation
+            .push(compose_message(self.network, message));
+        self
+    }
+
+    /// Sends the message right after the Verack message, e.g. feature
+    /// negotiation coming too late
+    pub fn with_unsolicited(mut self, message: &Message) -> Self {
+        self.unsolicited
+            .push(compose_message(self.network, message));
+        self
+    }
+
+    /// Sends the message in the same write as the Pong message, e.g.
+    /// announcements of a node chatting once the handshake is performed
+    pub fn with_chatter(mut self, message: &Message) -> Self {
+        self.chatter.push(compose_message(self.network, message));
+        self
+    }
+
+    /// Leaves Ping messages unanswered
+    pub fn without_pong(mut self) -> Self {
+        self.answers_ping = false;
+        self
+    }
+
+    /// Answers Ping messages with the nonce the function gets from the
+    /// Ping's one, e.g. a wrong one
+    pub fn with_pong_nonce(mut self, pong_nonce: fn(u64) -> u64) -> Self {
+        self.pong_nonce = pong_nonce;
+        self
+    }
+
+    /// Answers GetAddr messages with the addresses
+    #[cfg(feature = "addr")]
+    pub fn with_addresses(mut self, addresses: Vec<TimestampedAddress>) -> Self {
+        let addr = Message::Addr(AddrMessage { addresses });
+        self.addresses = Some(compose_message(self.network, &addr));
+        self
+    }
+
+    /// Spawns the node on an in-memory stream, returning the other end of
+    /// the stream, e.g. for [`Node::handshake_over`](crate::p2p::Node::handshake_over)
+    pub fn spawn(&self) -> Compat<DuplexStream> {
+        let (ours, theirs) = tokio::io::duplex(DUPLEX_CAPACITY);
+        let peer = self.clone();
+        tokio::spawn(async move { peer.serve(theirs).await });
+        ours.compat()
+    }
+
+    /// Spawns the node listening on a local port, e.g. for
+    /// [`Node::handshake`](crate::p2p::Node::handshake), returning its address
+    pub async fn listen(&self) -> std::io::Result<SocketAddrV4> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = match listener.local_addr()? {
+            SocketAddr::V4(address) => address,
+            SocketAddr::V6(_) => unreachable!("bound to an IPv4 address"),
+        };
+
+        let peer = self.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, receiver)) = listener.accept().await {
+                let peer = peer.clone();
+                tokio::spawn(async move { peer.serve_to(socket, receiver).await });
+            }
+        });
+        Ok(address)
+    }
+
+    /// Answers the handshake over the stream until the other side hangs up
+    /// or sends anything invalid, returning the messages received
+    pub async fn serve<S>(&self, stream: S) -> Vec<Message>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        self.serve_to(stream, SocketAddr::from(([0, 0, 0, 0], 0)))
+            .await
+    }
+
+    /// Answers the handshake like [`serve`](Self::serve), advertising the
+    /// address of the other side as seen by us in the Version message
+    async fn serve_to<S>(&self, stream: S, receiver: SocketAddr) -> Vec<Message>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut received = Vec::new();
+        if !self.v2 {
+            let _ = self.converse(stream, receiver, &mut received).await;
+            return received;
+        }
+
+        // Nodes speaking the v1 transport fail the key exchange
+        let magic = self.network as u32;
+        if let Ok(stream) = V2Stream::respond(stream.compat(), magic).await {
+            let _ = self
+                .converse(stream.compat(), receiver, &mut received)
+                .await;
+        }
+        received
+    }
+
+    async fn converse<S>(
+        &self,
+        mut stream: S,
+        receiver: SocketAddr,
+        received: &mut Vec<Message>,
+    ) -> Result<(), ConnectionError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let version = VersionMessage::new(receiver, &self.config);
+        let mut version = compose_message(self.network, &Message::Version(version));
+        if self.wrong_checksum {
+            version[20] ^= 0xff;
+        }
+
+        // Messages are written as composed, so that they can be corrupted
+        // or coalesced on purpose
+        let mut framed = Framed::new(&mut stream, BitcoinMessageCodec::new(self.network));
+        if self.speaks_first {
+            framed.get_mut().write_all(&version).await?;
+        }
+
+        while let Some(message) = framed.next().await {
+            let message = message?;
+            let stream = framed.get_mut();
+            match &message {
+                Message::Version(_) => {
+                    if !self.speaks_first {
+                        self.write_version(stream, &version).await?;
+                    }
+                    for data in &self.negotiation {
+                        stream.write_all(data).await?;
+                    }
+                    if let Some(delay) = self.verack_delay {
+                        sleep(delay).await;
+                    }
+                    let verack = Message::Verack(VerackMessage {});
+                    stream
+                        .write_all(&compose_message(self.network, &verack))
+                        .await?;
+                    for data in &self.unsolicited {
+                        stream.write_all(data).await?;
+                    }
+                }
+                Message::Ping(ping) if self.answers_ping => {
+                    let nonce = (self.pong_nonce)(ping.nonce());
+                    let pong = Message::Pong(PongMessage::new(nonce));
+                    let mut data = compose_message(self.network, &pong);
+                    for chatter in &self.chatter {
+                        data.extend_from_slice(chatter);
+                    }
+                    stream.write_all(&data).await?;
+                }
+                #[cfg(feature = "addr")]
+                Message::GetAddr(_) => {
+                    if let Some(data) = &self.addresses {
+                        stream.write_all(data).await?;
+                    }
+                }
+                _ => {}
+            }
+            received.push(message);
+        }
+        Ok(())
+    }
+
+    async fn write_version<S>(
+        &self,
+        stream: &mut S,
+        version: &[u8],
+    ) -> std::io::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        if !self.fragmented {
+            return stream.write_all(version).await;
+        }
+        for fragment in version.chunks(FRAGMENT_LENGTH) {
+            stream.write_all(fragment).await?;
+            stream.flush().await?;
+            sleep(Duration::from_millis(1)).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::p2p::{
+        messages::{
+            Command,
+            WtxidRelayMessage,
+        },
+        Node,
+    };
+
+    fn node() -> Node {
+        Node::new(NodeConfig {
+            version: ProtocolVersion::WTXID,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn handshake() {
+        let peer = MockPeer::new(Network::Testnet);
+        let report = node()
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .unwrap();
+        assert_eq!(report.config.version, ProtocolVersion::WTXID);
+        assert_eq!(report.config.user_agent, "/MockPeer/");
+        assert!(report.ping_rtt().is_some());
+
+        let report = node()
+            .handshake_over(Network::Testnet, peer.speaking_first().spawn())
+            .await
+            .unwrap();
+        assert_eq!(report.violations, 0);
+
+        let address = MockPeer::new(Network::Testnet).listen().await.unwrap();
+        let report = node().handshake(Network::Testnet, address).await.unwrap();
+        assert_eq!(report.config.user_agent, "/MockPeer/");
+    }
+
+    #[tokio::test]
+    async fn received() {
+        let (ours, theirs) = tokio::io::duplex(DUPLEX_CAPACITY);
+        let peer = MockPeer::new(Network::Testnet);
+        let node = node();
+        let (received, report) = tokio::join!(peer.serve(theirs), async move {
+            node.handshake_over(Network::Testnet, ours.compat()).await
+        });
+        assert!(report.is_ok());
+
+        let commands: Vec<_> = received.iter().map(Message::command).collect();
+        let mut expected = vec![Command::Version];
+        #[cfg(feature = "addr")]
+        expected.push(Command::SendAddrV2);
+        expected.extend([Command::Verack, Command::Ping]);
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn behaviors() {
+        let node = node();
+        let peer = MockPeer::new(Network::Testnet).with_wrong_checksum();
+        assert_eq!(
+            node.handshake_over(Network::Testnet, peer.spawn())
+                .await
+                .err(),
+            Some(ConnectionError::InvalidDataError)
+        );
+
+        let peer =
+            MockPeer::new(Network::Testnet).with_verack_delay(Duration::from_secs(5));
+        let handshake = node.handshake_over(Network::Testnet, peer.spawn());
+        assert!(tokio::time::timeout(Duration::from_millis(100), handshake)
+            .await
+            .is_err());
+
+        let peer = MockPeer::new(Network::Testnet).without_pong();
+        let handshake = node.handshake_over(Network::Testnet, peer.spawn());
+        assert!(tokio::time::timeout(Duration::from_millis(100), handshake)
+            .await
+            .is_err());
+
+        // Feature negotiation after the Verack message
+        let peer = MockPeer::new(Network::Testnet)
+            .with_unsolicited(&Message::WtxidRelay(WtxidRelayMessage {}));
+        let report = node
+            .handshake_over(Network::Testnet, peer.spawn())
+            .await
+            .unwrap();
+        assert_eq!(report.violations, 1);
+        assert!(report.wtxidrelay);
+    }
+}
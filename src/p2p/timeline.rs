@@ -0,0 +1,68 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    time::Duration,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Direction::Sent => write!(f, "sent"),
+            Direction::Received => write!(f, "received"),
+        }
+    }
+}
+
+/// Single message exchanged during a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelineEvent {
+    /// Time elapsed since the session started
+    pub elapsed: Duration,
+    /// Whether the message was sent or received
+    pub direction: Direction,
+    /// Command name of the message
+    pub command: String,
+    /// Length of the raw message, including the header
+    pub length: usize,
+    /// Raw message, including the header, if the node was asked to keep
+    /// it, see [`Node::with_frames`](crate::p2p::Node::with_frames)
+    pub frame: Option<Vec<u8>>,
+}
+
+impl Display for TimelineEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>9.3} ms {:<8} {} ({} B)",
+            self.elapsed.as_secs_f64() * 1000.0,
+            self.direction,
+            self.command,
+            self.length
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let event = TimelineEvent {
+            elapsed: Duration::from_micros(9_012_345),
+            direction: Direction::Received,
+            command: "verack".to_string(),
+            length: 24,
+            frame: None,
+        };
+        assert_eq!(event.to_string(), " 9012.345 ms received verack (24 B)");
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    time::Duration,
+};
+
+/// Time to wait for each message, scaled from the round-trip time measured
+/// while establishing the TCP connection, so that slow links such as Tor
+/// get more time while nearby nodes still fail fast.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveTimeout {
+    /// Number of round-trip times a message may take
+    pub multiplier: u32,
+    /// Shortest timeout, however fast the connection is
+    pub floor: Duration,
+    /// Longest timeout, however slow the connection is
+    pub ceiling: Duration,
+}
+
+impl Default for AdaptiveTimeout {
+    fn default() -> Self {
+        Self {
+            multiplier: 10,
+            floor: Duration::from_millis(200),
+            ceiling: Duration::from_secs(10),
+        }
+    }
+}
+
+impl AdaptiveTimeout {
+    /// Gets the timeout for the given round-trip time
+    pub fn timeout(&self, rtt: Duration) -> Duration {
+        rtt.saturating_mul(self.multiplier)
+            .min(self.ceiling)
+            .max(self.floor)
+    }
+}
+
+/// Stage of the handshake limited by its own timeout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    /// Opening the connection, through the proxy if set
+    Connect,
+    /// Awaiting the other node's Version message
+    Version,
+    /// Awaiting the Verack message once the Version message arrived
+    Verack,
+    /// Awaiting the Pong message once the Verack message arrived
+    Pong,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Connect => write!(f, "connect"),
+            Stage::Version => write!(f, "version"),
+            Stage::Verack => write!(f, "verack"),
+            Stage::Pong => write!(f, "pong"),
+        }
+    }
+}
+
+/// Longest time each stage of the handshake may take, measured from the
+/// end of the previous one, so that a slow connect does not eat into the
+/// time the node has to answer. Stages without a timeout are unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StageTimeouts {
+    pub connect: Option<Duration>,
+    pub version: Option<Duration>,
+    pub verack: Option<Duration>,
+    pub pong: Option<Duration>,
+}
+
+impl StageTimeouts {
+    /// Gets the timeout of the stage
+    pub fn get(&self, stage: Stage) -> Option<Duration> {
+        match stage {
+            Stage::Connect => self.connect,
+            Stage::Version => self.version,
+            Stage::Verack => self.verack,
+            Stage::Pong => self.pong,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout() {
+        let timeout = AdaptiveTimeout::default();
+        assert_eq!(
+            timeout.timeout(Duration::from_micros(300)),
+            Duration::from_millis(200)
+        );
+        assert_eq!(
+            timeout.timeout(Duration::from_millis(80)),
+            Duration::from_millis(800)
+        );
+        assert_eq!(
+            timeout.timeout(Duration::from_secs(3)),
+            Duration::from_secs(10)
+        );
+    }
+}
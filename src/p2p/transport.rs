@@ -0,0 +1,103 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    io,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use futures::io::{
+    AsyncRead,
+    AsyncWrite,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod v2;
+
+/// Transport messages were exchanged over with the node.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TransportVersion {
+    /// Cleartext messages framed by the magic bytes and a checksum
+    #[default]
+    V1,
+    /// Encrypted and authenticated packets (BIP324)
+    V2,
+}
+
+impl Display for TransportVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportVersion::V1 => write!(f, "v1"),
+            TransportVersion::V2 => write!(f, "v2"),
+        }
+    }
+}
+
+/// Stream to the node which carries messages framed as v1 ones, whatever
+/// transport is spoken on the wire, so that the handshake and further
+/// exchange work the same over both.
+pub enum Transport<S> {
+    V1(S),
+    #[cfg(not(target_arch = "wasm32"))]
+    V2(Box<v2::V2Stream<S>>),
+}
+
+impl<S> Transport<S> {
+    /// Gets the transport spoken on the wire
+    pub fn version(&self) -> TransportVersion {
+        match self {
+            Transport::V1(_) => TransportVersion::V1,
+            #[cfg(not(target_arch = "wasm32"))]
+            Transport::V2(_) => TransportVersion::V2,
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for Transport<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Transport::V1(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(not(target_arch = "wasm32"))]
+            Transport::V2(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for Transport<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Transport::V1(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(not(target_arch = "wasm32"))]
+            Transport::V2(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Transport::V1(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(not(target_arch = "wasm32"))]
+            Transport::V2(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Transport::V1(stream) => Pin::new(stream).poll_close(cx),
+            #[cfg(not(target_arch = "wasm32"))]
+            Transport::V2(stream) => Pin::new(stream).poll_close(cx),
+        }
+    }
+}
@@ -0,0 +1,917 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{
+        Context,
+        Poll,
+    },
+};
+
+use chacha20::{
+    cipher::{
+        KeyIvInit,
+        StreamCipher,
+    },
+    ChaCha20,
+};
+use chacha20poly1305::{
+    aead::{
+        AeadInPlace,
+        KeyInit,
+    },
+    ChaCha20Poly1305,
+    Tag,
+};
+use futures::{
+    io::{
+        AsyncRead,
+        AsyncReadExt,
+        AsyncWrite,
+        AsyncWriteExt,
+    },
+    ready,
+};
+use hkdf::Hkdf;
+use rand::Rng;
+use secp256k1::{
+    ellswift::{
+        ElligatorSwift,
+        ElligatorSwiftParty,
+    },
+    Secp256k1,
+    SecretKey,
+};
+use sha2::Sha256;
+
+use crate::p2p::{
+    connection::frame_length,
+    messages::{
+        calculate_checksum,
+        Command,
+        MessageHeader,
+    },
+    ConnectionError,
+    MAX_PAYLOAD_LENGTH,
+};
+
+/// Length of the ElligatorSwift encoded public key each side starts with
+const KEY_LENGTH: usize = 64;
+
+/// Most garbage bytes sent before the garbage terminator
+pub const MAX_GARBAGE_LENGTH: usize = 4095;
+
+/// Length of the terminator ending the garbage
+const TERMINATOR_LENGTH: usize = 16;
+
+/// Length of the encrypted length of the packet contents
+const LENGTH_LENGTH: usize = 3;
+
+/// Length of the authentication tag ending each packet
+const TAG_LENGTH: usize = 16;
+
+/// Bit of the packet header marking decoy packets, which are ignored
+const IGNORE_BIT: u8 = 0x80;
+
+/// Number of lengths or packets encrypted with a key before it is replaced
+const REKEY_INTERVAL: u32 = 224;
+
+/// Longest packet contents accepted, i.e. the long command encoding
+/// followed by the largest payload
+const MAX_CONTENTS_LENGTH: usize =
+    1 + Command::REQUIRED_LENGTH + MAX_PAYLOAD_LENGTH as usize;
+
+/// Commands sent as a single byte instead of their 12-byte name, the
+/// short ID of each being its position starting at 1
+const SHORT_IDS: [&str; 28] = [
+    "addr",
+    "block",
+    "blocktxn",
+    "cmpctblock",
+    "feefilter",
+    "filteradd",
+    "filterclear",
+    "filterload",
+    "getblocks",
+    "getblocktxn",
+    "getdata",
+    "getheaders",
+    "headers",
+    "inv",
+    "mempool",
+    "merkleblock",
+    "notfound",
+    "ping",
+    "pong",
+    "sendcmpct",
+    "tx",
+    "getcfilters",
+    "cfilter",
+    "getcfheaders",
+    "cfheaders",
+    "getcfcheckpt",
+    "cfcheckpt",
+    "addrv2",
+];
+
+/// Cipher of the packet lengths, i.e. a ChaCha20 keystream whose key is
+/// replaced by the keystream following every REKEY_INTERVAL lengths
+/// (FSChaCha20)
+struct LengthCipher {
+    cipher: ChaCha20,
+    /// Lengths encrypted with the current key
+    chunks: u32,
+    /// Number of times the key was replaced
+    rekeys: u64,
+}
+
+impl LengthCipher {
+    fn new(key: [u8; 32]) -> Self {
+        Self {
+            cipher: Self::keystream(key, 0),
+            chunks: 0,
+            rekeys: 0,
+        }
+    }
+
+    fn keystream(key: [u8; 32], rekeys: u64) -> ChaCha20 {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&rekeys.to_le_bytes());
+        ChaCha20::new(&key.into(), &nonce.into())
+    }
+
+    /// Encrypts or decrypts the length in place
+    fn crypt(&mut self, length: &mut [u8; LENGTH_LENGTH]) {
+        self.cipher.apply_keystream(length);
+        self.chunks += 1;
+        if self.chunks == REKEY_INTERVAL {
+            let mut key = [0; 32];
+            self.cipher.apply_keystream(&mut key);
+            self.chunks = 0;
+            self.rekeys += 1;
+            self.cipher = Self::keystream(key, self.rekeys);
+        }
+    }
+}
+
+/// Cipher of the packets, i.e. ChaCha20-Poly1305 whose key is replaced
+/// every REKEY_INTERVAL packets (FSChaCha20Poly1305)
+struct PacketCipher {
+    key: [u8; 32],
+    /// Packets encrypted with the current key
+    packets: u32,
+    /// Number of times the key was replaced
+    rekeys: u64,
+}
+
+impl PacketCipher {
+    fn new(key: [u8; 32]) -> Self {
+        Self {
+            key,
+            packets: 0,
+            rekeys: 0,
+        }
+    }
+
+    fn nonce(&self, counter: u32) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[..4].copy_from_slice(&counter.to_le_bytes());
+        nonce[4..].copy_from_slice(&self.rekeys.to_le_bytes());
+        nonce
+    }
+
+    /// Encrypts the plaintext in place, appending the authentication tag
+    fn encrypt(&mut self, aad: &[u8], text: &mut Vec<u8>) {
+        let nonce = self.nonce(self.packets);
+        let tag = ChaCha20Poly1305::new(&self.key.into())
+            .encrypt_in_place_detached(&nonce.into(), aad, text)
+            .expect("packet contents are limited in length");
+        text.extend_from_slice(&tag);
+        self.advance();
+    }
+
+    /// Decrypts the ciphertext followed by the authentication tag in place,
+    /// leaving the plaintext only
+    fn decrypt(&mut self, aad: &[u8], text: &mut Vec<u8>) -> Result<(), ConnectionError> {
+        let nonce = self.nonce(self.packets);
+        let tag = text.split_off(text.len() - TAG_LENGTH);
+        let result = ChaCha20Poly1305::new(&self.key.into()).decrypt_in_place_detached(
+            &nonce.into(),
+            aad,
+            text,
+            Tag::from_slice(&tag),
+        );
+        self.advance();
+        result.map_err(|_| ConnectionError::InvalidDataError)
+    }
+
+    fn advance(&mut self) {
+        self.packets += 1;
+        if self.packets == REKEY_INTERVAL {
+            let nonce = self.nonce(u32::MAX);
+            let mut key = [0; 32];
+            // Encrypting into an in-memory buffer cannot fail
+            let _ = ChaCha20Poly1305::new(&self.key.into()).encrypt_in_place_detached(
+                &nonce.into(),
+                &[],
+                &mut key,
+            );
+            self.key = key;
+            self.packets = 0;
+            self.rekeys += 1;
+        }
+    }
+}
+
+/// Stream speaking the v2 encrypted transport (BIP324) with the node,
+/// converting the v1 messages written to it into v2 packets and the packets
+/// received back into v1 messages. Since packets carry no checksum, the
+/// messages read get the sha256d one.
+pub struct V2Stream<S> {
+    stream: S,
+    /// Magic bytes of the network, put in front of the messages read
+    magic: u32,
+    send_length: LengthCipher,
+    send_packet: PacketCipher,
+    recv_length: LengthCipher,
+    recv_packet: PacketCipher,
+    send_terminator: [u8; TERMINATOR_LENGTH],
+    recv_terminator: [u8; TERMINATOR_LENGTH],
+    session_id: [u8; 32],
+    /// Bytes written which do not make a whole message yet
+    written: Vec<u8>,
+    /// Encrypted packets waiting to be sent
+    outgoing: Vec<u8>,
+    /// Encrypted bytes received which do not make a whole packet yet
+    incoming: Vec<u8>,
+    /// Contents length of the packet being received, once decrypted
+    pending_length: Option<usize>,
+    /// Messages decrypted, waiting to be read
+    readable: Vec<u8>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> V2Stream<S> {
+    /// Performs the key exchange as the side which opened the connection,
+    /// failing if the node does not speak the v2 transport, e.g. hangs up
+    pub async fn initiate(stream: S, magic: u32) -> Result<Self, ConnectionError> {
+        Self::handshake(stream, magic, true).await
+    }
+
+    /// Performs the key exchange as the side which accepted the connection
+    pub async fn respond(stream: S, magic: u32) -> Result<Self, ConnectionError> {
+        Self::handshake(stream, magic, false).await
+    }
+
+    /// Gets the identifier both sides derived for the session, e.g. to
+    /// compare it with the one Bitcoin Core shows for the connection
+    pub fn session_id(&self) -> [u8; 32] {
+        self.session_id
+    }
+
+    async fn handshake(
+        stream: S,
+        magic: u32,
+        initiating: bool,
+    ) -> Result<Self, ConnectionError> {
+        let (secret_key, garbage) = {
+            let mut rng = rand::thread_rng();
+            let secret_key = loop {
+                if let Ok(key) = SecretKey::from_slice(&rng.gen::<[u8; 32]>()) {
+                    break key;
+                }
+            };
+            let garbage: Vec<u8> = (0..rng.gen_range(0..=MAX_GARBAGE_LENGTH))
+                .map(|_| rng.gen())
+                .collect();
+            (secret_key, garbage)
+        };
+        Self::handshake_with(stream, magic, initiating, secret_key, garbage).await
+    }
+
+    /// Performs the key exchange with the given key, sending the given
+    /// garbage before the garbage terminator
+    async fn handshake_with(
+        mut stream: S,
+        magic: u32,
+        initiating: bool,
+        secret_key: SecretKey,
+        garbage: Vec<u8>,
+    ) -> Result<Self, ConnectionError> {
+        let ours = ElligatorSwift::from_seckey(
+            &Secp256k1::new(),
+            secret_key,
+            Some(rand::thread_rng().gen()),
+        );
+        let mut hello = ours.to_array().to_vec();
+        hello.extend_from_slice(&garbage);
+
+        let mut incoming = Vec::new();
+        if initiating {
+            stream.write_all(&hello).await?;
+            stream.flush().await?;
+        }
+        while incoming.len() < KEY_LENGTH {
+            fill(&mut stream, &mut incoming).await?;
+        }
+        if !initiating {
+            stream.write_all(&hello).await?;
+        }
+        let theirs =
+            ElligatorSwift::from_array(incoming[..KEY_LENGTH].try_into().unwrap());
+        incoming.drain(..KEY_LENGTH);
+
+        let secret = shared_secret(secret_key, ours, theirs, initiating);
+        let mut transport = Self::derive(stream, magic, secret, initiating);
+        transport.incoming = incoming;
+
+        // Version packet authenticates the garbage sent before it
+        let mut data = transport.send_terminator.to_vec();
+        data.extend(transport.encrypt_packet(&garbage, 0, &[]));
+        transport.stream.write_all(&data).await?;
+        transport.stream.flush().await?;
+
+        let received_garbage = loop {
+            let window = transport
+                .incoming
+                .len()
+                .min(MAX_GARBAGE_LENGTH + TERMINATOR_LENGTH);
+            let position = transport.incoming[..window]
+                .windows(TERMINATOR_LENGTH)
+                .position(|w| w == transport.recv_terminator);
+            if let Some(position) = position {
+                let garbage: Vec<u8> = transport.incoming.drain(..position).collect();
+                transport.incoming.drain(..TERMINATOR_LENGTH);
+                break garbage;
+            }
+            if window == MAX_GARBAGE_LENGTH + TERMINATOR_LENGTH {
+                return Err(ConnectionError::InvalidDataError);
+            }
+            fill(&mut transport.stream, &mut transport.incoming).await?;
+        };
+
+        // First packet authenticates the garbage received, even if a decoy,
+        // while the contents of the version packet are reserved for future
+        // extensions
+        let mut aad = received_garbage;
+        loop {
+            match transport.decrypt_packet(&aad)? {
+                Some((header, _)) => {
+                    aad.clear();
+                    if header & IGNORE_BIT == 0 {
+                        break;
+                    }
+                }
+                None => fill(&mut transport.stream, &mut transport.incoming).await?,
+            }
+        }
+        Ok(transport)
+    }
+
+    /// Derives the keys of the session from the shared secret
+    fn derive(stream: S, magic: u32, secret: [u8; 32], initiating: bool) -> Self {
+        let mut salt = b"bitcoin_v2_shared_secret".to_vec();
+        salt.extend_from_slice(&magic.to_le_bytes());
+        let hkdf = Hkdf::<Sha256>::new(Some(&salt), &secret);
+        let expand = |info: &str| {
+            let mut key = [0; 32];
+            hkdf.expand(info.as_bytes(), &mut key)
+                .expect("32 bytes are a valid length");
+            key
+        };
+
+        let terminators = expand("garbage_terminators");
+        let (initiator_terminator, responder_terminator) =
+            terminators.split_at(TERMINATOR_LENGTH);
+        let (send, recv) = match initiating {
+            true => ("initiator", "responder"),
+            false => ("responder", "initiator"),
+        };
+        let (send_terminator, recv_terminator) = match initiating {
+            true => (initiator_terminator, responder_terminator),
+            false => (responder_terminator, initiator_terminator),
+        };
+        Self {
+            stream,
+            magic,
+            send_length: LengthCipher::new(expand(&format!("{}_L", send))),
+            send_packet: PacketCipher::new(expand(&format!("{}_P", send))),
+            recv_length: LengthCipher::new(expand(&format!("{}_L", recv))),
+            recv_packet: PacketCipher::new(expand(&format!("{}_P", recv))),
+            send_terminator: send_terminator.try_into().unwrap(),
+            recv_terminator: recv_terminator.try_into().unwrap(),
+            session_id: expand("session_id"),
+            written: Vec::new(),
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+            pending_length: None,
+            readable: Vec::new(),
+        }
+    }
+}
+
+impl<S> V2Stream<S> {
+    /// Encrypts the packet with the given header and contents
+    fn encrypt_packet(&mut self, aad: &[u8], header: u8, contents: &[u8]) -> Vec<u8> {
+        let mut length: [u8; LENGTH_LENGTH] = (contents.len() as u32).to_le_bytes()
+            [..LENGTH_LENGTH]
+            .try_into()
+            .unwrap();
+        self.send_length.crypt(&mut length);
+
+        let mut packet = Vec::with_capacity(1 + contents.len() + TAG_LENGTH);
+        packet.push(header);
+        packet.extend_from_slice(contents);
+        self.send_packet.encrypt(aad, &mut packet);
+
+        let mut data = length.to_vec();
+        data.extend(packet);
+        data
+    }
+
+    /// Takes the next packet out of the bytes received, returning its
+    /// header and contents, or `None` until all of its bytes are received
+    fn decrypt_packet(
+        &mut self,
+        aad: &[u8],
+    ) -> Result<Option<(u8, Vec<u8>)>, ConnectionError> {
+        let length = match self.pending_length {
+            Some(length) => length,
+            // Length is decrypted once only, as the keystream moves on
+            None if self.incoming.len() >= LENGTH_LENGTH => {
+                let mut length: [u8; LENGTH_LENGTH] =
+                    self.incoming[..LENGTH_LENGTH].try_into().unwrap();
+                self.incoming.drain(..LENGTH_LENGTH);
+                self.recv_length.crypt(&mut length);
+                let length = u32::from_le_bytes([length[0], length[1], length[2], 0]);
+                if length as usize > MAX_CONTENTS_LENGTH {
+                    return Err(ConnectionError::InvalidDataError);
+                }
+                self.pending_length = Some(length as usize);
+                length as usize
+            }
+            None => return Ok(None),
+        };
+
+        if self.incoming.len() < 1 + length + TAG_LENGTH {
+            return Ok(None);
+        }
+        let mut packet: Vec<u8> =
+            self.incoming.drain(..1 + length + TAG_LENGTH).collect();
+        self.pending_length = None;
+        self.recv_packet.decrypt(aad, &mut packet)?;
+        let contents = packet.split_off(1);
+        Ok(Some((packet[0], contents)))
+    }
+
+    /// Converts the contents of the packet into the v1 message, or `None`
+    /// if its short ID is unknown
+    fn message(&self, contents: &[u8]) -> Result<Option<Vec<u8>>, ConnectionError> {
+        let (command, payload) = match contents.split_first() {
+            Some((0, rest)) if rest.len() >= Command::REQUIRED_LENGTH => {
+                let (command, payload) = rest.split_at(Command::REQUIRED_LENGTH);
+                (command.to_vec(), payload)
+            }
+            Some((&id, payload)) if id > 0 => match SHORT_IDS.get(id as usize - 1) {
+                Some(name) => {
+                    let mut command = name.as_bytes().to_vec();
+                    command.resize(Command::REQUIRED_LENGTH, 0);
+                    (command, payload)
+                }
+                None => return Ok(None),
+            },
+            _ => return Err(ConnectionError::InvalidDataError),
+        };
+
+        let mut data = Vec::with_capacity(MessageHeader::LENGTH + payload.len());
+        data.extend_from_slice(&self.magic.to_le_bytes());
+        data.extend_from_slice(&command);
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(&calculate_checksum(payload).to_le_bytes());
+        data.extend_from_slice(payload);
+        Ok(Some(data))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> V2Stream<S> {
+    /// Writes the encrypted packets waiting to be sent
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.outgoing.is_empty() {
+            let n = ready!(Pin::new(&mut self.stream).poll_write(cx, &self.outgoing))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.outgoing.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Computes the secret shared with the node from our secret key and the
+/// public keys of both sides
+fn shared_secret(
+    secret_key: SecretKey,
+    ours: ElligatorSwift,
+    theirs: ElligatorSwift,
+    initiating: bool,
+) -> [u8; 32] {
+    // Public key of the initiator comes first, whichever side we are
+    let (party, a, b) = match initiating {
+        true => (ElligatorSwiftParty::A, ours, theirs),
+        false => (ElligatorSwiftParty::B, theirs, ours),
+    };
+    ElligatorSwift::shared_secret(a, b, secret_key, party, None).to_secret_bytes()
+}
+
+/// Converts the v1 message into the contents of a v2 packet, shortening
+/// the command to its short ID if it has one
+fn contents(message: &[u8]) -> Vec<u8> {
+    let command = &message[4..4 + Command::REQUIRED_LENGTH];
+    let payload = &message[MessageHeader::LENGTH..];
+    let name = command.split(|&b| b == 0).next().unwrap_or_default();
+
+    let mut contents = Vec::with_capacity(1 + Command::REQUIRED_LENGTH + payload.len());
+    match SHORT_IDS.iter().position(|id| id.as_bytes() == name) {
+        Some(position) => contents.push(position as u8 + 1),
+        None => {
+            contents.push(0);
+            contents.extend_from_slice(command);
+        }
+    }
+    contents.extend_from_slice(payload);
+    contents
+}
+
+/// Reads whatever the node sent so far into the buffer
+async fn fill<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buffer: &mut Vec<u8>,
+) -> Result<(), ConnectionError> {
+    let mut chunk = [0; 4096];
+    match stream.read(&mut chunk).await? {
+        0 => Err(ConnectionError::ConnectionHangUp),
+        n => {
+            buffer.extend_from_slice(&chunk[..n]);
+            Ok(())
+        }
+    }
+}
+
+fn invalid_data(e: ConnectionError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for V2Stream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // Messages written are usually answered, so send them before waiting
+        if let Poll::Ready(Err(e)) = this.poll_send(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        loop {
+            if !this.readable.is_empty() {
+                let n = buf.len().min(this.readable.len());
+                buf[..n].copy_from_slice(&this.readable[..n]);
+                this.readable.drain(..n);
+                return Poll::Ready(Ok(n));
+            }
+
+            if let Some((header, contents)) =
+                this.decrypt_packet(&[]).map_err(invalid_data)?
+            {
+                if header & IGNORE_BIT == 0 {
+                    if let Some(message) =
+                        this.message(&contents).map_err(invalid_data)?
+                    {
+                        this.readable = message;
+                    }
+                }
+                continue;
+            }
+
+            let mut chunk = [0; 4096];
+            let n = ready!(Pin::new(&mut this.stream).poll_read(cx, &mut chunk))?;
+            if n == 0 {
+                return Poll::Ready(Ok(0));
+            }
+            this.incoming.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for V2Stream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+
+        this.written.extend_from_slice(buf);
+        while let Some(length) = frame_length(&this.written)
+            .map_err(invalid_data)?
+            .filter(|&length| this.written.len() >= length)
+        {
+            let message: Vec<u8> = this.written.drain(..length).collect();
+            let packet = this.encrypt_packet(&[], 0, &contents(&message));
+            this.outgoing.extend(packet);
+        }
+
+        // Whatever is not sent right away is sent before the next read
+        if let Poll::Ready(Err(e)) = this.poll_send(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.stream).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send(cx))?;
+        Pin::new(&mut this.stream).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    use crate::p2p::messages::{
+        compose,
+        Network,
+        PingMessage,
+        VerackMessage,
+    };
+
+    async fn pair() -> (
+        V2Stream<impl AsyncRead + AsyncWrite + Unpin>,
+        V2Stream<impl AsyncRead + AsyncWrite + Unpin>,
+    ) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let magic = Network::Testnet as u32;
+        let (initiator, responder) = tokio::join!(
+            V2Stream::initiate(a.compat(), magic),
+            V2Stream::respond(b.compat(), magic)
+        );
+        (initiator.unwrap(), responder.unwrap())
+    }
+
+    #[test]
+    fn short_ids() {
+        let ping = compose(Network::Testnet, Command::Ping, PingMessage::new(7));
+        assert_eq!(contents(&ping), [&[18][..], &7u64.to_le_bytes()].concat());
+
+        let verack = compose(Network::Testnet, Command::Verack, VerackMessage {});
+        assert_eq!(contents(&verack), b"\x00verack\x00\x00\x00\x00\x00\x00");
+    }
+
+    #[tokio::test]
+    async fn exchange() {
+        let (mut initiator, mut responder) = pair().await;
+        assert_eq!(initiator.session_id(), responder.session_id());
+
+        // Enough messages for the keys to be replaced twice
+        for i in 0..500 {
+            let ping = compose(Network::Testnet, Command::Ping, PingMessage::new(i));
+            initiator.write_all(&ping).await.unwrap();
+            let mut received = vec![0; ping.len()];
+            responder.read_exact(&mut received).await.unwrap();
+            assert_eq!(received, ping);
+        }
+
+        let verack = compose(Network::Testnet, Command::Verack, VerackMessage {});
+        responder.write_all(&verack).await.unwrap();
+        let mut received = vec![0; verack.len()];
+        initiator.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, verack);
+    }
+
+    #[tokio::test]
+    async fn decoys() {
+        let (mut initiator, mut responder) = pair().await;
+        let mut data = initiator.encrypt_packet(&[], IGNORE_BIT, b"decoy");
+        // Unknown short IDs are skipped as well
+        data.extend(initiator.encrypt_packet(&[], 0, &[255]));
+        data.extend(initiator.encrypt_packet(
+            &[],
+            0,
+            &contents(&compose(
+                Network::Testnet,
+                Command::Ping,
+                PingMessage::new(1),
+            )),
+        ));
+        initiator.stream.write_all(&data).await.unwrap();
+
+        let mut received = vec![0; MessageHeader::LENGTH + 8];
+        responder.read_exact(&mut received).await.unwrap();
+        assert_eq!(
+            received,
+            compose(Network::Testnet, Command::Ping, PingMessage::new(1))
+        );
+
+        // Tampered packets fail authentication
+        let mut data = initiator.encrypt_packet(&[], 0, &[18, 0, 0, 0, 0, 0, 0, 0, 0]);
+        *data.last_mut().unwrap() ^= 1;
+        initiator.stream.write_all(&data).await.unwrap();
+        assert!(responder.read(&mut received).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn network_mismatch() {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        let key = |byte| SecretKey::from_slice(&[byte; 32]).unwrap();
+        // With the most garbage, each side receives more bytes than may come
+        // before the terminator without finding the other's one
+        let initiator = V2Stream::handshake_with(
+            a.compat(),
+            Network::Testnet as u32,
+            true,
+            key(1),
+            vec![0; MAX_GARBAGE_LENGTH],
+        );
+        let responder = V2Stream::handshake_with(
+            b.compat(),
+            Network::Main as u32,
+            false,
+            key(2),
+            vec![0; MAX_GARBAGE_LENGTH],
+        );
+        let (initiator, responder) = tokio::join!(initiator, responder);
+        assert!(matches!(initiator, Err(ConnectionError::InvalidDataError)));
+        assert!(matches!(responder, Err(ConnectionError::InvalidDataError)));
+    }
+
+    /// Row of the BIP324 test vectors (packet_encoding_test_vectors.csv),
+    /// with the columns checked here
+    struct Vector {
+        idx: u32,
+        priv_ours: &'static str,
+        ellswift_ours: &'static str,
+        ellswift_theirs: &'static str,
+        initiating: bool,
+        contents: &'static str,
+        ignore: bool,
+        shared_secret: &'static str,
+        /// Whole packet, or its end only for the long ones
+        ciphertext: &'static str,
+        ciphertext_endswith: &'static str,
+    }
+
+    const VECTORS: [Vector; 3] = [
+        Vector {
+            idx: 1,
+            priv_ours: "61062ea5071d800bbfd59e2e8b53d47d194b095ae5a4df04936b49772ef0d4d7",
+            ellswift_ours: concat!(
+                "ec0adff257bbfe500c188c80b4fdd640f6b45a482bbc15fc7cef5931deff0aa1",
+                "86f6eb9bba7b85dc4dcc28b28722de1e3d9108b985e2967045668f66098e475b"
+            ),
+            ellswift_theirs: concat!(
+                "a4a94dfce69b4a2a0a099313d10f9f7e7d649d60501c9e1d274c300e0d89aafa",
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffff8faf88d5"
+            ),
+            initiating: true,
+            contents: "8e",
+            ignore: false,
+            shared_secret:
+                "c6992a117f5edbea70c3f511d32d26b9798be4b81a62eaee1a5acaa8459a3592",
+            ciphertext: "7530d2a18720162ac09c25329a60d75adf36eda3c3",
+            ciphertext_endswith: "",
+        },
+        Vector {
+            idx: 999,
+            priv_ours: "1f9c581b35231838f0f17cf0c979835baccb7f3abbbb96ffcc318ab71e6e126f",
+            ellswift_ours: concat!(
+                "a1855e10e94e00baa23041d916e259f7044e491da6171269694763f018c7e636",
+                "93d29575dcb464ac816baa1be353ba12e3876cba7628bd0bd8e755e721eb0140"
+            ),
+            ellswift_theirs: concat!(
+                "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+                "0000000000000000000000000000000000000000000000000000000000000000"
+            ),
+            initiating: false,
+            contents: "3eb1d4e98035cfd8eeb29bac969ed3824a",
+            ignore: false,
+            shared_secret:
+                "a0138f564f74d0ad70bc337dacc9d0bf1d2349364caf1188a1e6e8ddb3b7b184",
+            ciphertext: concat!(
+                "1da1bcf589f9b61872f45b7fa5371dd3f8bdf5d515b0c5f9fe9f0044afb8dc0a",
+                "a1cd39a8c4"
+            ),
+            ciphertext_endswith: "",
+        },
+        Vector {
+            idx: 223,
+            priv_ours: "6c77432d1fda31e9f942f8af44607e10f3ad38a65f8a4bddae823e5eff90dc38",
+            ellswift_ours: concat!(
+                "d2685070c1e6376e633e825296634fd461fa9e5bdf2109bcebd735e5a91f3e58",
+                "7c5cb782abb797fbf6bb5074fd1542a474f2a45b673763ec2db7fb99b737bbb9"
+            ),
+            ellswift_theirs: concat!(
+                "56bd0c06f10352c3a1a9f4b4c92f6fa2b26df124b57878353c1fc691c51abea7",
+                "7c8817daeeb9fa546b77c8daf79d89b22b0e1b87574ece42371f00237aa9d83a"
+            ),
+            initiating: false,
+            contents: concat!(
+                "7e0e78eb6990b059e6cf0ded66ea93ef82e72aa2f18ac24f2fc6ebab561ae557",
+                "420729da103f64cecfa20527e15f9fb669a49bbbf274ef0389b3e43c8c44e5f6",
+                "0bf2ac38e2b55e7ec4273dba15ba41d21f8f5b3ee1688b3c29951218caf847a9",
+                "7fb50d75a86515d445699497d968164bf740012679b8962de573be941c62b7ef"
+            ),
+            ignore: true,
+            shared_secret:
+                "1918b741ef5f9d1d7670b050c152b4a4ead2c31be9aecb0681c0cd4324150853",
+            ciphertext: "",
+            ciphertext_endswith: concat!(
+                "729847a3e9eba7a5bff454b5de3b393431ee360736b6c030d7a5bd01d1203d2e",
+                "98f528543fd2bf886ccaa1ada5e215a730a36b3f4abfc4e252c89eb01d9512f9",
+                "4916dae8a76bf16e4da28986ffe159090fe5267ee3394300b7ccf4dfad389a26",
+                "321b3a3423e4594a82ccfbad16d6561ecb8772b0cb040280ff999a29e3d9d4fd"
+            ),
+        },
+    ];
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_vectors() {
+        for vector in VECTORS {
+            let secret = shared_secret(
+                SecretKey::from_slice(&unhex(vector.priv_ours)).unwrap(),
+                ElligatorSwift::from_array(
+                    unhex(vector.ellswift_ours).try_into().unwrap(),
+                ),
+                ElligatorSwift::from_array(
+                    unhex(vector.ellswift_theirs).try_into().unwrap(),
+                ),
+                vector.initiating,
+            );
+            assert_eq!(secret.to_vec(), unhex(vector.shared_secret));
+
+            let mut transport = V2Stream::derive(
+                futures::io::Cursor::new(Vec::new()),
+                Network::Main as u32,
+                secret,
+                vector.initiating,
+            );
+            // Packets before the one checked only move the keys forward,
+            // replacing them every REKEY_INTERVAL packets
+            for _ in 0..vector.idx {
+                transport.encrypt_packet(&[], 0, &[]);
+            }
+            let header = if vector.ignore { IGNORE_BIT } else { 0 };
+            let packet = transport.encrypt_packet(&[], header, &unhex(vector.contents));
+            if vector.ciphertext.is_empty() {
+                assert!(packet.ends_with(&unhex(vector.ciphertext_endswith)));
+            } else {
+                assert_eq!(packet, unhex(vector.ciphertext));
+            }
+        }
+    }
+
+    #[test]
+    fn key_derivation() {
+        let transport = V2Stream::derive(
+            futures::io::Cursor::new(Vec::new()),
+            Network::Main as u32,
+            unhex(VECTORS[0].shared_secret).try_into().unwrap(),
+            true,
+        );
+        assert_eq!(
+            transport.send_packet.key.to_vec(),
+            unhex("7d0c7820ba6a4d29ce40baf2caa6035e04f1e1cefd59f3e7e59e9e5af84f1f51")
+        );
+        assert_eq!(
+            transport.recv_packet.key.to_vec(),
+            unhex("9f0fc1c0e85fd9a8eee07e6fc41dba2ff54c7729068a239ac97c37c524cca1c0")
+        );
+        assert_eq!(
+            transport.send_terminator.to_vec(),
+            unhex("faef555dfcdb936425d84aba524758f3")
+        );
+        assert_eq!(
+            transport.recv_terminator.to_vec(),
+            unhex("02cb8ff24307a6e27de3b4e7ea3fa65b")
+        );
+        assert_eq!(
+            transport.session_id.to_vec(),
+            unhex("ce72dffb015da62b0d0f5474cab8bc72605225b0cee3f62312ec680ec5f41ba5")
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    str::FromStr,
+};
+
+/// Longest user agent accepted by Bitcoin Core
+pub const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// Characters separating the parts of a user agent
+const SEPARATORS: [char; 5] = ['/', ':', '(', ')', ';'];
+
+#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum UserAgentError {
+    InvalidCharacterError(char),
+    MissingComponentError,
+    TooLongError(usize),
+    MalformedError,
+}
+
+impl Display for UserAgentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserAgentError::InvalidCharacterError(c) => {
+                write!(f, "User agent cannot contain {:?}", c)
+            }
+            UserAgentError::MissingComponentError => {
+                write!(f, "User agent needs a component with a name")
+            }
+            UserAgentError::TooLongError(length) => write!(
+                f,
+                "User agent is {} bytes long, at most {} are allowed",
+                length, MAX_USER_AGENT_LENGTH
+            ),
+            UserAgentError::MalformedError => write!(
+                f,
+                "User agent should consist of /name:version(comments)/ components"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UserAgentError {}
+
+/// Client and version, e.g. `Satoshi` and `25.0.0`, with optional comments.
+#[derive(Clone, Debug, PartialEq)]
+struct Component {
+    name: String,
+    version: String,
+    comments: Vec<String>,
+}
+
+/// User agent as specified by BIP14, i.e. `/name:version(comments)/` with
+/// a component per software layer, e.g. `/MyApp:1.2/handshaker:0.1.0/`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserAgent {
+    components: Vec<Component>,
+}
+
+impl UserAgent {
+    pub fn builder() -> UserAgentBuilder {
+        UserAgentBuilder::default()
+    }
+
+    /// Gets the user agent of this library, e.g. `/handshaker:0.1.0/`, to
+    /// be stacked onto the one of an embedding application
+    pub fn library() -> Self {
+        Self {
+            components: vec![Component {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                comments: Vec::new(),
+            }],
+        }
+    }
+}
+
+impl Display for UserAgent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/")?;
+        for component in &self.components {
+            write!(f, "{}:{}", component.name, component.version)?;
+            if !component.comments.is_empty() {
+                write!(f, "({})", component.comments.join("; "))?;
+            }
+            write!(f, "/")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for UserAgent {
+    type Err = UserAgentError;
+
+    /// Parses the user agent, e.g. `/Satoshi:27.0.0/`, validating it the
+    /// same way as [`UserAgentBuilder::build`]
+    fn from_str(user_agent: &str) -> Result<Self, Self::Err> {
+        let inner = user_agent
+            .strip_prefix('/')
+            .and_then(|inner| inner.strip_suffix('/'))
+            .ok_or(UserAgentError::MalformedError)?;
+
+        let mut builder = UserAgent::builder();
+        for part in inner.split('/') {
+            let (component, comments) = match part.split_once('(') {
+                Some((component, comments)) => (
+                    component,
+                    Some(
+                        comments
+                            .strip_suffix(')')
+                            .ok_or(UserAgentError::MalformedError)?,
+                    ),
+                ),
+                None => (part, None),
+            };
+            let (name, version) = component
+                .split_once(':')
+                .ok_or(UserAgentError::MalformedError)?;
+            builder = builder.component(name, version);
+            for comment in comments.into_iter().flat_map(|c| c.split(';')) {
+                builder = builder.comment(comment.trim_start());
+            }
+        }
+        builder.build()
+    }
+}
+
+impl From<UserAgent> for String {
+    fn from(user_agent: UserAgent) -> Self {
+        user_agent.to_string()
+    }
+}
+
+/// Composes the user agent component by component, validating it once built.
+#[derive(Debug, Default)]
+pub struct UserAgentBuilder {
+    components: Vec<Component>,
+    /// Whether a comment was added before any component
+    orphan_comment: bool,
+}
+
+impl UserAgentBuilder {
+    /// Appends the component of the software with the given name and version
+    pub fn component(
+        mut self,
+        name: impl Into<String>,
+        version: impl Into<String>,
+    ) -> Self {
+        self.components.push(Component {
+            name: name.into(),
+            version: version.into(),
+            comments: Vec::new(),
+        });
+        self
+    }
+
+    /// Adds the comment, e.g. a platform, to the last component
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        match self.components.last_mut() {
+            Some(component) => component.comments.push(comment.into()),
+            None => self.orphan_comment = true,
+        }
+        self
+    }
+
+    /// Appends all components of the user agent, e.g. stacks the one of
+    /// this library onto the one of the embedding application
+    pub fn stack(mut self, user_agent: &UserAgent) -> Self {
+        self.components
+            .extend(user_agent.components.iter().cloned());
+        self
+    }
+
+    /// Validates the components, whose parts must be printable ASCII without
+    /// the separators, and the length of the user agent.
+    pub fn build(self) -> Result<UserAgent, UserAgentError> {
+        if self.orphan_comment
+            || self.components.is_empty()
+            || self.components.iter().any(|c| c.name.is_empty())
+        {
+            return Err(UserAgentError::MissingComponentError);
+        }
+
+        let parts = self
+            .components
+            .iter()
+            .flat_map(|c| [&c.name, &c.version].into_iter().chain(c.comments.iter()));
+        for part in parts {
+            if let Some(c) = part
+                .chars()
+                .find(|c| !(' '..='~').contains(c) || SEPARATORS.contains(c))
+            {
+                return Err(UserAgentError::InvalidCharacterError(c));
+            }
+        }
+
+        let user_agent = UserAgent {
+            components: self.components,
+        };
+        let length = user_agent.to_string().len();
+        if length > MAX_USER_AGENT_LENGTH {
+            return Err(UserAgentError::TooLongError(length));
+        }
+        Ok(user_agent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build() {
+        let user_agent = UserAgent::builder()
+            .component("BitcoinJ", "0.2")
+            .comment("iPad")
+            .comment("U")
+            .component("AndroidBuild", "0.8")
+            .build()
+            .unwrap();
+        assert_eq!(
+            user_agent.to_string(),
+            "/BitcoinJ:0.2(iPad; U)/AndroidBuild:0.8/"
+        );
+
+        let stacked = UserAgent::builder()
+            .component("MyApp", "1.2")
+            .stack(&UserAgent::library())
+            .build()
+            .unwrap();
+        assert_eq!(
+            stacked.to_string(),
+            format!("/MyApp:1.2/handshaker:{}/", env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(
+            UserAgent::builder().component("My/App", "1.2").build(),
+            Err(UserAgentError::InvalidCharacterError('/'))
+        );
+        assert_eq!(
+            UserAgent::builder()
+                .component("MyApp", "1.2")
+                .comment("a;b")
+                .build(),
+            Err(UserAgentError::InvalidCharacterError(';'))
+        );
+        assert_eq!(
+            UserAgent::builder().component("MyApp", "1.2\n").build(),
+            Err(UserAgentError::InvalidCharacterError('\n'))
+        );
+        assert_eq!(
+            UserAgent::builder()
+                .comment("x")
+                .component("MyApp", "1.2")
+                .build(),
+            Err(UserAgentError::MissingComponentError)
+        );
+        assert_eq!(
+            UserAgent::builder().build(),
+            Err(UserAgentError::MissingComponentError)
+        );
+        assert_eq!(
+            UserAgent::builder()
+                .component("MyApp", "1".repeat(300))
+                .build(),
+            Err(UserAgentError::TooLongError(308))
+        );
+    }
+
+    #[test]
+    fn parse() {
+        for user_agent in [
+            "/Satoshi:27.0.0/",
+            "/BitcoinJ:0.2(iPad; U)/AndroidBuild:0.8/",
+            "/btcwire:0.5.0/btcd:0.24.0/",
+        ] {
+            assert_eq!(
+                user_agent.parse::<UserAgent>().unwrap().to_string(),
+                user_agent
+            );
+        }
+
+        assert_eq!(
+            "Satoshi:27.0.0".parse::<UserAgent>(),
+            Err(UserAgentError::MalformedError)
+        );
+        assert_eq!(
+            "/Satoshi/".parse::<UserAgent>(),
+            Err(UserAgentError::MalformedError)
+        );
+        assert_eq!(
+            "/Satoshi:27.0.0(x/".parse::<UserAgent>(),
+            Err(UserAgentError::MalformedError)
+        );
+        assert_eq!(
+            "/:1.0/".parse::<UserAgent>(),
+            Err(UserAgentError::MissingComponentError)
+        );
+        assert_eq!(
+            "/My App:1.0\n/".parse::<UserAgent>(),
+            Err(UserAgentError::InvalidCharacterError('\n'))
+        );
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    collections::HashMap,
+    fmt::{
+        Display,
+        Formatter,
+    },
+    fs,
+    io,
+    net::SocketAddrV4,
+    path::PathBuf,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use log::warn;
+use rand::seq::SliceRandom;
+
+use handshaker::p2p::messages::Network;
+
+use crate::{
+    cli::Order,
+    summary::{
+        HandshakeSummary,
+        Outcome,
+    },
+};
+
+/// Period after which the history of a node counts half as much
+const HALF_LIFE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Weight of the latest latency in the moving average
+const LATENCY_WEIGHT: f64 = 0.3;
+
+/// History of handshakes with a single node.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PeerRecord {
+    /// Number of successful handshakes
+    pub successes: u32,
+    /// Number of failed or timed out handshakes
+    pub failures: u32,
+    /// Moving average of successful handshake latencies in milliseconds
+    pub latency: Option<f64>,
+    /// UNIX timestamp of the last handshake
+    pub last_seen: u64,
+    /// UNIX timestamp of the last handshake which completed, even if slowly
+    pub last_reached: Option<u64>,
+    /// Network the node was last reached on
+    pub network: Option<Network>,
+}
+
+impl PeerRecord {
+    /// Gets the expected handshake success rate, decaying towards
+    /// the one of an unknown node as the history gets older.
+    pub fn score(&self, now: u64) -> f64 {
+        // Unknown nodes are expected to succeed half of the time
+        let attempts = f64::from(self.successes + self.failures);
+        let success_rate = (f64::from(self.successes) + 1.0) / (attempts + 2.0);
+
+        let age = now.saturating_sub(self.last_seen) as f64;
+        let freshness = 0.5_f64.powf(age / HALF_LIFE.as_secs_f64());
+        0.5 + (success_rate - 0.5) * freshness
+    }
+}
+
+/// Change of the set of reachable nodes of a network between two crawls.
+#[derive(Debug, Default, PartialEq)]
+pub struct Churn {
+    /// Network the nodes were reached on, if known
+    pub network: Option<Network>,
+    /// Nodes reachable now, but not in the previous crawl
+    pub appeared: Vec<SocketAddrV4>,
+    /// Nodes reachable in the previous crawl, but not now
+    pub disappeared: Vec<SocketAddrV4>,
+    /// Number of nodes reachable in both crawls
+    pub retained: usize,
+}
+
+impl Churn {
+    /// Gets the share of nodes reachable in either crawl which appeared
+    /// or disappeared
+    pub fn turnover(&self) -> f64 {
+        let changed = self.appeared.len() + self.disappeared.len();
+        match changed + self.retained {
+            0 => 0.0,
+            total => changed as f64 / total as f64,
+        }
+    }
+}
+
+impl Display for Churn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.network {
+            Some(network) => write!(f, "{:?}", network)?,
+            None => write!(f, "Unknown")?,
+        }
+        write!(
+            f,
+            ": {} appeared, {} disappeared, {} retained, {:.1}% turnover",
+            self.appeared.len(),
+            self.disappeared.len(),
+            self.retained,
+            self.turnover() * 100.0
+        )
+    }
+}
+
+/// Handshake history of all nodes, stored as a text file with a line
+/// per node in form of `address successes failures latency last_seen
+/// last_reached network`, where the last two fields are optional.
+pub struct PeerDb {
+    /// File the history is stored in
+    path: PathBuf,
+    /// History per node
+    peers: HashMap<SocketAddrV4, PeerRecord>,
+    /// UNIX timestamp of the crawl the history was last updated by
+    previous_crawl: Option<u64>,
+}
+
+impl PeerDb {
+    /// Loads the history from the given file, starting from the empty one
+    /// if the file does not exist yet.
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let content = match fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut peers = HashMap::new();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            match parse_line(line) {
+                Some((address, record)) => {
+                    peers.insert(address, record);
+                }
+                None => warn!("Ignoring invalid peer database entry: {}", line),
+            }
+        }
+
+        let previous_crawl = peers.values().map(|r| r.last_seen).max();
+        Ok(Self {
+            path,
+            peers,
+            previous_crawl,
+        })
+    }
+
+    pub fn get(&self, address: &SocketAddrV4) -> Option<&PeerRecord> {
+        self.peers.get(address)
+    }
+
+    /// Updates the history of the node with the handshake result
+    pub fn record(&mut self, result: &HandshakeSummary, now: u64) {
+        let record = self.peers.entry(result.address).or_default();
+        match result.outcome {
+            Outcome::Success(_) => {
+                let latency = result.latency.as_secs_f64() * 1000.0;
+                record.successes += 1;
+                record.latency = Some(match record.latency {
+                    Some(average) => average + LATENCY_WEIGHT * (latency - average),
+                    None => latency,
+                });
+            }
+            Outcome::Slow(_)
+            | Outcome::Failure(_)
+            | Outcome::Timeout
+            | Outcome::Stalled => record.failures += 1,
+        }
+        if let Outcome::Success(report) | Outcome::Slow(report) = &result.outcome {
+            record.last_reached = Some(now);
+            record.network = report.network;
+        }
+        record.last_seen = now;
+    }
+
+    /// Compares the nodes reached by the crawl with the ones reached by the
+    /// previous crawl, per network. Nodes the crawl did not attempt to reach
+    /// are not considered disappeared. Empty if there was no previous crawl.
+    pub fn churn(&self, results: &[HandshakeSummary]) -> Vec<Churn> {
+        let Some(previous_crawl) = self.previous_crawl else {
+            return Vec::new();
+        };
+
+        let mut churn: Vec<Churn> = Vec::new();
+        for result in results {
+            let previous = self
+                .get(&result.address)
+                .filter(|r| r.last_reached == Some(previous_crawl));
+            let current = match &result.outcome {
+                Outcome::Success(report) | Outcome::Slow(report) => Some(report.network),
+                Outcome::Failure(_) | Outcome::Timeout | Outcome::Stalled => None,
+            };
+
+            let network = match (previous, current) {
+                (_, Some(network)) => network,
+                (Some(record), None) => record.network,
+                (None, None) => continue,
+            };
+            let entry = match churn.iter().position(|c| c.network == network) {
+                Some(i) => &mut churn[i],
+                None => {
+                    churn.push(Churn {
+                        network,
+                        ..Default::default()
+                    });
+                    churn.last_mut().unwrap()
+                }
+            };
+            match (previous, current) {
+                (Some(_), Some(_)) => entry.retained += 1,
+                (None, Some(_)) => entry.appeared.push(result.address),
+                _ => entry.disappeared.push(result.address),
+            }
+        }
+        churn
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut addresses: Vec<&SocketAddrV4> = self.peers.keys().collect();
+        addresses.sort();
+
+        let mut content = String::new();
+        for address in addresses {
+            let record = &self.peers[address];
+            content.push_str(&format!(
+                "{} {} {} {} {} {} {}\n",
+                address,
+                record.successes,
+                record.failures,
+                record
+                    .latency
+                    .map_or("-".to_string(), |l| format!("{:.1}", l)),
+                record.last_seen,
+                record
+                    .last_reached
+                    .map_or("-".to_string(), |t| t.to_string()),
+                record
+                    .network
+                    .and_then(|n| n.to_possible_value())
+                    .map_or("-".to_string(), |v| v.get_name().to_string())
+            ));
+        }
+        fs::write(&self.path, content)
+    }
+}
+
+/// Orders the addresses according to the given strategy, where nodes
+/// missing from the history are treated as unknown.
+pub fn order(
+    addresses: &mut [SocketAddrV4],
+    order: Order,
+    db: Option<&PeerDb>,
+    now: u64,
+) {
+    match order {
+        Order::Input => {}
+        Order::Random => addresses.shuffle(&mut rand::thread_rng()),
+        Order::Fastest => {
+            let key = |address: &SocketAddrV4| {
+                let record = db
+                    .and_then(|db| db.get(address))
+                    .cloned()
+                    .unwrap_or_default();
+                (record.score(now), record.latency.unwrap_or(f64::INFINITY))
+            };
+            // Highest score first, lowest latency breaking ties
+            addresses.sort_by(|a, b| {
+                let (a, b) = (key(a), key(b));
+                b.0.total_cmp(&a.0).then(a.1.total_cmp(&b.1))
+            });
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<(SocketAddrV4, PeerRecord)> {
+    let mut fields = line.split_whitespace();
+    let address = fields.next()?.parse().ok()?;
+    let record = PeerRecord {
+        successes: fields.next()?.parse().ok()?,
+        failures: fields.next()?.parse().ok()?,
+        latency: match fields.next()? {
+            "-" => None,
+            latency => Some(latency.parse().ok()?),
+        },
+        last_seen: fields.next()?.parse().ok()?,
+        ..Default::default()
+    };
+
+    // Entries written before crawls were compared lack the last two fields
+    let record = match fields.next() {
+        None => record,
+        Some(last_reached) => PeerRecord {
+            last_reached: match last_reached {
+                "-" => None,
+                last_reached => Some(last_reached.parse().ok()?),
+            },
+            network: match fields.next()? {
+                "-" => None,
+                network => Some(Network::from_str(network, true).ok()?),
+            },
+            ..record
+        },
+    };
+
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((address, record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use handshaker::p2p::{
+        ConnectionError,
+        HandshakeReport,
+    };
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn address(last: u8) -> SocketAddrV4 {
+        SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, last), 8333)
+    }
+
+    fn summary(last: u8, outcome: Outcome, latency: u64) -> HandshakeSummary {
+        HandshakeSummary {
+            address: address(last),
+            outcome,
+            latency: Duration::from_millis(latency),
+            attempts: 1,
+            score: 0.0,
+        }
+    }
+
+    #[test]
+    fn score() {
+        let unknown = PeerRecord::default();
+        assert_eq!(unknown.score(NOW), 0.5);
+
+        let reliable = PeerRecord {
+            successes: 8,
+            failures: 0,
+            latency: Some(100.0),
+            last_seen: NOW,
+            ..Default::default()
+        };
+        assert!((reliable.score(NOW) - 0.9).abs() < 1e-9);
+
+        // History fades away towards the score of an unknown node
+        let day_old = PeerRecord {
+            last_seen: NOW - HALF_LIFE.as_secs(),
+            ..reliable
+        };
+        assert!((day_old.score(NOW) - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn record_and_order() {
+        let mut db = PeerDb {
+            path: PathBuf::new(),
+            peers: HashMap::new(),
+            previous_crawl: None,
+        };
+
+        db.record(
+            &summary(
+                1,
+                Outcome::Failure(ConnectionError::ConnectionRefusedError),
+                10,
+            ),
+            NOW,
+        );
+        db.record(&summary(2, Outcome::Success(Default::default()), 300), NOW);
+        db.record(&summary(3, Outcome::Success(Default::default()), 100), NOW);
+        db.record(&summary(3, Outcome::Success(Default::default()), 200), NOW);
+
+        let record = db.get(&address(3)).unwrap();
+        assert_eq!(record.successes, 2);
+        assert_eq!(record.latency, Some(130.0));
+        assert_eq!(db.get(&address(1)).unwrap().failures, 1);
+
+        let mut addresses = [address(1), address(4), address(2), address(3)];
+        order(&mut addresses, Order::Input, Some(&db), NOW);
+        assert_eq!(addresses, [address(1), address(4), address(2), address(3)]);
+
+        order(&mut addresses, Order::Fastest, None, NOW);
+        assert_eq!(addresses, [address(1), address(4), address(2), address(3)]);
+
+        order(&mut addresses, Order::Fastest, Some(&db), NOW);
+        assert_eq!(addresses, [address(3), address(2), address(4), address(1)]);
+    }
+
+    #[test]
+    fn entries() {
+        assert_eq!(
+            parse_line("10.0.0.1:8333 3 1 120.5 1700000000"),
+            Some((
+                address(1),
+                PeerRecord {
+                    successes: 3,
+                    failures: 1,
+                    latency: Some(120.5),
+                    last_seen: NOW,
+                    ..Default::default()
+                }
+            ))
+        );
+        let (_, record) =
+            parse_line("10.0.0.1:8333 3 1 120.5 1700000000 1700000000 testnet").unwrap();
+        assert_eq!(record.last_reached, Some(NOW));
+        assert_eq!(record.network, Some(Network::Testnet));
+        assert_eq!(
+            parse_line("10.0.0.1:8333 3 1 - 1700000000 - -")
+                .unwrap()
+                .1
+                .network,
+            None
+        );
+        assert_eq!(parse_line("10.0.0.1:8333 3 1 - 1700000000 - bitcoin"), None);
+        assert_eq!(
+            parse_line("10.0.0.1:8333 0 2 - 1700000000")
+                .unwrap()
+                .1
+                .latency,
+            None
+        );
+        assert_eq!(parse_line("10.0.0.1:8333 0 2 -"), None);
+        assert_eq!(parse_line("10.0.0.1:8333 0 2 - 1700000000 x"), None);
+        assert_eq!(parse_line("10.0.0.1 0 2 - 1700000000"), None);
+    }
+
+    #[test]
+    fn churn() {
+        let reached = |network| {
+            Outcome::Success(HandshakeReport {
+                network: Some(network),
+                ..Default::default()
+            })
+        };
+        let mut db = PeerDb {
+            path: PathBuf::new(),
+            peers: HashMap::new(),
+            previous_crawl: None,
+        };
+        let first = [
+            summary(1, reached(Network::Main), 100),
+            summary(2, reached(Network::Main), 100),
+            summary(3, reached(Network::Testnet), 100),
+            summary(
+                4,
+                Outcome::Failure(ConnectionError::ConnectionRefusedError),
+                100,
+            ),
+        ];
+        assert!(db.churn(&first).is_empty());
+        for result in &first {
+            db.record(result, NOW);
+        }
+        db.previous_crawl = Some(NOW);
+
+        let second = [
+            summary(1, reached(Network::Main), 100),
+            summary(2, Outcome::Timeout, 100),
+            summary(4, reached(Network::Main), 100),
+            summary(
+                5,
+                Outcome::Failure(ConnectionError::ConnectionRefusedError),
+                100,
+            ),
+        ];
+        let churn = db.churn(&second);
+        assert_eq!(
+            churn,
+            [Churn {
+                network: Some(Network::Main),
+                appeared: vec![address(4)],
+                disappeared: vec![address(2)],
+                retained: 1,
+            }]
+        );
+        assert!((churn[0].turnover() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            churn[0].to_string(),
+            "Main: 1 appeared, 1 disappeared, 1 retained, 66.7% turnover"
+        );
+    }
+}
@@ -0,0 +1,24 @@
+//! Commonly used types, meant to be glob imported:
+//!
+//! ```
+//! use handshaker::prelude::*;
+//! ```
+
+pub use crate::p2p::{
+    messages::{
+        CodecError,
+        Message,
+        Network,
+        ProtocolVersion,
+        Service,
+        Services,
+    },
+    Connection,
+    ConnectionError,
+    HandshakeError,
+    HandshakeReport,
+    HandshakeResult,
+    Node,
+    NodeConfig,
+    UserAgent,
+};
@@ -0,0 +1,211 @@
+use std::{
+    fmt::Write,
+    net::SocketAddrV4,
+    sync::Arc,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use log::{
+    error,
+    info,
+    warn,
+};
+use tokio::time::timeout;
+
+use handshaker::{
+    p2p::{
+        messages::{
+            dissect,
+            NetworkParams,
+        },
+        Pretty,
+        RetryPolicy,
+    },
+    prelude::*,
+};
+
+use crate::{
+    cli::{
+        Format,
+        NetworkChoice,
+        Verbosity,
+    },
+    summary::{
+        self,
+        HandshakeSummary,
+        Outcome,
+    },
+};
+
+/// Settings of the handshakes with the nodes given on the command line,
+/// shared by all of them running concurrently.
+#[derive(Clone, Debug)]
+pub struct Probe {
+    pub network: NetworkChoice,
+    /// Network given by its parameters instead, e.g. from a chain file
+    pub chain: Option<Arc<NetworkParams>>,
+    pub format: Format,
+    /// Maximum time of the whole handshake
+    pub time_limit: Duration,
+    /// Latency above which successful handshakes are reported as slow
+    pub max_latency: Option<Duration>,
+    /// Whether to print the messages exchanged as JSON
+    pub trace_timeline: bool,
+    /// Whether to print the field by field breakdown of the messages
+    pub dissect: bool,
+    /// How transient failures are retried
+    pub retry: RetryPolicy,
+    /// How much is printed about each handshake
+    pub verbosity: Verbosity,
+}
+
+impl Probe {
+    /// Performs the handshake with the node, printing its outcome as soon
+    /// as it is known. Output of a single node is printed at once, so that
+    /// it does not interleave with the one of nodes handshaked concurrently.
+    pub async fn run(self, node: &Node, address: SocketAddrV4) -> HandshakeSummary {
+        info!("Performing a handshake with {}", address);
+
+        let mut start = Instant::now();
+        let (result, failed) = self
+            .retry
+            .run(
+                || {
+                    start = Instant::now();
+                    self.attempt(node, address)
+                },
+                Outcome::is_transient,
+            )
+            .await;
+        let elapsed = start.elapsed();
+        let attempts = failed.len() as u32 + 1;
+        let (outcome, latency) = match result {
+            Ok(report) => {
+                // Observing the node or waiting for its addresses afterwards
+                // does not make the handshake any slower
+                let latency = report.handshake_time().unwrap_or(elapsed);
+                (self.succeeded(address, report, latency, attempts), latency)
+            }
+            Err(outcome) => {
+                if self.trace_timeline {
+                    println!(
+                        "{}",
+                        summary::timeline_json(address, &[], outcome.error_code())
+                    );
+                }
+                (outcome, elapsed)
+            }
+        };
+
+        HandshakeSummary {
+            address,
+            outcome,
+            latency,
+            attempts,
+            score: 0.0,
+        }
+    }
+
+    /// Performs a single attempt of the handshake within the time limit
+    async fn attempt(
+        &self,
+        node: &Node,
+        address: SocketAddrV4,
+    ) -> Result<HandshakeReport, Outcome> {
+        let handshake = async {
+            match (&self.chain, self.network) {
+                (Some(params), _) => node.handshake(params.as_ref(), address).await,
+                (None, NetworkChoice::Auto) => node.detect_handshake(address).await,
+                (None, NetworkChoice::Fixed(network)) => {
+                    node.handshake(network, address).await
+                }
+            }
+        };
+        match timeout(self.time_limit, handshake).await {
+            Ok(Ok(report)) => Ok(report),
+            Ok(Err(e)) => {
+                error!("Error occurred during handshake with {}: {}", address, e);
+                match e {
+                    ConnectionError::Stalled => Err(Outcome::Stalled),
+                    e => Err(Outcome::Failure(e)),
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Timeout of {} ms exceeded with {}: {}",
+                    self.time_limit.as_millis(),
+                    address,
+                    e
+                );
+                Err(Outcome::Timeout)
+            }
+        }
+    }
+
+    fn succeeded(
+        self,
+        address: SocketAddrV4,
+        report: HandshakeReport,
+        latency: Duration,
+        attempts: u32,
+    ) -> Outcome {
+        let mut output = String::new();
+        match self.format {
+            Format::Text | Format::Table if attempts > 1 => info!(
+                "Handshake successfully performed after {} attempts, node at {}: {}",
+                attempts, address, report
+            ),
+            Format::Text | Format::Table => info!(
+                "Handshake successfully performed, node at {}: {}",
+                address, report
+            ),
+            Format::Pretty => {
+                let _ = write!(output, "Node at {}\n{}", address, Pretty(&report));
+            }
+        }
+        if self.verbosity >= Verbosity::Verbose {
+            output.push_str(&summary::render_messages(address, &report.timeline));
+        }
+        if self.trace_timeline {
+            let _ = writeln!(
+                output,
+                "{}",
+                summary::timeline_json(address, &report.timeline, None)
+            );
+        }
+        if self.dissect {
+            for (i, event) in report.timeline.iter().enumerate() {
+                let _ = writeln!(
+                    output,
+                    "Frame {}: {} {} ({} bytes) at {:.3} ms",
+                    i + 1,
+                    event.direction,
+                    event.command,
+                    event.length,
+                    event.elapsed.as_secs_f64() * 1000.0
+                );
+                if let Some(frame) = &event.frame {
+                    let _ = write!(output, "{}", dissect(frame));
+                }
+            }
+        }
+        print!("{}", output);
+
+        let slow = self.max_latency.is_some_and(|max| {
+            latency > max || report.ping_rtt().is_some_and(|rtt| rtt > max)
+        });
+        if slow {
+            warn!(
+                "Node at {} is slower than the maximum latency of {} ms",
+                address,
+                self.max_latency.unwrap_or_default().as_millis()
+            );
+            Outcome::Slow(report)
+        } else {
+            Outcome::Success(report)
+        }
+    }
+}
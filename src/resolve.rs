@@ -0,0 +1,197 @@
+use std::{
+    future::Future,
+    io,
+    net::{
+        SocketAddr,
+        SocketAddrV4,
+        SocketAddrV6,
+    },
+};
+
+use futures::future::try_join_all;
+use log::{
+    info,
+    warn,
+};
+use tokio::net::lookup_host;
+
+use crate::cli::{
+    ResolveStrategy,
+    Target,
+};
+
+/// Reason names resolving to IPv6 addresses only fail
+const NO_IPV4_ADDRESS: &str = "no IPv4 address, handshakes are performed over IPv4 only";
+
+/// Resolves the host names among the targets concurrently, keeping the
+/// order of the targets. Handshakes are performed over IPv4 only, so IPv6
+/// addresses the names resolve to are reported and skipped, while names
+/// without any IPv4 address fail the resolution.
+pub async fn resolve(
+    targets: Vec<Target>,
+    strategy: ResolveStrategy,
+) -> Result<Vec<SocketAddrV4>, (Target, io::Error)> {
+    resolve_with(targets, strategy, |host, port| async move {
+        Ok(lookup_host((host.as_str(), port)).await?.collect())
+    })
+    .await
+}
+
+/// Resolves the host names among the targets the same way as [`resolve`],
+/// looking them up with the given function
+async fn resolve_with<F, Fut>(
+    targets: Vec<Target>,
+    strategy: ResolveStrategy,
+    lookup: F,
+) -> Result<Vec<SocketAddrV4>, (Target, io::Error)>
+where
+    F: Fn(String, u16) -> Fut,
+    Fut: Future<Output = io::Result<Vec<SocketAddr>>>,
+{
+    let lookup = &lookup;
+    let lookups = targets.into_iter().map(|target| async move {
+        match &target {
+            Target::Address(address) => Ok(vec![*address]),
+            // Onion services cannot be resolved, only dialed through the proxy
+            Target::Onion(..) => Ok(Vec::new()),
+            Target::Host(host, port) => {
+                let resolved = lookup(host.clone(), *port)
+                    .await
+                    .map_err(|e| (target.clone(), e))?;
+                let (addresses, skipped) = select(resolved, strategy);
+                if addresses.is_empty() {
+                    let e = io::Error::new(io::ErrorKind::NotFound, NO_IPV4_ADDRESS);
+                    return Err((target.clone(), e));
+                }
+                for address in skipped {
+                    warn!(
+                        "Skipping IPv6 address {} of {}, handshakes are performed \
+                        over IPv4 only",
+                        address, target
+                    );
+                }
+                info!("Resolved {} to {} nodes", target, addresses.len());
+                Ok(addresses)
+            }
+        }
+    });
+
+    Ok(try_join_all(lookups).await?.into_iter().flatten().collect())
+}
+
+/// Picks the IPv4 addresses to handshake with out of the resolved ones,
+/// returning the IPv6 ones skipped too
+fn select(
+    resolved: impl IntoIterator<Item = SocketAddr>,
+    strategy: ResolveStrategy,
+) -> (Vec<SocketAddrV4>, Vec<SocketAddrV6>) {
+    let mut addresses = Vec::new();
+    let mut skipped = Vec::new();
+    for address in resolved {
+        match address {
+            SocketAddr::V4(address) if !addresses.contains(&address) => {
+                addresses.push(address);
+                if strategy == ResolveStrategy::First {
+                    break;
+                }
+            }
+            SocketAddr::V4(_) => {}
+            SocketAddr::V6(address) => skipped.push(address),
+        }
+    }
+    (addresses, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{
+        Ipv4Addr,
+        Ipv6Addr,
+    };
+
+    #[test]
+    fn selection() {
+        let resolved: Vec<SocketAddr> = vec![
+            "[::1]:8333".parse().unwrap(),
+            "10.0.0.1:8333".parse().unwrap(),
+            "10.0.0.2:8333".parse().unwrap(),
+            "10.0.0.1:8333".parse().unwrap(),
+        ];
+        let ipv6 = "[::1]:8333".parse().unwrap();
+        assert_eq!(
+            select(resolved.clone(), ResolveStrategy::All),
+            (
+                vec![
+                    SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333),
+                    SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8333)
+                ],
+                vec![ipv6]
+            )
+        );
+        assert_eq!(
+            select(resolved, ResolveStrategy::First),
+            (
+                vec![SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333)],
+                vec![ipv6]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn resolution() {
+        // Names resolved by a stub instead of the system resolver
+        let lookup = |host: String, port| async move {
+            match host.as_str() {
+                "node.test" => Ok(vec![
+                    SocketAddr::from((Ipv4Addr::new(10, 0, 0, 2), port)),
+                    SocketAddr::from((Ipv6Addr::LOCALHOST, port)),
+                    SocketAddr::from((Ipv4Addr::new(10, 0, 0, 3), port)),
+                ]),
+                "ipv6.test" => Ok(vec![SocketAddr::from((Ipv6Addr::LOCALHOST, port))]),
+                _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+            }
+        };
+
+        let address = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333);
+        let targets = vec![
+            Target::Host("node.test".to_string(), 18333),
+            Target::Address(address),
+        ];
+        assert_eq!(
+            resolve_with(targets.clone(), ResolveStrategy::First, lookup)
+                .await
+                .unwrap(),
+            [SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 18333), address]
+        );
+        assert_eq!(
+            resolve_with(targets, ResolveStrategy::All, lookup)
+                .await
+                .unwrap(),
+            [
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 18333),
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 3), 18333),
+                address
+            ]
+        );
+
+        let targets = vec![Target::Host("nonexistent.test".to_string(), 8333)];
+        let (target, _) = resolve_with(targets, ResolveStrategy::All, lookup)
+            .await
+            .unwrap_err();
+        assert_eq!(target, Target::Host("nonexistent.test".to_string(), 8333));
+
+        // Names without any IPv4 address cannot be handshaked with
+        let targets = vec![
+            Target::Address(address),
+            Target::Host("ipv6.test".to_string(), 8333),
+        ];
+        let (target, e) = resolve_with(targets, ResolveStrategy::All, lookup)
+            .await
+            .unwrap_err();
+        assert_eq!(target, Target::Host("ipv6.test".to_string(), 8333));
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert_eq!(e.to_string(), NO_IPV4_ADDRESS);
+    }
+}
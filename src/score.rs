@@ -0,0 +1,291 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    str::FromStr,
+};
+
+use handshaker::prelude::*;
+
+use crate::summary::{
+    HandshakeSummary,
+    Outcome,
+};
+
+/// Most recent protocol version, scoring the highest
+const LATEST_VERSION: ProtocolVersion = ProtocolVersion::WTXID;
+
+/// Oldest protocol version still scoring above zero
+const OLDEST_VERSION: ProtocolVersion = ProtocolVersion::BIP37;
+
+/// Number of blocks behind the best height after which a node scores zero
+const STALE_BLOCKS: i32 = 144;
+
+/// Latency in milliseconds scoring half of the fastest possible one
+const HALF_SCORE_LATENCY: f64 = 500.0;
+
+/// Services a fully featured node is expected to provide
+const EXPECTED_SERVICES: [Service; 4] = [
+    Service::Network,
+    Service::Witness,
+    Service::Bloom,
+    Service::CompactFilters,
+];
+
+#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum WeightsError {
+    InvalidFormatError,
+    UnknownFactorError,
+    InvalidWeightError,
+}
+
+impl Display for WeightsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WeightsError::InvalidFormatError => {
+                write!(f, "Weights should be in form of factor=weight,...")
+            }
+            WeightsError::UnknownFactorError => write!(
+                f,
+                "Factor should be one of latency, version, services, height or violations"
+            ),
+            WeightsError::InvalidWeightError => {
+                write!(f, "Weight should be a non-negative number")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WeightsError {}
+
+/// Weights of the factors the peer quality score consists of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    /// Weight of the handshake latency
+    pub latency: f64,
+    /// Weight of the protocol version recency
+    pub version: f64,
+    /// Weight of the expected services provided
+    pub services: f64,
+    /// Weight of the start height freshness
+    pub height: f64,
+    /// Weight of the protocol violations
+    pub violations: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            latency: 1.0,
+            version: 1.0,
+            services: 1.0,
+            height: 1.0,
+            violations: 1.0,
+        }
+    }
+}
+
+impl FromStr for Weights {
+    type Err = WeightsError;
+
+    /// Parses weights in form of `latency=2,violations=3`, where factors
+    /// not mentioned keep the default weight
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Self::default();
+        for factor in s.split(',') {
+            let (name, weight) = factor
+                .split_once('=')
+                .ok_or(WeightsError::InvalidFormatError)?;
+
+            let weight: f64 = weight
+                .trim()
+                .parse()
+                .map_err(|_| WeightsError::InvalidWeightError)?;
+            if !weight.is_finite() || weight < 0.0 {
+                return Err(WeightsError::InvalidWeightError);
+            }
+
+            match name.trim() {
+                "latency" => weights.latency = weight,
+                "version" => weights.version = weight,
+                "services" => weights.services = weight,
+                "height" => weights.height = weight,
+                "violations" => weights.violations = weight,
+                _ => return Err(WeightsError::UnknownFactorError),
+            }
+        }
+        Ok(weights)
+    }
+}
+
+/// Calculates the peer quality score ranging from 0 to 100, comparing
+/// the start height with the best one of the run. Failed and slow
+/// handshakes score zero.
+pub fn score(result: &HandshakeSummary, weights: &Weights, best_height: i32) -> f64 {
+    let report = match &result.outcome {
+        Outcome::Success(report) => report,
+        Outcome::Slow(_) | Outcome::Failure(_) | Outcome::Timeout | Outcome::Stalled => {
+            return 0.0
+        }
+    };
+    let config = &report.config;
+
+    let latency =
+        1.0 / (1.0 + result.latency.as_secs_f64() * 1000.0 / HALF_SCORE_LATENCY);
+    let range = LATEST_VERSION.0 - OLDEST_VERSION.0;
+    let version = f64::from((config.version.0 - OLDEST_VERSION.0).clamp(0, range))
+        / f64::from(range);
+    let enabled = config.services.enabled();
+    let services = EXPECTED_SERVICES
+        .iter()
+        .filter(|&s| enabled.contains(s))
+        .count() as f64
+        / EXPECTED_SERVICES.len() as f64;
+    let height = 1.0
+        - f64::from(
+            best_height
+                .saturating_sub(config.start_height)
+                .clamp(0, STALE_BLOCKS),
+        ) / f64::from(STALE_BLOCKS);
+    let violations = 1.0 / (1.0 + f64::from(report.violations));
+
+    let total = weights.latency
+        + weights.version
+        + weights.services
+        + weights.height
+        + weights.violations;
+    if total == 0.0 {
+        return 0.0;
+    }
+
+    100.0
+        * (weights.latency * latency
+            + weights.version * version
+            + weights.services * services
+            + weights.height * height
+            + weights.violations * violations)
+        / total
+}
+
+/// Scores all results of the run
+pub fn assign_scores(results: &mut [HandshakeSummary], weights: &Weights) {
+    let best_height = best_height(results);
+    for result in results.iter_mut() {
+        result.score = score(result, weights, best_height);
+    }
+}
+
+/// Gets the highest start height advertised during the run
+pub fn best_height(results: &[HandshakeSummary]) -> i32 {
+    results
+        .iter()
+        .filter_map(|r| match &r.outcome {
+            Outcome::Success(report) | Outcome::Slow(report) => {
+                Some(report.config.start_height)
+            }
+            Outcome::Failure(_) | Outcome::Timeout | Outcome::Stalled => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        net::{
+            Ipv4Addr,
+            SocketAddrV4,
+        },
+        time::Duration,
+    };
+
+    fn result(
+        version: ProtocolVersion,
+        services: &[Service],
+        height: i32,
+        latency: u64,
+    ) -> HandshakeSummary {
+        HandshakeSummary {
+            address: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333),
+            outcome: Outcome::Success(HandshakeReport {
+                config: NodeConfig {
+                    version,
+                    services: Services::new(services),
+                    start_height: height,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+            latency: Duration::from_millis(latency),
+            attempts: 1,
+            score: 0.0,
+        }
+    }
+
+    #[test]
+    fn weights() {
+        assert_eq!("".parse::<Weights>(), Err(WeightsError::InvalidFormatError));
+        assert_eq!(
+            "speed=1".parse::<Weights>(),
+            Err(WeightsError::UnknownFactorError)
+        );
+        assert_eq!(
+            "latency=-1".parse::<Weights>(),
+            Err(WeightsError::InvalidWeightError)
+        );
+        assert_eq!(
+            "latency=2, violations=0.5".parse::<Weights>(),
+            Ok(Weights {
+                latency: 2.0,
+                violations: 0.5,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn factors() {
+        let best = result(LATEST_VERSION, &EXPECTED_SERVICES, 1000, 0);
+        assert_eq!(score(&best, &Default::default(), 1000), 100.0);
+
+        let peer = result(
+            ProtocolVersion(70001),
+            &[Service::Network, Service::Witness],
+            928,
+            500,
+        );
+        let weights = |latency, version, services, height, violations| Weights {
+            latency,
+            version,
+            services,
+            height,
+            violations,
+        };
+        assert_eq!(score(&peer, &weights(1.0, 0.0, 0.0, 0.0, 0.0), 1000), 50.0);
+        assert_eq!(score(&peer, &weights(0.0, 1.0, 0.0, 0.0, 0.0), 1000), 0.0);
+        assert_eq!(score(&peer, &weights(0.0, 0.0, 1.0, 0.0, 0.0), 1000), 50.0);
+        assert_eq!(score(&peer, &weights(0.0, 0.0, 0.0, 1.0, 0.0), 1000), 50.0);
+        assert_eq!(score(&peer, &weights(0.0, 0.0, 0.0, 0.0, 0.0), 1000), 0.0);
+
+        let mut violating = peer;
+        if let Outcome::Success(report) = &mut violating.outcome {
+            report.violations = 1;
+        }
+        assert_eq!(
+            score(&violating, &weights(0.0, 0.0, 0.0, 0.0, 1.0), 1000),
+            50.0
+        );
+    }
+
+    #[test]
+    fn failures() {
+        let mut failed = result(LATEST_VERSION, &EXPECTED_SERVICES, 1000, 0);
+        failed.outcome = Outcome::Timeout;
+        assert_eq!(score(&failed, &Default::default(), 1000), 0.0);
+        assert_eq!(best_height(&[failed]), 0);
+    }
+}
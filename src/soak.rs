@@ -0,0 +1,278 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{
+        Display,
+        Formatter,
+    },
+    net::SocketAddrV4,
+    time::Duration,
+};
+
+use futures::future::join_all;
+use log::{
+    info,
+    warn,
+};
+use tokio::{
+    sync::mpsc,
+    time::{
+        timeout,
+        timeout_at,
+        Instant,
+    },
+};
+
+use handshaker::{
+    p2p::messages::{
+        PingMessage,
+        PongMessage,
+    },
+    prelude::*,
+};
+
+/// Number of points the survival curve is reported at
+const SURVIVAL_POINTS: u32 = 10;
+
+/// Connection held open during the soak test.
+#[derive(Debug, PartialEq)]
+pub struct Session {
+    pub target: SocketAddrV4,
+    /// Time the connection stayed open for
+    pub lifetime: Duration,
+    /// Reason of the disconnection, if the connection did not survive
+    /// the whole test
+    pub cause: Option<ConnectionError>,
+}
+
+/// Connections of the soak test.
+#[derive(Debug, Default)]
+pub struct SoakReport {
+    /// Duration of the test
+    pub duration: Duration,
+    /// Connections established
+    pub sessions: Vec<Session>,
+    /// Number of connections whose handshake failed
+    pub failed: usize,
+}
+
+impl SoakReport {
+    /// Gets the share of the established connections still open after
+    /// the given time, from 0 to 1
+    pub fn survival(&self, after: Duration) -> f64 {
+        if self.sessions.is_empty() {
+            return 0.0;
+        }
+        let alive = self
+            .sessions
+            .iter()
+            .filter(|s| s.cause.is_none() || s.lifetime >= after)
+            .count();
+        alive as f64 / self.sessions.len() as f64
+    }
+
+    /// Counts the disconnections per cause
+    pub fn causes(&self) -> BTreeMap<String, usize> {
+        let mut causes = BTreeMap::new();
+        for cause in self.sessions.iter().filter_map(|s| s.cause.as_ref()) {
+            *causes.entry(cause.to_string()).or_default() += 1;
+        }
+        causes
+    }
+}
+
+impl Display for SoakReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Established: {} of {}",
+            self.sessions.len(),
+            self.sessions.len() + self.failed
+        )?;
+
+        writeln!(f, "Survival:")?;
+        for i in 0..=SURVIVAL_POINTS {
+            let after = self.duration * i / SURVIVAL_POINTS;
+            writeln!(
+                f,
+                "  {:>12}  {:.1}%",
+                humantime::format_duration(Duration::from_secs(after.as_secs()))
+                    .to_string(),
+                self.survival(after) * 100.0
+            )?;
+        }
+
+        writeln!(f, "Disconnect causes:")?;
+        let causes = self.causes();
+        if causes.is_empty() {
+            writeln!(f, "  none")?;
+        }
+        for (cause, count) in causes {
+            writeln!(f, "  {}: {}", cause, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Establishes the given number of connections, spread over the targets,
+/// and holds them open for the duration of the test, sending a Ping
+/// message every interval and expecting the Pong within the time limit
+pub async fn run(
+    node: &Node,
+    targets: &[(Network, SocketAddrV4)],
+    connections: u32,
+    duration: Duration,
+    ping_interval: Duration,
+    time_limit: Duration,
+) -> SoakReport {
+    let end = Instant::now() + duration;
+
+    let attempts = (0..connections as usize).map(|i| {
+        let (network, target) = targets[i % targets.len()];
+        async move {
+            let start = Instant::now();
+            let connection =
+                match timeout(time_limit, node.connect(network, target)).await {
+                    Ok(Ok(connection)) => connection,
+                    Ok(Err(e)) => {
+                        warn!("Connection {} to {} failed: {}", i + 1, target, e);
+                        return None;
+                    }
+                    Err(_) => {
+                        warn!("Connection {} to {} timed out", i + 1, target);
+                        return None;
+                    }
+                };
+
+            let cause = hold(connection, end, ping_interval, time_limit).await;
+            match &cause {
+                Some(e) => info!("Connection {} to {} closed: {}", i + 1, target, e),
+                None => info!("Connection {} to {} survived", i + 1, target),
+            }
+            Some(Session {
+                target,
+                lifetime: start.elapsed(),
+                cause,
+            })
+        }
+    });
+
+    let results = join_all(attempts).await;
+    let failed = results.iter().filter(|r| r.is_none()).count();
+    SoakReport {
+        duration,
+        sessions: results.into_iter().flatten().collect(),
+        failed,
+    }
+}
+
+/// Keeps the connection alive until the end, answering the node's Ping
+/// messages. Returns the reason of the disconnection, if any.
+async fn hold(
+    connection: Connection,
+    end: Instant,
+    ping_interval: Duration,
+    time_limit: Duration,
+) -> Option<ConnectionError> {
+    let (mut recv_half, mut send_half) = connection.into_split();
+
+    // Receiving is not cancellation safe, so it runs in its own task
+    let (sender, mut receiver) = mpsc::channel(16);
+    let reader = tokio::spawn(async move {
+        loop {
+            let message = recv_half.recv().await;
+            let failed = message.is_err();
+            if sender.send(message).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut next_ping = Instant::now() + ping_interval;
+    // Nonce of the Ping message sent and the time its Pong is expected by
+    let mut awaited: Option<(u64, Instant)> = None;
+
+    let cause = loop {
+        let deadline = awaited.map_or(next_ping, |(_, by)| by).min(end);
+        match timeout_at(deadline, receiver.recv()).await {
+            Ok(Some(Ok(Message::Ping(ping)))) => {
+                let pong = Message::Pong(PongMessage::new(ping.nonce()));
+                if let Err(e) = send_half.send(&pong).await {
+                    break Some(e);
+                }
+            }
+            Ok(Some(Ok(Message::Pong(pong)))) => {
+                if awaited.is_some_and(|(nonce, _)| nonce == pong.nonce()) {
+                    awaited = None;
+                    next_ping = Instant::now() + ping_interval;
+                }
+            }
+            Ok(Some(Ok(_))) => {}
+            Ok(Some(Err(e))) => break Some(e),
+            Ok(None) => break Some(ConnectionError::ConnectionHangUp),
+            Err(_) if Instant::now() >= end => break None,
+            Err(_) if awaited.is_some() => {
+                break Some(ConnectionError::MessageTimeoutError)
+            }
+            Err(_) => {
+                let nonce = rand::random();
+                let ping = Message::Ping(PingMessage::new(nonce));
+                if let Err(e) = send_half.send(&ping).await {
+                    break Some(e);
+                }
+                awaited = Some((nonce, Instant::now() + time_limit));
+            }
+        }
+    };
+
+    reader.abort();
+    cause
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    fn session(lifetime: u64, cause: Option<ConnectionError>) -> Session {
+        Session {
+            target: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8333),
+            lifetime: Duration::from_secs(lifetime),
+            cause,
+        }
+    }
+
+    #[test]
+    fn report() {
+        let report = SoakReport {
+            duration: Duration::from_secs(100),
+            sessions: vec![
+                session(100, None),
+                session(100, None),
+                session(25, Some(ConnectionError::ConnectionHangUp)),
+                session(55, Some(ConnectionError::MessageTimeoutError)),
+            ],
+            failed: 1,
+        };
+
+        assert_eq!(report.survival(Duration::ZERO), 1.0);
+        assert_eq!(report.survival(Duration::from_secs(30)), 0.75);
+        assert_eq!(report.survival(Duration::from_secs(100)), 0.5);
+        assert_eq!(
+            report.causes(),
+            BTreeMap::from([
+                ("Connection hang up".to_string(), 1),
+                ("No message received within the timeout".to_string(), 1)
+            ])
+        );
+
+        let text = report.to_string();
+        assert!(text.starts_with("Established: 4 of 5\nSurvival:\n"));
+        assert!(text.contains("           30s  75.0%\n"));
+        assert!(text.ends_with(
+            "Disconnect causes:\n  \
+             Connection hang up: 1\n  \
+             No message received within the timeout: 1\n"
+        ));
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    fs,
+    io,
+    net::Ipv4Addr,
+    path::Path,
+    str::FromStr,
+};
+
+#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum SubnetError {
+    InvalidAddrError,
+    InvalidPrefixError,
+}
+
+impl Display for SubnetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubnetError::InvalidAddrError => {
+                write!(f, "Subnet should start with an IPv4 address")
+            }
+            SubnetError::InvalidPrefixError => {
+                write!(f, "Subnet prefix length should range from 0 to 32")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubnetError {}
+
+/// IPv4 subnet in CIDR notation, e.g. `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Subnet {
+    /// Network address, with bits outside of the prefix cleared
+    pub addr: Ipv4Addr,
+    /// Number of leading bits shared by all addresses of the subnet
+    pub prefix: u8,
+}
+
+impl Subnet {
+    pub fn new(addr: Ipv4Addr, prefix: u8) -> Self {
+        let prefix = prefix.min(32);
+        Self {
+            addr: Ipv4Addr::from(u32::from(addr) & mask(prefix)),
+            prefix,
+        }
+    }
+
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        u32::from(addr) & mask(self.prefix) == u32::from(self.addr)
+    }
+}
+
+fn mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+}
+
+impl FromStr for Subnet {
+    type Err = SubnetError;
+
+    /// Parses a subnet in CIDR notation, where a bare address stands for
+    /// the subnet consisting of that address only
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (
+                addr,
+                prefix
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|&p| p <= 32)
+                    .ok_or(SubnetError::InvalidPrefixError)?,
+            ),
+            None => (s, 32),
+        };
+        let addr = addr.parse().map_err(|_| SubnetError::InvalidAddrError)?;
+        Ok(Self::new(addr, prefix))
+    }
+}
+
+impl Display for Subnet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Loads subnets listed one per line, skipping blank lines and `#` comments
+pub fn load_list(path: &Path) -> io::Result<Vec<Subnet>> {
+    parse_list(&fs::read_to_string(path)?)
+}
+
+fn parse_list(content: &str) -> io::Result<Vec<Subnet>> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                return None;
+            }
+            Some(line.parse().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", i + 1, e),
+                )
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "10.1.2.3/8".parse(),
+            Ok(Subnet {
+                addr: Ipv4Addr::new(10, 0, 0, 0),
+                prefix: 8
+            })
+        );
+        assert_eq!(
+            "10.1.2.3".parse::<Subnet>().unwrap().to_string(),
+            "10.1.2.3/32"
+        );
+        assert_eq!(
+            "10.1.2.3/33".parse::<Subnet>(),
+            Err(SubnetError::InvalidPrefixError)
+        );
+        assert_eq!(
+            "::1/128".parse::<Subnet>(),
+            Err(SubnetError::InvalidPrefixError)
+        );
+        assert_eq!("::1".parse::<Subnet>(), Err(SubnetError::InvalidAddrError));
+    }
+
+    #[test]
+    fn contains() {
+        let subnet: Subnet = "192.168.0.0/16".parse().unwrap();
+        assert!(subnet.contains(Ipv4Addr::new(192, 168, 10, 1)));
+        assert!(!subnet.contains(Ipv4Addr::new(192, 169, 0, 1)));
+
+        let everything: Subnet = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains(Ipv4Addr::new(8, 8, 8, 8)));
+    }
+
+    #[test]
+    fn list() {
+        let subnets =
+            parse_list("# Own infrastructure\n10.0.0.0/8\n\n192.168.1.1  # router\n")
+                .unwrap();
+        assert_eq!(
+            subnets,
+            [
+                Subnet::new(Ipv4Addr::new(10, 0, 0, 0), 8),
+                Subnet::new(Ipv4Addr::new(192, 168, 1, 1), 32)
+            ]
+        );
+
+        let e = parse_list("10.0.0.0/8\n10.0.0.0/40\n").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "line 2: Subnet prefix length should range from 0 to 32"
+        );
+    }
+}
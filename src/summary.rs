@@ -0,0 +1,529 @@
+use std::{
+    fmt::{
+        Display,
+        Formatter,
+    },
+    net::SocketAddrV4,
+    time::Duration,
+};
+
+use handshaker::{
+    p2p::TimelineEvent,
+    prelude::*,
+};
+use serde_json::json;
+
+const HEADERS: [&str; 10] = [
+    "PEER",
+    "STATUS",
+    "VERSION",
+    "USER AGENT",
+    "HEIGHT",
+    "LATENCY",
+    "SENT",
+    "RECEIVED",
+    "GOODPUT",
+    "SCORE",
+];
+
+/// Code of the error of handshakes exceeding the time limit
+pub const TIMEOUT_CODE: &str = "E_TIMEOUT";
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+pub enum Outcome {
+    Success(HandshakeReport),
+    /// Handshake succeeded, but took longer than the maximum latency
+    Slow(HandshakeReport),
+    Failure(ConnectionError),
+    Timeout,
+    /// Node kept trickling data beyond the stall budget, e.g. a tarpit
+    Stalled,
+}
+
+/// Exit code when any handshake failed, timed out or stalled, other than 2
+/// which invalid arguments exit with
+pub const FAILURE_EXIT_CODE: u8 = 3;
+
+/// Exit code when no handshake failed, but any was slow
+pub const SLOW_EXIT_CODE: u8 = 4;
+
+/// Result of the handshake with a single node.
+pub struct HandshakeSummary {
+    /// Address of the node
+    pub address: SocketAddrV4,
+    /// How the handshake ended
+    pub outcome: Outcome,
+    /// Time it took for the last attempt of the handshake to end
+    pub latency: Duration,
+    /// Number of attempts made, more than one if transient failures
+    /// were retried
+    pub attempts: u32,
+    /// Peer quality score ranging from 0 to 100
+    pub score: f64,
+}
+
+impl Outcome {
+    /// Gets the code of the error the handshake failed with, if any
+    pub fn error_code(&self) -> Option<&'static str> {
+        match self {
+            Outcome::Success(_) | Outcome::Slow(_) => None,
+            Outcome::Failure(e) => Some(e.code()),
+            Outcome::Timeout => Some(TIMEOUT_CODE),
+            Outcome::Stalled => Some(ConnectionError::Stalled.code()),
+        }
+    }
+
+    /// Checks whether the handshake is worth attempting again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Outcome::Failure(e) => e.is_transient(),
+            Outcome::Timeout => true,
+            Outcome::Success(_) | Outcome::Slow(_) | Outcome::Stalled => false,
+        }
+    }
+}
+
+impl HandshakeSummary {
+    /// Gets the payload bytes exchanged per second, if the handshake completed
+    pub fn goodput(&self) -> Option<f64> {
+        match &self.outcome {
+            Outcome::Success(report) | Outcome::Slow(report) => Some(
+                report.stats.payload_bytes() as f64
+                    / self.latency.as_secs_f64().max(f64::EPSILON),
+            ),
+            Outcome::Failure(_) | Outcome::Timeout | Outcome::Stalled => None,
+        }
+    }
+
+    fn columns(&self) -> [String; 10] {
+        let (status, version, user_agent, height, sent, received) = match &self.outcome {
+            Outcome::Success(report) | Outcome::Slow(report) => (
+                if matches!(self.outcome, Outcome::Slow(_)) {
+                    "SLOW"
+                } else {
+                    "OK"
+                },
+                report.config.version.to_string(),
+                report.config.user_agent.clone(),
+                report.config.start_height.to_string(),
+                format!("{} B", report.stats.bytes_sent()),
+                format!("{} B", report.stats.bytes_received()),
+            ),
+            Outcome::Failure(_) => (
+                "FAILED",
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+            ),
+            Outcome::Timeout => (
+                "TIMEOUT",
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+            ),
+            Outcome::Stalled => (
+                "STALLED",
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+            ),
+        };
+
+        [
+            self.address.to_string(),
+            status.to_string(),
+            version,
+            user_agent,
+            height,
+            format!("{} ms", self.latency.as_millis()),
+            sent,
+            received,
+            self.goodput()
+                .map_or("-".to_string(), |g| format!("{:.0} B/s", g)),
+            match self.outcome {
+                Outcome::Success(_) => format!("{:.1}", self.score),
+                Outcome::Slow(_)
+                | Outcome::Failure(_)
+                | Outcome::Timeout
+                | Outcome::Stalled => "-".to_string(),
+            },
+        ]
+    }
+
+    fn color(&self) -> &'static str {
+        match self.outcome {
+            Outcome::Success(_) => GREEN,
+            Outcome::Failure(_) => RED,
+            Outcome::Slow(_) | Outcome::Timeout | Outcome::Stalled => YELLOW,
+        }
+    }
+}
+
+/// Traffic of all completed handshakes of a run.
+#[derive(Debug, Default, PartialEq)]
+pub struct Bandwidth {
+    /// Number of bytes sent
+    pub sent: u64,
+    /// Number of bytes received
+    pub received: u64,
+    /// Number of payload bytes exchanged in both directions
+    pub payload: u64,
+    /// Total duration of the handshakes
+    pub elapsed: Duration,
+}
+
+impl Bandwidth {
+    pub fn of(results: &[HandshakeSummary]) -> Self {
+        let mut bandwidth = Self::default();
+        for result in results {
+            if let Outcome::Success(report) | Outcome::Slow(report) = &result.outcome {
+                bandwidth.sent += report.stats.bytes_sent();
+                bandwidth.received += report.stats.bytes_received();
+                bandwidth.payload += report.stats.payload_bytes();
+                bandwidth.elapsed += result.latency;
+            }
+        }
+        bandwidth
+    }
+
+    /// Gets the payload bytes exchanged per second of handshaking
+    pub fn goodput(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+        self.payload as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl Display for Bandwidth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sent {} B, received {} B, goodput {:.0} B/s",
+            self.sent,
+            self.received,
+            self.goodput()
+        )
+    }
+}
+
+/// Renders the messages exchanged with the node as a single line JSON
+/// object, with times in milliseconds since the session started, along
+/// with the code of the error the handshake failed with, if any.
+pub fn timeline_json(
+    address: SocketAddrV4,
+    timeline: &[TimelineEvent],
+    error: Option<&str>,
+) -> String {
+    let events: Vec<serde_json::Value> = timeline
+        .iter()
+        .map(|event| {
+            json!({
+                "elapsed_ms": event.elapsed.as_micros() as f64 / 1000.0,
+                "direction": event.direction.to_string(),
+                "command": event.command,
+                "bytes": event.length,
+            })
+        })
+        .collect();
+
+    let mut line = json!({
+        "peer": address.to_string(),
+        "events": events,
+    });
+    if let Some(code) = error {
+        line["error"] = code.into();
+    }
+    line.to_string()
+}
+
+/// Renders the messages exchanged with the node, one line per message,
+/// printed when verbose output is requested.
+pub fn render_messages(address: SocketAddrV4, timeline: &[TimelineEvent]) -> String {
+    let mut lines = format!("Messages exchanged with {}:\n", address);
+    for event in timeline {
+        lines.push_str(&format!("  {}\n", event));
+    }
+    lines
+}
+
+/// Gets the exit code of the run, failures taking precedence over slow
+/// handshakes
+pub fn exit_code(results: &[HandshakeSummary]) -> u8 {
+    let any = |f: fn(&Outcome) -> bool| results.iter().any(|r| f(&r.outcome));
+    if any(|o| matches!(o, Outcome::Failure(_) | Outcome::Timeout | Outcome::Stalled)) {
+        FAILURE_EXIT_CODE
+    } else if any(|o| matches!(o, Outcome::Slow(_))) {
+        SLOW_EXIT_CODE
+    } else {
+        0
+    }
+}
+
+/// Keeps only the successful handshakes, ordered from the fastest one.
+pub fn fastest_successes(mut results: Vec<HandshakeSummary>) -> Vec<HandshakeSummary> {
+    results.retain(|r| matches!(r.outcome, Outcome::Success(_)));
+    results.sort_by_key(|r| r.latency);
+    results
+}
+
+/// Renders the results as a table with aligned columns, coloring
+/// the status column with ANSI escape codes if requested.
+pub fn render_table(results: &[HandshakeSummary], color: bool) -> String {
+    let rows: Vec<[String; 10]> = results.iter().map(|r| r.columns()).collect();
+
+    let mut widths = HEADERS.map(str::len);
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
+
+    let mut table = String::new();
+    push_row(&mut table, &HEADERS.map(String::from), &widths, None);
+    for (row, result) in rows.iter().zip(results) {
+        push_row(&mut table, row, &widths, color.then(|| result.color()));
+    }
+    table
+}
+
+fn push_row(
+    table: &mut String,
+    row: &[String; 10],
+    widths: &[usize; 10],
+    color: Option<&str>,
+) {
+    let mut line = String::new();
+    for (i, (column, width)) in row.iter().zip(widths).enumerate() {
+        if i > 0 {
+            line.push_str("  ");
+        }
+
+        let padded = format!("{:<width$}", column, width = width);
+        match color {
+            // Only the status column is colored
+            Some(color) if i == 1 => {
+                line.push_str(&format!("{}{}{}", color, padded, RESET))
+            }
+            _ => line.push_str(&padded),
+        }
+    }
+    table.push_str(line.trim_end());
+    table.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    use handshaker::p2p::{
+        messages::Command,
+        Direction,
+    };
+
+    fn results() -> Vec<HandshakeSummary> {
+        let mut report = HandshakeReport {
+            config: NodeConfig {
+                version: ProtocolVersion(70016),
+                user_agent: "/Satoshi:25.0.0/".to_string(),
+                start_height: 812345,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        report.stats.record_sent(Command::Version, 126);
+        report.stats.record_sent(Command::Verack, 24);
+        report.stats.record_received("version", 126);
+        report.stats.record_received("verack", 24);
+
+        vec![
+            HandshakeSummary {
+                address: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333),
+                outcome: Outcome::Success(report),
+                latency: Duration::from_millis(120),
+                attempts: 1,
+                score: 87.25,
+            },
+            HandshakeSummary {
+                address: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8333),
+                outcome: Outcome::Failure(ConnectionError::ConnectionRefusedError),
+                latency: Duration::from_millis(3),
+                attempts: 1,
+                score: 0.0,
+            },
+            HandshakeSummary {
+                address: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 3), 18333),
+                outcome: Outcome::Timeout,
+                latency: Duration::from_millis(1000),
+                attempts: 1,
+                score: 0.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            render_table(&results(), false),
+            concat!(
+                "PEER            STATUS   VERSION  USER AGENT        HEIGHT  LATENCY  SENT   RECEIVED  GOODPUT   SCORE\n",
+                "10.0.0.1:8333   OK       70016    /Satoshi:25.0.0/  812345  120 ms   150 B  150 B     1700 B/s  87.2\n",
+                "10.0.0.2:8333   FAILED   -        -                 -       3 ms     -      -         -         -\n",
+                "10.0.0.3:18333  TIMEOUT  -        -                 -       1000 ms  -      -         -         -\n",
+            )
+        );
+    }
+
+    #[test]
+    fn bandwidth() {
+        let results = results();
+        assert_eq!(results[0].goodput(), Some(204.0 / 0.12));
+        assert_eq!(results[1].goodput(), None);
+
+        let bandwidth = Bandwidth::of(&results);
+        assert_eq!(bandwidth.sent, 150);
+        assert_eq!(bandwidth.received, 150);
+        assert_eq!(bandwidth.payload, 204);
+        assert_eq!(bandwidth.elapsed, Duration::from_millis(120));
+        assert_eq!(
+            bandwidth.to_string(),
+            "sent 150 B, received 150 B, goodput 1700 B/s"
+        );
+        assert_eq!(Bandwidth::of(&[]).goodput(), 0.0);
+    }
+
+    #[test]
+    fn slow() {
+        let mut results = results();
+        results.truncate(1);
+        if let Outcome::Success(report) = std::mem::replace(
+            &mut results[0].outcome,
+            Outcome::Failure(ConnectionError::ConnectionRefusedError),
+        ) {
+            results[0].outcome = Outcome::Slow(report);
+        }
+
+        let table = render_table(&results, false);
+        assert!(table.ends_with(
+            "10.0.0.1:8333  SLOW    70016    /Satoshi:25.0.0/  812345  120 ms   150 B  150 B     1700 B/s  -\n"
+        ));
+        assert_eq!(Bandwidth::of(&results).sent, 150);
+        assert_eq!(exit_code(&results), SLOW_EXIT_CODE);
+        assert!(fastest_successes(results).is_empty());
+    }
+
+    #[test]
+    fn exit_codes() {
+        let mut results = results();
+        assert_eq!(exit_code(&results), FAILURE_EXIT_CODE);
+
+        results.truncate(1);
+        assert_eq!(exit_code(&results), 0);
+        assert_eq!(exit_code(&[]), 0);
+    }
+
+    #[test]
+    fn stalled() {
+        let mut results = results();
+        results[2].outcome = Outcome::Stalled;
+
+        let table = render_table(&results, false);
+        assert!(table.ends_with(
+            "10.0.0.3:18333  STALLED  -        -                 -       1000 ms  -      -         -         -\n"
+        ));
+        assert_eq!(results[2].goodput(), None);
+    }
+
+    #[test]
+    fn timeline() {
+        let timeline = [
+            TimelineEvent {
+                elapsed: Duration::from_micros(250),
+                direction: Direction::Sent,
+                command: "version".to_string(),
+                length: 126,
+                frame: None,
+            },
+            TimelineEvent {
+                elapsed: Duration::from_millis(9000),
+                direction: Direction::Received,
+                command: "verack".to_string(),
+                length: 24,
+                frame: None,
+            },
+        ];
+
+        assert_eq!(
+            timeline_json(
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333),
+                &timeline,
+                None
+            ),
+            concat!(
+                r#"{"events":["#,
+                r#"{"bytes":126,"command":"version","direction":"sent","elapsed_ms":0.25},"#,
+                r#"{"bytes":24,"command":"verack","direction":"received","elapsed_ms":9000.0}"#,
+                r#"],"peer":"10.0.0.1:8333"}"#
+            )
+        );
+        assert_eq!(
+            timeline_json(
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 8333),
+                &[],
+                Some(ConnectionError::ConnectionRefusedError.code())
+            ),
+            r#"{"error":"E_REFUSED","events":[],"peer":"10.0.0.2:8333"}"#
+        );
+        assert_eq!(
+            render_messages(
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 8333),
+                &timeline
+            ),
+            concat!(
+                "Messages exchanged with 10.0.0.1:8333:\n",
+                "      0.250 ms sent version (126 B)\n",
+                "   9000.000 ms received verack (24 B)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn fastest() {
+        let mut results = results();
+        results.push(HandshakeSummary {
+            address: SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 4), 8333),
+            outcome: Outcome::Success(Default::default()),
+            latency: Duration::from_millis(40),
+            attempts: 1,
+            score: 0.0,
+        });
+
+        let addresses: Vec<String> = fastest_successes(results)
+            .iter()
+            .map(|r| r.address.to_string())
+            .collect();
+        assert_eq!(addresses, ["10.0.0.4:8333", "10.0.0.1:8333"]);
+    }
+
+    #[test]
+    fn colored_table() {
+        let table = render_table(&results(), true);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert!(!lines[0].contains('\x1b'));
+        assert!(lines[1].contains("\x1b[32mOK     \x1b[0m"));
+        assert!(lines[2].contains("\x1b[31mFAILED \x1b[0m"));
+        assert!(lines[3].contains("\x1b[33mTIMEOUT\x1b[0m"));
+    }
+}
@@ -0,0 +1,378 @@
+//! Differential tests decoding the same frames with both our codec and
+//! rust-bitcoin's network message deserializer, asserting they agree.
+//!
+//! Run with `cargo test --features differential --test differential`.
+
+use std::net::{
+    IpAddr,
+    Ipv4Addr,
+    Ipv6Addr,
+    SocketAddr,
+};
+
+use bitcoin::{
+    consensus::encode,
+    p2p::{
+        address::Address,
+        message::{
+            NetworkMessage,
+            RawNetworkMessage,
+        },
+        message_compact_blocks,
+        message_network,
+        Magic,
+        ServiceFlags,
+    },
+};
+use rand::{
+    rngs::StdRng,
+    Rng,
+    SeedableRng,
+};
+
+use handshaker::p2p::{
+    messages::{
+        calculate_checksum,
+        compose,
+        compose_message,
+        AddrMessage,
+        Chain,
+        Command,
+        FeeFilterMessage,
+        FrameHeader,
+        GetAddrMessage,
+        Message,
+        MessageHeader,
+        NetworkAddress,
+        PingMessage,
+        PongMessage,
+        SendCmpctMessage,
+        SendHeadersMessage,
+        TimestampedAddress,
+        VerackMessage,
+        VersionMessage,
+        WtxidRelayMessage,
+    },
+    NodeConfig,
+};
+
+/// Protocol version from which the Version message carries all fields.
+/// Older ones are encoded without the trailing fields, unlike rust-bitcoin
+/// always encoding them, so they are not compared.
+const RELAY_VERSION: i32 = 70001;
+
+/// Number of random messages generated per message type
+const ROUNDS: usize = 200;
+
+/// Number of mutations of each random message
+const MUTATIONS: usize = 20;
+
+/// Frames of messages on the main network
+const CORPUS: &[&str] = &[
+    // verack
+    "f9beb4d976657261636b000000000000000000005df6e0e2",
+    // getaddr
+    "f9beb4d9676574616464720000000000000000005df6e0e2",
+    // ping
+    "f9beb4d970696e6700000000000000000800000087aeecb7a1c7c4fc8e2b5a1d",
+];
+
+/// Network address as raw bytes, so that addresses neither decoder can
+/// turn into a socket address are still compared
+type RawAddress = (u64, [u8; 16], u16);
+
+/// Message decoded by either decoder, in a form both can be compared in.
+#[derive(Debug, PartialEq)]
+enum Decoded {
+    Version {
+        version: i32,
+        services: u64,
+        timestamp: i64,
+        receiver: RawAddress,
+        sender: RawAddress,
+        nonce: u64,
+        user_agent: String,
+        start_height: i32,
+        relay: bool,
+    },
+    Verack,
+    Ping(u64),
+    Pong(u64),
+    GetAddr,
+    Addr(Vec<(u32, RawAddress)>),
+    SendHeaders,
+    SendCmpct(bool, u64),
+    FeeFilter(i64),
+    WtxidRelay,
+}
+
+fn our_address(address: &NetworkAddress) -> RawAddress {
+    let ip = match address.address().ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    (
+        address.services.into(),
+        ip.octets(),
+        address.address().port(),
+    )
+}
+
+fn their_address(address: &Address) -> RawAddress {
+    let ip = Ipv6Addr::from(address.address);
+    (address.services.to_u64(), ip.octets(), address.port)
+}
+
+/// Decodes the frame with our codec
+fn ours(frame: &[u8]) -> Option<Decoded> {
+    let chain = handshaker::p2p::messages::Network::Main;
+    let mut data = frame;
+    let header = FrameHeader::decode(&chain, &mut data).ok()?;
+    if header.magic != chain.magic()
+        || header.length as usize != data.len()
+        || chain.checksum(data) != header.checksum
+    {
+        return None;
+    }
+
+    Some(match Message::decode(header.command, &mut data).ok()? {
+        Message::Version(msg) => Decoded::Version {
+            version: msg.version.into(),
+            services: msg.services.into(),
+            timestamp: msg.timestamp().as_secs() as i64,
+            receiver: our_address(msg.receiver()),
+            sender: our_address(msg.sender()),
+            nonce: msg.nonce(),
+            user_agent: msg.user_agent,
+            start_height: msg.start_height,
+            relay: msg.relay,
+        },
+        Message::Verack(_) => Decoded::Verack,
+        Message::Ping(msg) => Decoded::Ping(msg.nonce()),
+        Message::Pong(msg) => Decoded::Pong(msg.nonce()),
+        Message::GetAddr(_) => Decoded::GetAddr,
+        Message::Addr(msg) => Decoded::Addr(
+            msg.addresses
+                .iter()
+                .map(|a| (a.time, our_address(&a.address)))
+                .collect(),
+        ),
+        Message::SendHeaders(_) => Decoded::SendHeaders,
+        Message::SendCmpct(msg) => Decoded::SendCmpct(msg.announce, msg.version),
+        Message::FeeFilter(msg) => Decoded::FeeFilter(msg.fee_rate),
+        Message::WtxidRelay(_) => Decoded::WtxidRelay,
+        _ => return None,
+    })
+}
+
+/// Decodes the frame with rust-bitcoin
+fn theirs(frame: &[u8]) -> Option<Decoded> {
+    let message: RawNetworkMessage = encode::deserialize(frame).ok()?;
+    if *message.magic() != Magic::BITCOIN {
+        return None;
+    }
+
+    Some(match message.into_payload() {
+        NetworkMessage::Version(msg) => Decoded::Version {
+            version: msg.version as i32,
+            services: msg.services.to_u64(),
+            // Times before the epoch are reported as the epoch itself
+            timestamp: msg.timestamp.max(0),
+            receiver: their_address(&msg.receiver),
+            sender: their_address(&msg.sender),
+            nonce: msg.nonce,
+            user_agent: msg.user_agent,
+            start_height: msg.start_height,
+            relay: msg.relay,
+        },
+        NetworkMessage::Verack => Decoded::Verack,
+        NetworkMessage::Ping(nonce) => Decoded::Ping(nonce),
+        NetworkMessage::Pong(nonce) => Decoded::Pong(nonce),
+        NetworkMessage::GetAddr => Decoded::GetAddr,
+        NetworkMessage::Addr(addresses) => Decoded::Addr(
+            addresses
+                .iter()
+                .map(|(time, a)| (*time, their_address(a)))
+                .collect(),
+        ),
+        NetworkMessage::SendHeaders => Decoded::SendHeaders,
+        NetworkMessage::SendCmpct(msg) => {
+            Decoded::SendCmpct(msg.send_compact, msg.version)
+        }
+        NetworkMessage::FeeFilter(fee_rate) => Decoded::FeeFilter(fee_rate),
+        NetworkMessage::WtxidRelay => Decoded::WtxidRelay,
+        _ => return None,
+    })
+}
+
+fn random_socket_address(rng: &mut StdRng) -> SocketAddr {
+    if rng.gen() {
+        SocketAddr::from((Ipv4Addr::from(rng.gen::<u32>()), rng.gen()))
+    } else {
+        SocketAddr::from((Ipv6Addr::from(rng.gen::<u128>()), rng.gen()))
+    }
+}
+
+fn random_user_agent(rng: &mut StdRng) -> String {
+    let length = rng.gen_range(0..64);
+    (0..length).map(|_| rng.gen_range(' '..='~')).collect()
+}
+
+/// Generates a random frame of each message type, encoded by our codec
+fn our_frames(rng: &mut StdRng) -> Vec<Vec<u8>> {
+    let chain = handshaker::p2p::messages::Network::Main;
+    let config = NodeConfig {
+        version: rng.gen_range(RELAY_VERSION..=70016).into(),
+        services: rng.gen::<u64>().into(),
+        user_agent: random_user_agent(rng),
+        start_height: rng.gen(),
+        relay: rng.gen(),
+        nonce: Some(rng.gen()),
+        timestamp: Some(rng.gen_range(0..i64::MAX)),
+        ..Default::default()
+    };
+    let version = VersionMessage::new(random_socket_address(rng), &config);
+
+    let addresses = (0..rng.gen_range(0..20))
+        .map(|_| TimestampedAddress {
+            time: rng.gen(),
+            address: NetworkAddress::new(
+                rng.gen::<u64>().into(),
+                random_socket_address(rng),
+            ),
+        })
+        .collect();
+
+    vec![
+        compose(chain, Command::Version, version),
+        compose(chain, Command::Verack, VerackMessage {}),
+        compose(chain, Command::Ping, PingMessage::new(rng.gen())),
+        compose(chain, Command::Pong, PongMessage::new(rng.gen())),
+        compose(chain, Command::GetAddr, GetAddrMessage {}),
+        compose_message(chain, &Message::Addr(AddrMessage { addresses })),
+        compose(chain, Command::SendHeaders, SendHeadersMessage {}),
+        compose(
+            chain,
+            Command::SendCmpct,
+            SendCmpctMessage::new(rng.gen(), rng.gen()),
+        ),
+        compose(chain, Command::FeeFilter, FeeFilterMessage::new(rng.gen())),
+        compose(chain, Command::WtxidRelay, WtxidRelayMessage {}),
+    ]
+}
+
+/// Generates a random frame of each message type, encoded by rust-bitcoin
+fn their_frames(rng: &mut StdRng) -> Vec<Vec<u8>> {
+    let version = message_network::VersionMessage {
+        version: rng.gen_range(RELAY_VERSION as u32..=70016),
+        services: ServiceFlags::from(rng.gen::<u64>()),
+        timestamp: rng.gen_range(0..i64::MAX),
+        receiver: Address::new(
+            &random_socket_address(rng),
+            ServiceFlags::from(rng.gen::<u64>()),
+        ),
+        sender: Address::new(&random_socket_address(rng), ServiceFlags::NONE),
+        nonce: rng.gen(),
+        user_agent: random_user_agent(rng),
+        start_height: rng.gen(),
+        relay: rng.gen(),
+    };
+    let addresses = (0..rng.gen_range(0..20))
+        .map(|_| {
+            (
+                rng.gen(),
+                Address::new(
+                    &random_socket_address(rng),
+                    ServiceFlags::from(rng.gen::<u64>()),
+                ),
+            )
+        })
+        .collect();
+
+    [
+        NetworkMessage::Version(version),
+        NetworkMessage::Verack,
+        NetworkMessage::Ping(rng.gen()),
+        NetworkMessage::Pong(rng.gen()),
+        NetworkMessage::GetAddr,
+        NetworkMessage::Addr(addresses),
+        NetworkMessage::SendHeaders,
+        NetworkMessage::SendCmpct(message_compact_blocks::SendCmpct {
+            send_compact: rng.gen(),
+            version: rng.gen(),
+        }),
+        NetworkMessage::FeeFilter(rng.gen()),
+        NetworkMessage::WtxidRelay,
+    ]
+    .into_iter()
+    .map(|payload| encode::serialize(&RawNetworkMessage::new(Magic::BITCOIN, payload)))
+    .collect()
+}
+
+/// Overwrites a random payload byte, fixing up the checksum so that
+/// the payload decoding is exercised
+fn mutate(rng: &mut StdRng, frame: &[u8]) -> Vec<u8> {
+    let mut frame = frame.to_vec();
+    // Keep the protocol version of Version messages, see RELAY_VERSION
+    let start = match &frame[4..11] {
+        b"version" => MessageHeader::LENGTH + 4,
+        _ => MessageHeader::LENGTH,
+    };
+    if frame.len() > start {
+        let i = rng.gen_range(start..frame.len());
+        frame[i] = rng.gen();
+        let checksum = calculate_checksum(&frame[MessageHeader::LENGTH..]);
+        frame[20..24].copy_from_slice(&checksum.to_le_bytes());
+    }
+    frame
+}
+
+fn assert_agreement(frame: &[u8]) {
+    assert_eq!(ours(frame), theirs(frame), "Frame {:02x?}", frame);
+}
+
+#[test]
+fn our_encoding() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..ROUNDS {
+        for frame in our_frames(&mut rng) {
+            assert!(theirs(&frame).is_some(), "Frame {:02x?}", frame);
+            assert_agreement(&frame);
+        }
+    }
+}
+
+#[test]
+fn their_encoding() {
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..ROUNDS {
+        for frame in their_frames(&mut rng) {
+            assert!(ours(&frame).is_some(), "Frame {:02x?}", frame);
+            assert_agreement(&frame);
+        }
+    }
+}
+
+#[test]
+fn mutations() {
+    let mut rng = StdRng::seed_from_u64(3);
+    for _ in 0..ROUNDS {
+        for frame in our_frames(&mut rng) {
+            for _ in 0..MUTATIONS {
+                assert_agreement(&mutate(&mut rng, &frame));
+            }
+        }
+    }
+}
+
+#[test]
+fn corpus() {
+    for hex in CORPUS {
+        let frame: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        assert!(ours(&frame).is_some(), "Frame {}", hex);
+        assert_agreement(&frame);
+    }
+}
@@ -1 +1,108 @@
+use std::{
+    net::TcpListener,
+    process::{
+        Command,
+        Output,
+    },
+    time::Duration,
+};
 
+use handshaker::p2p::{
+    messages::Network,
+    MockPeer,
+};
+
+/// Runs the binary with the given arguments and `HANDSHAKER_*` environment
+/// variables, ignoring the ones the tests were run with
+fn run(args: &[&str], env: &[(&str, &str)]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_handshaker"));
+    for (name, _) in std::env::vars() {
+        if name.starts_with("HANDSHAKER_") {
+            command.env_remove(name);
+        }
+    }
+    command.args(args).envs(env.iter().copied()).output().unwrap()
+}
+
+#[test]
+fn arguments_from_env() {
+    let env = [
+        ("HANDSHAKER_ADDRESSES", "127.0.0.1:3000 127.0.0.1:3001"),
+        ("HANDSHAKER_DRY_RUN", "true"),
+        ("HANDSHAKER_NETWORK", "signet"),
+        ("HANDSHAKER_USER_AGENT", "/Env:1.0/"),
+    ];
+    let output = run(&[], &env);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Version message for 127.0.0.1:3000"));
+    assert!(stdout.contains("Version message for 127.0.0.1:3001"));
+    assert!(stdout.contains("Network         Signet"));
+    assert!(stdout.contains("User agent      /Env:1.0/"));
+
+    // Command line arguments take precedence over environment variables
+    let output = run(&["--user-agent", "/Cli:1.0/"], &env);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("User agent      /Cli:1.0/"));
+    assert!(!stdout.contains("/Env:1.0/"));
+}
+
+/// Runs the binary against the nodes, returning its exit code
+async fn exit_code(args: &[&str], addresses: &[String]) -> Option<i32> {
+    let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    args.extend_from_slice(addresses);
+    // Mock peers run on this runtime, so the binary must not block it
+    let output = tokio::task::spawn_blocking(move || {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        run(&args, &[])
+    })
+    .await
+    .unwrap();
+    output.status.code()
+}
+
+#[tokio::test]
+async fn exit_codes() {
+    // The v2 key exchange is left out, so that no latency comes from it
+    let args = [
+        "--network",
+        "signet",
+        "--transport",
+        "v1",
+        "--max-latency",
+        "500ms",
+        "--timeout",
+        "5000",
+        "-q",
+    ];
+    let fast = MockPeer::new(Network::Signet).listen().await.unwrap();
+    let slow = MockPeer::new(Network::Signet)
+        .with_verack_delay(Duration::from_millis(1500))
+        .listen()
+        .await
+        .unwrap();
+    let closed = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+
+    assert_eq!(exit_code(&args, &[fast.to_string()]).await, Some(0));
+    // Observing the node after the handshake does not make it slow
+    let observing = [&args[..], &["--observe", "1"]].concat();
+    assert_eq!(exit_code(&observing, &[fast.to_string()]).await, Some(0));
+    // Nor does waiting for addresses the node never sends
+    #[cfg(feature = "addr")]
+    {
+        let requesting =
+            [&args[..], &["--request-addrs", "--addr-window", "2s"]].concat();
+        assert_eq!(exit_code(&requesting, &[fast.to_string()]).await, Some(0));
+    }
+    assert_eq!(
+        exit_code(&args, &[fast.to_string(), slow.to_string()]).await,
+        Some(4)
+    );
+    assert_eq!(
+        exit_code(&args, &[slow.to_string(), closed.to_string()]).await,
+        Some(3)
+    );
+}